@@ -0,0 +1,10 @@
+//! ABI encoding of the contract calls estimates are built from
+
+use web3::ethabi::{self, ParamType, Token};
+
+/// ABI-encodes a call of the function `name` taking `param_types` with `tokens`
+pub fn encode_call(name: &str, param_types: &[ParamType], tokens: &[Token]) -> Vec<u8> {
+    let mut data = ethabi::short_signature(name, param_types).to_vec();
+    data.extend(ethabi::encode(tokens));
+    data
+}
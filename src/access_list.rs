@@ -0,0 +1,282 @@
+//! EIP-2930 access list suggestions from `eth_createAccessList`
+
+use crate::estimator::{ApiError, EstimateGasRequest, EstimateReason, GasEstimator};
+use crate::revert;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use web3::{
+    Transport,
+    api::Namespace,
+    helpers::{self, CallFuture},
+    types::{AccessList, BlockNumber, CallRequest, U256},
+};
+
+/// Result of `eth_createAccessList`, which web3 has no typed binding for
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AccessListResult {
+    access_list: AccessList,
+    gas_used: U256,
+    /// Set when execution failed, e.g. because the transaction reverts
+    #[serde(default)]
+    error: Option<String>,
+}
+
+/// Response for the access list endpoint
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct CreateAccessListResponse {
+    pub access_list: AccessList,
+    /// Gas used by the transaction when it carries the access list
+    pub gas_used: U256,
+}
+
+/// Estimate of the same transaction carrying the node's suggested access list,
+/// returned when it is cheaper than the plain estimate
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct AccessListEstimate {
+    pub access_list: AccessList,
+    /// Gas limit with the access list, padded like the plain estimate
+    pub gas_limit: U256,
+}
+
+/// Whether the node rejected the call because it doesn't implement the method
+pub(crate) fn is_method_unsupported(error: &web3::Error) -> bool {
+    let web3::Error::Rpc(error) = error else {
+        return false;
+    };
+    let message = error.message.to_lowercase();
+    error.code == jsonrpc_core::ErrorCode::MethodNotFound
+        || message.contains("method not found")
+        || message.contains("does not exist")
+        || message.contains("not supported")
+}
+
+impl<T: Transport> GasEstimator<T> {
+    /// Asks the node for the access list the transaction would benefit from
+    pub async fn create_access_list(
+        &self,
+        tx: &CallRequest,
+        block: Option<BlockNumber>,
+    ) -> Result<CreateAccessListResponse, ApiError> {
+        let result: AccessListResult = CallFuture::new(self.eth.transport().execute(
+            "eth_createAccessList",
+            vec![
+                helpers::serialize(tx),
+                helpers::serialize(&block.unwrap_or(BlockNumber::Latest)),
+            ],
+        ))
+        .await
+        .map_err(|error| {
+            if is_method_unsupported(&error) {
+                ApiError::NotImplemented(format!(
+                    "The configured RPC doesn't support eth_createAccessList: {error}"
+                ))
+            } else {
+                revert::estimate_error(error)
+            }
+        })?;
+
+        if let Some(error) = result.error {
+            return Err(ApiError::ExecutionReverted {
+                reason: error
+                    .strip_prefix("execution reverted: ")
+                    .map(str::to_string),
+                data: None,
+            });
+        }
+        Ok(CreateAccessListResponse {
+            access_list: result.access_list,
+            gas_used: result.gas_used,
+        })
+    }
+
+    /// Estimates the request again with the node's suggested access list, returning
+    /// it only when it beats `plain_gas_limit`. Any failure, including nodes without
+    /// `eth_createAccessList`, yields `None`.
+    pub(crate) async fn access_list_estimate(
+        &self,
+        request: &EstimateGasRequest,
+        reason: EstimateReason,
+        plain_gas_limit: U256,
+    ) -> Option<AccessListEstimate> {
+        let block = request.requested_block().ok()?;
+        let suggestion = self.create_access_list(&request.tx, block).await.ok()?;
+        if suggestion.access_list.is_empty() {
+            return None;
+        }
+
+        let mut with_access_list = request.clone();
+        with_access_list.tx.access_list = Some(suggestion.access_list.clone());
+        with_access_list.include_access_list = false;
+        with_access_list.include_cost = false;
+        with_access_list.check_balance = false;
+        with_access_list.simulate = false;
+        let estimate = Box::pin(self.estimate_with_reason(with_access_list, reason))
+            .await
+            .ok()?;
+
+        (estimate.gas_limit < plain_gas_limit).then_some(AccessListEstimate {
+            access_list: suggestion.access_list,
+            gas_limit: estimate.gas_limit,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use web3::transports::test::TestTransport;
+    use web3::types::{AccessListItem, Address, Bytes, H256};
+
+    fn access_list_response(gas_used: &str) -> serde_json::Value {
+        json!({
+            "accessList": [{
+                "address": "0x6b175474e89094c44da98b954eedeac495271d0f",
+                "storageKeys": [
+                    "0x0000000000000000000000000000000000000000000000000000000000000001"
+                ]
+            }],
+            "gasUsed": gas_used
+        })
+    }
+
+    fn call() -> CallRequest {
+        CallRequest {
+            to: Some(Address::repeat_byte(0x11)),
+            data: Some(Bytes::from(vec![0x70, 0xa0, 0x82, 0x31])),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_create_access_list() {
+        let mut transport = TestTransport::default();
+        transport.add_response(access_list_response("0x6d60"));
+        let estimator = GasEstimator::new(transport.clone());
+
+        let response = estimator.create_access_list(&call(), None).await.unwrap();
+        assert_eq!(
+            response.access_list,
+            vec![AccessListItem {
+                address: "0x6b175474e89094c44da98b954eedeac495271d0f"
+                    .parse()
+                    .unwrap(),
+                storage_keys: vec![H256::from_low_u64_be(1)],
+            }]
+        );
+        assert_eq!(response.gas_used, 28000.into());
+        transport.assert_request(
+            "eth_createAccessList",
+            &[
+                serde_json::to_value(call()).unwrap().to_string(),
+                r#""latest""#.into(),
+            ],
+        );
+    }
+
+    #[tokio::test]
+    async fn test_create_access_list_reports_execution_errors() {
+        let mut transport = TestTransport::default();
+        transport.add_response(json!({
+            "accessList": [],
+            "gasUsed": "0x0",
+            "error": "execution reverted: Ownable: caller is not the owner"
+        }));
+        let estimator = GasEstimator::new(transport);
+
+        let error = estimator
+            .create_access_list(&call(), None)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            ApiError::ExecutionReverted { reason: Some(reason), .. }
+                if reason == "Ownable: caller is not the owner"
+        ));
+    }
+
+    fn include_access_list() -> EstimateGasRequest {
+        EstimateGasRequest {
+            include_access_list: true,
+            ..call().into()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cheaper_access_list_estimate() {
+        let mut transport = TestTransport::default();
+        transport.add_response("0xc350".into()); // plain: 50000
+        transport.add_response(access_list_response("0xb3b0"));
+        transport.add_response("0xb798".into()); // with access list: 47000
+        let estimator = GasEstimator::new(transport.clone());
+
+        let response = estimator.estimate(include_access_list()).await.unwrap();
+        assert_eq!(response.gas_limit, 50000.into());
+        let alternative = response.access_list_estimate.unwrap();
+        assert_eq!(alternative.gas_limit, 47000.into());
+        assert_eq!(alternative.access_list.len(), 1);
+
+        transport.assert_request(
+            "eth_estimateGas",
+            &[serde_json::to_value(call()).unwrap().to_string()],
+        );
+        transport.assert_request(
+            "eth_createAccessList",
+            &[
+                serde_json::to_value(call()).unwrap().to_string(),
+                r#""latest""#.into(),
+            ],
+        );
+        let with_access_list = CallRequest {
+            access_list: Some(alternative.access_list),
+            ..call()
+        };
+        transport.assert_request(
+            "eth_estimateGas",
+            &[serde_json::to_value(with_access_list).unwrap().to_string()],
+        );
+    }
+
+    #[tokio::test]
+    async fn test_costlier_access_list_estimate_is_omitted() {
+        let mut transport = TestTransport::default();
+        transport.add_response("0xc350".into());
+        transport.add_response(access_list_response("0xc350"));
+        transport.add_response("0xc738".into()); // 51000
+        let estimator = GasEstimator::new(transport);
+
+        let response = estimator.estimate(include_access_list()).await.unwrap();
+        assert_eq!(response.access_list_estimate, None);
+    }
+
+    #[tokio::test]
+    async fn test_access_list_failure_keeps_estimate() {
+        let mut transport = TestTransport::default();
+        // Nothing queued after the plain estimate, so eth_createAccessList fails
+        transport.add_response("0xc350".into());
+        let estimator = GasEstimator::new(transport);
+
+        let response = estimator.estimate(include_access_list()).await.unwrap();
+        assert_eq!(response.gas_limit, 50000.into());
+        assert_eq!(response.access_list_estimate, None);
+    }
+
+    #[test]
+    fn test_is_method_unsupported() {
+        let error = |code: i64, message: &str| {
+            web3::Error::Rpc(jsonrpc_core::Error {
+                code: code.into(),
+                message: message.to_string(),
+                data: None,
+            })
+        };
+        assert!(is_method_unsupported(&error(
+            -32601,
+            "the method eth_createAccessList does not exist/is not available"
+        )));
+        assert!(is_method_unsupported(&error(-32000, "Method not found")));
+        assert!(!is_method_unsupported(&error(3, "execution reverted")));
+        assert!(!is_method_unsupported(&web3::Error::Unreachable));
+    }
+}
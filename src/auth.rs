@@ -0,0 +1,150 @@
+//! Optional API key authentication
+
+use crate::estimator::ErrorResponse;
+use crate::metrics::Metrics;
+use axum::{
+    Json,
+    extract::{Request, State},
+    http::{HeaderMap, Method, StatusCode, header::AUTHORIZATION},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::collections::HashSet;
+use std::sync::Arc;
+use tracing::Span;
+
+pub const API_KEY_HEADER: &str = "x-api-key";
+/// `GET` paths served without a key unless configured otherwise, so load
+/// balancers and Prometheus can reach them
+pub const DEFAULT_OPEN_PATHS: [&str; 4] = ["/health", "/health/live", "/health/ready", "/metrics"];
+
+/// Parses a key list separated by commas or newlines, ignoring blank entries
+pub fn parse_api_keys(keys: &str) -> HashSet<String> {
+    keys.split([',', '\n'])
+        .map(str::trim)
+        .filter(|key| !key.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Paths in [`DEFAULT_OPEN_PATHS`]
+pub fn default_open_paths() -> HashSet<String> {
+    DEFAULT_OPEN_PATHS.map(str::to_string).into()
+}
+
+/// Short identifier of a key for logs and metrics, which can't be turned back into
+/// the key. FNV-1a, so it is the same in every process.
+pub fn key_id(key: &str) -> String {
+    let hash = key.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+    });
+    format!("{:08x}", hash >> 32)
+}
+
+/// Settings of [`require_api_key`]
+#[derive(Clone)]
+pub struct AuthState {
+    /// Accepted keys; empty leaves the API open
+    pub api_keys: Arc<HashSet<String>>,
+    /// `GET` paths served without a key
+    pub open_paths: Arc<HashSet<String>>,
+    pub metrics: Arc<Metrics>,
+}
+
+/// Key sent in the `X-Api-Key` header or as an `Authorization: Bearer` token
+fn presented_key(headers: &HeaderMap) -> Option<&str> {
+    let header = |name| headers.get(name).and_then(|value| value.to_str().ok());
+    header(API_KEY_HEADER).or_else(|| {
+        let (scheme, token) = header(AUTHORIZATION.as_str())?.split_once(' ')?;
+        scheme.eq_ignore_ascii_case("bearer").then(|| token.trim())
+    })
+}
+
+/// Compares without stopping at the first differing byte, so response times don't
+/// reveal how much of a key was guessed
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// The configured key matching `presented`, checking every key
+fn find_key<'a>(api_keys: &'a HashSet<String>, presented: &str) -> Option<&'a str> {
+    api_keys.iter().fold(None, |found, key| {
+        if constant_time_eq(key.as_bytes(), presented.as_bytes()) {
+            Some(key.as_str())
+        } else {
+            found
+        }
+    })
+}
+
+/// Middleware rejecting requests without a known key in `X-Api-Key` or
+/// `Authorization: Bearer` with `401`. Does nothing when no keys are configured;
+/// `GET` requests to the open paths never need one. The key's [`key_id`] is
+/// recorded on the request span and counted per key.
+pub async fn require_api_key(
+    State(auth): State<AuthState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let is_open = request.method() == Method::GET && auth.open_paths.contains(request.uri().path());
+    if auth.api_keys.is_empty() || is_open {
+        return next.run(request).await;
+    }
+
+    let Some(key) = presented_key(request.headers()).and_then(|key| find_key(&auth.api_keys, key))
+    else {
+        let body = ErrorResponse {
+            error: "invalid or missing API key".to_string(),
+            code: "unauthorized".to_string(),
+            reason: None,
+            data: None,
+        };
+        return (StatusCode::UNAUTHORIZED, Json(body)).into_response();
+    };
+    let key_id = key_id(key);
+    Span::current().record("api_key_id", key_id.as_str());
+    auth.metrics.record_api_key(&key_id);
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_api_keys() {
+        let keys = parse_api_keys(" alpha,beta ,,");
+        assert_eq!(keys.len(), 2);
+        assert!(keys.contains("alpha") && keys.contains("beta"));
+        assert!(parse_api_keys("").is_empty());
+        assert_eq!(parse_api_keys("alpha\r\nbeta\n\n").len(), 2);
+    }
+
+    #[test]
+    fn test_presented_key() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(presented_key(&headers), None);
+        headers.insert(AUTHORIZATION, "Basic c2VjcmV0".parse().unwrap());
+        assert_eq!(presented_key(&headers), None);
+        headers.insert(AUTHORIZATION, "bearer secret".parse().unwrap());
+        assert_eq!(presented_key(&headers), Some("secret"));
+        headers.insert(API_KEY_HEADER, "other".parse().unwrap());
+        assert_eq!(presented_key(&headers), Some("other"));
+    }
+
+    #[test]
+    fn test_find_key() {
+        let keys = parse_api_keys("alpha,beta");
+        assert_eq!(find_key(&keys, "beta"), Some("beta"));
+        assert_eq!(find_key(&keys, "bet"), None);
+        assert_eq!(find_key(&keys, "betA"), None);
+    }
+
+    #[test]
+    fn test_key_id() {
+        assert_eq!(key_id("secret"), key_id("secret"));
+        assert_ne!(key_id("secret"), key_id("secret2"));
+        assert_eq!(key_id("secret").len(), 8);
+        assert!(!key_id("secret").contains("secret"));
+    }
+}
@@ -0,0 +1,197 @@
+//! Advisory check that the sender can afford the estimated transaction
+
+use crate::estimator::{GasEstimateResponse, GasEstimator};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use web3::{
+    Transport,
+    types::{BlockNumber, CallRequest, U256},
+};
+
+/// Whether `from` can pay for the transaction at its fee cap
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct BalanceCheck {
+    /// Balance of `from` at the latest block
+    pub balance: U256,
+    /// Worst-case cost: `value + gas_limit * max_fee_per_gas`, plus the blob fee
+    /// cap of blob transactions
+    pub required: U256,
+    pub sufficient: bool,
+}
+
+/// Sender balance and the fee cap to price the estimate at
+pub(crate) struct BalanceInputs {
+    balance: U256,
+    max_fee_per_gas: U256,
+}
+
+impl BalanceInputs {
+    /// Prices the final estimate against the balance
+    pub(crate) fn check(&self, tx: &CallRequest, response: &GasEstimateResponse) -> BalanceCheck {
+        let blob_fee = match (response.blob_gas_used, response.max_fee_per_blob_gas) {
+            (Some(blob_gas), Some(fee)) => fee.saturating_mul(blob_gas.into()),
+            _ => U256::zero(),
+        };
+        let required = tx
+            .value
+            .unwrap_or_default()
+            .saturating_add(response.gas_limit.saturating_mul(self.max_fee_per_gas))
+            .saturating_add(blob_fee);
+        BalanceCheck {
+            balance: self.balance,
+            required,
+            sufficient: self.balance >= required,
+        }
+    }
+}
+
+impl<T: Transport> GasEstimator<T> {
+    /// Fetches the balance of `from`, and the pending block's fee cap when the
+    /// transaction doesn't set one. The check is advisory, so failures are logged
+    /// and yield `None`.
+    pub(crate) async fn balance_inputs(&self, tx: &CallRequest) -> Option<BalanceInputs> {
+        let from = tx.from?;
+        let supplied_fee = tx.max_fee_per_gas.or(tx.gas_price);
+        let max_fee_per_gas = async {
+            match supplied_fee {
+                Some(fee) => Ok(fee),
+                None => self
+                    .fetch_fees(BlockNumber::Pending)
+                    .await
+                    .map(|fees| fees.max_fee_per_gas),
+            }
+        };
+        let (balance, max_fee_per_gas) = tokio::join!(
+            self.eth.balance(from, Some(BlockNumber::Latest)),
+            max_fee_per_gas
+        );
+
+        match (balance, max_fee_per_gas) {
+            (Ok(balance), Ok(max_fee_per_gas)) => Some(BalanceInputs {
+                balance,
+                max_fee_per_gas,
+            }),
+            (Err(error), _) => {
+                tracing::warn!(%error, "Balance lookup failed, skipping the balance check");
+                None
+            }
+            (_, Err(error)) => {
+                tracing::warn!(%error, "Fee lookup failed, skipping the balance check");
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::estimator::{ApiError, EstimateGasRequest, GasEstimator};
+    use serde_json::json;
+    use web3::transports::test::TestTransport;
+    use web3::types::{Address, Block, CallRequest, H256};
+
+    fn request(value: u64) -> EstimateGasRequest {
+        EstimateGasRequest {
+            tx: CallRequest {
+                from: Some(Address::from_low_u64_be(0xf00)),
+                to: Some(Address::from_low_u64_be(0xb0b)),
+                value: Some(value.into()),
+                data: Some(vec![0x01].into()),
+                max_fee_per_gas: Some(10.into()),
+                transaction_type: Some(2.into()),
+                ..Default::default()
+            },
+            check_balance: true,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sufficient_balance() {
+        let mut transport = TestTransport::default();
+        transport.add_response("0xc350".into());
+        // 50000 gas at 10 wei plus the value of 1000
+        transport.add_response(json!(format!("{:#x}", 501_000)));
+        let estimator = GasEstimator::new(transport.clone())
+            .with_buffer_percent(0)
+            .unwrap();
+
+        let response = estimator.estimate(request(1000)).await.unwrap();
+        let check = response.balance_check.unwrap();
+        assert_eq!(check.required, 501_000.into());
+        assert!(check.sufficient);
+        transport.assert_request(
+            "eth_estimateGas",
+            &[serde_json::to_value(request(1000).tx).unwrap().to_string()],
+        );
+        transport.assert_request(
+            "eth_getBalance",
+            &[
+                r#""0x0000000000000000000000000000000000000f00""#.to_string(),
+                r#""latest""#.to_string(),
+            ],
+        );
+        transport.assert_no_more_requests();
+    }
+
+    #[tokio::test]
+    async fn test_insufficient_balance() {
+        let mut transport = TestTransport::default();
+        transport.add_response("0xc350".into());
+        transport.add_response(json!(format!("{:#x}", 500_999)));
+        let estimator = GasEstimator::new(transport).with_buffer_percent(0).unwrap();
+
+        // Insufficient funds are reported without failing the estimate
+        let response = estimator.estimate(request(1000)).await.unwrap();
+        assert_eq!(response.gas_limit, 50_000.into());
+        let check = response.balance_check.unwrap();
+        assert_eq!(check.balance, 500_999.into());
+        assert!(!check.sufficient);
+    }
+
+    #[tokio::test]
+    async fn test_fee_cap_is_fetched_when_not_supplied() {
+        let mut transport = TestTransport::default();
+        transport.add_response("0xc350".into());
+        transport.add_response(json!("0x0"));
+        transport.add_response(
+            serde_json::to_value(Block::<H256> {
+                base_fee_per_gas: Some(4.into()),
+                ..Default::default()
+            })
+            .unwrap(),
+        );
+        transport.add_response(json!("0x2"));
+        let estimator = GasEstimator::new(transport).with_buffer_percent(0).unwrap();
+
+        let mut request = request(0);
+        request.tx.max_fee_per_gas = None;
+        let check = estimator.estimate(request).await.unwrap().balance_check;
+        // 2 * 4 + 2 wei per gas
+        assert_eq!(check.unwrap().required, 500_000.into());
+    }
+
+    #[tokio::test]
+    async fn test_failed_lookup_omits_check() {
+        let mut transport = TestTransport::default();
+        transport.add_response("0xc350".into());
+        transport.add_response(json!({ "unexpected": true }));
+        let estimator = GasEstimator::new(transport);
+
+        let response = estimator.estimate(request(0)).await.unwrap();
+        assert!(response.balance_check.is_none());
+        assert!(serde_json::to_value(&response).unwrap()["balance_check"].is_null());
+    }
+
+    #[tokio::test]
+    async fn test_missing_from() {
+        let transport = TestTransport::default();
+        let estimator = GasEstimator::new(transport.clone());
+
+        let mut request = request(0);
+        request.tx.from = None;
+        let error = estimator.estimate(request).await.unwrap_err();
+        assert!(matches!(error, ApiError::BadRequest(_)), "{error:?}");
+        transport.assert_no_more_requests();
+    }
+}
@@ -0,0 +1,136 @@
+//! Next-block base fee prediction following the EIP-1559 update rule
+
+use crate::estimator::{ApiError, GasEstimator};
+use crate::gas_costs::{BASE_FEE_MAX_CHANGE_DENOMINATOR, ELASTICITY_MULTIPLIER};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use web3::{
+    Transport,
+    types::{BlockId, BlockNumber, U256},
+};
+
+/// Response for the next base fee endpoint
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct BaseFeeResponse {
+    /// Base fee of the latest block
+    pub current: U256,
+    /// Base fee of the block after it
+    pub predicted_next: U256,
+    /// Number of the latest block
+    pub block_number: u64,
+}
+
+impl<T: Transport> GasEstimator<T> {
+    /// Predicts the next block's base fee from the latest block
+    pub async fn next_base_fee(&self) -> Result<BaseFeeResponse, ApiError> {
+        let block = self
+            .eth
+            .block(BlockId::Number(BlockNumber::Latest))
+            .await
+            .map_err(|e| ApiError::InternalServerError(format!("RPC call failed: {e}")))?
+            .ok_or_else(|| {
+                ApiError::InternalServerError("Node returned no latest block".to_string())
+            })?;
+        let current = block.base_fee_per_gas.ok_or_else(|| {
+            ApiError::ServiceUnavailable(
+                "Node did not return EIP-1559 fee fields (pre-London chain?)".to_string(),
+            )
+        })?;
+
+        Ok(BaseFeeResponse {
+            current,
+            predicted_next: predict_next_base_fee(current, block.gas_used, block.gas_limit),
+            block_number: block.number.unwrap_or_default().as_u64(),
+        })
+    }
+}
+
+/// Applies the EIP-1559 base fee update: the fee moves by up to 1/8 depending on
+/// how far the block's gas usage was from the target (half the gas limit).
+/// Uses the consensus integer arithmetic, so results match the node exactly.
+pub fn predict_next_base_fee(base_fee: U256, gas_used: U256, gas_limit: U256) -> U256 {
+    let gas_target = gas_limit / ELASTICITY_MULTIPLIER;
+    if gas_target.is_zero() || gas_used == gas_target {
+        return base_fee;
+    }
+
+    let denominator = gas_target * BASE_FEE_MAX_CHANGE_DENOMINATOR;
+    if gas_used > gas_target {
+        let delta = base_fee.saturating_mul(gas_used - gas_target) / denominator;
+        base_fee.saturating_add(delta.max(U256::one()))
+    } else {
+        let delta = base_fee.saturating_mul(gas_target - gas_used) / denominator;
+        base_fee.saturating_sub(delta)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use web3::transports::test::TestTransport;
+    use web3::types::{Block, H256, U64};
+
+    const GWEI: u64 = 1_000_000_000;
+    const GAS_LIMIT: u64 = 30_000_000;
+
+    fn predict(base_fee: u64, gas_used: u64) -> U256 {
+        predict_next_base_fee(base_fee.into(), gas_used.into(), GAS_LIMIT.into())
+    }
+
+    #[test]
+    fn test_full_block_raises_by_one_eighth() {
+        assert_eq!(predict(10 * GWEI, GAS_LIMIT), 11_250_000_000u64.into());
+    }
+
+    #[test]
+    fn test_half_full_block_keeps_base_fee() {
+        assert_eq!(predict(10 * GWEI, GAS_LIMIT / 2), (10 * GWEI).into());
+    }
+
+    #[test]
+    fn test_empty_block_lowers_by_one_eighth() {
+        assert_eq!(predict(10 * GWEI, 0), 8_750_000_000u64.into());
+    }
+
+    #[test]
+    fn test_partial_blocks() {
+        // 75% full: +1/16
+        assert_eq!(predict(10 * GWEI, 22_500_000), 10_625_000_000u64.into());
+        // Rounds down; 12_345_678 * 3_000_000 / 120_000_000 = 308_641.95
+        assert_eq!(predict(12_345_678, 18_000_000), 12_654_319u64.into());
+        // An increase is at least 1 wei
+        assert_eq!(predict(7, GAS_LIMIT), 8.into());
+        assert_eq!(predict(7, 0), 7.into());
+    }
+
+    #[tokio::test]
+    async fn test_next_base_fee() {
+        let mut transport = TestTransport::default();
+        transport.add_response(
+            serde_json::to_value(Block::<H256> {
+                number: Some(U64::from(20_000_000)),
+                base_fee_per_gas: Some((10 * GWEI).into()),
+                gas_used: GAS_LIMIT.into(),
+                gas_limit: GAS_LIMIT.into(),
+                ..Default::default()
+            })
+            .unwrap(),
+        );
+        let estimator = GasEstimator::new(transport);
+
+        let response = estimator.next_base_fee().await.unwrap();
+        assert_eq!(response.current, (10 * GWEI).into());
+        assert_eq!(response.predicted_next, 11_250_000_000u64.into());
+        assert_eq!(response.block_number, 20_000_000);
+    }
+
+    #[tokio::test]
+    async fn test_next_base_fee_pre_london() {
+        let mut transport = TestTransport::default();
+        transport.add_response(serde_json::to_value(Block::<H256>::default()).unwrap());
+        let estimator = GasEstimator::new(transport);
+
+        let error = estimator.next_base_fee().await.unwrap_err();
+        assert!(matches!(error, ApiError::ServiceUnavailable(_)));
+    }
+}
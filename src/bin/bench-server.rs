@@ -0,0 +1,329 @@
+//! Load testing tool for a running gas estimator service.
+//!
+//! Usage:
+//!   cargo run --release --bin bench-server -- --url http://localhost:3000 --rps 100 --duration 30s
+
+use gas_estimator::GasEstimateResponse;
+use reqwest::Client;
+use serde::Serialize;
+use serde_json::{Value, json};
+use std::sync::{
+    Arc,
+    atomic::{AtomicUsize, Ordering},
+};
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+const DEFAULT_URL: &str = "http://localhost:3000";
+const DEFAULT_RPS: u64 = 100;
+const DEFAULT_DURATION_SECS: u64 = 30;
+const RESULTS_FILE: &str = "bench-results.json";
+const REQUEST_TIMEOUT_SECS: u64 = 30;
+
+#[derive(Debug)]
+struct BenchConfig {
+    url: String,
+    rps: u64,
+    duration: Duration,
+}
+
+/// Outcome of a single request
+enum Outcome {
+    Ok { method: String },
+    RpcError,
+    Error,
+}
+
+#[derive(Default)]
+struct Samples {
+    latencies_ms: Vec<f64>,
+    errors: usize,
+    rpc_errors: usize,
+    rpc_responses: usize,
+    cache_hits: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct BenchSummary {
+    url: String,
+    target_rps: u64,
+    duration_secs: f64,
+    total_requests: usize,
+    successful_requests: usize,
+    achieved_rps: f64,
+    latency_p50_ms: f64,
+    latency_p95_ms: f64,
+    latency_p99_ms: f64,
+    errors: usize,
+    errors_per_sec: f64,
+    rpc_errors: usize,
+    cache_hit_ratio: f64,
+    max_concurrency: usize,
+}
+
+fn usage() -> String {
+    "Usage: bench-server [--url <url>] [--rps <requests/sec>] [--duration <30s|2m|500ms>]"
+        .to_string()
+}
+
+/// Parses command line arguments
+fn parse_args(args: impl Iterator<Item = String>) -> Result<BenchConfig, String> {
+    let mut config = BenchConfig {
+        url: DEFAULT_URL.to_string(),
+        rps: DEFAULT_RPS,
+        duration: Duration::from_secs(DEFAULT_DURATION_SECS),
+    };
+
+    let mut args = args;
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format!("Missing value for {arg}"))
+        };
+        match arg.as_str() {
+            "--url" => config.url = value()?,
+            "--rps" => {
+                config.rps = value()?
+                    .parse()
+                    .map_err(|e| format!("Invalid --rps: {e}"))?;
+                if config.rps == 0 {
+                    return Err("--rps must be greater than zero".to_string());
+                }
+            }
+            "--duration" => config.duration = parse_duration(&value()?)?,
+            "-h" | "--help" => return Err(usage()),
+            other => return Err(format!("Unknown argument: {other}\n{}", usage())),
+        }
+    }
+
+    Ok(config)
+}
+
+/// Parses durations like `30s`, `2m`, `500ms` or a bare number of seconds
+fn parse_duration(value: &str) -> Result<Duration, String> {
+    let invalid = || format!("Invalid duration: {value}");
+    let (number, unit) = match value.find(|c: char| !c.is_ascii_digit()) {
+        Some(idx) => value.split_at(idx),
+        None => (value, "s"),
+    };
+    let number: u64 = number.parse().map_err(|_| invalid())?;
+
+    match unit {
+        "ms" => Ok(Duration::from_millis(number)),
+        "s" => Ok(Duration::from_secs(number)),
+        "m" => Ok(Duration::from_secs(number * 60)),
+        _ => Err(invalid()),
+    }
+}
+
+/// Builds the n-th synthetic request: a mix of simple transfers, calldata and blob transactions
+fn synthetic_payload(n: usize) -> Value {
+    let from = "0x0000000000000000000000000000000000000001";
+    match n % 3 {
+        0 => json!({
+            "from": from,
+            "to": "0x0000000000000000000000000000000000000002",
+            "value": "0x1",
+        }),
+        1 => json!({
+            "from": from,
+            "to": "0x6b175474e89094c44da98b954eedeac495271d0f",
+            "data": "0x70a082310000000000000000000000007b84eF0B14eEeDF32197bDD2B2B8CaCD17d9627c",
+        }),
+        _ => json!({
+            "from": from,
+            "to": "0x0000000000000000000000000000000000000002",
+            "type": "0x3",
+        }),
+    }
+}
+
+/// Returns the value at the given percentile (0-100) of sorted samples
+fn percentile(sorted: &[f64], pct: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let rank = (pct / 100.0 * (sorted.len() - 1) as f64).round() as usize;
+    sorted[rank.min(sorted.len() - 1)]
+}
+
+async fn send_request(client: &Client, endpoint: &str, payload: &Value) -> Outcome {
+    let response = match client.post(endpoint).json(payload).send().await {
+        Ok(response) => response,
+        Err(_) => return Outcome::Error,
+    };
+
+    let status = response.status();
+    let body: Value = match response.json().await {
+        Ok(body) => body,
+        Err(_) => return Outcome::Error,
+    };
+
+    if !status.is_success() {
+        let is_rpc_error = body["error"]
+            .as_str()
+            .is_some_and(|e| e.starts_with("RPC call failed"));
+        return if is_rpc_error {
+            Outcome::RpcError
+        } else {
+            Outcome::Error
+        };
+    }
+
+    match serde_json::from_value::<GasEstimateResponse>(body) {
+        Ok(estimate) if !estimate.gas_limit.is_zero() => Outcome::Ok {
+            method: estimate.method,
+        },
+        _ => Outcome::Error,
+    }
+}
+
+async fn run(config: &BenchConfig) -> Result<BenchSummary, String> {
+    let client = Client::builder()
+        .timeout(Duration::from_secs(REQUEST_TIMEOUT_SECS))
+        .build()
+        .map_err(|e| format!("Failed to create HTTP client: {e}"))?;
+    let endpoint = format!("{}/api/estimate-gas", config.url.trim_end_matches('/'));
+
+    let samples = Arc::new(Mutex::new(Samples::default()));
+    let in_flight = Arc::new(AtomicUsize::new(0));
+    let max_concurrency = Arc::new(AtomicUsize::new(0));
+
+    let mut ticker = tokio::time::interval(Duration::from_secs_f64(1.0 / config.rps as f64));
+    let started = Instant::now();
+    let mut handles = Vec::new();
+    let mut n = 0;
+
+    while started.elapsed() < config.duration {
+        ticker.tick().await;
+
+        let client = client.clone();
+        let endpoint = endpoint.clone();
+        let samples = samples.clone();
+        let in_flight = in_flight.clone();
+        let max_concurrency = max_concurrency.clone();
+        let payload = synthetic_payload(n);
+        n += 1;
+
+        handles.push(tokio::spawn(async move {
+            let current = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            max_concurrency.fetch_max(current, Ordering::SeqCst);
+
+            let request_started = Instant::now();
+            let outcome = send_request(&client, &endpoint, &payload).await;
+            let latency_ms = request_started.elapsed().as_secs_f64() * 1000.0;
+            in_flight.fetch_sub(1, Ordering::SeqCst);
+
+            let mut samples = samples.lock().await;
+            match outcome {
+                Outcome::Ok { method } => {
+                    samples.latencies_ms.push(latency_ms);
+                    if method.starts_with("rpc") {
+                        samples.rpc_responses += 1;
+                    }
+                    if method.contains("cached") {
+                        samples.cache_hits += 1;
+                    }
+                }
+                Outcome::RpcError => {
+                    samples.errors += 1;
+                    samples.rpc_errors += 1;
+                }
+                Outcome::Error => samples.errors += 1,
+            }
+        }));
+    }
+
+    for handle in handles {
+        let _ = handle.await;
+    }
+
+    let elapsed = started.elapsed().as_secs_f64();
+    let mut samples = samples.lock().await;
+    samples.latencies_ms.sort_by(f64::total_cmp);
+    let successful = samples.latencies_ms.len();
+    let total = successful + samples.errors;
+
+    Ok(BenchSummary {
+        url: config.url.clone(),
+        target_rps: config.rps,
+        duration_secs: elapsed,
+        total_requests: total,
+        successful_requests: successful,
+        achieved_rps: total as f64 / elapsed,
+        latency_p50_ms: percentile(&samples.latencies_ms, 50.0),
+        latency_p95_ms: percentile(&samples.latencies_ms, 95.0),
+        latency_p99_ms: percentile(&samples.latencies_ms, 99.0),
+        errors: samples.errors,
+        errors_per_sec: samples.errors as f64 / elapsed,
+        rpc_errors: samples.rpc_errors,
+        cache_hit_ratio: if samples.rpc_responses == 0 {
+            0.0
+        } else {
+            samples.cache_hits as f64 / samples.rpc_responses as f64
+        },
+        max_concurrency: max_concurrency.load(Ordering::SeqCst),
+    })
+}
+
+#[tokio::main]
+async fn main() -> Result<(), String> {
+    let config = parse_args(std::env::args().skip(1))?;
+    println!(
+        "Benchmarking {} at {} rps for {:?}",
+        config.url, config.rps, config.duration
+    );
+
+    let summary = run(&config).await?;
+    let json = serde_json::to_string_pretty(&summary)
+        .map_err(|e| format!("Failed to serialize results: {e}"))?;
+    std::fs::write(RESULTS_FILE, &json)
+        .map_err(|e| format!("Failed to write {RESULTS_FILE}: {e}"))?;
+
+    println!("{json}");
+    println!("Results written to {RESULTS_FILE}");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("30s").unwrap(), Duration::from_secs(30));
+        assert_eq!(parse_duration("2m").unwrap(), Duration::from_secs(120));
+        assert_eq!(parse_duration("500ms").unwrap(), Duration::from_millis(500));
+        assert_eq!(parse_duration("15").unwrap(), Duration::from_secs(15));
+        assert!(parse_duration("abc").is_err());
+        assert!(parse_duration("10h").is_err());
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let args = [
+            "--url",
+            "http://example.com",
+            "--rps",
+            "5",
+            "--duration",
+            "1m",
+        ];
+        let config = parse_args(args.iter().map(|s| s.to_string())).unwrap();
+        assert_eq!(config.url, "http://example.com");
+        assert_eq!(config.rps, 5);
+        assert_eq!(config.duration, Duration::from_secs(60));
+
+        let args = ["--rps", "0"];
+        assert!(parse_args(args.iter().map(|s| s.to_string())).is_err());
+    }
+
+    #[test]
+    fn test_percentile() {
+        let samples: Vec<f64> = (1..=100).map(f64::from).collect();
+        assert_eq!(percentile(&samples, 50.0), 51.0);
+        assert_eq!(percentile(&samples, 99.0), 99.0);
+        assert_eq!(percentile(&[], 50.0), 0.0);
+    }
+}
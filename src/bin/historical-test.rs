@@ -0,0 +1,303 @@
+//! Compares estimates against the gas actually used by mined transactions.
+//!
+//! Scans the most recent blocks on an Alchemy mainnet endpoint for transactions to
+//! `--contract` whose calldata starts with `--method`, estimates each one against the
+//! state of its parent block and compares the result to `gas_used` from its receipt.
+//! Alchemy's `alchemy_minedTransactions` is a websocket subscription, so recent blocks
+//! are read with the standard block and receipt methods over HTTP instead.
+//!
+//! Usage:
+//!   cargo run --bin historical-test -- --alchemy-key <key> --contract 0x... --method 0xa9059cbb --blocks 100
+
+use gas_estimator::{GasEstimator, estimator::EstimateGasRequest};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use web3::{
+    Transport, Web3,
+    transports::Http,
+    types::{Address, BlockId, BlockNumber, CallRequest, Transaction, U64},
+};
+
+const ALCHEMY_URL: &str = "https://eth-mainnet.g.alchemy.com/v2";
+const DEFAULT_BLOCKS: u64 = 100;
+
+#[derive(Debug)]
+struct Config {
+    alchemy_key: String,
+    contract: Address,
+    selector: [u8; 4],
+    blocks: u64,
+}
+
+/// Estimate for one mined transaction
+struct Sample {
+    method: String,
+    estimated: u64,
+    gas_used: u64,
+}
+
+#[derive(Debug, Default, PartialEq, Serialize)]
+struct MethodStats {
+    transactions: usize,
+    mean_abs_error_pct: f64,
+    max_abs_error_pct: f64,
+    /// Estimates below the gas actually used, which would have run out of gas
+    underestimates: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct Report {
+    blocks_scanned: u64,
+    matching_transactions: usize,
+    failed_estimates: usize,
+    by_method: BTreeMap<String, MethodStats>,
+}
+
+fn usage() -> String {
+    "Usage: historical-test --alchemy-key <key> --contract <address> --method <0x selector> [--blocks <n>]"
+        .to_string()
+}
+
+/// Parses command line arguments
+fn parse_args(args: impl Iterator<Item = String>) -> Result<Config, String> {
+    let mut alchemy_key = None;
+    let mut contract = None;
+    let mut selector = None;
+    let mut blocks = DEFAULT_BLOCKS;
+
+    let mut args = args;
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format!("Missing value for {arg}"))
+        };
+        match arg.as_str() {
+            "--alchemy-key" => alchemy_key = Some(value()?),
+            "--contract" => {
+                contract = Some(
+                    value()?
+                        .parse()
+                        .map_err(|e| format!("Invalid --contract: {e}"))?,
+                )
+            }
+            "--method" => selector = Some(parse_selector(&value()?)?),
+            "--blocks" => {
+                blocks = value()?
+                    .parse()
+                    .map_err(|e| format!("Invalid --blocks: {e}"))?;
+                if blocks == 0 {
+                    return Err("--blocks must be greater than zero".to_string());
+                }
+            }
+            "-h" | "--help" => return Err(usage()),
+            other => return Err(format!("Unknown argument: {other}\n{}", usage())),
+        }
+    }
+
+    let missing = |name: &str| format!("Missing {name}\n{}", usage());
+    Ok(Config {
+        alchemy_key: alchemy_key.ok_or_else(|| missing("--alchemy-key"))?,
+        contract: contract.ok_or_else(|| missing("--contract"))?,
+        selector: selector.ok_or_else(|| missing("--method"))?,
+        blocks,
+    })
+}
+
+/// Parses a 4-byte function selector like `0xa9059cbb`
+fn parse_selector(value: &str) -> Result<[u8; 4], String> {
+    let invalid = || format!("Invalid --method selector: {value}");
+    let hex = value.strip_prefix("0x").unwrap_or(value);
+    if hex.len() != 8 {
+        return Err(invalid());
+    }
+    let mut selector = [0u8; 4];
+    for (i, byte) in selector.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).map_err(|_| invalid())?;
+    }
+    Ok(selector)
+}
+
+/// Whether a mined transaction calls the configured contract method
+fn matches(tx: &Transaction, config: &Config) -> bool {
+    tx.to == Some(config.contract) && tx.input.0.starts_with(&config.selector)
+}
+
+/// Rebuilds the estimation request for a mined transaction
+fn estimate_request(tx: &Transaction, block: U64) -> EstimateGasRequest {
+    EstimateGasRequest {
+        tx: CallRequest {
+            from: tx.from,
+            to: tx.to,
+            value: Some(tx.value),
+            data: Some(tx.input.clone()),
+            transaction_type: tx.transaction_type,
+            access_list: tx.access_list.clone(),
+            ..Default::default()
+        },
+        // Estimate against the state before the transaction's block
+        block_number: Some(block.saturating_sub(U64::one()).as_u64().into()),
+        ..Default::default()
+    }
+}
+
+/// Aggregates accuracy statistics per estimation method
+fn summarize(samples: &[Sample]) -> BTreeMap<String, MethodStats> {
+    let mut by_method: BTreeMap<String, MethodStats> = BTreeMap::new();
+    for sample in samples {
+        let error_pct = (sample.estimated as f64 - sample.gas_used as f64).abs()
+            / sample.gas_used.max(1) as f64
+            * 100.0;
+        let stats = by_method.entry(sample.method.clone()).or_default();
+        stats.transactions += 1;
+        // Holds the sum until it is averaged below
+        stats.mean_abs_error_pct += error_pct;
+        stats.max_abs_error_pct = stats.max_abs_error_pct.max(error_pct);
+        if sample.estimated < sample.gas_used {
+            stats.underestimates += 1;
+        }
+    }
+    for stats in by_method.values_mut() {
+        stats.mean_abs_error_pct /= stats.transactions as f64;
+    }
+    by_method
+}
+
+async fn run<T: Transport>(config: &Config, web3: Web3<T>) -> Result<Report, String> {
+    let rpc_error = |e| format!("RPC call failed: {e}");
+    let estimator = GasEstimator::new(web3.transport().clone());
+    let latest = web3.eth().block_number().await.map_err(rpc_error)?;
+
+    let mut samples = Vec::new();
+    let mut matching = 0;
+    let mut failed = 0;
+
+    for offset in 0..config.blocks {
+        let number = latest.saturating_sub(offset.into());
+        let Some(block) = web3
+            .eth()
+            .block_with_txs(BlockId::Number(BlockNumber::Number(number)))
+            .await
+            .map_err(rpc_error)?
+        else {
+            continue;
+        };
+
+        for tx in block.transactions.iter().filter(|tx| matches(tx, config)) {
+            matching += 1;
+            let receipt = web3
+                .eth()
+                .transaction_receipt(tx.hash)
+                .await
+                .map_err(rpc_error)?;
+            let Some(gas_used) = receipt.and_then(|receipt| receipt.gas_used) else {
+                failed += 1;
+                continue;
+            };
+
+            match estimator.estimate(estimate_request(tx, number)).await {
+                Ok(estimate) => samples.push(Sample {
+                    method: estimate.method,
+                    estimated: estimate.gas_limit.low_u64(),
+                    gas_used: gas_used.low_u64(),
+                }),
+                Err(e) => {
+                    eprintln!("Estimate failed for {:?}: {e}", tx.hash);
+                    failed += 1;
+                }
+            }
+        }
+    }
+
+    Ok(Report {
+        blocks_scanned: config.blocks,
+        matching_transactions: matching,
+        failed_estimates: failed,
+        by_method: summarize(&samples),
+    })
+}
+
+#[tokio::main]
+async fn main() -> Result<(), String> {
+    let config = parse_args(std::env::args().skip(1))?;
+    let transport = Http::new(&format!("{ALCHEMY_URL}/{}", config.alchemy_key))
+        .map_err(|e| format!("Failed to create transport: {e}"))?;
+
+    println!(
+        "Scanning the last {} blocks for calls to {:?}",
+        config.blocks, config.contract
+    );
+    let report = run(&config, Web3::new(transport)).await?;
+    let json = serde_json::to_string_pretty(&report)
+        .map_err(|e| format!("Failed to serialize report: {e}"))?;
+    println!("{json}");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> impl Iterator<Item = String> {
+        args.iter()
+            .map(|s| s.to_string())
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let config = parse_args(args(&[
+            "--alchemy-key",
+            "key",
+            "--contract",
+            "0x6b175474e89094c44da98b954eedeac495271d0f",
+            "--method",
+            "0xa9059cbb",
+            "--blocks",
+            "10",
+        ]))
+        .unwrap();
+        assert_eq!(config.alchemy_key, "key");
+        assert_eq!(config.selector, [0xa9, 0x05, 0x9c, 0xbb]);
+        assert_eq!(config.blocks, 10);
+
+        assert!(parse_args(args(&["--alchemy-key", "key"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_selector() {
+        assert_eq!(
+            parse_selector("a9059cbb").unwrap(),
+            [0xa9, 0x05, 0x9c, 0xbb]
+        );
+        assert!(parse_selector("0xa9059c").is_err());
+        assert!(parse_selector("0xzz059cbb").is_err());
+    }
+
+    #[test]
+    fn test_summarize() {
+        let samples = [
+            Sample {
+                method: "rpc".to_string(),
+                estimated: 110,
+                gas_used: 100,
+            },
+            Sample {
+                method: "rpc".to_string(),
+                estimated: 95,
+                gas_used: 100,
+            },
+        ];
+        let stats = summarize(&samples);
+        assert_eq!(
+            stats["rpc"],
+            MethodStats {
+                transactions: 2,
+                mean_abs_error_pct: 7.5,
+                max_abs_error_pct: 10.0,
+                underestimates: 1,
+            }
+        );
+    }
+}
@@ -0,0 +1,254 @@
+//! Fallback for nodes whose `eth_estimateGas` fails on transactions that execute
+//! fine with an explicit gas limit: the limit is searched for with `eth_call`
+
+use crate::estimator::{ApiError, EstimateGasRequest, GasEstimator};
+use std::time::Duration;
+use tokio::time::Instant;
+use web3::Transport;
+
+/// Width of the bracket at which the search stops
+pub const BINARY_SEARCH_TOLERANCE: u64 = 5000;
+/// Most `eth_call` probes one search sends
+pub const BINARY_SEARCH_MAX_PROBES: u32 = 12;
+/// Longest time one search may take, unless the request timeout is shorter
+pub const DEFAULT_BINARY_SEARCH_BUDGET: Duration = Duration::from_secs(5);
+
+/// Whether a failed node estimate looks like a bug of `eth_estimateGas`, such as
+/// `gas required exceeds allowance` for a transaction that fits, rather than a
+/// revert, a transaction the node refuses or an outage
+pub(crate) fn is_estimation_bug(error: &ApiError) -> bool {
+    matches!(error, ApiError::InternalServerError(_))
+}
+
+impl<T: Transport> GasEstimator<T> {
+    /// Lowest gas limit, within [`BINARY_SEARCH_TOLERANCE`], that the transaction
+    /// executes at without failing, between its `intrinsic` cost and `cap`.
+    ///
+    /// Most transactions need a small multiple of their intrinsic cost, so the
+    /// limit is doubled from there until a call succeeds, and the bracket is then
+    /// halved. The search gives up after [`BINARY_SEARCH_MAX_PROBES`] calls,
+    /// once `budget` has elapsed or when the node fails us, returning the lowest
+    /// limit a call succeeded at so far, if any.
+    pub(crate) async fn binary_search_gas(
+        &self,
+        request: &EstimateGasRequest,
+        intrinsic: u64,
+        cap: u64,
+        budget: Duration,
+    ) -> Option<u64> {
+        let deadline = Instant::now() + budget;
+        let mut low = intrinsic;
+        let mut succeeding: Option<u64> = None;
+        let mut probes = 0;
+        while probes < BINARY_SEARCH_MAX_PROBES {
+            let candidate = match succeeding {
+                Some(high) if high - low <= BINARY_SEARCH_TOLERANCE => break,
+                Some(high) => low + (high - low) / 2,
+                None if low >= cap => break,
+                None => low.saturating_mul(2).min(cap),
+            };
+            probes += 1;
+            let succeeded =
+                match tokio::time::timeout_at(deadline, self.simulate(request, candidate.into()))
+                    .await
+                {
+                    Ok(Ok(simulation)) => simulation.success,
+                    // Nodes fail calls that run out of gas with a plain error
+                    Ok(Err(ApiError::InternalServerError(_))) => false,
+                    Ok(Err(error)) => {
+                        tracing::warn!(%error, probes, "Binary search probe failed");
+                        break;
+                    }
+                    Err(_) => {
+                        tracing::warn!(probes, "Binary search ran out of time");
+                        break;
+                    }
+                };
+            if succeeded {
+                succeeding = Some(candidate);
+            } else {
+                low = candidate;
+            }
+        }
+        tracing::info!(gas = ?succeeding, probes, "Binary searched the gas limit");
+        succeeding
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::future::{BoxFuture, FutureExt, ready};
+    use jsonrpc_core::{Call, ErrorCode};
+    use serde_json::{Value, json};
+    use std::sync::{Arc, Mutex};
+    use web3::RequestId;
+    use web3::helpers;
+    use web3::types::{Address, Bytes, CallRequest};
+
+    /// Transport whose `eth_estimateGas` fails, and whose `eth_call` succeeds
+    /// above 80,000 gas and runs out of gas below
+    #[derive(Debug, Clone, Default)]
+    struct ScriptedTransport {
+        calls: Arc<Mutex<Vec<u64>>>,
+    }
+
+    impl ScriptedTransport {
+        fn calls(&self) -> Vec<u64> {
+            self.calls.lock().unwrap().clone()
+        }
+    }
+
+    fn rpc_error(message: &str) -> web3::Error {
+        web3::Error::Rpc(jsonrpc_core::Error {
+            code: ErrorCode::ServerError(-32000),
+            message: message.to_string(),
+            data: None,
+        })
+    }
+
+    impl Transport for ScriptedTransport {
+        type Out = BoxFuture<'static, web3::Result<Value>>;
+
+        fn prepare(&self, method: &str, params: Vec<Value>) -> (RequestId, Call) {
+            (1, helpers::build_request(1, method, params))
+        }
+
+        fn send(&self, _id: RequestId, request: Call) -> Self::Out {
+            let Call::MethodCall(call) = request else {
+                unreachable!("only method calls are sent")
+            };
+            let response = match call.method.as_str() {
+                "eth_estimateGas" => Err(rpc_error("gas required exceeds allowance (30000000)")),
+                "eth_call" => {
+                    let params: Vec<Value> = call.params.parse().unwrap();
+                    let gas = params[0]["gas"].as_str().unwrap();
+                    let gas = u64::from_str_radix(gas.trim_start_matches("0x"), 16).unwrap();
+                    self.calls.lock().unwrap().push(gas);
+                    if gas > 80_000 {
+                        Ok(json!("0x"))
+                    } else {
+                        Err(rpc_error("out of gas"))
+                    }
+                }
+                method => unreachable!("unexpected {method}"),
+            };
+            ready(response).boxed()
+        }
+    }
+
+    fn contract_call() -> EstimateGasRequest {
+        EstimateGasRequest {
+            tx: CallRequest {
+                to: Some(Address::from_low_u64_be(0xb0b)),
+                // 21,000 + 4 non-zero bytes at 16 gas
+                data: Some(Bytes::from(vec![0xa9, 0x05, 0x9c, 0xbb])),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_binary_search_converges() {
+        let transport = ScriptedTransport::default();
+        let estimator = GasEstimator::new(transport.clone());
+
+        let gas = estimator
+            .binary_search_gas(
+                &contract_call(),
+                21_064,
+                30_000_000,
+                DEFAULT_BINARY_SEARCH_BUDGET,
+            )
+            .await
+            .unwrap();
+        assert!(
+            (80_001..=80_000 + BINARY_SEARCH_TOLERANCE).contains(&gas),
+            "{gas}"
+        );
+        // Doubling to 84,256 brackets the limit, and four halvings narrow it
+        assert_eq!(
+            transport.calls(),
+            [42_128, 84_256, 63_192, 73_724, 78_990, 81_623]
+        );
+        assert_eq!(gas, 81_623);
+    }
+
+    #[tokio::test]
+    async fn test_binary_search_gives_up_below_the_cap() {
+        let transport = ScriptedTransport::default();
+        let estimator = GasEstimator::new(transport.clone());
+
+        let gas = estimator
+            .binary_search_gas(
+                &contract_call(),
+                21_064,
+                60_000,
+                DEFAULT_BINARY_SEARCH_BUDGET,
+            )
+            .await;
+        assert_eq!(gas, None);
+        assert_eq!(transport.calls(), [42_128, 60_000]);
+    }
+
+    #[tokio::test]
+    async fn test_binary_search_probe_cap() {
+        let transport = ScriptedTransport::default();
+        let estimator = GasEstimator::new(transport.clone());
+
+        // From an intrinsic cost of 1, doubling alone takes 17 probes
+        let gas = estimator
+            .binary_search_gas(
+                &contract_call(),
+                1,
+                30_000_000,
+                DEFAULT_BINARY_SEARCH_BUDGET,
+            )
+            .await;
+        assert_eq!(gas, None);
+        assert_eq!(transport.calls().len(), BINARY_SEARCH_MAX_PROBES as usize);
+    }
+
+    #[tokio::test]
+    async fn test_estimate_falls_back_to_binary_search() {
+        let transport = ScriptedTransport::default();
+        let estimator = GasEstimator::new(transport.clone())
+            .with_binary_search_fallback(DEFAULT_BINARY_SEARCH_BUDGET);
+
+        let result = estimator.estimate(contract_call()).await.unwrap();
+        assert_eq!(result.method, "binary_search");
+        assert_eq!(result.gas_limit, 81_623.into());
+        assert_eq!(transport.calls().len(), 6);
+    }
+
+    #[tokio::test]
+    async fn test_estimate_without_fallback_fails() {
+        let transport = ScriptedTransport::default();
+        let estimator = GasEstimator::new(transport.clone());
+
+        let error = estimator.estimate(contract_call()).await.unwrap_err();
+        assert!(
+            matches!(error, ApiError::InternalServerError(_)),
+            "{error:?}"
+        );
+        assert!(transport.calls().is_empty());
+    }
+
+    #[test]
+    fn test_reverts_are_not_estimation_bugs() {
+        assert!(is_estimation_bug(&ApiError::InternalServerError(
+            "RPC call failed: gas required exceeds allowance".to_string()
+        )));
+        for error in [
+            ApiError::ExecutionReverted {
+                reason: None,
+                data: None,
+            },
+            ApiError::InvalidTransaction("Invalid transaction: insufficient funds".to_string()),
+            ApiError::UpstreamUnavailable("RPC call failed".to_string()),
+        ] {
+            assert!(!is_estimation_bug(&error), "{error:?}");
+        }
+    }
+}
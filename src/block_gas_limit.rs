@@ -0,0 +1,117 @@
+//! Gas limit of the latest block, the most any transaction can use
+
+use crate::estimator::GasEstimator;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use web3::{Transport, api::Namespace, helpers::CallFuture, types::U256};
+
+/// Limit assumed when the node's can't be fetched, so estimates keep working
+pub const DEFAULT_BLOCK_GAS_LIMIT: u64 = 30_000_000;
+/// The limit can only change once per block
+pub const DEFAULT_BLOCK_GAS_LIMIT_TTL_SECS: u64 = 12;
+
+/// Latest block gas limit fetched from the node
+pub struct BlockGasLimitCache {
+    entry: Mutex<Option<(Instant, u64)>>,
+    ttl: Duration,
+}
+
+impl BlockGasLimitCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            entry: Mutex::new(None),
+            ttl,
+        }
+    }
+
+    /// The limit, if fetched within the TTL
+    fn get(&self) -> Option<u64> {
+        match *self.entry.lock().unwrap() {
+            Some((inserted, limit)) if inserted.elapsed() < self.ttl => Some(limit),
+            _ => None,
+        }
+    }
+
+    fn insert(&self, limit: u64) {
+        *self.entry.lock().unwrap() = Some((Instant::now(), limit));
+    }
+}
+
+impl<T: Transport> GasEstimator<T> {
+    /// Gas limit of the latest block, or [`DEFAULT_BLOCK_GAS_LIMIT`] when the
+    /// estimator doesn't track it or the node can't be asked
+    pub async fn block_gas_limit(&self) -> u64 {
+        let Some(cache) = &self.block_gas_limit_cache else {
+            return DEFAULT_BLOCK_GAS_LIMIT;
+        };
+        if let Some(limit) = cache.get() {
+            return limit;
+        }
+        match self.fetch_block_gas_limit().await {
+            Ok(limit) => {
+                cache.insert(limit);
+                limit
+            }
+            Err(error) => {
+                tracing::warn!(%error, "Failed to fetch the block gas limit, assuming the default");
+                DEFAULT_BLOCK_GAS_LIMIT
+            }
+        }
+    }
+
+    async fn fetch_block_gas_limit(&self) -> Result<u64, String> {
+        let block: serde_json::Value = CallFuture::new(self.eth.transport().execute(
+            "eth_getBlockByNumber",
+            vec![serde_json::json!("latest"), serde_json::json!(false)],
+        ))
+        .await
+        .map_err(|e| e.to_string())?;
+        let limit: U256 = block
+            .get("gasLimit")
+            .and_then(|value| serde_json::from_value(value.clone()).ok())
+            .ok_or("Node did not return gasLimit")?;
+        limit
+            .try_into()
+            .map_err(|_| "Node returned a gasLimit above u64".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use web3::transports::test::TestTransport;
+
+    #[tokio::test]
+    async fn test_block_gas_limit_is_cached() {
+        let mut transport = TestTransport::default();
+        transport.add_response(serde_json::json!({ "gasLimit": "0x2255100" }));
+        let estimator =
+            GasEstimator::new(transport.clone()).with_block_gas_limit_ttl(Duration::from_secs(60));
+
+        assert_eq!(estimator.block_gas_limit().await, 36_000_000);
+        assert_eq!(estimator.block_gas_limit().await, 36_000_000);
+        transport.assert_request(
+            "eth_getBlockByNumber",
+            &[r#""latest""#.into(), "false".into()],
+        );
+        transport.assert_no_more_requests();
+    }
+
+    #[tokio::test]
+    async fn test_block_gas_limit_falls_back() {
+        // No response queued, so the lookup fails and isn't cached
+        let transport = TestTransport::default();
+        let estimator =
+            GasEstimator::new(transport).with_block_gas_limit_ttl(Duration::from_secs(60));
+        assert_eq!(estimator.block_gas_limit().await, DEFAULT_BLOCK_GAS_LIMIT);
+
+        let mut transport = TestTransport::default();
+        transport.add_response(serde_json::json!({ "number": "0x1" }));
+        let estimator =
+            GasEstimator::new(transport).with_block_gas_limit_ttl(Duration::from_secs(60));
+        assert_eq!(estimator.block_gas_limit().await, DEFAULT_BLOCK_GAS_LIMIT);
+
+        let estimator = GasEstimator::new(TestTransport::default());
+        assert_eq!(estimator.block_gas_limit().await, DEFAULT_BLOCK_GAS_LIMIT);
+    }
+}
@@ -0,0 +1,375 @@
+//! Gas estimation for Blur marketplace purchases through `BlurExchange`
+
+use crate::abi::encode_call;
+use crate::estimator::{ApiError, GasEstimator};
+use crate::revert;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use web3::{
+    Transport,
+    ethabi::{ParamType, Token},
+    types::{Address, Bytes, CallRequest, H256, U256},
+};
+
+/// Fee rates are expressed in basis points
+const FEE_RATE_DENOMINATOR: u64 = 10000;
+
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct BlurFee {
+    /// Fee rate in basis points
+    pub rate: u16,
+    pub recipient: Address,
+}
+
+/// A signed order as passed to `BlurExchange` (the contract's `Input` struct).
+/// Signature fields default to zero, which is enough for estimating against
+/// oracle-authorized or pre-approved orders.
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct BlurOrder {
+    pub trader: Address,
+    /// 0 for buy, 1 for sell
+    pub side: u8,
+    pub matching_policy: Address,
+    pub collection: Address,
+    pub token_id: U256,
+    pub amount: U256,
+    /// Zero address for ETH
+    pub payment_token: Address,
+    pub price: U256,
+    pub listing_time: U256,
+    pub expiration_time: U256,
+    #[serde(default)]
+    pub fees: Vec<BlurFee>,
+    pub salt: U256,
+    #[serde(default)]
+    pub extra_params: Bytes,
+    #[serde(default)]
+    pub v: u8,
+    #[serde(default)]
+    pub r: H256,
+    #[serde(default)]
+    pub s: H256,
+    #[serde(default)]
+    pub extra_signature: Bytes,
+    #[serde(default)]
+    pub signature_version: u8,
+    #[serde(default)]
+    pub block_number: U256,
+}
+
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct BlurExecution {
+    pub sell_order: BlurOrder,
+    pub buy_order: BlurOrder,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct BlurPurchaseParams {
+    pub exchange: Address,
+    pub sell_order: BlurOrder,
+    pub buy_order: BlurOrder,
+    pub from: Address,
+    /// Further items to purchase together with the first one via `bulkExecute`
+    #[serde(default)]
+    pub additional_executions: Vec<BlurExecution>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct BlurPurchaseEstimate {
+    pub single_purchase_gas: U256,
+    /// Gas for `bulkExecute` over all items, when more than one item is purchased
+    pub bulk_purchase_gas: Option<U256>,
+    pub gas_per_item_bulk: Option<U256>,
+    /// Fees taken from the sale price by the sell order's fee recipients
+    pub blur_pool_fee: Option<U256>,
+}
+
+impl<T: Transport> GasEstimator<T> {
+    /// Estimates `BlurExchange.execute` for a single purchase and `bulkExecute` when
+    /// additional items are purchased
+    pub async fn estimate_blur_purchase(
+        &self,
+        params: BlurPurchaseParams,
+    ) -> Result<BlurPurchaseEstimate, ApiError> {
+        let single = BlurExecution {
+            sell_order: params.sell_order,
+            buy_order: params.buy_order,
+        };
+
+        let single_purchase_gas = self
+            .estimate_blur_call(
+                params.from,
+                params.exchange,
+                encode_execute(&single),
+                eth_value(std::slice::from_ref(&single)),
+            )
+            .await?;
+
+        let (bulk_purchase_gas, gas_per_item_bulk) = if params.additional_executions.is_empty() {
+            (None, None)
+        } else {
+            let mut executions = vec![single.clone()];
+            executions.extend(params.additional_executions);
+            let gas = self
+                .estimate_blur_call(
+                    params.from,
+                    params.exchange,
+                    encode_bulk_execute(&executions),
+                    eth_value(&executions),
+                )
+                .await?;
+            (Some(gas), Some(gas / U256::from(executions.len())))
+        };
+
+        Ok(BlurPurchaseEstimate {
+            single_purchase_gas,
+            bulk_purchase_gas,
+            gas_per_item_bulk,
+            blur_pool_fee: total_fees(&single.sell_order),
+        })
+    }
+
+    /// Estimates a payable call to the exchange via RPC
+    async fn estimate_blur_call(
+        &self,
+        from: Address,
+        exchange: Address,
+        data: Vec<u8>,
+        value: U256,
+    ) -> Result<U256, ApiError> {
+        self.eth
+            .estimate_gas(
+                CallRequest {
+                    from: Some(from),
+                    to: Some(exchange),
+                    value: (!value.is_zero()).then_some(value),
+                    data: Some(Bytes(data)),
+                    ..Default::default()
+                },
+                None,
+            )
+            .await
+            .map_err(revert::estimate_error)
+    }
+}
+
+/// ETH sent with the call: the price of every item paid in ETH
+fn eth_value(executions: &[BlurExecution]) -> U256 {
+    executions
+        .iter()
+        .filter(|execution| execution.sell_order.payment_token.is_zero())
+        .fold(U256::zero(), |total, execution| {
+            total.saturating_add(execution.sell_order.price)
+        })
+}
+
+/// Sum of the order's fees, or `None` if it has none
+fn total_fees(order: &BlurOrder) -> Option<U256> {
+    if order.fees.is_empty() {
+        return None;
+    }
+    let rate: u64 = order.fees.iter().map(|fee| fee.rate as u64).sum();
+    Some(order.price.saturating_mul(rate.into()) / U256::from(FEE_RATE_DENOMINATOR))
+}
+
+/// `Order` tuple type
+fn order_type() -> ParamType {
+    ParamType::Tuple(vec![
+        ParamType::Address,   // trader
+        ParamType::Uint(8),   // side
+        ParamType::Address,   // matchingPolicy
+        ParamType::Address,   // collection
+        ParamType::Uint(256), // tokenId
+        ParamType::Uint(256), // amount
+        ParamType::Address,   // paymentToken
+        ParamType::Uint(256), // price
+        ParamType::Uint(256), // listingTime
+        ParamType::Uint(256), // expirationTime
+        ParamType::Array(Box::new(ParamType::Tuple(vec![
+            ParamType::Uint(16),
+            ParamType::Address,
+        ]))), // fees
+        ParamType::Uint(256), // salt
+        ParamType::Bytes,     // extraParams
+    ])
+}
+
+/// `Input` tuple type: an order with its signature
+fn input_type() -> ParamType {
+    ParamType::Tuple(vec![
+        order_type(),
+        ParamType::Uint(8),        // v
+        ParamType::FixedBytes(32), // r
+        ParamType::FixedBytes(32), // s
+        ParamType::Bytes,          // extraSignature
+        ParamType::Uint(8),        // signatureVersion
+        ParamType::Uint(256),      // blockNumber
+    ])
+}
+
+fn input_token(order: &BlurOrder) -> Token {
+    let fees = order
+        .fees
+        .iter()
+        .map(|fee| {
+            Token::Tuple(vec![
+                Token::Uint(fee.rate.into()),
+                Token::Address(fee.recipient),
+            ])
+        })
+        .collect();
+    let order_token = Token::Tuple(vec![
+        Token::Address(order.trader),
+        Token::Uint(order.side.into()),
+        Token::Address(order.matching_policy),
+        Token::Address(order.collection),
+        Token::Uint(order.token_id),
+        Token::Uint(order.amount),
+        Token::Address(order.payment_token),
+        Token::Uint(order.price),
+        Token::Uint(order.listing_time),
+        Token::Uint(order.expiration_time),
+        Token::Array(fees),
+        Token::Uint(order.salt),
+        Token::Bytes(order.extra_params.0.clone()),
+    ]);
+    Token::Tuple(vec![
+        order_token,
+        Token::Uint(order.v.into()),
+        Token::FixedBytes(order.r.as_bytes().to_vec()),
+        Token::FixedBytes(order.s.as_bytes().to_vec()),
+        Token::Bytes(order.extra_signature.0.clone()),
+        Token::Uint(order.signature_version.into()),
+        Token::Uint(order.block_number),
+    ])
+}
+
+/// ABI-encodes `execute(Input sell, Input buy)`
+fn encode_execute(execution: &BlurExecution) -> Vec<u8> {
+    encode_call(
+        "execute",
+        &[input_type(), input_type()],
+        &[
+            input_token(&execution.sell_order),
+            input_token(&execution.buy_order),
+        ],
+    )
+}
+
+/// ABI-encodes `bulkExecute(Execution[] executions)`
+fn encode_bulk_execute(executions: &[BlurExecution]) -> Vec<u8> {
+    let execution_type = ParamType::Tuple(vec![input_type(), input_type()]);
+    let tokens = executions
+        .iter()
+        .map(|execution| {
+            Token::Tuple(vec![
+                input_token(&execution.sell_order),
+                input_token(&execution.buy_order),
+            ])
+        })
+        .collect();
+    encode_call(
+        "bulkExecute",
+        &[ParamType::Array(Box::new(execution_type))],
+        &[Token::Array(tokens)],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use web3::transports::test::TestTransport;
+
+    fn order(side: u8, fees: Vec<BlurFee>) -> BlurOrder {
+        BlurOrder {
+            trader: Address::repeat_byte(side + 1),
+            side,
+            matching_policy: Address::repeat_byte(0x0b),
+            collection: Address::repeat_byte(0x0c),
+            token_id: 1.into(),
+            amount: 1.into(),
+            payment_token: Address::zero(),
+            price: U256::exp10(18),
+            listing_time: 0.into(),
+            expiration_time: U256::MAX,
+            fees,
+            salt: 0.into(),
+            extra_params: Bytes::default(),
+            v: 27,
+            r: H256::zero(),
+            s: H256::zero(),
+            extra_signature: Bytes::default(),
+            signature_version: 0,
+            block_number: 0.into(),
+        }
+    }
+
+    fn execution() -> BlurExecution {
+        BlurExecution {
+            sell_order: order(1, Vec::new()),
+            buy_order: order(0, Vec::new()),
+        }
+    }
+
+    fn params(additional_executions: Vec<BlurExecution>) -> BlurPurchaseParams {
+        let fee = BlurFee {
+            rate: 50,
+            recipient: Address::repeat_byte(0xfe),
+        };
+        BlurPurchaseParams {
+            exchange: Address::repeat_byte(0xb1),
+            sell_order: order(1, vec![fee]),
+            buy_order: order(0, Vec::new()),
+            from: Address::repeat_byte(0x01),
+            additional_executions,
+        }
+    }
+
+    #[test]
+    fn test_selectors() {
+        assert_eq!(encode_execute(&execution())[..4], [0x9a, 0x1f, 0xc3, 0xa7]);
+        assert_eq!(
+            encode_bulk_execute(&[execution()])[..4],
+            [0xb3, 0xbe, 0x57, 0xf8]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_single_purchase() {
+        let mut transport = TestTransport::default();
+        transport.add_response("0x30d40".into()); // 200000
+        let estimator = GasEstimator::new(transport);
+
+        let estimate = estimator
+            .estimate_blur_purchase(params(Vec::new()))
+            .await
+            .unwrap();
+        assert_eq!(estimate.single_purchase_gas, 200000.into());
+        assert_eq!(estimate.bulk_purchase_gas, None);
+        assert_eq!(estimate.gas_per_item_bulk, None);
+        // 0.5% of 1 ETH
+        assert_eq!(estimate.blur_pool_fee, Some(U256::exp10(15) * 5));
+    }
+
+    #[tokio::test]
+    async fn test_bulk_purchase() {
+        let mut transport = TestTransport::default();
+        transport.add_response("0x30d40".into()); // 200000
+        transport.add_response("0x7a120".into()); // 500000
+        let estimator = GasEstimator::new(transport);
+
+        let estimate = estimator
+            .estimate_blur_purchase(params(vec![execution(), execution()]))
+            .await
+            .unwrap();
+        assert_eq!(estimate.bulk_purchase_gas, Some(500000.into()));
+        assert_eq!(estimate.gas_per_item_bulk, Some(166666.into()));
+    }
+
+    #[test]
+    fn test_eth_value_skips_token_payments() {
+        let mut weth = execution();
+        weth.sell_order.payment_token = Address::repeat_byte(0xee);
+        assert_eq!(eth_value(&[execution(), weth]), U256::exp10(18));
+    }
+}
@@ -0,0 +1,502 @@
+//! Gas estimates of transaction bundles, such as an approval followed by a swap,
+//! where each transaction runs against the state the ones before it leave
+
+use crate::access_list::is_method_unsupported;
+use crate::estimator::{ApiError, GasEstimator};
+use crate::request::Quantity;
+use crate::revert;
+use crate::state_override::{self, StateOverride};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::BTreeMap;
+use utoipa::ToSchema;
+use web3::{
+    Transport,
+    helpers::{self, CallFuture},
+    types::{Address, BlockNumber, Bytes, CallRequest, H256, U64, U256},
+};
+
+pub const DEFAULT_MAX_BUNDLE_SIZE: usize = 10;
+
+/// Transactions to estimate in execution order
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct BundleRequest {
+    /// JSON-RPC transaction objects, as sent to `eth_estimateGas`
+    #[schema(value_type = Vec<Object>)]
+    pub transactions: Vec<CallRequest>,
+    /// Whether to keep simulating the transactions after one that fails
+    #[serde(default, alias = "continueOnFailure")]
+    pub continue_on_failure: bool,
+}
+
+/// Estimate of one transaction of a bundle
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct BundleTxEstimate {
+    /// Gas limit to send the transaction with, when it succeeded
+    pub gas_limit: Option<U256>,
+    pub success: bool,
+    /// Why the transaction failed, when the node says
+    pub revert_reason: Option<String>,
+    /// False for transactions after a failed one, unless `continue_on_failure`
+    pub simulated: bool,
+}
+
+impl BundleTxEstimate {
+    fn succeeded(gas_limit: U256) -> Self {
+        Self {
+            gas_limit: Some(gas_limit),
+            success: true,
+            revert_reason: None,
+            simulated: true,
+        }
+    }
+
+    fn failed(revert_reason: Option<String>) -> Self {
+        Self {
+            gas_limit: None,
+            success: false,
+            revert_reason,
+            simulated: true,
+        }
+    }
+
+    fn not_simulated() -> Self {
+        Self {
+            gas_limit: None,
+            success: false,
+            revert_reason: None,
+            simulated: false,
+        }
+    }
+}
+
+/// Block of an `eth_simulateV1` result
+#[derive(Debug, Deserialize)]
+struct SimulatedBlock {
+    calls: Vec<SimulatedCall>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SimulatedCall {
+    status: U64,
+    gas_used: U256,
+    error: Option<SimulatedError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SimulatedError {
+    message: String,
+    data: Option<Bytes>,
+}
+
+/// Account in a `prestateTracer` diff. Geth reports nonces as numbers.
+#[derive(Debug, Default, Deserialize)]
+struct TracedAccount {
+    balance: Option<U256>,
+    nonce: Option<Quantity>,
+    code: Option<Bytes>,
+    #[serde(default)]
+    storage: BTreeMap<H256, H256>,
+}
+
+#[derive(Debug, Deserialize)]
+struct StateDiff {
+    #[serde(default)]
+    pre: BTreeMap<Address, TracedAccount>,
+    #[serde(default)]
+    post: BTreeMap<Address, TracedAccount>,
+}
+
+impl<T: Transport> GasEstimator<T> {
+    /// Estimates each transaction of a bundle against the state left by the ones
+    /// before it, in one `eth_simulateV1` call when the node supports it, or else
+    /// one by one with the earlier transactions' state changes as overrides
+    pub async fn estimate_bundle(
+        &self,
+        request: BundleRequest,
+    ) -> Result<Vec<BundleTxEstimate>, ApiError> {
+        if request.transactions.is_empty() {
+            return Err(ApiError::BadRequest(
+                "transactions must contain at least one transaction".to_string(),
+            ));
+        }
+        let mut estimates = if self.supports_simulate_v1().await {
+            self.simulate_bundle(&request.transactions).await?
+        } else {
+            self.estimate_bundle_sequentially(&request.transactions, request.continue_on_failure)
+                .await?
+        };
+        if !request.continue_on_failure
+            && let Some(failed) = estimates.iter().position(|estimate| !estimate.success)
+        {
+            estimates[failed + 1..].fill(BundleTxEstimate::not_simulated());
+        }
+        Ok(estimates)
+    }
+
+    /// Asks the node whether it implements `eth_simulateV1`, remembering a definite
+    /// answer. Transport failures count as unsupported until the next probe.
+    pub async fn probe_simulate_v1(&self) -> bool {
+        let probe = CallFuture::<serde_json::Value, _>::new(self.transport().execute(
+            "eth_simulateV1",
+            vec![json!({ "blockStateCalls": [] }), json!("latest")],
+        ))
+        .await;
+        let supported = match probe {
+            Ok(_) => true,
+            Err(error) if is_method_unsupported(&error) => false,
+            Err(error) => {
+                tracing::warn!(%error, "Failed to probe eth_simulateV1 support");
+                return false;
+            }
+        };
+        *self.simulate_v1_support.lock().unwrap() = Some(supported);
+        supported
+    }
+
+    async fn supports_simulate_v1(&self) -> bool {
+        let known = *self.simulate_v1_support.lock().unwrap();
+        match known {
+            Some(supported) => supported,
+            None => self.probe_simulate_v1().await,
+        }
+    }
+
+    /// Simulates the bundle in one block with `eth_simulateV1`. Simulations report
+    /// the gas used, which is raised by the 1/64 of the gas calls keep back, since
+    /// the node doesn't search for the lowest limit that succeeds.
+    async fn simulate_bundle(
+        &self,
+        transactions: &[CallRequest],
+    ) -> Result<Vec<BundleTxEstimate>, ApiError> {
+        let blocks: Vec<SimulatedBlock> = CallFuture::new(self.transport().execute(
+            "eth_simulateV1",
+            vec![
+                json!({ "blockStateCalls": [{ "calls": transactions }], "validation": false }),
+                helpers::serialize(&BlockNumber::Latest),
+            ],
+        ))
+        .await
+        .map_err(revert::estimate_error)?;
+        let calls = blocks
+            .into_iter()
+            .next()
+            .map(|block| block.calls)
+            .unwrap_or_default();
+        if calls.len() != transactions.len() {
+            return Err(ApiError::InternalServerError(format!(
+                "Node simulated {} of {} bundle transactions",
+                calls.len(),
+                transactions.len()
+            )));
+        }
+
+        Ok(calls
+            .into_iter()
+            .map(|call| match call.error {
+                None if call.status == U64::one() => {
+                    let gas_limit = (call.gas_used.saturating_mul(64.into()) + 62) / 63;
+                    BundleTxEstimate::succeeded(self.buffered(gas_limit))
+                }
+                error => BundleTxEstimate::failed(error.and_then(revert_reason)),
+            })
+            .collect())
+    }
+
+    /// Estimates the transactions one by one with `eth_estimateGas`, passing the
+    /// state changes of those that succeeded, traced with `debug_traceCall`, as
+    /// overrides to the ones after them
+    async fn estimate_bundle_sequentially(
+        &self,
+        transactions: &[CallRequest],
+        continue_on_failure: bool,
+    ) -> Result<Vec<BundleTxEstimate>, ApiError> {
+        let mut overrides = StateOverride::new();
+        let mut estimates = Vec::with_capacity(transactions.len());
+        for (index, tx) in transactions.iter().enumerate() {
+            let mut params = vec![
+                helpers::serialize(tx),
+                helpers::serialize(&BlockNumber::Latest),
+            ];
+            if !overrides.is_empty() {
+                params.push(helpers::serialize(&overrides));
+            }
+            let estimate =
+                CallFuture::<U256, _>::new(self.transport().execute("eth_estimateGas", params))
+                    .await;
+            match estimate {
+                Ok(gas_limit) => {
+                    estimates.push(BundleTxEstimate::succeeded(self.buffered(gas_limit)));
+                    if index + 1 < transactions.len() {
+                        self.apply_state_changes(tx, &mut overrides).await?;
+                    }
+                }
+                Err(error) => match state_override::estimate_error(error.clone()) {
+                    ApiError::ExecutionReverted { reason, .. } => {
+                        let reason = reason.or_else(|| match error {
+                            web3::Error::Rpc(error) => message_reason(error.message),
+                            _ => None,
+                        });
+                        estimates.push(BundleTxEstimate::failed(reason));
+                        if !continue_on_failure {
+                            break;
+                        }
+                    }
+                    error => return Err(error),
+                },
+            }
+        }
+        estimates.resize(transactions.len(), BundleTxEstimate::not_simulated());
+        Ok(estimates)
+    }
+
+    /// Adds the state changes of `tx`, run on top of `overrides`, to `overrides`
+    async fn apply_state_changes(
+        &self,
+        tx: &CallRequest,
+        overrides: &mut StateOverride,
+    ) -> Result<(), ApiError> {
+        let diff: StateDiff = CallFuture::new(self.transport().execute(
+            "debug_traceCall",
+            vec![
+                helpers::serialize(tx),
+                helpers::serialize(&BlockNumber::Latest),
+                json!({
+                    "tracer": "prestateTracer",
+                    "tracerConfig": { "diffMode": true },
+                    "stateOverrides": overrides,
+                }),
+            ],
+        ))
+        .await
+        .map_err(|error| {
+            if is_method_unsupported(&error) {
+                ApiError::NotImplemented(format!(
+                    "The configured RPC supports neither eth_simulateV1 nor debug_traceCall, \
+                     which bundles need to see the effects of earlier transactions: {error}"
+                ))
+            } else {
+                revert::estimate_error(error)
+            }
+        })?;
+        merge_state_diff(overrides, diff);
+        Ok(())
+    }
+
+    fn buffered(&self, gas_limit: U256) -> U256 {
+        gas_limit.saturating_mul((100 + self.buffer_percent).into()) / U256::from(100)
+    }
+}
+
+/// Applies a `prestateTracer` diff to `overrides`. Slots the diff only lists
+/// before the transaction were cleared by it.
+fn merge_state_diff(overrides: &mut StateOverride, diff: StateDiff) {
+    for (address, pre) in &diff.pre {
+        let cleared: Vec<_> = pre
+            .storage
+            .keys()
+            .filter(|slot| {
+                diff.post
+                    .get(address)
+                    .is_none_or(|post| !post.storage.contains_key(*slot))
+            })
+            .map(|slot| (*slot, H256::zero()))
+            .collect();
+        if !cleared.is_empty() {
+            set_storage(overrides.entry(*address).or_default(), cleared);
+        }
+    }
+    for (address, post) in diff.post {
+        let account = overrides.entry(address).or_default();
+        account.balance = post.balance.or(account.balance);
+        account.nonce = post
+            .nonce
+            .map(|nonce| U64::from(nonce.0.low_u64()))
+            .or(account.nonce);
+        account.code = post.code.or(account.code.take());
+        set_storage(account, post.storage);
+    }
+}
+
+/// Writes slots into the account's full storage override when it has one, and
+/// into its per-slot overrides otherwise
+fn set_storage(
+    account: &mut state_override::AccountOverride,
+    slots: impl IntoIterator<Item = (H256, H256)>,
+) {
+    match &mut account.state {
+        Some(state) => state.extend(slots),
+        None => account.state_diff.get_or_insert_default().extend(slots),
+    }
+}
+
+/// Reason of a failed simulated call: its decoded revert data, or the node's
+/// message when that says more than "execution reverted"
+fn revert_reason(error: SimulatedError) -> Option<String> {
+    error
+        .data
+        .and_then(|data| revert::decode_revert(&data.0))
+        .or_else(|| message_reason(error.message))
+}
+
+fn message_reason(message: String) -> Option<String> {
+    match message.strip_prefix("execution reverted: ") {
+        Some(reason) => Some(reason.to_string()),
+        None if message == "execution reverted" => None,
+        None => Some(message),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use web3::transports::test::TestTransport;
+
+    fn approve_then_swap() -> BundleRequest {
+        let call = |selector: [u8; 4]| CallRequest {
+            from: Some(Address::repeat_byte(0x01)),
+            to: Some(Address::repeat_byte(0x02)),
+            data: Some(Bytes::from(selector.to_vec())),
+            ..Default::default()
+        };
+        BundleRequest {
+            transactions: vec![
+                call([0x09, 0x5e, 0xa7, 0xb3]),
+                call([0x38, 0xed, 0x17, 0x39]),
+                call([0xa9, 0x05, 0x9c, 0xbb]),
+            ],
+            continue_on_failure: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_simulate_bundle() {
+        let mut transport = TestTransport::default();
+        transport.add_response(json!([])); // Probe
+        transport.add_response(json!([{
+            "calls": [
+                { "status": "0x1", "gasUsed": "0xb411", "returnData": "0x" },
+                {
+                    "status": "0x0",
+                    "gasUsed": "0x5a3c",
+                    "returnData": "0x",
+                    "error": {
+                        "code": 3,
+                        "message": "execution reverted",
+                        "data": "0x08c379a00000000000000000000000000000000000000000000000000000000000000020000000000000000000000000000000000000000000000000000000000000000c696e73756666696369656e740000000000000000000000000000000000000000"
+                    }
+                },
+                { "status": "0x1", "gasUsed": "0x5208", "returnData": "0x" }
+            ]
+        }]));
+        let estimator = GasEstimator::new(transport.clone());
+
+        let estimates = estimator
+            .estimate_bundle(approve_then_swap())
+            .await
+            .unwrap();
+        // 46097 gas used, raised by 1/63
+        assert_eq!(estimates[0], BundleTxEstimate::succeeded(46829.into()));
+        assert_eq!(
+            estimates[1],
+            BundleTxEstimate::failed(Some("insufficient".to_string()))
+        );
+        assert_eq!(estimates[2], BundleTxEstimate::not_simulated());
+
+        transport.assert_request(
+            "eth_simulateV1",
+            &[r#"{"blockStateCalls":[]}"#.into(), r#""latest""#.into()],
+        );
+        let calls = helpers::serialize(&approve_then_swap().transactions);
+        transport.assert_request(
+            "eth_simulateV1",
+            &[
+                json!({ "blockStateCalls": [{ "calls": calls }], "validation": false }).to_string(),
+                r#""latest""#.into(),
+            ],
+        );
+        transport.assert_no_more_requests();
+    }
+
+    #[tokio::test]
+    async fn test_estimate_bundle_sequentially() {
+        let token = Address::repeat_byte(0x02);
+        let slot = |byte| format!("{:?}", H256::repeat_byte(byte));
+        let mut transport = TestTransport::default();
+        transport.add_response(json!("0xb411"));
+        transport.add_response(json!({
+            "pre": { format!("{token:?}"): { "storage": { slot(1): slot(9) } } },
+            "post": { format!("{token:?}"): { "nonce": 1, "storage": { slot(2): slot(3) } } }
+        }));
+        transport.add_response(json!("0x1d4c0"));
+        transport.add_response(json!({ "pre": {}, "post": {} }));
+        transport.add_response(json!("0x5208"));
+        let estimator = GasEstimator::new(transport.clone());
+        // As probed at startup
+        *estimator.simulate_v1_support.lock().unwrap() = Some(false);
+
+        let estimates = estimator
+            .estimate_bundle(approve_then_swap())
+            .await
+            .unwrap();
+        assert_eq!(
+            estimates,
+            vec![
+                BundleTxEstimate::succeeded(46097.into()),
+                BundleTxEstimate::succeeded(120000.into()),
+                BundleTxEstimate::succeeded(21000.into()),
+            ]
+        );
+
+        let [approve, swap, _] = approve_then_swap().transactions.try_into().unwrap();
+        transport.assert_request(
+            "eth_estimateGas",
+            &[
+                helpers::serialize(&approve).to_string(),
+                r#""latest""#.into(),
+            ],
+        );
+        transport.assert_request(
+            "debug_traceCall",
+            &[
+                helpers::serialize(&approve).to_string(),
+                r#""latest""#.into(),
+                json!({
+                    "tracer": "prestateTracer",
+                    "tracerConfig": { "diffMode": true },
+                    "stateOverrides": {},
+                })
+                .to_string(),
+            ],
+        );
+        // The swap sees the approval's storage writes, and the slot it cleared
+        let overrides = json!({
+            format!("{token:?}"): {
+                "nonce": "0x1",
+                "stateDiff": { slot(1): format!("{:?}", H256::zero()), slot(2): slot(3) }
+            }
+        });
+        transport.assert_request(
+            "eth_estimateGas",
+            &[
+                helpers::serialize(&swap).to_string(),
+                r#""latest""#.into(),
+                overrides.to_string(),
+            ],
+        );
+    }
+
+    #[tokio::test]
+    async fn test_empty_bundle_is_rejected() {
+        let estimator = GasEstimator::new(TestTransport::default());
+        let request = BundleRequest {
+            transactions: vec![],
+            continue_on_failure: false,
+        };
+        assert!(matches!(
+            estimator.estimate_bundle(request).await,
+            Err(ApiError::BadRequest(_))
+        ));
+    }
+}
@@ -0,0 +1,215 @@
+//! In-memory LRU cache for RPC gas estimates
+
+use crate::eip7702;
+use crate::estimator::{ApiError, EstimateGasRequest, GasEstimateResponse, GasEstimator};
+use lru::LruCache;
+use std::num::NonZeroUsize;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use web3::{Transport, types::BlockNumber};
+
+pub const DEFAULT_CACHE_MAX_ENTRIES: usize = 1000;
+pub const DEFAULT_CACHE_TTL_SECS: u64 = 12;
+
+/// Wraps a [`GasEstimator`] and serves repeated RPC-bound requests from memory.
+/// Static estimates are cheap to compute and are never cached, and neither are
+/// estimates with state overrides or against the pending block, which are
+/// hypothetical or change with every transaction the node sees.
+pub struct CachedGasEstimator<T: Transport> {
+    estimator: Arc<GasEstimator<T>>,
+    cache: Mutex<LruCache<u64, (Instant, GasEstimateResponse)>>,
+    ttl: Duration,
+    hasher: ahash::RandomState,
+}
+
+impl<T: Transport> CachedGasEstimator<T> {
+    pub fn new(estimator: Arc<GasEstimator<T>>, max_entries: usize, ttl: Duration) -> Self {
+        let capacity = NonZeroUsize::new(max_entries).unwrap_or(NonZeroUsize::MIN);
+        Self {
+            estimator,
+            cache: Mutex::new(LruCache::new(capacity)),
+            ttl,
+            hasher: ahash::RandomState::new(),
+        }
+    }
+
+    /// Estimates a transaction, answering from the cache when an identical
+    /// RPC-bound request was estimated within the TTL
+    pub async fn estimate(
+        &self,
+        mut request: EstimateGasRequest,
+    ) -> Result<GasEstimateResponse, ApiError> {
+        GasEstimator::<T>::fill_transaction_type(&mut request.tx);
+        let reason = self.estimator.estimate_reason(&request).await?;
+        if !reason.requires_rpc() || !is_cacheable(&request) {
+            return self.estimator.estimate_with_reason(request, reason).await;
+        }
+
+        let key = self.cache_key(&request);
+        if let Some((mut response, age)) = self.lookup(key).await {
+            response.method = response.method.replacen("rpc", "rpc-cached", 1);
+            response.cached = true;
+            response.cache_age_secs = Some(age.as_secs());
+            // The node wasn't asked this time
+            response.rpc_latency_ms = None;
+            return Ok(response);
+        }
+
+        let response = self.estimator.estimate_with_reason(request, reason).await?;
+        self.cache
+            .lock()
+            .await
+            .put(key, (Instant::now(), response.clone()));
+        Ok(response)
+    }
+
+    /// Returns a fresh cached response and its age, evicting it if it has expired
+    async fn lookup(&self, key: u64) -> Option<(GasEstimateResponse, Duration)> {
+        let mut cache = self.cache.lock().await;
+        match cache.get(&key) {
+            Some((inserted, response)) if inserted.elapsed() < self.ttl => {
+                Some((response.clone(), inserted.elapsed()))
+            }
+            Some(_) => {
+                cache.pop(&key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Hashes the canonical JSON of the transaction and the options that affect the result
+    fn cache_key(&self, request: &EstimateGasRequest) -> u64 {
+        let canonical = serde_json::json!({
+            "tx": request.tx,
+            "block_number": request.block_number,
+            "block": request.block,
+            "blob_versioned_hashes": request.blob_versioned_hashes,
+            "blob_count": request.blob_count,
+            "authorization_list": request.authorization_list,
+            "buffer_percent": request.buffer_percent,
+            "margin_bps": request.margin_bps,
+            "include_cost": request.include_cost,
+            "include_access_list": request.include_access_list,
+            "simulate": request.simulate,
+            "no_heuristic": request.no_heuristic,
+            "confidence": request.confidence,
+        });
+        self.hasher.hash_one(canonical.to_string())
+    }
+}
+
+/// Whether the request's result may be reused by identical requests
+fn is_cacheable(request: &EstimateGasRequest) -> bool {
+    // EIP-7702 transactions without signed tuples are estimated statically
+    let static_set_code =
+        eip7702::is_set_code_transaction(&request.tx) && request.authorization_list.is_empty();
+    // Balances change with every transaction the sender makes, and transient
+    // storage is flagged to have the node estimate every time
+    request.state_overrides.is_none()
+        && !request.check_balance
+        && !request.has_transient_storage
+        && !static_set_code
+        && !matches!(request.requested_block(), Ok(Some(BlockNumber::Pending)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use web3::{
+        transports::test::TestTransport,
+        types::{Bytes, CallRequest},
+    };
+
+    fn rpc_request() -> EstimateGasRequest {
+        CallRequest {
+            data: Some(Bytes::from(vec![0x01])),
+            ..Default::default()
+        }
+        .into()
+    }
+
+    // TestTransport is single-threaded, which is fine for these tests
+    #[allow(clippy::arc_with_non_send_sync)]
+    fn cached_estimator(
+        transport: &TestTransport,
+        ttl: Duration,
+    ) -> CachedGasEstimator<TestTransport> {
+        CachedGasEstimator::new(
+            Arc::new(GasEstimator::new(transport.clone())),
+            DEFAULT_CACHE_MAX_ENTRIES,
+            ttl,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_second_request_is_served_from_cache() {
+        let mut transport = TestTransport::default();
+        transport.add_response("0x5208".into());
+        let estimator = cached_estimator(&transport, Duration::from_secs(60));
+
+        let first = estimator.estimate(rpc_request()).await.unwrap();
+        assert_eq!(first.method, "rpc");
+
+        assert!(!first.cached);
+
+        let second = estimator.estimate(rpc_request()).await.unwrap();
+        assert_eq!(second.method, "rpc-cached");
+        assert!(second.cached);
+        assert_eq!(second.cache_age_secs, Some(0));
+        assert_eq!(second.gas_limit, first.gas_limit);
+
+        transport.assert_request("eth_estimateGas", &[r#"{"data":"0x01"}"#.into()]);
+        transport.assert_no_more_requests();
+    }
+
+    #[tokio::test]
+    async fn test_expired_entries_are_refetched() {
+        let mut transport = TestTransport::default();
+        transport.add_response("0x5208".into());
+        transport.add_response("0x5209".into());
+        let estimator = cached_estimator(&transport, Duration::ZERO);
+
+        estimator.estimate(rpc_request()).await.unwrap();
+        let second = estimator.estimate(rpc_request()).await.unwrap();
+        assert_eq!(second.method, "rpc");
+        assert_eq!(second.gas_limit, 21001.into());
+    }
+
+    #[tokio::test]
+    async fn test_different_requests_use_different_entries() {
+        let mut transport = TestTransport::default();
+        transport.add_response("0x5208".into());
+        transport.add_response("0x5209".into());
+        let estimator = cached_estimator(&transport, Duration::from_secs(60));
+
+        estimator.estimate(rpc_request()).await.unwrap();
+        let mut other = rpc_request();
+        other.block_number = Some(20_000_000.into());
+        let second = estimator.estimate(other).await.unwrap();
+        assert_eq!(second.method, "rpc");
+    }
+
+    #[tokio::test]
+    async fn test_pending_and_overridden_requests_bypass_the_cache() {
+        let mut transport = TestTransport::default();
+        for gas in ["0x5208", "0x5209", "0x520a", "0x520b"] {
+            transport.add_response(gas.into());
+        }
+        let estimator = cached_estimator(&transport, Duration::from_secs(60));
+        let pending = EstimateGasRequest {
+            block: Some("pending".to_string()),
+            ..rpc_request()
+        };
+        let overridden = EstimateGasRequest {
+            state_overrides: Some(Default::default()),
+            ..rpc_request()
+        };
+
+        for request in [pending.clone(), pending, overridden.clone(), overridden] {
+            let response = estimator.estimate(request).await.unwrap();
+            assert!(!response.cached);
+        }
+    }
+}
@@ -0,0 +1,90 @@
+//! Zero and non-zero byte counts of a transaction's calldata, returned with its
+//! estimate to show how much compressing the calldata could save
+
+use serde::{Deserialize, Serialize, Serializer};
+use utoipa::ToSchema;
+
+/// Share of zero bytes above which [`CalldataStats::hint`] suggests compression
+pub const HIGH_ZERO_RATIO: f64 = 0.5;
+
+/// Byte counts of the transaction's `data`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct CalldataStats {
+    pub total_bytes: usize,
+    /// Zero bytes, cheaper to send than non-zero ones and well compressed
+    pub zero_bytes: usize,
+    pub non_zero_bytes: usize,
+    /// `zero_bytes / total_bytes`, rounded to two decimals; 0 without calldata
+    #[serde(serialize_with = "two_decimals")]
+    #[schema(example = 0.25)]
+    pub zero_ratio: f64,
+    /// Suggestion for calldata that would compress well
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hint: Option<String>,
+}
+
+impl CalldataStats {
+    pub fn new(data: &[u8]) -> Self {
+        let zero_bytes = data.iter().filter(|byte| **byte == 0).count();
+        let zero_ratio = if data.is_empty() {
+            0.0
+        } else {
+            zero_bytes as f64 / data.len() as f64
+        };
+        Self {
+            total_bytes: data.len(),
+            zero_bytes,
+            non_zero_bytes: data.len() - zero_bytes,
+            zero_ratio,
+            hint: (zero_ratio > HIGH_ZERO_RATIO).then(|| {
+                "high zero-byte ratio; consider calldata compression for L2 deployment".to_string()
+            }),
+        }
+    }
+}
+
+fn two_decimals<S: Serializer>(value: &f64, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64((value * 100.0).round() / 100.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counts_and_ratio() {
+        // transfer(address,uint256) selector, then a padded address word
+        let mut data = vec![0xa9, 0x05, 0x9c, 0xbb];
+        data.extend([0; 12]);
+        data.extend([0x11; 20]);
+        let stats = CalldataStats::new(&data);
+        assert_eq!(stats.total_bytes, 36);
+        assert_eq!(stats.zero_bytes, 12);
+        assert_eq!(stats.non_zero_bytes, 24);
+        assert_eq!(stats.zero_ratio, 12.0 / 36.0);
+        assert_eq!(stats.hint, None);
+
+        let json = serde_json::to_value(&stats).unwrap();
+        assert_eq!(json["zero_ratio"], 0.33);
+        assert!(json.get("hint").is_none());
+    }
+
+    #[test]
+    fn test_high_zero_ratio_hint() {
+        let stats = CalldataStats::new(&[0, 0, 0, 1]);
+        assert_eq!((stats.zero_bytes, stats.non_zero_bytes), (3, 1));
+        assert_eq!(stats.zero_ratio, 0.75);
+        assert!(stats.hint.unwrap().contains("calldata compression"));
+
+        // Exactly half isn't high
+        assert_eq!(CalldataStats::new(&[0, 1]).hint, None);
+    }
+
+    #[test]
+    fn test_empty_calldata() {
+        let stats = CalldataStats::new(&[]);
+        assert_eq!(stats.total_bytes, 0);
+        assert_eq!(stats.zero_ratio, 0.0);
+        assert_eq!(serde_json::to_value(&stats).unwrap()["zero_ratio"], 0.0);
+    }
+}
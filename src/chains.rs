@@ -0,0 +1,228 @@
+//! Chain registry: one estimator per configured chain, selected per request
+
+use crate::estimator::{ApiError, GasEstimator};
+use crate::upstream_error::classify_rpc_error;
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, RawPathParams},
+    http::request::Parts,
+};
+use reqwest::Url;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use web3::Transport;
+
+/// Estimators by chain id, for the chains selectable in requests
+pub type ChainRegistry<T> = HashMap<u64, Arc<GasEstimator<T>>>;
+
+/// Chain used by requests that don't select one when `ETH_RPC_URLS` maps chain ids
+pub const DEFAULT_CHAIN_ID: u64 = 1;
+
+/// RPC endpoints of the default chain and of the chains selectable per request
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChainConfig {
+    /// Endpoints of the chain used when a request selects none
+    pub default_urls: Vec<Url>,
+    /// Id of the default chain when it is also selectable, so both share an estimator
+    pub default_chain_id: Option<u64>,
+    pub chains: Vec<(u64, Vec<Url>)>,
+}
+
+impl ChainConfig {
+    /// Reads `ETH_RPC_URLS` (falling back to `ETH_RPC_URL`, then `default_url`) and
+    /// `CHAINS`. `ETH_RPC_URLS` is either a comma-separated URL list for the default
+    /// chain or a JSON object mapping chain ids to URLs, such as
+    /// `{"1": "https://...", "11155111": "https://..."}`, whose chain 1 is the default.
+    pub fn from_env(default_url: &str) -> Result<Self, String> {
+        let rpc_urls = std::env::var("ETH_RPC_URLS")
+            .or_else(|_| std::env::var("ETH_RPC_URL"))
+            .unwrap_or_else(|_| default_url.to_string());
+        Self::parse(&rpc_urls, &std::env::var("CHAINS").unwrap_or_default())
+    }
+
+    /// Parses the values of `ETH_RPC_URLS` and `CHAINS`, as described in [`Self::from_env`]
+    pub fn parse(rpc_urls: &str, chains: &str) -> Result<Self, String> {
+        let mut config = if rpc_urls.trim_start().starts_with('{') {
+            let chains = parse_chain_urls_json(rpc_urls)?;
+            let default_urls = chains
+                .iter()
+                .find(|(chain_id, _)| *chain_id == DEFAULT_CHAIN_ID)
+                .map(|(_, urls)| urls.clone())
+                .ok_or_else(|| {
+                    format!("ETH_RPC_URLS must include the default chain {DEFAULT_CHAIN_ID}")
+                })?;
+            ChainConfig {
+                default_urls,
+                default_chain_id: Some(DEFAULT_CHAIN_ID),
+                chains,
+            }
+        } else {
+            let default_urls = parse_urls(rpc_urls)?;
+            if default_urls.is_empty() {
+                return Err("ETH_RPC_URLS must contain at least one URL".to_string());
+            }
+            ChainConfig {
+                default_urls,
+                default_chain_id: None,
+                chains: Vec::new(),
+            }
+        };
+
+        config.chains.extend(parse_chains(chains)?);
+        check_unique(&config.chains)?;
+        Ok(config)
+    }
+}
+
+/// Parses a JSON object mapping chain ids to comma-separated RPC URLs
+fn parse_chain_urls_json(json: &str) -> Result<Vec<(u64, Vec<Url>)>, String> {
+    let entries: BTreeMap<String, String> = serde_json::from_str(json)
+        .map_err(|e| format!("Failed to parse ETH_RPC_URLS as JSON: {e}"))?;
+    entries
+        .into_iter()
+        .map(|(chain_id, urls)| parse_chain(&chain_id, &urls))
+        .collect()
+}
+
+/// Parses a chain list such as `1=https://a,https://b;8453=https://c`: entries are
+/// separated by `;` and each maps a chain id to one or more comma-separated RPC URLs
+pub fn parse_chains(chains: &str) -> Result<Vec<(u64, Vec<Url>)>, String> {
+    let chains = chains
+        .split(';')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let (chain_id, urls) = entry
+                .split_once('=')
+                .ok_or_else(|| format!("Expected chain_id=url in chain entry {entry}"))?;
+            parse_chain(chain_id, urls)
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    check_unique(&chains)?;
+    Ok(chains)
+}
+
+/// Parses one chain id and its comma-separated RPC URLs
+fn parse_chain(chain_id: &str, urls: &str) -> Result<(u64, Vec<Url>), String> {
+    let chain_id = chain_id
+        .trim()
+        .parse()
+        .map_err(|e| format!("Failed to parse chain id {chain_id}: {e}"))?;
+    let urls = parse_urls(urls)?;
+    if urls.is_empty() {
+        return Err(format!("Chain {chain_id} has no RPC URLs"));
+    }
+    Ok((chain_id, urls))
+}
+
+/// Parses a comma-separated URL list, ignoring blank entries
+fn parse_urls(urls: &str) -> Result<Vec<Url>, String> {
+    urls.split(',')
+        .map(str::trim)
+        .filter(|url| !url.is_empty())
+        .map(|url| Url::parse(url).map_err(|e| format!("Failed to parse RPC URL {url}: {e}")))
+        .collect()
+}
+
+fn check_unique(chains: &[(u64, Vec<Url>)]) -> Result<(), String> {
+    for (index, (chain_id, _)) in chains.iter().enumerate() {
+        if chains[..index].iter().any(|(other, _)| other == chain_id) {
+            return Err(format!("Chain {chain_id} is configured more than once"));
+        }
+    }
+    Ok(())
+}
+
+impl<T: Transport> GasEstimator<T> {
+    /// Chain id reported by the node, for checking it serves the configured chain
+    pub async fn chain_id(&self) -> Result<u64, ApiError> {
+        let chain_id = self.eth.chain_id().await.map_err(classify_rpc_error)?;
+        if chain_id > u64::MAX.into() {
+            return Err(ApiError::InternalServerError(format!(
+                "Node reported out of range chain id {chain_id}"
+            )));
+        }
+        Ok(chain_id.as_u64())
+    }
+}
+
+/// Error for a chain that isn't configured, listing the ones that are
+pub fn unknown_chain<T: Transport>(chain_id: u64, chains: &ChainRegistry<T>) -> ApiError {
+    let mut supported: Vec<_> = chains.keys().collect();
+    supported.sort();
+    ApiError::NotFound(format!(
+        "Chain {chain_id} is not supported; supported chains: {supported:?}"
+    ))
+}
+
+/// Chain id given as the `chain_id` path segment of `/api/{chain_id}/...` routes
+pub struct PathChainId(pub Option<u64>);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for PathChainId {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let params = RawPathParams::from_request_parts(parts, state)
+            .await
+            .map_err(|rejection| ApiError::BadRequest(rejection.body_text()))?;
+        params
+            .iter()
+            .find(|(name, _)| *name == "chain_id")
+            .map(|(_, value)| {
+                value
+                    .parse()
+                    .map_err(|_| ApiError::BadRequest(format!("Invalid chain id {value}")))
+            })
+            .transpose()
+            .map(PathChainId)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use web3::transports::test::TestTransport;
+
+    #[test]
+    fn test_parse_chains() {
+        let chains =
+            parse_chains("1=https://eth.example,https://eth2.example; 8453=https://base.example;")
+                .unwrap();
+        assert_eq!(chains.len(), 2);
+        assert_eq!(chains[0].0, 1);
+        assert_eq!(chains[0].1.len(), 2);
+        assert_eq!(chains[1].0, 8453);
+        assert_eq!(chains[1].1[0].as_str(), "https://base.example/");
+
+        assert!(parse_chains("https://eth.example").is_err());
+        assert!(parse_chains("mainnet=https://eth.example").is_err());
+        assert!(parse_chains("1=").is_err());
+        assert!(parse_chains("1=https://a.example;1=https://b.example").is_err());
+    }
+
+    #[test]
+    fn test_parse_chain_urls_json() {
+        let chains = parse_chain_urls_json(
+            r#"{"1": "https://eth.example", "11155111": "https://sepolia.example"}"#,
+        )
+        .unwrap();
+        assert_eq!(chains.len(), 2);
+        assert_eq!(chains[0].0, 1);
+        assert_eq!(chains[1].0, 11155111);
+        assert_eq!(chains[1].1[0].as_str(), "https://sepolia.example/");
+
+        assert!(parse_chain_urls_json(r#"{"mainnet": "https://eth.example"}"#).is_err());
+        assert!(parse_chain_urls_json(r#"{"1": ""}"#).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_chain_id() {
+        let mut transport = TestTransport::default();
+        transport.add_response("0x2105".into());
+        let estimator = GasEstimator::new(transport.clone());
+
+        assert_eq!(estimator.chain_id().await.unwrap(), 8453);
+        transport.assert_request("eth_chainId", &[]);
+    }
+}
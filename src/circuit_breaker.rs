@@ -0,0 +1,176 @@
+//! Fails RPC estimates fast while the node keeps failing, instead of letting each
+//! one wait for its own timeout
+
+use crate::estimator::ApiError;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Consecutive failed estimates that open the circuit
+pub const DEFAULT_CIRCUIT_FAILURE_THRESHOLD: u32 = 5;
+/// How long an open circuit rejects estimates before letting a probe through
+pub const DEFAULT_CIRCUIT_OPEN_SECS: u64 = 30;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    /// Estimates reach the node; counts the consecutive ones that failed
+    Closed { failures: u32 },
+    /// Estimates are rejected until the given time
+    Open { until: Instant },
+    /// A single probe estimate is let through, and decides whether to close again
+    HalfOpen { probing: bool },
+}
+
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    failure_threshold: u32,
+    open_duration: Duration,
+    state: Mutex<CircuitState>,
+}
+
+impl CircuitBreaker {
+    pub fn new(failure_threshold: u32, open_duration: Duration) -> Self {
+        Self {
+            failure_threshold: failure_threshold.max(1),
+            open_duration,
+            state: Mutex::new(CircuitState::Closed { failures: 0 }),
+        }
+    }
+
+    pub fn state(&self) -> CircuitState {
+        *self.state.lock().unwrap()
+    }
+
+    /// Admits an estimate to the node, or rejects it while the circuit is open or
+    /// another estimate is probing it
+    pub fn acquire(&self) -> Result<CircuitPermit<'_>, ApiError> {
+        let mut state = self.state.lock().unwrap();
+        match *state {
+            CircuitState::Closed { .. } => {}
+            CircuitState::Open { until } if until <= Instant::now() => {
+                tracing::info!("Circuit half-open, probing the node");
+                *state = CircuitState::HalfOpen { probing: true };
+            }
+            CircuitState::HalfOpen { probing: false } => {
+                *state = CircuitState::HalfOpen { probing: true };
+            }
+            CircuitState::Open { .. } | CircuitState::HalfOpen { probing: true } => {
+                return Err(ApiError::InternalServerError("circuit open".to_string()));
+            }
+        }
+        Ok(CircuitPermit { breaker: self })
+    }
+
+    /// Records an estimate the node answered, closing the circuit
+    fn record_success(&self) {
+        let mut state = self.state.lock().unwrap();
+        if matches!(*state, CircuitState::HalfOpen { .. }) {
+            tracing::info!("Probe succeeded, closing the circuit");
+        }
+        *state = CircuitState::Closed { failures: 0 };
+    }
+
+    /// Records an estimate that failed upstream, opening the circuit after
+    /// `failure_threshold` in a row or a failed probe
+    fn record_failure(&self) {
+        let mut state = self.state.lock().unwrap();
+        let failures = match *state {
+            CircuitState::Closed { failures } => failures + 1,
+            CircuitState::HalfOpen { .. } => self.failure_threshold,
+            CircuitState::Open { .. } => return,
+        };
+        *state = if failures >= self.failure_threshold {
+            tracing::warn!(
+                failures,
+                open_secs = self.open_duration.as_secs(),
+                "Estimates keep failing, opening the circuit"
+            );
+            CircuitState::Open {
+                until: Instant::now() + self.open_duration,
+            }
+        } else {
+            CircuitState::Closed { failures }
+        };
+    }
+
+    /// Lets another estimate probe the circuit when an admitted one says nothing
+    /// about the node
+    fn release(&self) {
+        let mut state = self.state.lock().unwrap();
+        if *state == (CircuitState::HalfOpen { probing: true }) {
+            *state = CircuitState::HalfOpen { probing: false };
+        }
+    }
+}
+
+/// An admitted estimate, whose outcome is recorded when it is consumed. One
+/// dropped without an outcome, e.g. because the client's timeout cut it short or
+/// the request was cancelled, lets another estimate probe instead.
+#[derive(Debug)]
+#[must_use]
+pub struct CircuitPermit<'a> {
+    breaker: &'a CircuitBreaker,
+}
+
+impl CircuitPermit<'_> {
+    /// The node answered, even if with a revert
+    pub fn succeeded(self) {
+        self.breaker.record_success();
+        std::mem::forget(self);
+    }
+
+    /// The estimate failed upstream
+    pub fn failed(self) {
+        self.breaker.record_failure();
+        std::mem::forget(self);
+    }
+}
+
+impl Drop for CircuitPermit<'_> {
+    fn drop(&mut self) {
+        self.breaker.release();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_opens_after_consecutive_failures() {
+        let breaker = CircuitBreaker::new(3, Duration::from_secs(30));
+        for _ in 0..2 {
+            breaker.acquire().unwrap().failed();
+        }
+        // A success resets the count
+        breaker.acquire().unwrap().succeeded();
+        for _ in 0..2 {
+            breaker.acquire().unwrap().failed();
+        }
+        assert_eq!(breaker.state(), CircuitState::Closed { failures: 2 });
+
+        breaker.acquire().unwrap().failed();
+        assert!(matches!(breaker.state(), CircuitState::Open { .. }));
+        let error = breaker.acquire().unwrap_err();
+        assert_eq!(error.to_string(), "circuit open");
+    }
+
+    #[test]
+    fn test_half_open_admits_one_probe() {
+        let breaker = CircuitBreaker::new(1, Duration::ZERO);
+        breaker.acquire().unwrap().failed();
+
+        let probe = breaker.acquire().unwrap();
+        assert_eq!(breaker.state(), CircuitState::HalfOpen { probing: true });
+        assert!(breaker.acquire().is_err());
+
+        // A probe cut short lets the next estimate probe instead
+        drop(probe);
+        let probe = breaker.acquire().unwrap();
+
+        // A failed probe reopens the circuit, a successful one closes it
+        probe.failed();
+        assert!(matches!(breaker.state(), CircuitState::Open { .. }));
+        breaker.acquire().unwrap().succeeded();
+        assert_eq!(breaker.state(), CircuitState::Closed { failures: 0 });
+    }
+}
@@ -0,0 +1,73 @@
+//! In-memory LRU cache recording whether an address has contract code
+
+use lru::LruCache;
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use web3::types::Address;
+
+pub const DEFAULT_CODE_CACHE_MAX_ENTRIES: usize = 10_000;
+/// Entries expire since contracts can be deployed to addresses that had no code
+pub const DEFAULT_CODE_CACHE_TTL_SECS: u64 = 60;
+
+pub struct CodeCache {
+    entries: Mutex<LruCache<Address, (Instant, bool)>>,
+    ttl: Duration,
+}
+
+impl CodeCache {
+    pub fn new(max_entries: usize, ttl: Duration) -> Self {
+        let capacity = NonZeroUsize::new(max_entries).unwrap_or(NonZeroUsize::MIN);
+        Self {
+            entries: Mutex::new(LruCache::new(capacity)),
+            ttl,
+        }
+    }
+
+    /// Whether the address has code, if looked up within the TTL
+    pub fn get(&self, address: &Address) -> Option<bool> {
+        let mut entries = self.entries.lock().unwrap();
+        match entries.get(address) {
+            Some((inserted, has_code)) if inserted.elapsed() < self.ttl => Some(*has_code),
+            Some(_) => {
+                entries.pop(address);
+                None
+            }
+            None => None,
+        }
+    }
+
+    pub fn insert(&self, address: Address, has_code: bool) {
+        self.entries
+            .lock()
+            .unwrap()
+            .put(address, (Instant::now(), has_code));
+    }
+}
+
+impl Default for CodeCache {
+    fn default() -> Self {
+        Self::new(
+            DEFAULT_CODE_CACHE_MAX_ENTRIES,
+            Duration::from_secs(DEFAULT_CODE_CACHE_TTL_SECS),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_and_expire() {
+        let address = Address::repeat_byte(0x01);
+        let cache = CodeCache::new(10, Duration::from_secs(60));
+        assert_eq!(cache.get(&address), None);
+        cache.insert(address, true);
+        assert_eq!(cache.get(&address), Some(true));
+
+        let expired = CodeCache::new(10, Duration::ZERO);
+        expired.insert(address, false);
+        assert_eq!(expired.get(&address), None);
+    }
+}
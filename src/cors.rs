@@ -0,0 +1,121 @@
+//! Cross-origin policy of the API, read from `CORS_ALLOWED_*` variables
+
+use crate::rate_limit::RATE_LIMIT_REMAINING_HEADER;
+use axum::http::{HeaderName, HeaderValue, Method};
+use reqwest::Url;
+use tower_http::cors::{AllowHeaders, AllowMethods, AllowOrigin, Any, CorsLayer};
+
+/// Builds the CORS layer from `CORS_ALLOWED_ORIGINS` (comma-separated origin URLs),
+/// `CORS_ALLOWED_METHODS` and `CORS_ALLOWED_HEADERS` (comma-separated). Each may be
+/// `*` to allow anything, which is also the default when unset. Malformed values
+/// are reported with the variable they came from.
+pub fn cors_layer(var: impl Fn(&str) -> Option<String>) -> Result<CorsLayer, String> {
+    let origins = match list(var("CORS_ALLOWED_ORIGINS")) {
+        None => AllowOrigin::from(Any),
+        Some(origins) => AllowOrigin::list(
+            origins
+                .iter()
+                .map(|origin| parse_origin(origin))
+                .collect::<Result<Vec<_>, _>>()?,
+        ),
+    };
+    let methods = match list(var("CORS_ALLOWED_METHODS")) {
+        None => AllowMethods::from(Any),
+        Some(methods) => AllowMethods::list(
+            methods
+                .iter()
+                .map(|method| {
+                    Method::from_bytes(method.to_ascii_uppercase().as_bytes()).map_err(|e| {
+                        format!("Failed to parse CORS_ALLOWED_METHODS entry {method:?}: {e}")
+                    })
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+        ),
+    };
+    let headers = match list(var("CORS_ALLOWED_HEADERS")) {
+        None => AllowHeaders::from(Any),
+        Some(headers) => AllowHeaders::list(
+            headers
+                .iter()
+                .map(|header| {
+                    HeaderName::try_from(header.as_str()).map_err(|e| {
+                        format!("Failed to parse CORS_ALLOWED_HEADERS entry {header:?}: {e}")
+                    })
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+        ),
+    };
+
+    Ok(CorsLayer::new()
+        .allow_origin(origins)
+        .allow_methods(methods)
+        .allow_headers(headers)
+        .expose_headers([
+            HeaderName::from_static("x-request-id"),
+            HeaderName::from_static("retry-after"),
+            HeaderName::from_static(RATE_LIMIT_REMAINING_HEADER),
+        ]))
+}
+
+/// Entries of a comma-separated variable, or `None` when it is unset, empty or `*`
+fn list(value: Option<String>) -> Option<Vec<String>> {
+    let entries: Vec<String> = value?
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(str::to_string)
+        .collect();
+    (!entries.is_empty() && entries != ["*"]).then_some(entries)
+}
+
+/// Origin of a URL like `https://app.example.com`, as browsers send it
+fn parse_origin(origin: &str) -> Result<HeaderValue, String> {
+    let invalid =
+        |reason: String| format!("Invalid CORS_ALLOWED_ORIGINS entry {origin:?}: {reason}");
+    let url = Url::parse(origin).map_err(|e| invalid(e.to_string()))?;
+    if !matches!(url.scheme(), "http" | "https") || url.host().is_none() {
+        return Err(invalid(
+            "expected an http or https URL with a host".to_string(),
+        ));
+    }
+    HeaderValue::from_str(&url.origin().ascii_serialization()).map_err(|e| invalid(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_list() {
+        assert_eq!(list(None), None);
+        assert_eq!(list(Some("*".to_string())), None);
+        assert_eq!(list(Some(" ".to_string())), None);
+        assert_eq!(
+            list(Some("GET, post,".to_string())),
+            Some(vec!["GET".to_string(), "post".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_parse_origin() {
+        assert_eq!(
+            parse_origin("https://app.example.com/").unwrap(),
+            "https://app.example.com"
+        );
+        assert_eq!(
+            parse_origin("http://localhost:3000").unwrap(),
+            "http://localhost:3000"
+        );
+        for invalid in ["app.example.com", "ftp://example.com", "https://"] {
+            let error = parse_origin(invalid).unwrap_err();
+            assert!(error.contains("CORS_ALLOWED_ORIGINS"), "{error}");
+        }
+    }
+
+    #[test]
+    fn test_invalid_method() {
+        let vars = |name: &str| (name == "CORS_ALLOWED_METHODS").then(|| "GET,B@D".to_string());
+        let error = cors_layer(vars).unwrap_err();
+        assert!(error.contains("CORS_ALLOWED_METHODS"), "{error}");
+    }
+}
@@ -0,0 +1,242 @@
+//! Gas estimation for EigenLayer restaking through the `StrategyManager`
+
+use crate::abi::encode_call;
+use crate::estimator::{ApiError, GasEstimator};
+use crate::revert;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use web3::{
+    Transport,
+    ethabi::{self, ParamType, Token},
+    types::{Address, Bytes, CallRequest, U256},
+};
+
+/// Conservative deposit cost for standard strategies, used when the deposit can't be
+/// simulated because the approval hasn't happened yet
+const DEPOSIT_GAS_WITHOUT_ALLOWANCE: u64 = 200000;
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct EigenLayerParams {
+    pub strategy_manager: Address,
+    pub strategy: Address,
+    pub token: Address,
+    pub amount: U256,
+    pub staker: Address,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct EigenLayerRestakeEstimate {
+    /// Gas for `token.approve(strategy_manager, amount)`, when the allowance is insufficient
+    pub approval_gas: Option<U256>,
+    pub deposit_gas: U256,
+    pub total_gas: U256,
+    pub shares_expected: U256,
+}
+
+impl<T: Transport> GasEstimator<T> {
+    /// Estimates `StrategyManager.depositIntoStrategy` including a token approval if needed
+    pub async fn estimate_eigenlayer_restake(
+        &self,
+        params: EigenLayerParams,
+    ) -> Result<EigenLayerRestakeEstimate, ApiError> {
+        let allowance = self
+            .call_uint(
+                params.token,
+                encode_call(
+                    "allowance",
+                    &[ParamType::Address, ParamType::Address],
+                    &[
+                        Token::Address(params.staker),
+                        Token::Address(params.strategy_manager),
+                    ],
+                ),
+            )
+            .await?;
+
+        let approval_gas = if allowance < params.amount {
+            let approve = encode_call(
+                "approve",
+                &[ParamType::Address, ParamType::Uint(256)],
+                &[
+                    Token::Address(params.strategy_manager),
+                    Token::Uint(params.amount),
+                ],
+            );
+            Some(
+                self.estimate_call(params.staker, params.token, approve)
+                    .await?,
+            )
+        } else {
+            None
+        };
+
+        let deposit = encode_call(
+            "depositIntoStrategy",
+            &[ParamType::Address, ParamType::Address, ParamType::Uint(256)],
+            &[
+                Token::Address(params.strategy),
+                Token::Address(params.token),
+                Token::Uint(params.amount),
+            ],
+        );
+        let deposit_gas = match self
+            .estimate_call(params.staker, params.strategy_manager, deposit)
+            .await
+        {
+            Ok(gas) => gas,
+            // Without the allowance in place the node reverts the deposit simulation.
+            // Any other failure, like an unreachable node, is the node's to report.
+            Err(ApiError::ExecutionReverted { .. }) if approval_gas.is_some() => {
+                DEPOSIT_GAS_WITHOUT_ALLOWANCE.into()
+            }
+            Err(e) => return Err(e),
+        };
+
+        let shares_expected = self
+            .call_uint(
+                params.strategy,
+                encode_call(
+                    "underlyingToShares",
+                    &[ParamType::Uint(256)],
+                    &[Token::Uint(params.amount)],
+                ),
+            )
+            .await?;
+
+        Ok(EigenLayerRestakeEstimate {
+            approval_gas,
+            deposit_gas,
+            total_gas: deposit_gas.saturating_add(approval_gas.unwrap_or_default()),
+            shares_expected,
+        })
+    }
+
+    /// Estimates a contract call via RPC
+    async fn estimate_call(
+        &self,
+        from: Address,
+        to: Address,
+        data: Vec<u8>,
+    ) -> Result<U256, ApiError> {
+        self.eth
+            .estimate_gas(
+                CallRequest {
+                    from: Some(from),
+                    to: Some(to),
+                    data: Some(Bytes(data)),
+                    ..Default::default()
+                },
+                None,
+            )
+            .await
+            .map_err(revert::estimate_error)
+    }
+
+    /// Performs an `eth_call` returning a single `uint256`
+    async fn call_uint(&self, to: Address, data: Vec<u8>) -> Result<U256, ApiError> {
+        let output = self
+            .eth
+            .call(
+                CallRequest {
+                    to: Some(to),
+                    data: Some(Bytes(data)),
+                    ..Default::default()
+                },
+                None,
+            )
+            .await
+            .map_err(|e| ApiError::InternalServerError(format!("RPC call failed: {e}")))?;
+
+        match ethabi::decode(&[ParamType::Uint(256)], &output.0)
+            .ok()
+            .and_then(|tokens| tokens.into_iter().next())
+        {
+            Some(Token::Uint(value)) => Ok(value),
+            _ => Err(ApiError::InternalServerError(format!(
+                "Unexpected call result from {to:?}"
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use web3::transports::test::TestTransport;
+
+    fn params() -> EigenLayerParams {
+        EigenLayerParams {
+            strategy_manager: Address::repeat_byte(0x01),
+            strategy: Address::repeat_byte(0x02),
+            token: Address::repeat_byte(0x03),
+            amount: U256::from(1000),
+            staker: Address::repeat_byte(0x04),
+        }
+    }
+
+    fn uint_response(value: u64) -> serde_json::Value {
+        let encoded = ethabi::encode(&[Token::Uint(value.into())]);
+        serde_json::to_value(Bytes(encoded)).unwrap()
+    }
+
+    #[test]
+    fn test_selectors() {
+        let deposit = encode_call(
+            "depositIntoStrategy",
+            &[ParamType::Address, ParamType::Address, ParamType::Uint(256)],
+            &[],
+        );
+        assert_eq!(deposit, [0xe7, 0xa0, 0x50, 0xaa]);
+    }
+
+    #[tokio::test]
+    async fn test_restake_with_approval() {
+        let mut transport = TestTransport::default();
+        transport.add_response(uint_response(0)); // allowance
+        transport.add_response("0xb411".into()); // approve: 46097
+        transport.add_response("0x249f0".into()); // deposit: 150000
+        transport.add_response(uint_response(990)); // shares
+        let estimator = GasEstimator::new(transport);
+
+        let estimate = estimator
+            .estimate_eigenlayer_restake(params())
+            .await
+            .unwrap();
+        assert_eq!(estimate.approval_gas, Some(46097.into()));
+        assert_eq!(estimate.deposit_gas, 150000.into());
+        assert_eq!(estimate.total_gas, 196097.into());
+        assert_eq!(estimate.shares_expected, 990.into());
+    }
+
+    #[tokio::test]
+    async fn test_restake_deposit_failure_propagates() {
+        let mut transport = TestTransport::default();
+        transport.add_response(uint_response(0)); // allowance
+        transport.add_response("0xb411".into()); // approve: 46097
+        transport.add_response(serde_json::Value::Null); // deposit: malformed response
+        let estimator = GasEstimator::new(transport);
+
+        // Only a revert falls back to the fixed deposit cost
+        let error = estimator
+            .estimate_eigenlayer_restake(params())
+            .await
+            .unwrap_err();
+        assert!(error.is_upstream_failure(), "{error:?}");
+    }
+
+    #[tokio::test]
+    async fn test_restake_with_existing_allowance() {
+        let mut transport = TestTransport::default();
+        transport.add_response(uint_response(5000)); // allowance
+        transport.add_response("0x249f0".into()); // deposit: 150000
+        transport.add_response(uint_response(1000)); // shares
+        let estimator = GasEstimator::new(transport);
+
+        let estimate = estimator
+            .estimate_eigenlayer_restake(params())
+            .await
+            .unwrap();
+        assert_eq!(estimate.approval_gas, None);
+        assert_eq!(estimate.total_gas, 150000.into());
+    }
+}
@@ -0,0 +1,211 @@
+//! EIP-7702 set-code transactions, which delegate accounts to code through signed
+//! authorizations
+
+use crate::estimator::{ApiError, EstimateGasRequest, GasEstimator};
+use crate::gas_costs::SET_CODE_TX_TYPE;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use web3::{
+    Transport,
+    api::Namespace,
+    helpers::{self, CallFuture},
+    types::{Address, BlockNumber, CallRequest, U64, U256},
+};
+
+/// Signed authorization tuple, in the JSON-RPC format of `authorizationList`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SignedAuthorization {
+    #[serde(alias = "chain_id")]
+    pub chain_id: U256,
+    pub address: Address,
+    pub nonce: U64,
+    #[serde(alias = "y_parity")]
+    pub y_parity: U64,
+    pub r: U256,
+    pub s: U256,
+}
+
+/// Whether the transaction is an EIP-7702 set-code transaction
+pub fn is_set_code_transaction(tx: &CallRequest) -> bool {
+    tx.transaction_type == Some(SET_CODE_TX_TYPE.into())
+}
+
+impl EstimateGasRequest {
+    /// Resolves the number of authorizations from `authorization_count` or the
+    /// tuples themselves. The EIP requires at least one.
+    pub fn authorization_count(&self) -> Result<u64, ApiError> {
+        let tuples = self.authorization_list.len() as u64;
+        let count = match self.authorization_count {
+            Some(count) if tuples > 0 && count != tuples => {
+                return Err(ApiError::BadRequest(format!(
+                    "authorization_count of {count} doesn't match {tuples} authorizations"
+                )));
+            }
+            Some(count) => count,
+            None => tuples,
+        };
+        if count == 0 {
+            return Err(ApiError::BadRequest(
+                "EIP-7702 transactions need at least one authorization".to_string(),
+            ));
+        }
+        Ok(count)
+    }
+}
+
+/// Rejects set-code transactions no node would accept, and combinations the
+/// estimator can't serve
+pub(crate) fn check_set_code_transaction(request: &EstimateGasRequest) -> Result<(), ApiError> {
+    request.authorization_count()?;
+    if request.tx.to.is_none() {
+        return Err(ApiError::BadRequest(
+            "EIP-7702 transactions can't create contracts".to_string(),
+        ));
+    }
+    if request.state_overrides.is_some() {
+        return Err(ApiError::BadRequest(
+            "State overrides can't be combined with an authorization list".to_string(),
+        ));
+    }
+    let has_calldata = request
+        .tx
+        .data
+        .as_ref()
+        .is_some_and(|data| !data.0.is_empty());
+    if has_calldata && request.authorization_list.is_empty() {
+        return Err(ApiError::BadRequest(
+            "Estimating calldata of an EIP-7702 transaction needs the signed authorization_list"
+                .to_string(),
+        ));
+    }
+    Ok(())
+}
+
+impl<T: Transport> GasEstimator<T> {
+    /// Calls `eth_estimateGas` with the authorization list, which web3's
+    /// `CallRequest` can't carry
+    pub(crate) fn estimate_gas_with_authorizations(
+        &self,
+        tx: &CallRequest,
+        block: Option<BlockNumber>,
+        authorization_list: &[SignedAuthorization],
+    ) -> CallFuture<U256, T::Out> {
+        let mut tx = helpers::serialize(tx);
+        tx["authorizationList"] = helpers::serialize(&authorization_list);
+        let mut params = vec![tx];
+        if let Some(block) = block {
+            params.push(helpers::serialize(&block));
+        }
+        CallFuture::new(self.eth.transport().execute("eth_estimateGas", params))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::estimator::EstimateReason;
+    use crate::gas_costs::{GAS_TX_BASE, PER_EMPTY_ACCOUNT_COST};
+    use web3::transports::test::TestTransport;
+
+    fn set_code_request(authorization_count: Option<u64>) -> EstimateGasRequest {
+        EstimateGasRequest {
+            tx: CallRequest {
+                to: Some(Address::from_low_u64_be(1)),
+                transaction_type: Some(SET_CODE_TX_TYPE.into()),
+                ..Default::default()
+            },
+            authorization_count,
+            ..Default::default()
+        }
+    }
+
+    fn authorization(nonce: u64) -> SignedAuthorization {
+        SignedAuthorization {
+            chain_id: 1.into(),
+            address: Address::from_low_u64_be(0x7702),
+            nonce: nonce.into(),
+            y_parity: 1.into(),
+            r: 2.into(),
+            s: 3.into(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_without_authorizations() {
+        let transport = TestTransport::default();
+        let estimator = GasEstimator::new(transport.clone());
+
+        for request in [set_code_request(None), set_code_request(Some(0))] {
+            let error = estimator.estimate(request).await.unwrap_err();
+            assert!(matches!(error, ApiError::BadRequest(_)), "{error:?}");
+        }
+        transport.assert_no_more_requests();
+    }
+
+    #[tokio::test]
+    async fn test_static_estimate_per_authorization() {
+        let transport = TestTransport::default();
+        let estimator = GasEstimator::new(transport.clone());
+
+        for count in [1, 3] {
+            let response = estimator
+                .estimate(set_code_request(Some(count)))
+                .await
+                .unwrap();
+            assert_eq!(
+                response.gas_limit,
+                (GAS_TX_BASE + count * PER_EMPTY_ACCOUNT_COST).into()
+            );
+            assert_eq!(response.method, "static");
+            assert_eq!(response.reason, Some(EstimateReason::Eip7702));
+            assert_eq!(
+                response.breakdown.unwrap().authorization,
+                count * PER_EMPTY_ACCOUNT_COST
+            );
+        }
+        transport.assert_no_more_requests();
+    }
+
+    #[tokio::test]
+    async fn test_authorization_list_is_sent_to_node() {
+        let mut transport = TestTransport::default();
+        transport.add_response("0x1d4c0".into());
+        let estimator = GasEstimator::new(transport.clone());
+
+        let mut request = set_code_request(None);
+        request.tx.data = Some(vec![0xd0, 0x9d, 0xe0, 0x8a].into());
+        request.authorization_list = (0..3).map(authorization).collect();
+        let response = estimator.estimate(request.clone()).await.unwrap();
+        assert_eq!(response.method, "rpc");
+        assert_eq!(response.raw_gas_limit, Some(120_000.into()));
+        assert_eq!(response.reason, Some(EstimateReason::Eip7702));
+
+        let mut params = serde_json::to_value(&request.tx).unwrap();
+        params["authorizationList"] = serde_json::to_value(&request.authorization_list).unwrap();
+        transport.assert_request("eth_estimateGas", &[params.to_string()]);
+        transport.assert_no_more_requests();
+    }
+
+    #[test]
+    fn test_authorization_count_must_match_list() {
+        let mut request = set_code_request(Some(2));
+        request.authorization_list = vec![authorization(0)];
+        assert!(request.authorization_count().is_err());
+
+        request.authorization_count = None;
+        assert_eq!(request.authorization_count().unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_calldata_needs_signed_authorizations() {
+        let transport = TestTransport::default();
+        let estimator = GasEstimator::new(transport.clone());
+
+        let mut request = set_code_request(Some(1));
+        request.tx.data = Some(vec![0x01].into());
+        let error = estimator.estimate(request).await.unwrap_err();
+        assert!(matches!(error, ApiError::BadRequest(_)), "{error:?}");
+        transport.assert_no_more_requests();
+    }
+}
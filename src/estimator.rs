@@ -0,0 +1,3645 @@
+//! Core gas estimation logic
+
+use crate::access_list::AccessListEstimate;
+use crate::balance::BalanceCheck;
+use crate::binary_search;
+use crate::block_gas_limit::BlockGasLimitCache;
+use crate::calldata::CalldataStats;
+use crate::circuit_breaker::CircuitBreaker;
+use crate::code_cache::CodeCache;
+use crate::config::redact_urls;
+use crate::eip7702::{self, SignedAuthorization};
+use crate::fee_history::FeeHistoryCache;
+use crate::gas_costs::*;
+use crate::health::UpstreamHealth;
+use crate::heuristic::{HeuristicConfig, HeuristicStore};
+use crate::l2::L2Kind;
+use crate::precompile;
+use crate::request::EstimateRequest;
+use crate::revert;
+use crate::rpc_batch::NodeEstimate;
+use crate::rpc_override;
+use crate::simulate;
+use crate::state_override::{self, StateOverride};
+use crate::static_estimator::{self, predates_eip3860};
+use crate::transport::{self, BatchSender};
+use crate::user_operation::DEFAULT_VERIFICATION_GAS_LIMIT;
+use axum::{
+    Json,
+    http::{HeaderValue, StatusCode},
+    response::IntoResponse,
+};
+use futures::{StreamExt, stream};
+use serde::{Deserialize, Serialize};
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+use tokio::time::Instant;
+use utoipa::ToSchema;
+use web3::{
+    Transport, Web3,
+    api::{Eth, Namespace},
+    helpers::CallFuture,
+    types::{Address, BlockId, BlockNumber, Bytes, CallRequest, H256, U64, U256},
+};
+
+/// Seconds clients are asked to wait with `Retry-After` while the node's provider
+/// rate limits us
+pub const UPSTREAM_RETRY_AFTER_SECS: u64 = 1;
+/// Maximum safety buffer that can be applied to RPC estimates, in percent
+pub const MAX_BUFFER_PERCENT: u64 = 100;
+/// Maximum safety margin that can be applied to any estimate, in basis points
+pub const MAX_MARGIN_BPS: u16 = 5000;
+/// How far apart latest and pending estimates may be before they are reported
+/// unstable, in percent of the latest estimate
+pub const DEFAULT_CONFIDENCE_THRESHOLD_PERCENT: u64 = 10;
+
+/// Gas estimation request: a transaction plus estimation options. Request bodies
+/// are parsed leniently through [`EstimateRequest`].
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(try_from = "EstimateRequest")]
+pub struct EstimateGasRequest {
+    pub tx: CallRequest,
+    /// Block to estimate against (latest when absent)
+    pub block_number: Option<U256>,
+    /// Block tag (`latest`, `pending`, `earliest`, `safe`, `finalized`) or a hex or
+    /// decimal block number; an alternative to `block_number`
+    pub block: Option<String>,
+    /// Versioned hashes of the blobs carried by a blob transaction
+    pub blob_versioned_hashes: Vec<H256>,
+    /// Number of blobs carried by a blob transaction, when hashes aren't given
+    pub blob_count: Option<u64>,
+    /// Signed authorizations of an EIP-7702 transaction
+    pub authorization_list: Vec<SignedAuthorization>,
+    /// Number of authorizations of an EIP-7702 transaction, when tuples aren't given.
+    /// Enough for the static estimate of a transaction without calldata.
+    pub authorization_count: Option<u64>,
+    /// Safety buffer for RPC estimates, overriding the estimator's default
+    pub buffer_percent: Option<u64>,
+    /// Safety margin in basis points applied to the final estimate on both paths
+    pub margin_bps: Option<u16>,
+    /// Account state to override for the estimate; forces the RPC path
+    pub state_overrides: Option<StateOverride>,
+    /// Chain to estimate on, when the server is configured with several
+    pub chain_id: Option<u64>,
+    /// Whether to price the estimate at the current gas price
+    pub include_cost: bool,
+    /// Whether to also estimate with the node's suggested access list
+    pub include_access_list: bool,
+    /// Whether to check that `from` can afford the estimated transaction
+    pub check_balance: bool,
+    /// Whether to also execute the transaction with `eth_call` and return its output
+    pub simulate: bool,
+    /// Time the node may take to estimate, instead of the RPC client's timeout
+    pub rpc_timeout: Option<Duration>,
+    /// Whether to ask the node even when a learned estimate is available
+    pub no_heuristic: bool,
+    /// Whether to also estimate against the pending block and report whether the
+    /// two estimates agree
+    pub confidence: bool,
+    /// Whether the called code uses EIP-1153 transient storage, whose cost only
+    /// execution reveals; forces the RPC path
+    pub has_transient_storage: bool,
+    /// Node to estimate against instead of the configured ones. Only the HTTP
+    /// API's single estimates act on it, through its
+    /// [`RpcOverride`](crate::rpc_override::RpcOverride); the estimator rejects it.
+    pub rpc_url: Option<String>,
+}
+
+impl EstimateGasRequest {
+    /// Resolves the requested block from either `block` or `block_number`
+    pub fn requested_block(&self) -> Result<Option<BlockNumber>, ApiError> {
+        match (&self.block, self.block_number) {
+            (Some(_), Some(_)) => Err(ApiError::BadRequest(
+                "Specify either block or block_number, not both".to_string(),
+            )),
+            (Some(block), None) => parse_block(block).map(Some),
+            (None, Some(number)) => block_number(number).map(Some),
+            (None, None) => Ok(None),
+        }
+    }
+
+    /// Resolves the number of blobs from `blob_count` or the versioned hashes.
+    /// Blob transactions without either are assumed to carry a single blob.
+    pub fn blob_count(&self) -> Result<u64, ApiError> {
+        let hashes = self.blob_versioned_hashes.len() as u64;
+        let count = match self.blob_count {
+            Some(count) if hashes > 0 && count != hashes => {
+                return Err(ApiError::BadRequest(format!(
+                    "blob_count of {count} doesn't match {hashes} blob versioned hashes"
+                )));
+            }
+            Some(count) => count,
+            None => hashes.max(1),
+        };
+        if !(1..=MAX_BLOBS_PER_TX).contains(&count) {
+            return Err(ApiError::BadRequest(format!(
+                "Blob count of {count} must be between 1 and {MAX_BLOBS_PER_TX}"
+            )));
+        }
+        Ok(count)
+    }
+}
+
+impl From<CallRequest> for EstimateGasRequest {
+    fn from(tx: CallRequest) -> Self {
+        Self {
+            tx,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+#[schema(example = json!({
+    "gas_limit": "0xfd20",
+    "method": "rpc+margin",
+    "fees": {
+        "base_fee_per_gas": "0x3b9aca00",
+        "max_priority_fee_per_gas": "0x5f5e100",
+        "max_fee_per_gas": "0x7d2b7500"
+    },
+    "raw_gas_limit": "0xd2f0",
+    "buffer_percent": 20,
+    "pricing_rule": "eip7623",
+    "block_gas_limit": 30000000,
+    "blob_gas_used": null,
+    "max_fee_per_blob_gas": null,
+    "reason": "calldata_present",
+    "estimated_cost_wei": null,
+    "gas_price_used": null,
+    "l2_execution_fee_wei": null,
+    "cached": false
+}))]
+pub struct GasEstimateResponse {
+    pub gas_limit: U256,
+    #[schema(value_type = EstimateMethod)]
+    pub method: String, // "static", "static-blob", "rpc", "rpc-forced", "rpc-cached" or "binary_search", with "+margin" when padded
+    pub fees: Option<FeeEstimate>,
+    /// RPC estimate before the safety buffer was applied
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub raw_gas_limit: Option<U256>,
+    /// Safety buffer applied to the RPC estimate, in percent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub buffer_percent: Option<u64>,
+    /// RPC estimate against the pending block, when `confidence` was requested
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pending_gas_limit: Option<U256>,
+    /// Whether the latest and pending estimates agree, when `confidence` was
+    /// requested. Null when the node couldn't estimate against the pending block.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<bool>)]
+    pub stable: Option<Option<bool>>,
+    /// Calldata pricing the estimate follows: `eip7623` when calldata pays at least
+    /// its floor, which static estimates charge and RPC estimates are raised to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pricing_rule: Option<PricingRule>,
+    /// Gas limit of the latest block, which `raw_gas_limit` or a static estimate
+    /// never exceeds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub block_gas_limit: Option<u64>,
+    /// Blob gas consumed by a blob transaction
+    pub blob_gas_used: Option<u64>,
+    /// Suggested blob fee cap: twice the latest block's blob base fee
+    pub max_fee_per_blob_gas: Option<U256>,
+    /// Block the RPC path estimates against, echoed back when one was requested
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub block: Option<BlockNumber>,
+    /// RPC endpoint that served the estimate, when failover between several is configured
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub endpoint: Option<String>,
+    /// Attempts the RPC estimate took, when transient failures are retried
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rpc_attempts: Option<u32>,
+    /// Time the node took to answer the estimate, including retries, when it was
+    /// asked. Batch items estimated together report the batch's round trip.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rpc_latency_ms: Option<u64>,
+    /// Per-component gas of a static estimate, summing to `gas_limit` before any margin.
+    /// The HTTP API only returns it when asked to with `?detail=true`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub breakdown: Option<GasBreakdown>,
+    /// Why the estimate took the static or RPC path
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<EstimateReason>,
+    /// `gas_limit * gas_price_used`, when `include_cost` was requested
+    pub estimated_cost_wei: Option<U256>,
+    /// `eth_gasPrice` at estimation time, when `include_cost` was requested
+    pub gas_price_used: Option<U256>,
+    /// Cheaper estimate with an access list, when `include_access_list` found one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub access_list_estimate: Option<AccessListEstimate>,
+    /// Whether `from` can afford the transaction, when `check_balance` was requested
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub balance_check: Option<BalanceCheck>,
+    /// Output of the transaction executed with `eth_call`, when `simulate` was requested
+    /// and the call succeeded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub return_data: Option<Bytes>,
+    /// Whether the transaction succeeds at `gas_limit`, when `simulate` was requested
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub simulation_success: Option<bool>,
+    /// Decoded reason the simulated transaction reverted with at `gas_limit`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub revert_reason: Option<String>,
+    /// Fee in wei for posting the transaction's data to L1, on chains configured
+    /// as OP-stack or Arbitrum rollups. Paid on top of `gas_limit` on OP-stack
+    /// chains; on Arbitrum `gas_limit` already includes it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub l1_fee: Option<U256>,
+    /// L1 gas of the transaction's data on OP-stack chains, or the L2 gas that
+    /// pays for it on Arbitrum
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub l1_gas_used: Option<U256>,
+    /// `gas_limit * eth_gasPrice` on OP-stack chains, which together with
+    /// `l1_fee` is what the transaction costs; null elsewhere
+    pub l2_execution_fee_wei: Option<U256>,
+    /// Why `l1_fee` is missing on a rollup
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub l1_fee_warning: Option<String>,
+    /// Zero and non-zero byte counts of `data`, when the transaction has any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub calldata_stats: Option<CalldataStats>,
+    /// Whether the estimate was answered from the estimate cache
+    #[serde(default)]
+    pub cached: bool,
+    /// Chain id of the node, once the service learned it at warmup
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chain_id: Option<u64>,
+    /// Seconds since a cached estimate was computed, also sent as the `Age` header
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_age_secs: Option<u64>,
+}
+
+/// Why a transaction is estimated statically or through the node
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum EstimateReason {
+    /// Value transfer to an address without code
+    EoaTransfer,
+    /// Transfer without value or calldata
+    ZeroValueTransfer,
+    /// Value transfer to a contract, which may run a payable receive or fallback
+    ContractReceiver,
+    CalldataPresent,
+    BlobTx,
+    /// State overrides only take effect when the node executes the transaction
+    StateOverride,
+    /// Block before EIP-2028, when calldata was priced differently
+    HistoricalBlock,
+    /// EIP-7702 set-code transaction, which pays for each authorization and runs
+    /// delegated code
+    Eip7702,
+    /// The client flagged the code as using EIP-1153 transient storage
+    TransientStorage,
+    /// Call to a precompile whose cost follows from the input alone
+    Precompile,
+}
+
+impl EstimateReason {
+    /// Whether transactions with this reason are estimated through the node
+    pub fn requires_rpc(self) -> bool {
+        !matches!(
+            self,
+            Self::EoaTransfer | Self::ZeroValueTransfer | Self::Precompile
+        )
+    }
+}
+
+/// Contribution of each intrinsic cost component to a static estimate
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct GasBreakdown {
+    pub base: u64,
+    pub create: u64,
+    pub calldata_zero: u64,
+    pub calldata_nonzero: u64,
+    pub access_list: u64,
+    /// EIP-7702 cost of the authorization list
+    pub authorization: u64,
+    /// EIP-3860 initcode word cost of a contract creation
+    pub initcode: u64,
+    /// Number of zero calldata bytes, priced in `calldata_zero`
+    pub calldata_zero_bytes: u64,
+    /// Number of non-zero calldata bytes, priced in `calldata_nonzero`
+    pub calldata_nonzero_bytes: u64,
+    /// Gas added so the calldata pays its EIP-7623 floor, on chains with that rule
+    #[serde(default)]
+    pub calldata_floor: u64,
+    /// Gas spent in the precompile the transaction calls
+    #[serde(default)]
+    pub precompile: u64,
+}
+
+impl GasBreakdown {
+    /// Sum of all gas components
+    pub fn total(&self) -> u64 {
+        self.base
+            + self.create
+            + self.calldata_zero
+            + self.calldata_nonzero
+            + self.access_list
+            + self.authorization
+            + self.initcode
+            + self.calldata_floor
+            + self.precompile
+    }
+}
+
+/// EIP-1559 fee parameters suggested for the transaction
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct FeeEstimate {
+    pub base_fee_per_gas: U256,
+    pub max_priority_fee_per_gas: U256,
+    pub max_fee_per_gas: U256,
+}
+
+/// Response for the fee suggestion endpoint
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct FeeSuggestionResponse {
+    pub base_fee_per_gas: U256,
+    pub max_priority_fee_per_gas: U256,
+    pub suggested_max_fee_per_gas: U256,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[schema(example = json!({
+    "error": "Execution reverted: Dai/insufficient-balance",
+    "code": "execution_reverted",
+    "reason": "Dai/insufficient-balance",
+    "data": "0x08c379a0"
+}))]
+pub struct ErrorResponse {
+    pub error: String,
+    /// Stable machine-readable kind of the error, like `upstream_rate_limited`
+    #[serde(default)]
+    pub code: String,
+    /// Decoded revert reason, for reverted executions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// Raw revert data as hex, for reverted executions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<String>,
+}
+
+/// Custom error type for our API
+#[derive(Debug)]
+pub enum ApiError {
+    BadRequest(String),
+    NotFound(String),
+    RequestTimeout(String),
+    PayloadTooLarge(String),
+    /// The client exceeded its rate limit
+    TooManyRequests(String),
+    /// The node didn't answer within the time the client allowed
+    GatewayTimeout(String),
+    /// The node doesn't implement a method the request needs
+    NotImplemented(String),
+    InternalServerError(String),
+    ServiceUnavailable(String),
+    /// The node couldn't be reached or answered with something that isn't JSON-RPC
+    UpstreamUnavailable(String),
+    /// The node's provider is rate limiting us
+    UpstreamRateLimited(String),
+    /// The node didn't answer before the RPC client gave up
+    UpstreamTimeout(String),
+    /// The node refused the transaction itself, e.g. for insufficient funds
+    InvalidTransaction(String),
+    /// The transaction would revert
+    ExecutionReverted {
+        reason: Option<String>,
+        data: Option<String>,
+    },
+    /// The transaction needs more gas than a block can hold
+    ExceedsBlockGasLimit {
+        gas_limit: U256,
+        block_gas_limit: u64,
+    },
+}
+
+impl ApiError {
+    /// HTTP status code for this error
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::RequestTimeout(_) => StatusCode::REQUEST_TIMEOUT,
+            ApiError::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            ApiError::TooManyRequests(_) => StatusCode::TOO_MANY_REQUESTS,
+            ApiError::GatewayTimeout(_) => StatusCode::GATEWAY_TIMEOUT,
+            ApiError::NotImplemented(_) => StatusCode::NOT_IMPLEMENTED,
+            ApiError::InternalServerError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            ApiError::ServiceUnavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::UpstreamUnavailable(_) => StatusCode::BAD_GATEWAY,
+            ApiError::UpstreamRateLimited(_) => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::UpstreamTimeout(_) => StatusCode::GATEWAY_TIMEOUT,
+            ApiError::InvalidTransaction(_) => StatusCode::BAD_REQUEST,
+            ApiError::ExecutionReverted { .. } | ApiError::ExceedsBlockGasLimit { .. } => {
+                StatusCode::UNPROCESSABLE_ENTITY
+            }
+        }
+    }
+
+    /// Stable machine-readable kind of the error, sent as `code` in error bodies
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::BadRequest(_) => "bad_request",
+            ApiError::NotFound(_) => "not_found",
+            ApiError::RequestTimeout(_) => "request_timeout",
+            ApiError::PayloadTooLarge(_) => "payload_too_large",
+            ApiError::TooManyRequests(_) => "too_many_requests",
+            ApiError::GatewayTimeout(_) => "gateway_timeout",
+            ApiError::NotImplemented(_) => "not_implemented",
+            ApiError::InternalServerError(_) => "internal_error",
+            ApiError::ServiceUnavailable(_) => "service_unavailable",
+            ApiError::UpstreamUnavailable(_) => "upstream_unavailable",
+            ApiError::UpstreamRateLimited(_) => "upstream_rate_limited",
+            ApiError::UpstreamTimeout(_) => "upstream_timeout",
+            ApiError::InvalidTransaction(_) => "invalid_transaction",
+            ApiError::ExecutionReverted { .. } => "execution_reverted",
+            ApiError::ExceedsBlockGasLimit { .. } => "exceeds_block_gas_limit",
+        }
+    }
+
+    /// Whether the error means the node failed us, rather than that it answered
+    /// or the client gave up on it
+    pub fn is_upstream_failure(&self) -> bool {
+        matches!(
+            self,
+            ApiError::InternalServerError(_)
+                | ApiError::ServiceUnavailable(_)
+                | ApiError::UpstreamUnavailable(_)
+                | ApiError::UpstreamRateLimited(_)
+                | ApiError::UpstreamTimeout(_)
+        )
+    }
+}
+
+impl std::fmt::Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ApiError::BadRequest(error)
+            | ApiError::NotFound(error)
+            | ApiError::RequestTimeout(error)
+            | ApiError::PayloadTooLarge(error)
+            | ApiError::TooManyRequests(error)
+            | ApiError::GatewayTimeout(error)
+            | ApiError::NotImplemented(error)
+            | ApiError::InternalServerError(error)
+            | ApiError::ServiceUnavailable(error)
+            | ApiError::UpstreamUnavailable(error)
+            | ApiError::UpstreamRateLimited(error)
+            | ApiError::UpstreamTimeout(error)
+            | ApiError::InvalidTransaction(error) => f.write_str(error),
+            ApiError::ExecutionReverted {
+                reason: Some(reason),
+                ..
+            } => write!(f, "execution reverted: {reason}"),
+            ApiError::ExecutionReverted { reason: None, .. } => f.write_str("execution reverted"),
+            ApiError::ExceedsBlockGasLimit {
+                gas_limit,
+                block_gas_limit,
+            } => write!(
+                f,
+                "Transaction needs {gas_limit} gas, more than the block gas limit of \
+                 {block_gas_limit}, so it can't fit in a block"
+            ),
+        }
+    }
+}
+
+impl From<ApiError> for ErrorResponse {
+    fn from(e: ApiError) -> Self {
+        let code = e.code().to_string();
+        match e {
+            ApiError::ExecutionReverted { reason, data } => ErrorResponse {
+                error: "execution reverted".to_string(),
+                code,
+                reason,
+                data,
+            },
+            // Upstream errors can name endpoints or node internals, so clients only
+            // learn what failed while the details go to the logs
+            ApiError::InternalServerError(error)
+            | ApiError::UpstreamUnavailable(error)
+            | ApiError::UpstreamRateLimited(error)
+            | ApiError::UpstreamTimeout(error) => {
+                tracing::warn!(%error, %code, "Upstream or internal error");
+                let summary = error
+                    .split_once(": ")
+                    .map_or(error.as_str(), |(summary, _)| summary);
+                ErrorResponse {
+                    error: summary.to_string(),
+                    code,
+                    reason: None,
+                    data: None,
+                }
+            }
+            e => ErrorResponse {
+                error: e.to_string(),
+                code,
+                reason: None,
+                data: None,
+            },
+        }
+    }
+}
+
+/// Implement IntoResponse for our error type
+impl IntoResponse for ApiError {
+    fn into_response(self) -> axum::response::Response {
+        let rate_limited = matches!(self, ApiError::UpstreamRateLimited(_));
+        let mut response = (self.status_code(), Json(ErrorResponse::from(self))).into_response();
+        if rate_limited {
+            response
+                .headers_mut()
+                .insert("retry-after", HeaderValue::from(UPSTREAM_RETRY_AFTER_SECS));
+        }
+        response
+    }
+}
+
+pub struct GasEstimator<T: Transport> {
+    pub(crate) eth: Eth<T>,
+    pub(crate) buffer_percent: u64,
+    /// Whether value transfer receivers have code, for the latest block
+    code_cache: CodeCache,
+    /// `verificationGasLimit` suggested for user operations
+    pub(crate) verification_gas_limit: u64,
+    /// Intrinsic costs charged by static estimates
+    pub(crate) gas_constants: GasConstants,
+    /// Recent `eth_feeHistory` results
+    pub(crate) fee_history_cache: FeeHistoryCache,
+    /// Outcome of recent RPC estimates and readiness probes
+    pub(crate) health: UpstreamHealth,
+    /// Margin in basis points for requests that don't set `margin_bps`
+    margin_bps: u16,
+    /// Rollup stack whose L1 fee is added to estimates, unset on L1s
+    l2_kind: Option<L2Kind>,
+    /// Node estimates of token calls, when learned estimates are enabled
+    pub(crate) heuristics: Option<HeuristicStore>,
+    /// Difference in percent beyond which latest and pending estimates are unstable
+    confidence_threshold_percent: u64,
+    /// How the chain prices calldata
+    pricing_rule: PricingRule,
+    /// Rejects RPC estimates while the node keeps failing, when enabled
+    pub(crate) circuit_breaker: Option<CircuitBreaker>,
+    /// Whether the node implements `eth_simulateV1`, once probed
+    pub(crate) simulate_v1_support: Mutex<Option<bool>>,
+    /// Latest block gas limit, when fetched from the node rather than assumed
+    pub(crate) block_gas_limit_cache: Option<BlockGasLimitCache>,
+    /// Sends the node estimates of batch items together, when the transport
+    /// supports JSON-RPC batches
+    pub(crate) batch_sender: Option<BatchSender>,
+    /// Chain id reported in estimates, once learned at warmup or configured
+    known_chain_id: OnceLock<u64>,
+    /// Time budget of the `eth_call` search for a gas limit when the node fails to
+    /// estimate one, when enabled
+    binary_search_budget: Option<Duration>,
+    /// Longest calldata estimated, in bytes
+    max_calldata_size: usize,
+}
+
+impl<T: Transport> GasEstimator<T> {
+    pub fn new(transport: T) -> Self {
+        Self {
+            eth: Web3::new(transport).eth(),
+            buffer_percent: 0,
+            code_cache: CodeCache::default(),
+            verification_gas_limit: DEFAULT_VERIFICATION_GAS_LIMIT,
+            gas_constants: GasConstants::default(),
+            fee_history_cache: FeeHistoryCache::default(),
+            health: UpstreamHealth::default(),
+            margin_bps: 0,
+            l2_kind: None,
+            heuristics: None,
+            confidence_threshold_percent: DEFAULT_CONFIDENCE_THRESHOLD_PERCENT,
+            pricing_rule: PricingRule::default(),
+            circuit_breaker: None,
+            simulate_v1_support: Mutex::new(None),
+            block_gas_limit_cache: None,
+            batch_sender: None,
+            known_chain_id: OnceLock::new(),
+            binary_search_budget: None,
+            max_calldata_size: MAX_CALLDATA_SIZE,
+        }
+    }
+
+    /// Transport the estimator sends its RPC requests through
+    pub fn transport(&self) -> &T {
+        self.eth.transport()
+    }
+
+    /// Sets the size and lifetime of the receiver code cache
+    pub fn with_code_cache(mut self, max_entries: usize, ttl: Duration) -> Self {
+        self.code_cache = CodeCache::new(max_entries, ttl);
+        self
+    }
+
+    /// Sets how long fee histories are reused
+    pub fn with_fee_history_cache_ttl(mut self, ttl: Duration) -> Self {
+        self.fee_history_cache = FeeHistoryCache::new(ttl);
+        self
+    }
+
+    /// Sets the default safety buffer applied to RPC estimates
+    pub fn with_buffer_percent(mut self, buffer_percent: u64) -> Result<Self, ApiError> {
+        check_buffer_percent(buffer_percent)?;
+        self.buffer_percent = buffer_percent;
+        Ok(self)
+    }
+
+    /// Sets the safety margin of requests that don't set `margin_bps`
+    pub fn with_margin_bps(mut self, margin_bps: u16) -> Result<Self, ApiError> {
+        check_margin_bps(margin_bps)?;
+        self.margin_bps = margin_bps;
+        Ok(self)
+    }
+
+    /// Sets how far apart latest and pending estimates may be, in percent, before
+    /// `confidence` requests report them unstable
+    pub fn with_confidence_threshold_percent(mut self, threshold_percent: u64) -> Self {
+        self.confidence_threshold_percent = threshold_percent;
+        self
+    }
+
+    /// Fetches the block gas limit estimates are checked against from the node,
+    /// reusing it for `ttl`
+    pub fn with_block_gas_limit_ttl(mut self, ttl: Duration) -> Self {
+        self.block_gas_limit_cache = Some(BlockGasLimitCache::new(ttl));
+        self
+    }
+
+    /// Fails RPC estimates fast with `circuit open` for `open_duration` once
+    /// `failure_threshold` in a row failed upstream, then lets one probe through
+    pub fn with_circuit_breaker(mut self, failure_threshold: u32, open_duration: Duration) -> Self {
+        self.circuit_breaker = Some(CircuitBreaker::new(failure_threshold, open_duration));
+        self
+    }
+
+    /// Sets how the chain prices calldata: with the EIP-7623 floor, static estimates
+    /// charge it and RPC estimates are raised to it
+    pub fn with_pricing_rule(mut self, pricing_rule: PricingRule) -> Self {
+        self.pricing_rule = pricing_rule;
+        self
+    }
+
+    /// Sends the node estimates of [`Self::estimate_gas_batch`] items as one
+    /// JSON-RPC batch through `batch_sender`
+    pub fn with_batch_sender(mut self, batch_sender: BatchSender) -> Self {
+        self.batch_sender = Some(batch_sender);
+        self
+    }
+
+    /// Sets the chain id reported in estimates, for chains whose id was checked
+    /// before the estimator was built
+    pub fn with_chain_id(self, chain_id: u64) -> Self {
+        self.set_known_chain_id(chain_id);
+        self
+    }
+
+    /// Chain id reported in estimates, unset until warmup learned it
+    pub fn known_chain_id(&self) -> Option<u64> {
+        self.known_chain_id.get().copied()
+    }
+
+    pub(crate) fn set_known_chain_id(&self, chain_id: u64) {
+        // The node's chain id doesn't change, so the first one learned is kept
+        let _ = self.known_chain_id.set(chain_id);
+    }
+
+    /// Searches for the gas limit with `eth_call` probes, for at most `budget`,
+    /// when the node fails to estimate a transaction without it reverting
+    pub fn with_binary_search_fallback(mut self, budget: Duration) -> Self {
+        self.binary_search_budget = Some(budget);
+        self
+    }
+
+    /// Sets the longest calldata estimated, in bytes; longer calldata is rejected
+    /// before any other check
+    pub fn with_max_calldata_size(mut self, max_calldata_size: usize) -> Self {
+        self.max_calldata_size = max_calldata_size;
+        self
+    }
+
+    /// Sets the rollup stack of the chain, whose L1 fee is then added to estimates
+    pub fn with_l2_kind(mut self, l2_kind: Option<L2Kind>) -> Self {
+        self.l2_kind = l2_kind;
+        self
+    }
+
+    /// Answers token transfers and approvals from earlier node estimates of the
+    /// same token call, once they are consistent enough
+    pub fn with_heuristics(mut self, config: HeuristicConfig) -> Self {
+        self.heuristics = Some(HeuristicStore::new(config));
+        self
+    }
+
+    /// Sets the intrinsic costs of static estimates, for chains that don't use
+    /// mainnet's
+    pub fn with_gas_constants(mut self, gas_constants: GasConstants) -> Self {
+        self.gas_constants = gas_constants;
+        self
+    }
+
+    /// Sets the `verificationGasLimit` suggested for user operations
+    pub fn with_verification_gas_limit(mut self, verification_gas_limit: u64) -> Self {
+        self.verification_gas_limit = verification_gas_limit;
+        self
+    }
+
+    /// Sets how many RPC estimates in a row must fail upstream before
+    /// [`GasEstimator::readiness`] reports the service as not ready
+    pub fn with_readiness_failure_threshold(mut self, failure_threshold: u32) -> Self {
+        self.health = UpstreamHealth::new(failure_threshold);
+        self
+    }
+
+    /// Number of the node's latest block, for checking that it is reachable
+    pub async fn latest_block_number(&self) -> Result<u64, ApiError> {
+        self.eth
+            .block_number()
+            .await
+            .map(|block| block.as_u64())
+            .map_err(|e| ApiError::InternalServerError(format!("RPC call failed: {e}")))
+    }
+
+    /// Main estimation logic
+    pub async fn estimate_gas(&self, tx: CallRequest) -> Result<GasEstimateResponse, ApiError> {
+        self.estimate(tx.into()).await
+    }
+
+    /// Estimates a transaction padded by `margin_bps`, in place of the estimator's
+    /// default margin. Padded estimates' method ends with `+margin`.
+    pub async fn estimate_gas_with_margin(
+        &self,
+        tx: CallRequest,
+        margin_bps: u16,
+    ) -> Result<GasEstimateResponse, ApiError> {
+        check_margin_bps(margin_bps)?;
+        self.estimate(EstimateGasRequest {
+            margin_bps: Some(margin_bps),
+            ..tx.into()
+        })
+        .await
+    }
+
+    /// Estimates a plain value transfer statically, without looking up whether the
+    /// receiver has code. Transfers to contracts can need more gas than this.
+    pub fn estimate_transfer(
+        &self,
+        from: Option<Address>,
+        to: Address,
+        value: Option<U256>,
+    ) -> GasEstimateResponse {
+        let tx = CallRequest {
+            from,
+            to: Some(to),
+            value,
+            ..Default::default()
+        };
+        let breakdown = self.calculate_static_gas(&tx, None);
+        GasEstimateResponse {
+            gas_limit: breakdown.total().into(),
+            method: "static".to_string(),
+            breakdown: Some(breakdown),
+            ..Default::default()
+        }
+    }
+
+    /// Estimates a transaction with the given request options
+    pub async fn estimate(
+        &self,
+        mut request: EstimateGasRequest,
+    ) -> Result<GasEstimateResponse, ApiError> {
+        Self::fill_transaction_type(&mut request.tx);
+        let reason = self.estimate_reason(&request).await?;
+        self.estimate_with_reason(request, reason).await
+    }
+
+    /// Estimates a transaction along the path chosen by [`Self::estimate_reason`]
+    pub(crate) async fn estimate_with_reason(
+        &self,
+        request: EstimateGasRequest,
+        reason: EstimateReason,
+    ) -> Result<GasEstimateResponse, ApiError> {
+        self.estimate_with_node_estimate(request, reason, None)
+            .await
+    }
+
+    /// Estimates a transaction along the path chosen by [`Self::estimate_reason`],
+    /// using `node_estimate` in place of asking the node when it was fetched already
+    pub(crate) async fn estimate_with_node_estimate(
+        &self,
+        request: EstimateGasRequest,
+        reason: EstimateReason,
+        node_estimate: Option<NodeEstimate>,
+    ) -> Result<GasEstimateResponse, ApiError> {
+        rpc_override::reject_rpc_url(&request)?;
+        let margin_bps = request.margin_bps.unwrap_or(self.margin_bps);
+        check_margin_bps(margin_bps)?;
+        let include_cost = request.include_cost;
+        // Access lists only matter to executed transactions, and the node can't
+        // suggest one against overridden state or delegated code
+        let access_list_request = (request.include_access_list
+            && reason.requires_rpc()
+            && reason != EstimateReason::Eip7702
+            && request.state_overrides.is_none())
+        .then(|| request.clone());
+        let balance_tx = request.check_balance.then(|| request.tx.clone());
+        let l1_fee_tx = self.l2_kind.map(|kind| (kind, request.tx.clone()));
+        let calldata_stats = request
+            .tx
+            .data
+            .as_ref()
+            .map(|data| CalldataStats::new(&data.0));
+        let simulate_request = if request.simulate {
+            simulate::check_simulate(&request, reason)?;
+            Some(request.clone())
+        } else {
+            None
+        };
+
+        let (response, balance_inputs) = tokio::join!(
+            self.estimate_unpadded(request, reason, node_estimate),
+            async {
+                match &balance_tx {
+                    Some(tx) => self.balance_inputs(tx).await,
+                    None => None,
+                }
+            }
+        );
+        let mut response =
+            response.inspect_err(|error| tracing::info!(%error, ?reason, "Estimate failed"))?;
+        if margin_bps > 0 {
+            response.gas_limit = response
+                .gas_limit
+                .saturating_mul((10000 + margin_bps as u64).into())
+                / U256::from(10000);
+            response.method.push_str("+margin");
+        }
+        // Estimates are checked against the block gas limit before padding, and a
+        // transaction that fits shouldn't get a limit no txpool accepts
+        if let Some(block_gas_limit) = response.block_gas_limit {
+            response.gas_limit = response.gas_limit.min(block_gas_limit.into());
+        }
+        // The call runs at the final gas limit, which it may still revert at
+        if let Some(request) = &simulate_request {
+            let simulation = self
+                .simulate(request, response.gas_limit)
+                .await
+                .inspect_err(|error| tracing::info!(%error, ?reason, "Simulation failed"))?;
+            response.simulation_success = Some(simulation.success);
+            response.return_data = simulation.return_data;
+            response.revert_reason = simulation.revert_reason;
+        }
+        response.calldata_stats = calldata_stats;
+        response.chain_id = self.known_chain_id();
+        if let Some((kind, tx)) = &l1_fee_tx {
+            self.add_l1_fee(*kind, tx, &mut response).await;
+        }
+        if let Some(request) = access_list_request {
+            response.access_list_estimate = self
+                .access_list_estimate(&request, reason, response.gas_limit)
+                .await;
+        }
+        if let (Some(tx), Some(inputs)) = (&balance_tx, balance_inputs) {
+            response.balance_check = Some(inputs.check(tx, &response));
+        }
+        if include_cost {
+            // The cost is informational, so a failed lookup leaves it unset
+            response.gas_price_used = self.eth.gas_price().await.ok();
+            response.estimated_cost_wei = response
+                .gas_price_used
+                .map(|gas_price| response.gas_limit.saturating_mul(gas_price));
+        }
+        tracing::info!(
+            method = %response.method,
+            gas_limit = %response.gas_limit,
+            ?reason,
+            "Estimated gas"
+        );
+        Ok(response)
+    }
+
+    /// Estimates a transaction before the safety margin is applied
+    async fn estimate_unpadded(
+        &self,
+        request: EstimateGasRequest,
+        reason: EstimateReason,
+        node_estimate: Option<NodeEstimate>,
+    ) -> Result<GasEstimateResponse, ApiError> {
+        let block = request.requested_block()?;
+        if request.confidence && block.is_some() {
+            return Err(ApiError::BadRequest(
+                "confidence compares the latest and pending blocks and can't be combined with block"
+                    .to_string(),
+            ));
+        }
+        check_initcode_size(&request.tx, block)?;
+        let buffer_percent = request.buffer_percent.unwrap_or(self.buffer_percent);
+        check_buffer_percent(buffer_percent)?;
+
+        let set_code = eip7702::is_set_code_transaction(&request.tx);
+        if set_code {
+            eip7702::check_set_code_transaction(&request)?;
+        }
+        let block_gas_limit = self.block_gas_limit().await;
+
+        // Without signed tuples the node can't execute the authorizations, but a
+        // transaction without calldata only pays its intrinsic cost
+        if reason.requires_rpc() && !(set_code && request.authorization_list.is_empty()) {
+            let is_blob = Self::is_blob_transaction(&request.tx);
+            let blob_count = if is_blob { request.blob_count()? } else { 0 };
+
+            let heuristic_call = self.heuristic_call(&request, block);
+            if !request.no_heuristic
+                && !request.confidence
+                && !request.has_transient_storage
+                && let Some((store, call)) = heuristic_call
+                && let Some(learned) = store.estimate(&call)
+            {
+                return Ok(GasEstimateResponse {
+                    gas_limit: learned.gas_limit.into(),
+                    method: "heuristic".to_string(),
+                    raw_gas_limit: Some(learned.high_water.into()),
+                    block_gas_limit: Some(block_gas_limit),
+                    block,
+                    reason: Some(reason),
+                    ..Default::default()
+                });
+            }
+
+            // Use RPC for complex transactions
+            let estimate_error: fn(web3::Error) -> ApiError = match &request.state_overrides {
+                Some(overrides) => {
+                    state_override::check_state_overrides(overrides, block)?;
+                    state_override::estimate_error
+                }
+                None => revert::estimate_error,
+            };
+            let send_estimate = |block: Option<BlockNumber>| match &request.state_overrides {
+                Some(overrides) => self.estimate_gas_with_overrides(&request.tx, block, overrides),
+                None if set_code => self.estimate_gas_with_authorizations(
+                    &request.tx,
+                    block,
+                    &request.authorization_list,
+                ),
+                None => self.eth.estimate_gas(request.tx.clone(), block),
+            };
+            let circuit_permit = self
+                .circuit_breaker
+                .as_ref()
+                .map(CircuitBreaker::acquire)
+                .transpose()?;
+            let rpc_timeout = request.rpc_timeout;
+            // Not every node estimates against pending state, so a failure only
+            // leaves the comparison out
+            let pending_estimate = async {
+                if !request.confidence {
+                    return None;
+                }
+                let estimate = send_estimate(Some(BlockNumber::Pending));
+                let result = match rpc_timeout {
+                    Some(timeout) => tokio::time::timeout(timeout, estimate).await.ok()?,
+                    None => estimate.await,
+                };
+                result
+                    .inspect_err(|error| tracing::info!(%error, "Pending estimate failed"))
+                    .ok()
+            };
+            let prefetched_latency = node_estimate.as_ref().map(|estimate| estimate.latency);
+            let started = Instant::now();
+            let ((result, upstream), pending_gas_limit) = tokio::join!(
+                transport::track_upstream(async {
+                    if let Some(node_estimate) = node_estimate {
+                        return node_estimate.result;
+                    }
+                    let estimate = send_estimate(block);
+                    match rpc_timeout {
+                        Some(timeout) => tokio::time::timeout(timeout, estimate)
+                            .await
+                            .map_err(|_| rpc_timeout_error(timeout))?
+                            .map_err(estimate_error),
+                        None => estimate.await.map_err(estimate_error),
+                    }
+                }),
+                pending_estimate
+            );
+            let upstream_latency_ms = prefetched_latency
+                .unwrap_or_else(|| started.elapsed())
+                .as_millis() as u64;
+            let logged_endpoint = upstream
+                .endpoint
+                .as_ref()
+                .map(|url| redact_urls(url.as_str()));
+            let mut binary_searched = false;
+            let raw_gas_limit = match result {
+                Ok(gas) => {
+                    self.health.record_success();
+                    if let Some(permit) = circuit_permit {
+                        permit.succeeded();
+                    }
+                    if let Some((store, call)) = heuristic_call {
+                        store.record(call, gas.low_u64());
+                    }
+                    tracing::info!(
+                        %gas,
+                        upstream_latency_ms,
+                        endpoint = ?logged_endpoint,
+                        attempts = ?upstream.attempts,
+                        "Node estimated gas"
+                    );
+                    gas
+                }
+                Err(error) => {
+                    // Reverts are answers from the node, and timeouts the client
+                    // chose say nothing about it
+                    let failed_upstream = error.is_upstream_failure();
+                    if failed_upstream {
+                        self.health.record_failure();
+                    }
+                    // Timeouts the client chose drop the permit without an outcome
+                    if let Some(permit) = circuit_permit {
+                        match error {
+                            _ if failed_upstream => permit.failed(),
+                            ApiError::GatewayTimeout(_) => {}
+                            _ => permit.succeeded(),
+                        }
+                    }
+                    tracing::warn!(
+                        %error,
+                        upstream_latency_ms,
+                        endpoint = ?logged_endpoint,
+                        attempts = ?upstream.attempts,
+                        "Node estimate failed"
+                    );
+                    let searched = match self.binary_search_budget {
+                        Some(budget) if binary_search::is_estimation_bug(&error) => {
+                            let intrinsic = self.calculate_static_gas(&request.tx, block).total();
+                            let cap = request.tx.gas.map_or(block_gas_limit, |gas| {
+                                gas.min(block_gas_limit.into()).low_u64()
+                            });
+                            self.binary_search_gas(&request, intrinsic, cap, budget)
+                                .await
+                        }
+                        _ => None,
+                    };
+                    match searched {
+                        Some(gas) => {
+                            binary_searched = true;
+                            gas.into()
+                        }
+                        None => return Err(error),
+                    }
+                }
+            };
+
+            let stable = request.confidence.then(|| {
+                pending_gas_limit.map(|pending| {
+                    estimates_agree(raw_gas_limit, pending, self.confidence_threshold_percent)
+                })
+            });
+            // Unstable estimates are padded from whichever block needs more gas
+            let raw_gas_limit = match (stable, pending_gas_limit) {
+                (Some(Some(false)), Some(pending)) => raw_gas_limit.max(pending),
+                _ => raw_gas_limit,
+            };
+            // Nodes that predate Prague estimate without the floor the chain charges
+            let raw_gas_limit = match self.pricing_rule {
+                PricingRule::Eip7623 => {
+                    let breakdown = self.calculate_static_gas(&request.tx, block);
+                    let floor = calldata_floor(
+                        self.gas_constants.tx_base,
+                        breakdown.calldata_zero_bytes,
+                        breakdown.calldata_nonzero_bytes,
+                    );
+                    raw_gas_limit.max(floor.into())
+                }
+                PricingRule::Eip2028 => raw_gas_limit,
+            };
+            check_block_gas_limit(raw_gas_limit, block_gas_limit)?;
+
+            // Static estimates are exact intrinsic costs, so only RPC results are buffered
+            let gas_limit =
+                raw_gas_limit.saturating_mul((100 + buffer_percent).into()) / U256::from(100);
+
+            let (blob_gas_used, max_fee_per_blob_gas) = if is_blob {
+                self.blob_gas(blob_count).await?
+            } else {
+                (None, None)
+            };
+
+            // Forced estimates say so, as the transaction alone would have been static,
+            // and so do limits found by probing after the node failed to estimate
+            let method = match reason {
+                _ if binary_searched => "binary_search",
+                EstimateReason::TransientStorage => "rpc-forced",
+                _ => "rpc",
+            };
+            return Ok(GasEstimateResponse {
+                gas_limit,
+                method: method.to_string(),
+                raw_gas_limit: Some(raw_gas_limit),
+                buffer_percent: Some(buffer_percent),
+                pending_gas_limit,
+                stable,
+                pricing_rule: Some(self.pricing_rule),
+                block_gas_limit: Some(block_gas_limit),
+                blob_gas_used,
+                max_fee_per_blob_gas,
+                block,
+                endpoint: upstream.endpoint.map(|url| url.to_string()),
+                rpc_attempts: upstream.attempts,
+                rpc_latency_ms: Some(upstream_latency_ms),
+                reason: Some(reason),
+                ..Default::default()
+            });
+        }
+
+        // Use static calculation for simple transactions
+        let mut breakdown = self.calculate_static_gas(&request.tx, block);
+        if set_code {
+            breakdown.authorization = request.authorization_count()? * PER_EMPTY_ACCOUNT_COST;
+        }
+        if reason == EstimateReason::Precompile
+            && let Some(gas) = precompile::call_gas(&request.tx)
+        {
+            breakdown.precompile = gas;
+            // Execution counts towards the EIP-7623 floor
+            breakdown.calldata_floor = breakdown.calldata_floor.saturating_sub(gas);
+        }
+        check_block_gas_limit(breakdown.total().into(), block_gas_limit)?;
+        let mut response = GasEstimateResponse {
+            gas_limit: breakdown.total().into(),
+            method: "static".to_string(),
+            breakdown: Some(breakdown),
+            pricing_rule: Some(self.pricing_rule),
+            block_gas_limit: Some(block_gas_limit),
+            block,
+            reason: Some(reason),
+            // Static estimates don't depend on state
+            stable: request.confidence.then_some(Some(true)),
+            ..Default::default()
+        };
+        if Self::is_blob_transaction(&request.tx) {
+            (response.blob_gas_used, response.max_fee_per_blob_gas) =
+                self.blob_gas(request.blob_count()?).await?;
+            response.method = "static-blob".to_string();
+        }
+        Ok(response)
+    }
+
+    /// Blob gas of `blob_count` blobs, and the suggested blob fee cap: twice the
+    /// latest block's blob base fee. The blob gas is known without the node, so
+    /// the fee cap is left out rather than failing the estimate when the node
+    /// can't be reached; chains without blobs are still an error.
+    async fn blob_gas(&self, blob_count: u64) -> Result<(Option<u64>, Option<U256>), ApiError> {
+        let max_fee_per_blob_gas = match self.blob_base_fee().await {
+            Ok(blob_base_fee) => Some(blob_base_fee.saturating_mul(2.into())),
+            Err(error @ ApiError::ServiceUnavailable(_)) => return Err(error),
+            Err(error) => {
+                tracing::warn!(%error, "Failed to fetch the blob base fee");
+                None
+            }
+        };
+        Ok((Some(blob_count * GAS_PER_BLOB), max_fee_per_blob_gas))
+    }
+
+    /// Estimates every transaction independently, preserving input order, with at
+    /// most `concurrency` estimates in flight. Static estimates may still need a code
+    /// lookup, so every item goes through the same queue. A failing item doesn't
+    /// abort the others.
+    ///
+    /// With a [batch sender](Self::with_batch_sender), the paths are chosen first and
+    /// the node estimates of items that need one are fetched in a single JSON-RPC batch.
+    pub async fn estimate_gas_batch(
+        &self,
+        txs: Vec<impl Into<EstimateGasRequest>>,
+        concurrency: usize,
+    ) -> Vec<Result<GasEstimateResponse, ApiError>> {
+        let concurrency = concurrency.max(1);
+        let mut results: Vec<Option<Result<GasEstimateResponse, ApiError>>> =
+            txs.iter().map(|_| None).collect();
+
+        let Some(batch_sender) = &self.batch_sender else {
+            let estimates: Vec<_> = stream::iter(txs.into_iter().enumerate())
+                .map(|(index, tx)| async move { (index, self.estimate(tx.into()).await) })
+                .buffer_unordered(concurrency)
+                .collect()
+                .await;
+            for (index, result) in estimates {
+                results[index] = Some(result);
+            }
+            return results.into_iter().flatten().collect();
+        };
+
+        let requests: Vec<EstimateGasRequest> = txs
+            .into_iter()
+            .map(|tx| {
+                let mut request = tx.into();
+                Self::fill_transaction_type(&mut request.tx);
+                request
+            })
+            .collect();
+        let reasons: Vec<_> = stream::iter(0..requests.len())
+            .map(|index| self.estimate_reason(&requests[index]))
+            .buffered(concurrency)
+            .collect()
+            .await;
+        let node_estimates = self
+            .prefetch_node_estimates(batch_sender, &requests, &reasons)
+            .await;
+
+        let estimates: Vec<_> = stream::iter(
+            requests
+                .into_iter()
+                .zip(reasons)
+                .zip(node_estimates)
+                .enumerate(),
+        )
+        .map(|(index, ((request, reason), node_estimate))| async move {
+            let result = match reason {
+                Ok(reason) => {
+                    self.estimate_with_node_estimate(request, reason, node_estimate)
+                        .await
+                }
+                Err(error) => Err(error),
+            };
+            (index, result)
+        })
+        .buffer_unordered(concurrency)
+        .collect()
+        .await;
+        for (index, result) in estimates {
+            results[index] = Some(result);
+        }
+
+        results.into_iter().flatten().collect()
+    }
+
+    /// Fetches fee data from the pending block and the node's priority fee suggestion.
+    /// Returns `None` if the node can't provide it (e.g. pre-London chains or RPC failures).
+    pub async fn estimate_fees(&self) -> Option<FeeEstimate> {
+        self.fetch_fees(BlockNumber::Pending).await.ok()
+    }
+
+    /// Suggests EIP-1559 fees based on the latest block
+    pub async fn fee_suggestion(&self) -> Result<FeeSuggestionResponse, ApiError> {
+        let fees = self.fetch_fees(BlockNumber::Latest).await?;
+        Ok(FeeSuggestionResponse {
+            base_fee_per_gas: fees.base_fee_per_gas,
+            max_priority_fee_per_gas: fees.max_priority_fee_per_gas,
+            suggested_max_fee_per_gas: fees.max_fee_per_gas,
+        })
+    }
+
+    /// Fetches the base fee of the given block and the priority fee suggestion,
+    /// computing `max_fee_per_gas = 2 * base_fee + priority_fee`
+    pub(crate) async fn fetch_fees(&self, block: BlockNumber) -> Result<FeeEstimate, ApiError> {
+        let rpc_error = |e| ApiError::InternalServerError(format!("RPC call failed: {e}"));
+
+        let base_fee_per_gas = self
+            .eth
+            .block(BlockId::Number(block))
+            .await
+            .map_err(rpc_error)?
+            .and_then(|block| block.base_fee_per_gas)
+            .ok_or_else(|| {
+                ApiError::ServiceUnavailable(
+                    "Node did not return EIP-1559 fee fields (pre-London chain?)".to_string(),
+                )
+            })?;
+
+        let max_priority_fee_per_gas = match self.max_priority_fee_per_gas().await {
+            Ok(fee) => fee,
+            // Not every node supports eth_maxPriorityFeePerGas
+            Err(_) => self
+                .eth
+                .gas_price()
+                .await
+                .map_err(rpc_error)?
+                .saturating_sub(base_fee_per_gas),
+        };
+
+        Ok(FeeEstimate {
+            base_fee_per_gas,
+            max_priority_fee_per_gas,
+            max_fee_per_gas: base_fee_per_gas
+                .saturating_mul(2.into())
+                .saturating_add(max_priority_fee_per_gas),
+        })
+    }
+
+    /// Calls `eth_maxPriorityFeePerGas`, which has no typed binding in web3
+    pub(crate) fn max_priority_fee_per_gas(&self) -> CallFuture<U256, T::Out> {
+        CallFuture::new(
+            self.eth
+                .transport()
+                .execute("eth_maxPriorityFeePerGas", vec![]),
+        )
+    }
+
+    /// Computes the blob base fee from the latest block's `excessBlobGas`.
+    /// The typed `Block` has no blob fields, so the block is fetched as raw JSON.
+    async fn blob_base_fee(&self) -> Result<U256, ApiError> {
+        let block: serde_json::Value = CallFuture::new(self.eth.transport().execute(
+            "eth_getBlockByNumber",
+            vec![serde_json::json!("latest"), serde_json::json!(false)],
+        ))
+        .await
+        .map_err(|e| ApiError::InternalServerError(format!("RPC call failed: {e}")))?;
+
+        let excess_blob_gas: U256 = block
+            .get("excessBlobGas")
+            .and_then(|value| serde_json::from_value(value.clone()).ok())
+            .ok_or_else(|| {
+                ApiError::ServiceUnavailable(
+                    "Node did not return excessBlobGas (pre-Cancun chain?)".to_string(),
+                )
+            })?;
+
+        Ok(fake_exponential(
+            MIN_BASE_FEE_PER_BLOB_GAS.into(),
+            excess_blob_gas,
+            BLOB_BASE_FEE_UPDATE_FRACTION.into(),
+        ))
+    }
+
+    /// Decides whether the transaction must be estimated by the node, and why.
+    /// Value transfers without calldata look up whether the receiver has code,
+    /// since a payable receive or fallback function consumes gas.
+    ///
+    /// Transient storage always needs the node: EIP-1153's `TSTORE` and `TLOAD`
+    /// cost 100 gas each, but how many run depends on the execution trace, which
+    /// nothing in the transaction reveals. Clients flag it with
+    /// `has_transient_storage`, which skips every static shortcut.
+    pub(crate) async fn estimate_reason(
+        &self,
+        request: &EstimateGasRequest,
+    ) -> Result<EstimateReason, ApiError> {
+        let tx = &request.tx;
+        // Every estimate starts here, so oversized calldata is rejected before
+        // any of it is scanned or sent to the node
+        self.check_calldata_size(tx)?;
+        self.validate(tx)?;
+        if request.check_balance && tx.from.is_none() {
+            return Err(ApiError::BadRequest(
+                "check_balance needs the from address".to_string(),
+            ));
+        }
+        if request.state_overrides.is_some() {
+            return Ok(EstimateReason::StateOverride);
+        }
+        if request.has_transient_storage {
+            return Ok(EstimateReason::TransientStorage);
+        }
+        // Blob gas follows from the blob count, so only blob transactions that
+        // don't give one need the node to estimate them
+        if Self::is_blob_transaction(tx) && (request.blob_count.is_none() || tx.to.is_none()) {
+            return Ok(EstimateReason::BlobTx);
+        }
+        if eip7702::is_set_code_transaction(tx) {
+            return Ok(EstimateReason::Eip7702);
+        }
+        // Precompiles don't run code, and some cost a fixed amount plus one per
+        // word of input. Their prices changed over forks, so only the current ones
+        // are charged.
+        if matches!(
+            request.requested_block()?,
+            None | Some(BlockNumber::Latest | BlockNumber::Pending)
+        ) && precompile::call_gas(tx).is_some()
+        {
+            return Ok(EstimateReason::Precompile);
+        }
+        // EIP-2929: storage and account access is priced by whether it is cold (2100
+        // gas for an SLOAD) or warm (100), which depends on what the code touches at
+        // runtime. Access list entries are pre-warmed for an intrinsic cost that is
+        // charged statically, but whether that pays off, like every other execution
+        // cost, is only known by running the calldata.
+        if tx.data.as_ref().is_some_and(|data| !data.0.is_empty()) {
+            return Ok(EstimateReason::CalldataPresent);
+        }
+        if Self::predates_eip2028(request) {
+            return Ok(EstimateReason::HistoricalBlock);
+        }
+        if tx.value.is_none_or(|value| value.is_zero()) {
+            return Ok(EstimateReason::ZeroValueTransfer);
+        }
+
+        // `validate` rejects contract creations without initcode, so only calls
+        // are left
+        match tx.to {
+            Some(to) if self.has_code(to, request.requested_block()?).await? => {
+                Ok(EstimateReason::ContractReceiver)
+            }
+            _ => Ok(EstimateReason::EoaTransfer),
+        }
+    }
+
+    /// Whether the address has code at the given block. Lookups against the
+    /// latest block are cached.
+    async fn has_code(
+        &self,
+        address: Address,
+        block: Option<BlockNumber>,
+    ) -> Result<bool, ApiError> {
+        if block.is_none()
+            && let Some(has_code) = self.code_cache.get(&address)
+        {
+            return Ok(has_code);
+        }
+
+        let has_code = !self
+            .eth
+            .code(address, block)
+            .await
+            .map_err(|e| ApiError::InternalServerError(format!("RPC call failed: {e}")))?
+            .0
+            .is_empty();
+        if block.is_none() {
+            self.code_cache.insert(address, has_code);
+        }
+        Ok(has_code)
+    }
+
+    /// Calldata was priced differently before EIP-2028, so historical
+    /// estimates for those blocks can't use the static calculator
+    fn predates_eip2028(request: &EstimateGasRequest) -> bool {
+        match request.requested_block() {
+            Ok(Some(BlockNumber::Earliest)) => true,
+            Ok(Some(BlockNumber::Number(block))) => block < EIP2028_ACTIVATION_BLOCK.into(),
+            _ => false,
+        }
+    }
+
+    /// Rejects transactions no node would accept, naming the offending field, so
+    /// they fail with a `400` instead of an upstream error
+    pub fn validate(&self, tx: &CallRequest) -> Result<(), ApiError> {
+        let has_data = tx.data.as_ref().is_some_and(|data| !data.0.is_empty());
+        if tx.to.is_none() && !has_data {
+            return Err(ApiError::BadRequest(
+                "to is missing and data is empty: a contract creation needs initcode".to_string(),
+            ));
+        }
+        if let Some(gas) = tx.gas
+            && gas < self.gas_constants.tx_base.into()
+        {
+            return Err(ApiError::BadRequest(format!(
+                "gas of {gas} is below the {} gas every transaction costs",
+                self.gas_constants.tx_base
+            )));
+        }
+        if let (Some(max_fee), Some(max_priority_fee)) =
+            (tx.max_fee_per_gas, tx.max_priority_fee_per_gas)
+            && max_fee < max_priority_fee
+        {
+            return Err(ApiError::BadRequest(format!(
+                "max_fee_per_gas of {max_fee} is below max_priority_fee_per_gas of {max_priority_fee}"
+            )));
+        }
+        if let Some(transaction_type) = tx.transaction_type
+            && transaction_type > SET_CODE_TX_TYPE.into()
+        {
+            return Err(ApiError::BadRequest(format!(
+                "transaction_type of {transaction_type:#x} is unknown, expected 0x0 to {SET_CODE_TX_TYPE:#x}"
+            )));
+        }
+        if let Some(gas_price) = tx.gas_price
+            && (tx.max_fee_per_gas.is_some() || tx.max_priority_fee_per_gas.is_some())
+        {
+            return Err(ApiError::BadRequest(format!(
+                "gas_price of {gas_price} can't be combined with max_fee_per_gas or \
+                 max_priority_fee_per_gas"
+            )));
+        }
+        if tx.from == Some(Address::zero())
+            && let Some(value) = tx.value
+            && !value.is_zero()
+        {
+            return Err(ApiError::BadRequest(format!(
+                "from is the zero address, which can't send a value of {value}"
+            )));
+        }
+        Ok(())
+    }
+
+    /// Infers the EIP-2718 type from the fields a transaction sets: EIP-1559 when it
+    /// sets either fee cap, EIP-2930 when it carries an access list, legacy otherwise.
+    /// Blob and set-code transactions can't be told apart by their fields and must
+    /// set their type.
+    pub fn infer_transaction_type(tx: &CallRequest) -> u8 {
+        if tx.max_fee_per_gas.is_some() || tx.max_priority_fee_per_gas.is_some() {
+            EIP1559_TX_TYPE
+        } else if tx.access_list.as_ref().is_some_and(|list| !list.is_empty()) {
+            ACCESS_LIST_TX_TYPE
+        } else {
+            LEGACY_TX_TYPE
+        }
+    }
+
+    /// Sets the inferred type on transactions that don't set one, so every path
+    /// routes on it the same way. Legacy transactions are left untyped, which nodes
+    /// read the same way, so that an access list suggested later doesn't contradict
+    /// their type.
+    pub(crate) fn fill_transaction_type(tx: &mut CallRequest) {
+        if tx.transaction_type.is_none() {
+            let inferred = Self::infer_transaction_type(tx);
+            if inferred != LEGACY_TX_TYPE {
+                tx.transaction_type = Some(inferred.into());
+            }
+        }
+    }
+
+    /// Check if this is a blob transaction (EIP-4844)
+    fn is_blob_transaction(tx: &CallRequest) -> bool {
+        tx.transaction_type == Some(BLOB_TX_TYPE.into())
+    }
+
+    /// Rejects calldata longer than the [configured limit](Self::with_max_calldata_size)
+    fn check_calldata_size(&self, tx: &CallRequest) -> Result<(), ApiError> {
+        match &tx.data {
+            Some(data) if data.0.len() > self.max_calldata_size => {
+                Err(ApiError::BadRequest(format!(
+                    "calldata exceeds maximum allowed size: {} bytes, limit is {}",
+                    data.0.len(),
+                    self.max_calldata_size
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Static gas calculation for simple transactions, itemized per component
+    fn calculate_static_gas(&self, tx: &CallRequest, block: Option<BlockNumber>) -> GasBreakdown {
+        debug_assert!(
+            self.check_calldata_size(tx).is_ok(),
+            "calldata size is checked before pricing it"
+        );
+        static_estimator::static_gas_breakdown(tx, &self.gas_constants, self.pricing_rule, block)
+    }
+}
+
+/// Rejects contract creations whose initcode exceeds the EIP-3860 limit
+fn check_initcode_size(tx: &CallRequest, block: Option<BlockNumber>) -> Result<(), ApiError> {
+    if tx.to.is_none()
+        && !predates_eip3860(block)
+        && let Some(data) = &tx.data
+        && data.0.len() > MAX_INITCODE_SIZE
+    {
+        return Err(ApiError::BadRequest(format!(
+            "Initcode of {} bytes exceeds the EIP-3860 limit of {MAX_INITCODE_SIZE} bytes",
+            data.0.len()
+        )));
+    }
+    Ok(())
+}
+
+/// Rejects safety margins above the allowed maximum
+fn check_margin_bps(margin_bps: u16) -> Result<(), ApiError> {
+    if margin_bps > MAX_MARGIN_BPS {
+        return Err(ApiError::BadRequest(format!(
+            "Margin of {margin_bps} bps exceeds maximum of {MAX_MARGIN_BPS} bps"
+        )));
+    }
+    Ok(())
+}
+
+/// Rejects safety buffers above the allowed maximum
+fn check_buffer_percent(buffer_percent: u64) -> Result<(), ApiError> {
+    if buffer_percent > MAX_BUFFER_PERCENT {
+        return Err(ApiError::BadRequest(format!(
+            "Buffer of {buffer_percent}% exceeds maximum of {MAX_BUFFER_PERCENT}%"
+        )));
+    }
+    Ok(())
+}
+
+/// Error of an estimate the node didn't answer within the client's timeout
+pub(crate) fn rpc_timeout_error(timeout: Duration) -> ApiError {
+    ApiError::GatewayTimeout(format!(
+        "Node didn't estimate within the requested {} ms",
+        timeout.as_millis()
+    ))
+}
+
+/// Rejects estimates no block could hold, which every node would refuse to pool
+fn check_block_gas_limit(gas: U256, block_gas_limit: u64) -> Result<(), ApiError> {
+    if gas > block_gas_limit.into() {
+        return Err(ApiError::ExceedsBlockGasLimit {
+            gas_limit: gas,
+            block_gas_limit,
+        });
+    }
+    Ok(())
+}
+
+/// Whether the pending estimate is within `threshold_percent` of the latest one
+fn estimates_agree(latest: U256, pending: U256, threshold_percent: u64) -> bool {
+    let difference = if latest > pending {
+        latest - pending
+    } else {
+        pending - latest
+    };
+    difference.saturating_mul(100.into()) <= latest.saturating_mul(threshold_percent.into())
+}
+
+/// Approximates `factor * e ** (numerator / denominator)` using the Taylor
+/// expansion from EIP-4844
+fn fake_exponential(factor: U256, numerator: U256, denominator: U256) -> U256 {
+    let mut output = U256::zero();
+    let mut accumulator = factor.saturating_mul(denominator);
+    let mut i = U256::one();
+    while !accumulator.is_zero() {
+        output = output.saturating_add(accumulator);
+        accumulator = accumulator.saturating_mul(numerator) / denominator.saturating_mul(i);
+        i += U256::one();
+    }
+    output / denominator
+}
+
+/// Parses a block tag or a hex or decimal block number
+fn parse_block(value: &str) -> Result<BlockNumber, ApiError> {
+    let invalid = || ApiError::BadRequest(format!("Invalid block parameter: {value}"));
+    match value {
+        "latest" => Ok(BlockNumber::Latest),
+        "pending" => Ok(BlockNumber::Pending),
+        "earliest" => Ok(BlockNumber::Earliest),
+        "safe" => Ok(BlockNumber::Safe),
+        "finalized" => Ok(BlockNumber::Finalized),
+        _ => {
+            let number = match value.strip_prefix("0x") {
+                Some(hex) => u64::from_str_radix(hex, 16),
+                None => value.parse(),
+            };
+            number.map(BlockNumber::from).map_err(|_| invalid())
+        }
+    }
+}
+
+/// Converts a requested block number into a web3 block parameter
+fn block_number(block: U256) -> Result<BlockNumber, ApiError> {
+    if block > U64::MAX.as_u64().into() {
+        return Err(ApiError::BadRequest(format!(
+            "Block number {block} is out of range"
+        )));
+    }
+    Ok(BlockNumber::Number(block.as_u64().into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use web3::{
+        transports::test::TestTransport,
+        types::{AccessListItem, Address, Block, Bytes, H256, U256},
+    };
+
+    /// Helper function to create a mock transport that returns fixed gas values
+    fn mock_transport() -> impl Transport {
+        let mut mock = TestTransport::default();
+        mock.set_response("0x5208".into()); // 21000 gas
+        mock
+    }
+
+    /// Helper function to create a block JSON response with the given base fee
+    fn block_response(base_fee_per_gas: Option<U256>) -> serde_json::Value {
+        serde_json::to_value(Block::<H256> {
+            base_fee_per_gas,
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    /// Helper function to create an address
+    fn address_from() -> Address {
+        "0xc0ffee254729296a45a3885639AC7E10F9d54979"
+            .parse()
+            .unwrap()
+    }
+
+    /// Helper function to create an address
+    fn address_to() -> Address {
+        "0xc0ffee254729296a45a3885639AC7E10F9d54979"
+            .parse()
+            .unwrap()
+    }
+
+    /// Records `address_to()` as an address without code, so value transfers to it
+    /// are estimated statically without a code lookup
+    fn eoa_receiver<T: Transport>(estimator: GasEstimator<T>) -> GasEstimator<T> {
+        estimator.code_cache.insert(address_to(), false);
+        estimator
+    }
+
+    /// Helper function to create a simple transfer request
+    fn simple_transfer_request() -> CallRequest {
+        CallRequest {
+            from: Some(address_from()),
+            to: Some(address_to()),
+            value: Some(U256::one()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_calculate_static_gas_simple_transfer() {
+        let estimator = GasEstimator::new(mock_transport());
+        let tx = simple_transfer_request();
+
+        let gas = estimator.calculate_static_gas(&tx, None).total();
+        assert_eq!(gas, GAS_TX_BASE);
+    }
+
+    #[test]
+    fn test_calculate_static_gas_contract_creation() {
+        let estimator = GasEstimator::new(mock_transport());
+        let tx = CallRequest {
+            to: None, // Contract creation
+            ..Default::default()
+        };
+
+        let gas = estimator.calculate_static_gas(&tx, None).total();
+        assert_eq!(gas, GAS_TX_BASE + GAS_TX_CREATE);
+    }
+
+    #[test]
+    fn test_calculate_static_gas_with_data() {
+        let estimator = GasEstimator::new(mock_transport());
+        let tx = CallRequest {
+            to: Some(address_to()),
+            data: Some(Bytes::from(vec![0x01, 0x00, 0x02])), // 2 non-zero, 1 zero byte
+            ..Default::default()
+        };
+
+        let breakdown = estimator.calculate_static_gas(&tx, None);
+        assert_eq!(breakdown.calldata_zero_bytes, 1);
+        assert_eq!(breakdown.calldata_nonzero_bytes, 2);
+        assert_eq!(breakdown.calldata_zero, GAS_TX_DATA_ZERO);
+        assert_eq!(breakdown.calldata_nonzero, GAS_TX_DATA_NON_ZERO * 2);
+        assert_eq!(
+            breakdown.total(),
+            GAS_TX_BASE + (GAS_TX_DATA_NON_ZERO * 2) + GAS_TX_DATA_ZERO
+        );
+    }
+
+    #[test]
+    fn test_calculate_static_gas_custom_constants() {
+        let estimator = GasEstimator::new(mock_transport()).with_gas_constants(GasConstants {
+            tx_base: 53000,
+            tx_data_non_zero: 8,
+            ..Default::default()
+        });
+        let tx = CallRequest {
+            to: None,
+            data: Some(Bytes::from(vec![0x01, 0x00, 0x02])),
+            ..Default::default()
+        };
+
+        let breakdown = estimator.calculate_static_gas(&tx, None);
+        assert_eq!(breakdown.base, 53000);
+        assert_eq!(breakdown.create, GAS_TX_CREATE);
+        assert_eq!(breakdown.calldata_zero, GAS_TX_DATA_ZERO);
+        assert_eq!(breakdown.calldata_nonzero, 16);
+    }
+
+    #[test]
+    fn test_calculate_static_gas_with_access_list() {
+        let estimator = GasEstimator::new(mock_transport());
+        let tx = CallRequest {
+            to: Some(address_to()),
+            access_list: Some(vec![
+                AccessListItem {
+                    address: address_from(),
+                    storage_keys: vec![H256::zero(), H256::repeat_byte(0x01)],
+                },
+                AccessListItem {
+                    address: address_to(),
+                    storage_keys: vec![H256::repeat_byte(0x02)],
+                },
+            ]),
+            ..Default::default()
+        };
+
+        let gas = estimator.calculate_static_gas(&tx, None).total();
+        assert_eq!(
+            gas,
+            GAS_TX_BASE + (GAS_ACCESS_LIST_ADDRESS * 2) + (GAS_ACCESS_LIST_STORAGE_KEY * 3)
+        );
+        assert_eq!(gas, 21000 + 2 * 2400 + 3 * 1900);
+    }
+
+    /// Helper function to create a contract creation with `len` non-zero initcode bytes
+    fn creation_request(len: usize) -> CallRequest {
+        CallRequest {
+            to: None,
+            data: Some(Bytes::from(vec![0x01; len])),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_calculate_static_gas_initcode_words() {
+        let estimator = GasEstimator::new(mock_transport());
+
+        let gas = estimator.calculate_static_gas(&creation_request(32), None);
+        assert_eq!(gas.initcode, 2);
+        assert_eq!(gas.total(), 21000 + 32000 + 32 * 16 + 2);
+
+        let gas = estimator.calculate_static_gas(&creation_request(33), None);
+        assert_eq!(gas.initcode, 4);
+        assert_eq!(gas.total(), 21000 + 32000 + 33 * 16 + 4);
+
+        let gas = estimator.calculate_static_gas(&creation_request(MAX_INITCODE_SIZE), None);
+        assert_eq!(gas.initcode, 3072);
+        assert_eq!(gas.total(), 842504);
+    }
+
+    #[test]
+    fn test_calculate_static_gas_initcode_before_shanghai() {
+        let estimator = GasEstimator::new(mock_transport());
+        let block = Some(BlockNumber::Number((EIP3860_ACTIVATION_BLOCK - 1).into()));
+
+        let gas = estimator.calculate_static_gas(&creation_request(33), block);
+        assert_eq!(gas.initcode, 0);
+
+        let block = Some(BlockNumber::Number(EIP3860_ACTIVATION_BLOCK.into()));
+        let gas = estimator.calculate_static_gas(&creation_request(33), block);
+        assert_eq!(gas.initcode, 4);
+    }
+
+    #[test]
+    fn test_initcode_size_not_limited_before_shanghai() {
+        let tx = creation_request(MAX_INITCODE_SIZE + 1);
+        let block = Some(BlockNumber::Number((EIP3860_ACTIVATION_BLOCK - 1).into()));
+        assert!(check_initcode_size(&tx, block).is_ok());
+        assert!(check_initcode_size(&tx, None).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_estimate_rejects_oversized_initcode() {
+        let estimator = GasEstimator::new(TestTransport::default());
+        let result = estimator
+            .estimate_gas(creation_request(MAX_INITCODE_SIZE + 1))
+            .await;
+
+        let Err(ApiError::BadRequest(message)) = result else {
+            panic!("expected a bad request");
+        };
+        assert!(message.contains("EIP-3860"));
+    }
+
+    #[tokio::test]
+    async fn test_calldata_size_limit() {
+        let call = |len: usize| CallRequest {
+            to: Some(address_to()),
+            data: Some(Bytes::from(vec![0x01; len])),
+            ..Default::default()
+        };
+        let mut transport = TestTransport::default();
+        transport.add_response("0x2dc6c0".into()); // 3000000 gas
+        let estimator = GasEstimator::new(transport.clone());
+
+        // Calldata at the limit is estimated
+        let result = estimator
+            .estimate_gas(call(MAX_CALLDATA_SIZE))
+            .await
+            .unwrap();
+        assert_eq!(result.method, "rpc");
+        transport.assert_request(
+            "eth_estimateGas",
+            &[serde_json::to_value(call(MAX_CALLDATA_SIZE))
+                .unwrap()
+                .to_string()],
+        );
+
+        // One byte over is rejected before reaching the node
+        let result = estimator.estimate_gas(call(MAX_CALLDATA_SIZE + 1)).await;
+        let Err(ApiError::BadRequest(message)) = result else {
+            panic!("expected a bad request");
+        };
+        assert!(message.starts_with("calldata exceeds maximum allowed size"));
+        transport.assert_no_more_requests();
+
+        // Empty calldata is a plain transfer
+        let result = estimator.estimate_gas(call(0)).await.unwrap();
+        assert_eq!(result.method, "static");
+        assert_eq!(result.gas_limit, 21_000.into());
+    }
+
+    #[tokio::test]
+    async fn test_configured_calldata_size_limit() {
+        let estimator = GasEstimator::new(TestTransport::default()).with_max_calldata_size(4);
+        let call = |len: usize| CallRequest {
+            to: Some(address_to()),
+            data: Some(Bytes::from(vec![0x01; len])),
+            ..Default::default()
+        };
+
+        assert!(estimator.check_calldata_size(&call(4)).is_ok());
+        let result = estimator.estimate_gas(call(5)).await;
+        assert!(matches!(result, Err(ApiError::BadRequest(_))), "{result:?}");
+        // The limit is separate from EIP-3860's, and applies to creations too
+        let creation = CallRequest {
+            data: Some(Bytes::from(vec![0x01; 5])),
+            ..Default::default()
+        };
+        assert!(estimator.check_calldata_size(&creation).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_static_breakdown_sums_to_gas_limit() {
+        let estimator = GasEstimator::new(mock_transport());
+        let tx = CallRequest {
+            to: Some(address_to()),
+            data: Some(Bytes::default()),
+            access_list: Some(vec![AccessListItem {
+                address: address_to(),
+                storage_keys: vec![H256::zero()],
+            }]),
+            ..Default::default()
+        };
+
+        let result = estimator.estimate_gas(tx).await.unwrap();
+        let breakdown = result.breakdown.unwrap();
+        assert_eq!(
+            breakdown,
+            GasBreakdown {
+                base: GAS_TX_BASE,
+                access_list: GAS_ACCESS_LIST_ADDRESS + GAS_ACCESS_LIST_STORAGE_KEY,
+                ..Default::default()
+            }
+        );
+        assert_eq!(U256::from(breakdown.total()), result.gas_limit);
+    }
+
+    #[test]
+    fn test_breakdown_sums_to_total_across_tx_shapes() {
+        let estimator = GasEstimator::new(mock_transport());
+        let access_list = vec![AccessListItem {
+            address: address_to(),
+            storage_keys: vec![H256::zero(), H256::repeat_byte(1)],
+        }];
+        let shapes = [
+            simple_transfer_request(),
+            CallRequest {
+                to: Some(address_to()),
+                data: Some(Bytes::from(vec![0xa9, 0x05, 0x00, 0x00, 0x9c])),
+                access_list: Some(access_list.clone()),
+                ..Default::default()
+            },
+            // Contract creation with mixed calldata spanning two initcode words
+            CallRequest {
+                to: None,
+                data: Some(Bytes::from([vec![0x60; 20], vec![0x00; 20]].concat())),
+                access_list: Some(access_list),
+                ..Default::default()
+            },
+        ];
+
+        for tx in &shapes {
+            let breakdown = estimator.calculate_static_gas(tx, None);
+            assert_eq!(
+                breakdown.total(),
+                breakdown.base
+                    + breakdown.create
+                    + breakdown.calldata_zero
+                    + breakdown.calldata_nonzero
+                    + breakdown.access_list
+                    + breakdown.initcode
+            );
+            let data_len = tx.data.as_ref().map_or(0, |data| data.0.len() as u64);
+            assert_eq!(
+                breakdown.calldata_zero_bytes + breakdown.calldata_nonzero_bytes,
+                data_len
+            );
+        }
+
+        let creation = estimator.calculate_static_gas(&shapes[2], None);
+        assert_eq!(
+            creation,
+            GasBreakdown {
+                base: GAS_TX_BASE,
+                create: GAS_TX_CREATE,
+                calldata_zero: 20 * GAS_TX_DATA_ZERO,
+                calldata_nonzero: 20 * GAS_TX_DATA_NON_ZERO,
+                access_list: GAS_ACCESS_LIST_ADDRESS + 2 * GAS_ACCESS_LIST_STORAGE_KEY,
+                authorization: 0,
+                initcode: 2 * INITCODE_WORD_COST,
+                calldata_zero_bytes: 20,
+                calldata_nonzero_bytes: 20,
+                calldata_floor: 0,
+                precompile: 0,
+            }
+        );
+        assert_eq!(creation.total(), 59_604);
+    }
+
+    #[test]
+    fn test_static_gas_with_calldata_floor() {
+        let estimator =
+            GasEstimator::new(TestTransport::default()).with_pricing_rule(PricingRule::Eip7623);
+
+        // Calldata-heavy call: 4000 tokens cost 16000 gas, but their floor is 40000
+        let call = CallRequest {
+            to: Some(Address::repeat_byte(0x22)),
+            data: Some(Bytes::from(vec![0xff; 1000])),
+            ..Default::default()
+        };
+        let breakdown = estimator.calculate_static_gas(&call, None);
+        assert_eq!(breakdown.calldata_nonzero, 16_000);
+        assert_eq!(breakdown.calldata_floor, 24_000);
+        assert_eq!(breakdown.total(), 61_000);
+
+        // Creation gas exceeds the floor of its 100 tokens
+        let creation = CallRequest {
+            data: Some(Bytes::from(vec![0xff; 25])),
+            ..Default::default()
+        };
+        let breakdown = estimator.calculate_static_gas(&creation, None);
+        assert_eq!(breakdown.calldata_floor, 0);
+        assert_eq!(breakdown.total(), 21_000 + 32_000 + 400 + 2);
+
+        // Without the rule calldata has no floor
+        let breakdown =
+            GasEstimator::new(TestTransport::default()).calculate_static_gas(&call, None);
+        assert_eq!(breakdown.total(), 37_000);
+    }
+
+    #[tokio::test]
+    async fn test_rpc_estimate_raised_to_calldata_floor() {
+        let call = CallRequest {
+            to: Some(Address::repeat_byte(0x22)),
+            data: Some(Bytes::from(vec![0xff; 1000])),
+            ..Default::default()
+        };
+        let mut transport = TestTransport::default();
+        // A node predating Prague charges the calldata at 16 gas per byte
+        transport.add_response("0x9088".into()); // 37000 gas
+        // Execution gas dominates the floor
+        transport.add_response("0x186a0".into()); // 100000 gas
+        let estimator = GasEstimator::new(transport).with_pricing_rule(PricingRule::Eip7623);
+
+        let result = estimator.estimate_gas(call.clone()).await.unwrap();
+        assert_eq!(result.raw_gas_limit, Some(61_000.into()));
+        assert_eq!(result.pricing_rule, Some(PricingRule::Eip7623));
+        let result = estimator.estimate_gas(call).await.unwrap();
+        assert_eq!(result.raw_gas_limit, Some(100_000.into()));
+
+        let mut transport = TestTransport::default();
+        transport.add_response("0x9088".into());
+        let result = GasEstimator::new(transport)
+            .estimate_gas(CallRequest {
+                data: Some(Bytes::from(vec![0xff; 1000])),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(result.raw_gas_limit, Some(37_000.into()));
+        assert_eq!(result.pricing_rule, Some(PricingRule::Eip2028));
+    }
+
+    #[tokio::test]
+    async fn test_include_cost() {
+        let mut transport = TestTransport::default();
+        transport.add_response("0xc350".into()); // 50000 gas
+        transport.add_response("0x4a817c800".into()); // 20 gwei
+        let estimator = GasEstimator::new(transport.clone());
+        let request = EstimateGasRequest {
+            include_cost: true,
+            margin_bps: Some(1000),
+            ..CallRequest {
+                data: Some(Bytes::from(vec![0x01])),
+                ..Default::default()
+            }
+            .into()
+        };
+
+        let result = estimator.estimate(request).await.unwrap();
+        assert_eq!(result.gas_limit, 55000.into());
+        assert_eq!(result.gas_price_used, Some(20_000_000_000u64.into()));
+        // Priced after the margin
+        assert_eq!(
+            result.estimated_cost_wei,
+            Some(1_100_000_000_000_000u64.into())
+        );
+        transport.assert_request("eth_estimateGas", &[r#"{"data":"0x01"}"#.into()]);
+        transport.assert_request("eth_gasPrice", &[]);
+        transport.assert_no_more_requests();
+    }
+
+    #[tokio::test]
+    async fn test_include_cost_degrades_to_none() {
+        // No gas price queued, so the lookup fails
+        let estimator = GasEstimator::new(TestTransport::default());
+        let request = EstimateGasRequest {
+            include_cost: true,
+            ..CallRequest {
+                to: Some(address_to()),
+                ..Default::default()
+            }
+            .into()
+        };
+
+        let result = estimator.estimate(request).await.unwrap();
+        assert_eq!(result.method, "static");
+        assert_eq!(result.gas_price_used, None);
+        assert_eq!(result.estimated_cost_wei, None);
+    }
+
+    fn confidence_request() -> EstimateGasRequest {
+        EstimateGasRequest {
+            confidence: true,
+            buffer_percent: Some(10),
+            ..CallRequest {
+                data: Some(Bytes::from(vec![0x01])),
+                ..Default::default()
+            }
+            .into()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_confidence_within_threshold_is_stable() {
+        let mut transport = TestTransport::default();
+        transport.add_response("0xc350".into()); // latest: 50000 gas
+        transport.add_response("0xd6d8".into()); // pending: 55000 gas, 10% more
+        let estimator = GasEstimator::new(transport.clone());
+
+        let result = estimator.estimate(confidence_request()).await.unwrap();
+        assert_eq!(result.stable, Some(Some(true)));
+        assert_eq!(result.raw_gas_limit, Some(50000.into()));
+        assert_eq!(result.pending_gas_limit, Some(55000.into()));
+        assert_eq!(result.gas_limit, 55000.into());
+        transport.assert_request("eth_estimateGas", &[r#"{"data":"0x01"}"#.into()]);
+        transport.assert_request(
+            "eth_estimateGas",
+            &[r#"{"data":"0x01"}"#.into(), r#""pending""#.into()],
+        );
+        transport.assert_no_more_requests();
+    }
+
+    #[tokio::test]
+    async fn test_confidence_beyond_threshold_takes_the_max() {
+        let mut transport = TestTransport::default();
+        transport.add_response("0xc350".into()); // latest: 50000 gas
+        transport.add_response("0x7530".into()); // pending: 30000 gas
+        let estimator = GasEstimator::new(transport.clone());
+
+        let result = estimator.estimate(confidence_request()).await.unwrap();
+        assert_eq!(result.stable, Some(Some(false)));
+        assert_eq!(result.pending_gas_limit, Some(30000.into()));
+        assert_eq!(result.gas_limit, 55000.into());
+
+        let mut transport = TestTransport::default();
+        transport.add_response("0x7530".into()); // latest: 30000 gas
+        transport.add_response("0xc350".into()); // pending: 50000 gas
+        let estimator = GasEstimator::new(transport.clone()).with_confidence_threshold_percent(100);
+        let result = estimator.estimate(confidence_request()).await.unwrap();
+        assert_eq!(result.stable, Some(Some(true)));
+        assert_eq!(result.gas_limit, 33000.into());
+
+        let mut transport = TestTransport::default();
+        transport.add_response("0x7530".into());
+        transport.add_response("0xc350".into());
+        let estimator = GasEstimator::new(transport.clone());
+        let result = estimator.estimate(confidence_request()).await.unwrap();
+        assert_eq!(result.stable, Some(Some(false)));
+        assert_eq!(result.raw_gas_limit, Some(50000.into()));
+        assert_eq!(result.gas_limit, 55000.into());
+    }
+
+    #[tokio::test]
+    async fn test_confidence_without_pending_support() {
+        let mut transport = TestTransport::default();
+        transport.add_response("0xc350".into());
+        // No response queued for the pending estimate, so it fails
+        let estimator = GasEstimator::new(transport.clone());
+
+        let result = estimator.estimate(confidence_request()).await.unwrap();
+        assert_eq!(result.stable, Some(None));
+        assert_eq!(result.pending_gas_limit, None);
+        assert_eq!(result.gas_limit, 55000.into());
+        let json = serde_json::to_value(&result).unwrap();
+        assert_eq!(json["stable"], serde_json::Value::Null);
+        assert!(json.get("pending_gas_limit").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_confidence_on_static_estimates() {
+        let transport = TestTransport::default();
+        let estimator = GasEstimator::new(transport.clone());
+        let transfer = CallRequest {
+            to: Some(Address::repeat_byte(0x22)),
+            ..Default::default()
+        };
+        let request = EstimateGasRequest {
+            confidence: true,
+            ..transfer.clone().into()
+        };
+
+        let result = estimator.estimate(request).await.unwrap();
+        assert_eq!(result.method, "static");
+        assert_eq!(result.stable, Some(Some(true)));
+        assert_eq!(result.pending_gas_limit, None);
+        transport.assert_no_more_requests();
+
+        // Without the flag neither field is returned
+        let result = estimator.estimate(transfer.into()).await.unwrap();
+        let json = serde_json::to_value(&result).unwrap();
+        assert!(json.get("stable").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_confidence_rejects_a_block() {
+        let estimator = GasEstimator::new(TestTransport::default());
+        let request = EstimateGasRequest {
+            block: Some("pending".to_string()),
+            ..confidence_request()
+        };
+        assert!(matches!(
+            estimator.estimate(request).await,
+            Err(ApiError::BadRequest(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_rpc_estimate_above_block_gas_limit() {
+        let mut transport = TestTransport::default();
+        transport.add_response(serde_json::json!({ "gasLimit": "0x1c9c380" })); // 30M
+        transport.add_response("0xfffffffffffffff".into());
+        let estimator =
+            GasEstimator::new(transport.clone()).with_block_gas_limit_ttl(Duration::from_secs(60));
+        let tx = CallRequest {
+            data: Some(Bytes::from(vec![0x01])),
+            ..Default::default()
+        };
+
+        let error = estimator.estimate_gas(tx.clone()).await.unwrap_err();
+        assert_eq!(error.status_code(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(
+            error.to_string(),
+            "Transaction needs 1152921504606846975 gas, more than the block gas limit of \
+             30000000, so it can't fit in a block"
+        );
+
+        // The cached limit is reported with estimates that fit
+        transport.add_response("0xc350".into());
+        let result = estimator.estimate_gas(tx).await.unwrap();
+        assert_eq!(result.block_gas_limit, Some(30_000_000));
+        transport.assert_request(
+            "eth_getBlockByNumber",
+            &[r#""latest""#.into(), "false".into()],
+        );
+        transport.assert_request("eth_estimateGas", &[r#"{"data":"0x01"}"#.into()]);
+        transport.assert_request("eth_estimateGas", &[r#"{"data":"0x01"}"#.into()]);
+        transport.assert_no_more_requests();
+    }
+
+    #[tokio::test]
+    async fn test_padded_estimate_capped_at_block_gas_limit() {
+        let mut transport = TestTransport::default();
+        transport.add_response(serde_json::json!({ "gasLimit": "0x1c9c380" })); // 30M
+        transport.add_response("0x1c83ce0".into()); // 29.9M
+        let estimator = GasEstimator::new(transport.clone())
+            .with_block_gas_limit_ttl(Duration::from_secs(60))
+            .with_buffer_percent(10)
+            .unwrap();
+        let request = EstimateGasRequest {
+            tx: CallRequest {
+                data: Some(Bytes::from(vec![0x01])),
+                ..Default::default()
+            },
+            margin_bps: Some(500),
+            ..Default::default()
+        };
+
+        // The node's estimate fits, but neither the buffer nor the margin does
+        let result = estimator.estimate(request).await.unwrap();
+        assert_eq!(result.raw_gas_limit, Some(29_900_000.into()));
+        assert_eq!(result.gas_limit, 30_000_000.into());
+        assert_eq!(result.method, "rpc+margin");
+    }
+
+    #[tokio::test]
+    async fn test_static_estimate_above_block_gas_limit() {
+        let mut transport = TestTransport::default();
+        transport.add_response(serde_json::json!({ "gasLimit": "0x4e20" })); // 20000
+        let estimator =
+            GasEstimator::new(transport.clone()).with_block_gas_limit_ttl(Duration::from_secs(60));
+
+        // A transfer without value costs 21000 gas
+        let transfer = CallRequest {
+            to: Some(Address::repeat_byte(0x22)),
+            ..Default::default()
+        };
+        let error = estimator.estimate_gas(transfer).await.unwrap_err();
+        assert!(matches!(
+            error,
+            ApiError::ExceedsBlockGasLimit {
+                block_gas_limit: 20000,
+                ..
+            }
+        ));
+        transport.assert_request(
+            "eth_getBlockByNumber",
+            &[r#""latest""#.into(), "false".into()],
+        );
+        transport.assert_no_more_requests();
+    }
+
+    #[tokio::test]
+    async fn test_rpc_estimate_has_no_breakdown() {
+        let estimator = GasEstimator::new(mock_transport());
+        let tx = CallRequest {
+            data: Some(Bytes::from(vec![0x01])),
+            ..Default::default()
+        };
+
+        let result = estimator.estimate_gas(tx).await.unwrap();
+        assert_eq!(result.method, "rpc");
+        assert_eq!(result.breakdown, None);
+    }
+
+    #[tokio::test]
+    async fn test_calldata_stats_on_both_paths() {
+        let estimator = GasEstimator::new(mock_transport());
+        let tx = CallRequest {
+            data: Some(Bytes::from(vec![0x01, 0x00, 0x00, 0x02])),
+            ..Default::default()
+        };
+        let stats = estimator.estimate_gas(tx).await.unwrap().calldata_stats;
+        let stats = stats.unwrap();
+        assert_eq!(
+            (stats.total_bytes, stats.zero_bytes, stats.non_zero_bytes),
+            (4, 2, 2)
+        );
+        assert_eq!(stats.zero_ratio, 0.5);
+
+        let estimator = eoa_receiver(GasEstimator::new(mock_transport()));
+        let transfer = CallRequest {
+            data: Some(Bytes::default()),
+            ..simple_transfer_request()
+        };
+        let result = estimator.estimate_gas(transfer).await.unwrap();
+        assert_eq!(result.method, "static");
+        assert_eq!(result.calldata_stats.unwrap().total_bytes, 0);
+        let result = estimator
+            .estimate_gas(simple_transfer_request())
+            .await
+            .unwrap();
+        assert_eq!(result.calldata_stats, None);
+    }
+
+    #[tokio::test]
+    async fn test_estimate_reason_without_code_lookup() {
+        let estimator = GasEstimator::new(TestTransport::default());
+        let reason = |tx: CallRequest| {
+            let request = EstimateGasRequest::from(tx);
+            let estimator = &estimator;
+            async move { estimator.estimate_reason(&request).await.unwrap() }
+        };
+
+        let call = CallRequest {
+            data: Some(Bytes::from(vec![0x01])),
+            ..simple_transfer_request()
+        };
+        assert_eq!(reason(call).await, EstimateReason::CalldataPresent);
+        let call_with_access_list = CallRequest {
+            data: Some(Bytes::from(vec![0x01])),
+            access_list: Some(vec![AccessListItem {
+                address: Address::zero(),
+                storage_keys: vec![H256::zero()],
+            }]),
+            ..simple_transfer_request()
+        };
+        assert_eq!(
+            reason(call_with_access_list).await,
+            EstimateReason::CalldataPresent
+        );
+        let blob = CallRequest {
+            transaction_type: Some(BLOB_TX_TYPE.into()),
+            ..simple_transfer_request()
+        };
+        assert_eq!(reason(blob).await, EstimateReason::BlobTx);
+        let zero_value = CallRequest {
+            value: None,
+            ..simple_transfer_request()
+        };
+        assert_eq!(reason(zero_value).await, EstimateReason::ZeroValueTransfer);
+    }
+
+    #[tokio::test]
+    async fn test_value_transfer_to_eoa_is_static() {
+        let mut transport = TestTransport::default();
+        transport.add_response("0x".into());
+        let estimator = GasEstimator::new(transport.clone());
+
+        let result = estimator
+            .estimate_gas(simple_transfer_request())
+            .await
+            .unwrap();
+        assert_eq!(result.method, "static");
+        assert_eq!(result.reason, Some(EstimateReason::EoaTransfer));
+        transport.assert_request(
+            "eth_getCode",
+            &[
+                serde_json::to_string(&address_to()).unwrap(),
+                r#""latest""#.into(),
+            ],
+        );
+    }
+
+    #[tokio::test]
+    async fn test_value_transfer_to_contract_uses_rpc() {
+        let mut transport = TestTransport::default();
+        transport.add_response("0x6080".into());
+        transport.add_response("0x5dc0".into()); // 24000 gas
+        let estimator = GasEstimator::new(transport);
+
+        let result = estimator
+            .estimate_gas(simple_transfer_request())
+            .await
+            .unwrap();
+        assert_eq!(result.method, "rpc");
+        assert_eq!(result.gas_limit, 24000.into());
+        assert_eq!(result.reason, Some(EstimateReason::ContractReceiver));
+        let json = serde_json::to_value(&result).unwrap();
+        assert_eq!(json["reason"], "contract_receiver");
+    }
+
+    #[tokio::test]
+    async fn test_value_transfer_with_empty_data_to_contract_uses_rpc() {
+        let mut transport = TestTransport::default();
+        transport.add_response("0x6080".into());
+        transport.add_response("0x5dc0".into());
+        let estimator = GasEstimator::new(transport);
+
+        let tx = CallRequest {
+            data: Some(Bytes::default()),
+            ..simple_transfer_request()
+        };
+        let result = estimator.estimate_gas(tx).await.unwrap();
+        assert_eq!(result.method, "rpc");
+        assert_eq!(result.reason, Some(EstimateReason::ContractReceiver));
+    }
+
+    #[tokio::test]
+    async fn test_transaction_type_inference() {
+        let access_list = Some(vec![AccessListItem {
+            address: address_to(),
+            storage_keys: vec![],
+        }]);
+        let legacy = CallRequest {
+            data: Some(Bytes::from(vec![0x01])),
+            ..simple_transfer_request()
+        };
+        let access_list_tx = CallRequest {
+            access_list: access_list.clone(),
+            ..legacy.clone()
+        };
+        let eip1559 = CallRequest {
+            max_priority_fee_per_gas: Some(1.into()),
+            access_list,
+            ..legacy.clone()
+        };
+        assert_eq!(
+            GasEstimator::<TestTransport>::infer_transaction_type(&legacy),
+            0
+        );
+        assert_eq!(
+            GasEstimator::<TestTransport>::infer_transaction_type(&access_list_tx),
+            1
+        );
+        assert_eq!(
+            GasEstimator::<TestTransport>::infer_transaction_type(&eip1559),
+            2
+        );
+
+        // Inferred types are sent to the node, legacy transactions stay untyped
+        let mut transport = TestTransport::default();
+        let estimator = GasEstimator::new(transport.clone());
+        for (tx, transaction_type) in [
+            (legacy, None),
+            (access_list_tx, Some(1)),
+            (eip1559, Some(2)),
+        ] {
+            transport.add_response("0xc350".into());
+            estimator.estimate_gas(tx.clone()).await.unwrap();
+            let sent = CallRequest {
+                transaction_type: transaction_type.map(U64::from),
+                ..tx
+            };
+            transport.assert_request(
+                "eth_estimateGas",
+                &[serde_json::to_value(sent).unwrap().to_string()],
+            );
+        }
+
+        // Blob transactions are only recognized by their explicit type
+        let blob = CallRequest {
+            max_fee_per_gas: Some(2.into()),
+            transaction_type: Some(BLOB_TX_TYPE.into()),
+            ..simple_transfer_request()
+        };
+        transport.add_response("0x5208".into());
+        transport.add_response(serde_json::json!({ "excessBlobGas": "0x0" }));
+        let result = estimator.estimate_gas(blob).await.unwrap();
+        assert_eq!(result.reason, Some(EstimateReason::BlobTx));
+        assert_eq!(result.blob_gas_used, Some(GAS_PER_BLOB));
+    }
+
+    #[tokio::test]
+    async fn test_validate() {
+        let transport = TestTransport::default();
+        let estimator = GasEstimator::new(transport.clone());
+        let invalid = [
+            (
+                CallRequest {
+                    to: None,
+                    data: Some(Bytes::default()),
+                    ..simple_transfer_request()
+                },
+                "to is missing",
+            ),
+            (
+                CallRequest {
+                    gas: Some(20_999.into()),
+                    ..simple_transfer_request()
+                },
+                "gas of 20999",
+            ),
+            (
+                CallRequest {
+                    max_fee_per_gas: Some(1.into()),
+                    max_priority_fee_per_gas: Some(2.into()),
+                    ..simple_transfer_request()
+                },
+                "max_fee_per_gas of 1",
+            ),
+            (
+                CallRequest {
+                    transaction_type: Some(5.into()),
+                    ..simple_transfer_request()
+                },
+                "transaction_type of 0x5",
+            ),
+            (
+                CallRequest {
+                    gas_price: Some(3.into()),
+                    max_fee_per_gas: Some(2.into()),
+                    ..simple_transfer_request()
+                },
+                "gas_price of 3",
+            ),
+            (
+                CallRequest {
+                    from: Some(Address::zero()),
+                    ..simple_transfer_request()
+                },
+                "from is the zero address",
+            ),
+        ];
+        for (tx, expected) in invalid {
+            let Err(ApiError::BadRequest(message)) = estimator.estimate_gas(tx).await else {
+                panic!("expected a bad request for {expected}");
+            };
+            assert!(message.contains(expected), "{message}");
+        }
+        transport.assert_no_more_requests();
+
+        let valid = CallRequest {
+            gas: Some(GAS_TX_BASE.into()),
+            max_fee_per_gas: Some(2.into()),
+            max_priority_fee_per_gas: Some(2.into()),
+            transaction_type: Some(2.into()),
+            ..simple_transfer_request()
+        };
+        assert!(estimator.validate(&valid).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_code_lookup_is_cached() {
+        let mut transport = TestTransport::default();
+        transport.add_response("0x".into());
+        let estimator = GasEstimator::new(transport.clone());
+
+        for _ in 0..2 {
+            let result = estimator
+                .estimate_gas(simple_transfer_request())
+                .await
+                .unwrap();
+            assert_eq!(result.reason, Some(EstimateReason::EoaTransfer));
+        }
+        transport.assert_request(
+            "eth_getCode",
+            &[
+                serde_json::to_string(&address_to()).unwrap(),
+                r#""latest""#.into(),
+            ],
+        );
+        transport.assert_no_more_requests();
+    }
+
+    #[tokio::test]
+    async fn test_estimate_gas_static() {
+        let estimator = eoa_receiver(GasEstimator::new(mock_transport()));
+        let tx = simple_transfer_request();
+
+        let result = estimator.estimate_gas(tx).await.unwrap();
+        assert_eq!(result.gas_limit, GAS_TX_BASE.into());
+        assert_eq!(result.method, "static");
+    }
+
+    #[tokio::test]
+    async fn test_estimate_gas_rpc() {
+        let estimator = GasEstimator::new(mock_transport());
+        let tx = CallRequest {
+            data: Some(Bytes::from(vec![0x01])), // Forces RPC path
+            ..Default::default()
+        };
+
+        let result = estimator.estimate_gas(tx).await.unwrap();
+        assert_eq!(result.gas_limit, 21000.into()); // From mock response
+        assert_eq!(result.method, "rpc");
+    }
+
+    #[tokio::test]
+    async fn test_state_overrides_force_rpc() {
+        let mut transport = TestTransport::default();
+        transport.add_response("0xc350".into());
+        let estimator = GasEstimator::new(transport.clone());
+        let overrides = StateOverride::from([(
+            address_from(),
+            state_override::AccountOverride {
+                balance: Some(U256::exp10(18)),
+                ..Default::default()
+            },
+        )]);
+        let request = EstimateGasRequest {
+            state_overrides: Some(overrides.clone()),
+            ..simple_transfer_request().into()
+        };
+
+        let result = estimator.estimate(request).await.unwrap();
+        assert_eq!(result.gas_limit, 50000.into());
+        assert_eq!(result.method, "rpc");
+        assert_eq!(result.reason, Some(EstimateReason::StateOverride));
+        // No code lookup: the overrides alone decide the path
+        transport.assert_request(
+            "eth_estimateGas",
+            &[
+                serde_json::to_string(&simple_transfer_request()).unwrap(),
+                r#""latest""#.into(),
+                serde_json::to_string(&overrides).unwrap(),
+            ],
+        );
+        transport.assert_no_more_requests();
+    }
+
+    #[tokio::test]
+    async fn test_transient_storage_forces_rpc() {
+        let mut transport = TestTransport::default();
+        transport.add_response("0x7530".into()); // 30000 gas
+        let estimator = GasEstimator::new(transport.clone());
+        // Without data or value this would be a static 21000 gas transfer
+        let tx = CallRequest {
+            to: Some(address_to()),
+            ..Default::default()
+        };
+        let request = EstimateGasRequest {
+            has_transient_storage: true,
+            ..tx.clone().into()
+        };
+
+        let result = estimator.estimate(request).await.unwrap();
+        assert_eq!(result.gas_limit, 30000.into());
+        assert_ne!(result.gas_limit, GAS_TX_BASE.into());
+        assert_eq!(result.method, "rpc-forced");
+        assert_eq!(result.reason, Some(EstimateReason::TransientStorage));
+        assert!(result.breakdown.is_none());
+        transport.assert_request("eth_estimateGas", &[serde_json::to_string(&tx).unwrap()]);
+        transport.assert_no_more_requests();
+    }
+
+    #[tokio::test]
+    async fn test_rpc_latency_only_for_rpc_estimates() {
+        let mut transport = TestTransport::default();
+        transport.add_response("0xc350".into());
+        let estimator = GasEstimator::new(transport);
+        let rpc = CallRequest {
+            to: Some(address_to()),
+            data: Some(Bytes::from(vec![0x01])),
+            ..Default::default()
+        };
+
+        let result = estimator.estimate(rpc.into()).await.unwrap();
+        assert_eq!(result.method, "rpc");
+        assert!(result.rpc_latency_ms.is_some());
+
+        let zero_value = CallRequest {
+            to: Some(address_to()),
+            ..Default::default()
+        };
+        let result = estimator.estimate(zero_value.into()).await.unwrap();
+        assert_eq!(result.method, "static");
+        assert_eq!(result.rpc_latency_ms, None);
+    }
+
+    #[tokio::test]
+    async fn test_precompile_call_is_static() {
+        let mut transport = TestTransport::default();
+        let estimator = GasEstimator::new(transport.clone());
+        // sha256 of two words
+        let request: EstimateGasRequest = CallRequest {
+            to: Some(Address::from_low_u64_be(0x02)),
+            data: Some(Bytes::from(vec![0x01; 64])),
+            ..Default::default()
+        }
+        .into();
+
+        let result = estimator.estimate(request.clone()).await.unwrap();
+        assert_eq!(result.method, "static");
+        assert_eq!(result.reason, Some(EstimateReason::Precompile));
+        let breakdown = result.breakdown.unwrap();
+        assert_eq!(breakdown.precompile, 60 + 2 * 12);
+        assert_eq!(result.gas_limit, (21000 + 64 * 16 + 84).into());
+        transport.assert_no_more_requests();
+
+        // Execution counts towards the EIP-7623 floor of 21000 + 64 * 4 * 10
+        let estimator =
+            GasEstimator::new(transport.clone()).with_pricing_rule(PricingRule::Eip7623);
+        let result = estimator.estimate(request.clone()).await.unwrap();
+        assert_eq!(result.gas_limit, 23560.into());
+        assert_eq!(result.breakdown.unwrap().calldata_floor, 23560 - 22108);
+
+        // Precompile prices changed over forks, so past blocks go to the node
+        transport.add_response("0x5654".into());
+        let historical = EstimateGasRequest {
+            block_number: Some(15_000_000.into()),
+            ..request
+        };
+        let result = estimator.estimate(historical).await.unwrap();
+        assert_eq!(result.reason, Some(EstimateReason::CalldataPresent));
+        assert_eq!(result.method, "rpc");
+    }
+
+    #[tokio::test]
+    async fn test_content_dependent_precompile_uses_rpc() {
+        let mut transport = TestTransport::default();
+        transport.add_response("0x5b0a".into());
+        let estimator = GasEstimator::new(transport.clone());
+        // ecAdd fails on points off the curve
+        let tx = CallRequest {
+            to: Some(Address::from_low_u64_be(0x06)),
+            data: Some(Bytes::from(vec![0x01; 128])),
+            ..Default::default()
+        };
+
+        let result = estimator.estimate(tx.clone().into()).await.unwrap();
+        assert_eq!(result.reason, Some(EstimateReason::CalldataPresent));
+        assert_eq!(result.method, "rpc");
+        transport.assert_request(
+            "eth_estimateGas",
+            &[serde_json::to_value(&tx).unwrap().to_string()],
+        );
+        transport.assert_no_more_requests();
+    }
+
+    #[tokio::test]
+    async fn test_estimate_gas_blob_transaction() {
+        let mut transport = TestTransport::default();
+        transport.add_response("0x5208".into());
+        transport.add_response(serde_json::json!({ "excessBlobGas": "0x0" }));
+        let estimator = GasEstimator::new(transport);
+        let request = EstimateGasRequest {
+            tx: CallRequest {
+                to: Some(address_to()),
+                transaction_type: Some(BLOB_TX_TYPE.into()),
+                ..Default::default()
+            },
+            blob_versioned_hashes: vec![H256::repeat_byte(0x01), H256::repeat_byte(0x02)],
+            ..Default::default()
+        };
+
+        let result = estimator.estimate(request).await.unwrap();
+        assert_eq!(result.method, "rpc");
+        assert_eq!(result.blob_gas_used, Some(2 * GAS_PER_BLOB));
+        // Blob base fee is at its minimum of 1 wei without excess blob gas
+        assert_eq!(result.max_fee_per_blob_gas, Some(2.into()));
+
+        let json = serde_json::to_value(&result).unwrap();
+        assert_eq!(json["blob_gas_used"], 262144);
+        assert_eq!(json["max_fee_per_blob_gas"], "0x2");
+    }
+
+    #[tokio::test]
+    async fn test_estimate_gas_non_blob_has_null_blob_fields() {
+        let estimator = eoa_receiver(GasEstimator::new(mock_transport()));
+        let tx = CallRequest {
+            transaction_type: Some(U64::zero()),
+            ..simple_transfer_request()
+        };
+
+        let result = estimator.estimate_gas(tx).await.unwrap();
+        let json = serde_json::to_value(&result).unwrap();
+        assert!(json["blob_gas_used"].is_null());
+        assert!(json["max_fee_per_blob_gas"].is_null());
+    }
+
+    #[test]
+    fn test_fake_exponential() {
+        let fraction = U256::from(BLOB_BASE_FEE_UPDATE_FRACTION);
+        assert_eq!(fake_exponential(1.into(), 0.into(), fraction), 1.into());
+        // e^1 rounded down
+        assert_eq!(fake_exponential(1.into(), fraction, fraction), 2.into());
+        assert_eq!(
+            fake_exponential(1000.into(), fraction * 2, fraction),
+            7389.into()
+        );
+    }
+
+    #[test]
+    fn test_fake_exponential_eip4844_vectors() {
+        // (factor, numerator, denominator, expected) from the EIP-4844 test suite
+        let vectors: [(u64, u64, u64, u64); 12] = [
+            (1, 0, 1, 1),
+            (38493, 0, 1000, 38493),
+            (0, 1234, 2345, 0),
+            (1, 2, 1, 6),
+            (1, 4, 2, 6),
+            (1, 3, 1, 16),
+            (1, 6, 2, 18),
+            (1, 4, 1, 49),
+            (1, 8, 2, 50),
+            (2, 5, 2, 23),
+            (1, 50_000_000, 2_225_652, 5_709_098_764),
+            (1, 380_928, 3_338_477, 1),
+        ];
+        for (factor, numerator, denominator, expected) in vectors {
+            assert_eq!(
+                fake_exponential(factor.into(), numerator.into(), denominator.into()),
+                expected.into(),
+                "fake_exponential({factor}, {numerator}, {denominator})"
+            );
+        }
+    }
+
+    fn blob_request(blob_count: u64) -> EstimateGasRequest {
+        EstimateGasRequest {
+            tx: CallRequest {
+                to: Some(address_to()),
+                transaction_type: Some(BLOB_TX_TYPE.into()),
+                ..Default::default()
+            },
+            blob_count: Some(blob_count),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_estimate_gas_blob_transaction_with_blob_count() {
+        for blob_count in 1..=MAX_BLOBS_PER_TX {
+            let mut transport = TestTransport::default();
+            // Only the latest block, for the blob base fee: the node doesn't estimate
+            transport.add_response(serde_json::json!({ "excessBlobGas": "0x0" }));
+            let estimator = GasEstimator::new(transport.clone());
+
+            let result = estimator.estimate(blob_request(blob_count)).await.unwrap();
+            assert_eq!(result.method, "static-blob");
+            assert_eq!(result.gas_limit, 21_000.into());
+            assert_eq!(result.blob_gas_used, Some(blob_count * GAS_PER_BLOB));
+            assert_eq!(result.max_fee_per_blob_gas, Some(2.into()));
+            assert_eq!(result.reason, Some(EstimateReason::ZeroValueTransfer));
+            transport.assert_request(
+                "eth_getBlockByNumber",
+                &[r#""latest""#.to_string(), "false".to_string()],
+            );
+            transport.assert_no_more_requests();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_estimate_gas_static_blob_without_node() {
+        // No responses queued: every request to the node fails
+        let transport = TestTransport::default();
+        let estimator = GasEstimator::new(transport);
+
+        let result = estimator.estimate(blob_request(2)).await.unwrap();
+        assert_eq!(result.method, "static-blob");
+        assert_eq!(result.gas_limit, 21_000.into());
+        assert_eq!(result.blob_gas_used, Some(2 * GAS_PER_BLOB));
+        assert_eq!(result.max_fee_per_blob_gas, None);
+    }
+
+    #[tokio::test]
+    async fn test_estimate_gas_blob_count_above_maximum() {
+        let transport = TestTransport::default();
+        let estimator = GasEstimator::new(transport.clone());
+
+        let error = estimator.estimate(blob_request(7)).await.unwrap_err();
+        assert!(
+            matches!(&error, ApiError::BadRequest(message) if message.contains("between 1 and 6")),
+            "{error:?}"
+        );
+        assert_eq!(error.status_code(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_estimate_gas_blob_transaction_with_calldata_and_blob_count() {
+        let mut transport = TestTransport::default();
+        transport.add_response("0xc350".into());
+        transport.add_response(serde_json::json!({ "excessBlobGas": "0x0" }));
+        let estimator = GasEstimator::new(transport);
+        let mut request = blob_request(3);
+        request.tx.data = Some(Bytes(vec![0x70, 0xa0, 0x82, 0x31]));
+
+        let result = estimator.estimate(request).await.unwrap();
+        assert_eq!(result.method, "rpc");
+        assert_eq!(result.blob_gas_used, Some(3 * GAS_PER_BLOB));
+    }
+
+    #[test]
+    fn test_blob_count_validation() {
+        let request = |blob_count, hashes| EstimateGasRequest {
+            blob_count,
+            blob_versioned_hashes: vec![H256::zero(); hashes],
+            ..Default::default()
+        };
+        assert_eq!(request(None, 0).blob_count().unwrap(), 1);
+        assert_eq!(request(None, 2).blob_count().unwrap(), 2);
+        assert_eq!(request(Some(6), 0).blob_count().unwrap(), 6);
+        assert_eq!(request(Some(2), 2).blob_count().unwrap(), 2);
+        for invalid in [
+            request(Some(0), 0),
+            request(Some(7), 0),
+            request(None, 7),
+            request(Some(1), 2),
+        ] {
+            assert!(matches!(invalid.blob_count(), Err(ApiError::BadRequest(_))));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_estimate_gas_batch_all_static() {
+        let estimator = eoa_receiver(GasEstimator::new(mock_transport()));
+        let txs = vec![simple_transfer_request(), simple_transfer_request()];
+
+        let results = estimator.estimate_gas_batch(txs, 4).await;
+        assert_eq!(results.len(), 2);
+        for result in results {
+            let result = result.unwrap();
+            assert_eq!(result.gas_limit, GAS_TX_BASE.into());
+            assert_eq!(result.method, "static");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_estimate_gas_batch_mixed() {
+        let mut transport = TestTransport::default();
+        transport.add_response("0x7530".into()); // 30000 gas
+        let estimator = eoa_receiver(GasEstimator::new(transport));
+        let txs = vec![
+            simple_transfer_request(),
+            CallRequest {
+                data: Some(Bytes::from(vec![0x01])),
+                ..Default::default()
+            },
+        ];
+
+        let results = estimator.estimate_gas_batch(txs, 4).await;
+        assert_eq!(results.len(), 2);
+        let first = results[0].as_ref().unwrap();
+        assert_eq!(first.gas_limit, GAS_TX_BASE.into());
+        assert_eq!(first.method, "static");
+        let second = results[1].as_ref().unwrap();
+        assert_eq!(second.gas_limit, 30000.into());
+        assert_eq!(second.method, "rpc");
+    }
+
+    #[tokio::test]
+    async fn test_estimate_gas_batch_failing_item() {
+        // Only one RPC response is available, so the second RPC-bound item fails
+        let estimator = eoa_receiver(GasEstimator::new(mock_transport()));
+        let rpc_tx = || CallRequest {
+            data: Some(Bytes::from(vec![0x01])),
+            ..Default::default()
+        };
+        let txs = vec![
+            simple_transfer_request(),
+            rpc_tx(),
+            rpc_tx(),
+            simple_transfer_request(),
+        ];
+
+        let results = estimator.estimate_gas_batch(txs, 4).await;
+        assert_eq!(results.len(), 4);
+        assert_eq!(results[0].as_ref().unwrap().method, "static");
+        assert_eq!(results[1].as_ref().unwrap().method, "rpc");
+        assert!(matches!(results[2], Err(ApiError::UpstreamUnavailable(_))));
+        assert_eq!(results[3].as_ref().unwrap().method, "static");
+    }
+
+    #[tokio::test]
+    async fn test_estimate_gas_batch_preserves_order() {
+        let mut transport = TestTransport::default();
+        transport.add_response("0x7530".into()); // 30000 gas
+        transport.add_response("0x9c40".into()); // 40000 gas
+        let estimator = eoa_receiver(GasEstimator::new(transport));
+        let rpc_tx = || CallRequest {
+            data: Some(Bytes::from(vec![0x01])),
+            ..Default::default()
+        };
+        let txs = vec![rpc_tx(), simple_transfer_request(), rpc_tx()];
+
+        let results = estimator.estimate_gas_batch(txs, 1).await;
+        let gas: Vec<U256> = results.into_iter().map(|r| r.unwrap().gas_limit).collect();
+        assert_eq!(
+            gas,
+            vec![30000.into(), GAS_TX_BASE.into(), U256::from(40000)]
+        );
+    }
+
+    /// Batch sender answering with `results` and recording the size of each batch
+    fn scripted_batch_sender(
+        results: Vec<web3::Result<serde_json::Value>>,
+    ) -> (BatchSender, std::sync::Arc<std::sync::Mutex<Vec<usize>>>) {
+        let batches = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sizes = batches.clone();
+        let sender: BatchSender = std::sync::Arc::new(move |requests| {
+            sizes.lock().unwrap().push(requests.len());
+            let results: Vec<_> = results
+                .iter()
+                .map(|result| match result {
+                    Ok(value) => Ok(value.clone()),
+                    Err(_) => Err(web3::Error::Rpc(jsonrpc_core::Error {
+                        code: jsonrpc_core::ErrorCode::ServerError(3),
+                        message: "execution reverted".to_string(),
+                        data: Some("0x".into()),
+                    })),
+                })
+                .collect();
+            Box::pin(futures::future::ready(Ok(results)))
+        });
+        (sender, batches)
+    }
+
+    #[tokio::test]
+    async fn test_estimate_gas_batch_sends_node_estimates_together() {
+        let mut transport = TestTransport::default();
+        let (sender, batches) = scripted_batch_sender(vec![
+            Ok("0x7530".into()), // 30000 gas
+            Ok("0x9c40".into()), // 40000 gas
+            Err(web3::Error::Unreachable),
+            Ok("0xc350".into()), // 50000 gas
+            Ok("0xea60".into()), // 60000 gas
+        ]);
+        let estimator =
+            eoa_receiver(GasEstimator::new(transport.clone()).with_batch_sender(sender));
+        let rpc_tx = |byte| CallRequest {
+            data: Some(Bytes::from(vec![byte])),
+            ..Default::default()
+        };
+        let txs = vec![
+            rpc_tx(1),
+            rpc_tx(2),
+            simple_transfer_request(),
+            rpc_tx(3),
+            rpc_tx(4),
+            rpc_tx(5),
+        ];
+
+        let results = estimator.estimate_gas_batch(txs, 2).await;
+        assert_eq!(*batches.lock().unwrap(), [5]);
+        assert_eq!(results[0].as_ref().unwrap().gas_limit, 30000.into());
+        assert_eq!(results[1].as_ref().unwrap().gas_limit, 40000.into());
+        assert_eq!(results[2].as_ref().unwrap().method, "static");
+        assert!(matches!(
+            results[3],
+            Err(ApiError::ExecutionReverted { .. })
+        ));
+        assert_eq!(results[4].as_ref().unwrap().gas_limit, 50000.into());
+        assert_eq!(results[5].as_ref().unwrap().method, "rpc");
+        assert_eq!(results[5].as_ref().unwrap().gas_limit, 60000.into());
+        // Batched items report the batch's round trip
+        assert!(results[0].as_ref().unwrap().rpc_latency_ms.is_some());
+        assert_eq!(results[2].as_ref().unwrap().rpc_latency_ms, None);
+        // Prepared for the batch rather than sent on their own
+        for byte in 1..=5 {
+            transport.assert_request("eth_estimateGas", &[format!(r#"{{"data":"0x0{byte}"}}"#)]);
+        }
+        transport.assert_no_more_requests();
+    }
+
+    #[tokio::test]
+    async fn test_estimate_gas_batch_falls_back_when_the_batch_fails() {
+        let mut transport = TestTransport::default();
+        transport.add_response("0x7530".into());
+        transport.add_response("0x9c40".into());
+        let batches = std::sync::Arc::new(std::sync::Mutex::new(0));
+        let attempts = batches.clone();
+        let sender: BatchSender = std::sync::Arc::new(move |_| {
+            *attempts.lock().unwrap() += 1;
+            Box::pin(futures::future::ready(Err(web3::Error::Unreachable)))
+        });
+        let estimator = GasEstimator::new(transport).with_batch_sender(sender);
+        let rpc_tx = CallRequest {
+            data: Some(Bytes::from(vec![0x01])),
+            ..Default::default()
+        };
+
+        let results = estimator
+            .estimate_gas_batch(vec![rpc_tx.clone(), rpc_tx], 1)
+            .await;
+        assert_eq!(*batches.lock().unwrap(), 1);
+        assert_eq!(results[0].as_ref().unwrap().gas_limit, 30000.into());
+        assert_eq!(results[1].as_ref().unwrap().gas_limit, 40000.into());
+    }
+
+    #[tokio::test]
+    async fn test_estimate_fees() {
+        let mut transport = TestTransport::default();
+        transport.add_response(block_response(Some(U256::from(10_000_000_000u64))));
+        transport.add_response("0x3b9aca00".into()); // 1 gwei
+        let estimator = GasEstimator::new(transport);
+
+        let fees = estimator.estimate_fees().await.unwrap();
+        assert_eq!(fees.base_fee_per_gas, U256::from(10_000_000_000u64));
+        assert_eq!(fees.max_priority_fee_per_gas, U256::from(1_000_000_000u64));
+        assert_eq!(fees.max_fee_per_gas, U256::from(21_000_000_000u64));
+    }
+
+    #[tokio::test]
+    async fn test_estimate_fees_gas_price_fallback() {
+        // eth_maxPriorityFeePerGas fails (no response queued), then eth_gasPrice is used
+        let mut transport = TestTransport::default();
+        transport.add_response(block_response(Some(U256::from(100))));
+        transport.add_response(serde_json::Value::Null);
+        transport.add_response("0x96".into()); // 150
+        let estimator = GasEstimator::new(transport);
+
+        let fees = estimator.estimate_fees().await.unwrap();
+        assert_eq!(fees.max_priority_fee_per_gas, U256::from(50));
+        assert_eq!(fees.max_fee_per_gas, U256::from(250));
+    }
+
+    #[tokio::test]
+    async fn test_estimate_fees_pre_london() {
+        let mut transport = TestTransport::default();
+        transport.add_response(block_response(None));
+        let estimator = GasEstimator::new(transport);
+
+        assert!(estimator.estimate_fees().await.is_none());
+    }
+
+    #[test]
+    fn test_fees_serialize_as_null_when_missing() {
+        let response = GasEstimateResponse {
+            gas_limit: GAS_TX_BASE.into(),
+            method: "static".to_string(),
+            ..Default::default()
+        };
+
+        let json = serde_json::to_value(&response).unwrap();
+        assert_eq!(json["gas_limit"], "0x5208");
+        assert!(json["fees"].is_null());
+    }
+
+    #[tokio::test]
+    async fn test_fee_suggestion() {
+        let mut transport = TestTransport::default();
+        transport.add_response(block_response(Some(U256::from(30))));
+        transport.add_response("0x2".into());
+        let estimator = GasEstimator::new(transport);
+
+        let suggestion = estimator.fee_suggestion().await.unwrap();
+        assert_eq!(suggestion.base_fee_per_gas, U256::from(30));
+        assert_eq!(suggestion.max_priority_fee_per_gas, U256::from(2));
+        assert_eq!(suggestion.suggested_max_fee_per_gas, U256::from(62));
+    }
+
+    #[tokio::test]
+    async fn test_fee_suggestion_pre_london() {
+        let mut transport = TestTransport::default();
+        transport.add_response(block_response(None));
+        let estimator = GasEstimator::new(transport);
+
+        let error = estimator.fee_suggestion().await.unwrap_err();
+        assert!(matches!(error, ApiError::ServiceUnavailable(_)));
+        assert_eq!(
+            error.into_response().status(),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+    }
+
+    #[tokio::test]
+    async fn test_estimate_with_block_number_rpc() {
+        let mut transport = TestTransport::default();
+        transport.set_response("0x5208".into());
+        let estimator = GasEstimator::new(transport.clone());
+        let request = EstimateGasRequest {
+            tx: CallRequest {
+                data: Some(Bytes::from(vec![0x01])),
+                ..Default::default()
+            },
+            block_number: Some(20_000_000.into()),
+            ..Default::default()
+        };
+
+        let result = estimator.estimate(request).await.unwrap();
+        assert_eq!(result.method, "rpc");
+        transport.assert_request(
+            "eth_estimateGas",
+            &[r#"{"data":"0x01"}"#.into(), r#""0x1312d00""#.into()],
+        );
+        transport.assert_no_more_requests();
+    }
+
+    #[tokio::test]
+    async fn test_estimate_with_block_number_static() {
+        let mut transport = TestTransport::default();
+        transport.add_response("0x".into());
+        let estimator = GasEstimator::new(transport.clone());
+        let request = EstimateGasRequest {
+            tx: simple_transfer_request(),
+            block_number: Some(EIP2028_ACTIVATION_BLOCK.into()),
+            ..Default::default()
+        };
+
+        let result = estimator.estimate(request).await.unwrap();
+        assert_eq!(result.method, "static");
+        // Code lookups at a specific block bypass the code cache
+        transport.assert_request(
+            "eth_getCode",
+            &[
+                serde_json::to_string(&address_to()).unwrap(),
+                r#""0x8a61c8""#.into(),
+            ],
+        );
+        transport.assert_no_more_requests();
+    }
+
+    #[tokio::test]
+    async fn test_estimate_with_block_before_eip2028() {
+        let mut transport = TestTransport::default();
+        transport.set_response("0x5208".into());
+        let estimator = GasEstimator::new(transport.clone());
+        let request = EstimateGasRequest {
+            tx: simple_transfer_request(),
+            block_number: Some((EIP2028_ACTIVATION_BLOCK - 1).into()),
+            ..Default::default()
+        };
+
+        let result = estimator.estimate(request).await.unwrap();
+        assert_eq!(result.method, "rpc");
+        transport.assert_request(
+            "eth_estimateGas",
+            &[
+                serde_json::to_string(&simple_transfer_request()).unwrap(),
+                r#""0x8a61c7""#.into(),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_estimate_gas_request_deserializes_flat_body() {
+        let request: EstimateGasRequest = serde_json::from_value(serde_json::json!({
+            "to": "0xc0ffee254729296a45a3885639AC7E10F9d54979",
+            "value": "0x1",
+            "block_number": "0x10"
+        }))
+        .unwrap();
+
+        assert_eq!(request.tx.to, Some(address_to()));
+        assert_eq!(request.tx.value, Some(U256::one()));
+        assert_eq!(request.block_number, Some(16.into()));
+    }
+
+    #[test]
+    fn test_parse_block() {
+        assert_eq!(parse_block("latest").unwrap(), BlockNumber::Latest);
+        assert_eq!(parse_block("pending").unwrap(), BlockNumber::Pending);
+        assert_eq!(parse_block("earliest").unwrap(), BlockNumber::Earliest);
+        assert_eq!(parse_block("0x10").unwrap(), BlockNumber::Number(16.into()));
+        assert_eq!(parse_block("16").unwrap(), BlockNumber::Number(16.into()));
+
+        let Err(ApiError::BadRequest(message)) = parse_block("yesterday") else {
+            panic!("expected a bad request");
+        };
+        assert!(message.contains("yesterday"));
+    }
+
+    #[test]
+    fn test_block_and_block_number_are_exclusive() {
+        let request = EstimateGasRequest {
+            block: Some("latest".to_string()),
+            block_number: Some(16.into()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            request.requested_block(),
+            Err(ApiError::BadRequest(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_estimate_with_pending_block_rpc() {
+        let mut transport = TestTransport::default();
+        transport.set_response("0x5208".into());
+        let estimator = GasEstimator::new(transport.clone());
+        let request = EstimateGasRequest {
+            tx: CallRequest {
+                data: Some(Bytes::from(vec![0x01])),
+                ..Default::default()
+            },
+            block: Some("pending".to_string()),
+            ..Default::default()
+        };
+
+        let result = estimator.estimate(request).await.unwrap();
+        assert_eq!(result.block, Some(BlockNumber::Pending));
+        transport.assert_request(
+            "eth_estimateGas",
+            &[r#"{"data":"0x01"}"#.into(), r#""pending""#.into()],
+        );
+    }
+
+    #[tokio::test]
+    async fn test_static_estimate_echoes_block() {
+        let mut transport = TestTransport::default();
+        transport.add_response("0x".into());
+        let estimator = GasEstimator::new(transport.clone());
+        let request = EstimateGasRequest {
+            tx: simple_transfer_request(),
+            block: Some("20000000".to_string()),
+            ..Default::default()
+        };
+
+        let result = estimator.estimate(request).await.unwrap();
+        assert_eq!(result.method, "static");
+        assert_eq!(result.block, Some(BlockNumber::Number(20_000_000.into())));
+        // Code lookups at a specific block bypass the code cache
+        transport.assert_request(
+            "eth_getCode",
+            &[
+                serde_json::to_string(&address_to()).unwrap(),
+                r#""0x1312d00""#.into(),
+            ],
+        );
+        transport.assert_no_more_requests();
+    }
+
+    #[tokio::test]
+    async fn test_estimate_with_invalid_block() {
+        let estimator = GasEstimator::new(TestTransport::default());
+        let request = EstimateGasRequest {
+            tx: simple_transfer_request(),
+            block: Some("0xzz".to_string()),
+            ..Default::default()
+        };
+
+        let result = estimator.estimate(request).await;
+        assert!(matches!(result, Err(ApiError::BadRequest(_))));
+    }
+
+    #[test]
+    fn test_block_number_out_of_range() {
+        assert!(matches!(
+            block_number(U256::MAX),
+            Err(ApiError::BadRequest(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_estimate_static_with_margin() {
+        let estimator = eoa_receiver(GasEstimator::new(mock_transport()));
+        let request = EstimateGasRequest {
+            tx: simple_transfer_request(),
+            margin_bps: Some(1000),
+            ..Default::default()
+        };
+
+        let result = estimator.estimate(request).await.unwrap();
+        assert_eq!(result.gas_limit, 23100.into());
+        assert_eq!(result.method, "static+margin");
+    }
+
+    #[tokio::test]
+    async fn test_estimate_rpc_with_margin() {
+        let mut transport = TestTransport::default();
+        transport.set_response("0xc350".into()); // 50000 gas
+        let estimator = GasEstimator::new(transport);
+        let request = EstimateGasRequest {
+            tx: CallRequest {
+                data: Some(Bytes::from(vec![0x01])),
+                ..Default::default()
+            },
+            margin_bps: Some(1000),
+            ..Default::default()
+        };
+
+        let result = estimator.estimate(request).await.unwrap();
+        assert_eq!(result.gas_limit, 55000.into());
+        assert_eq!(result.method, "rpc+margin");
+    }
+
+    #[tokio::test]
+    async fn test_estimate_with_zero_margin_keeps_method() {
+        let estimator = eoa_receiver(GasEstimator::new(mock_transport()));
+        let request = EstimateGasRequest {
+            tx: simple_transfer_request(),
+            margin_bps: Some(0),
+            ..Default::default()
+        };
+
+        let result = estimator.estimate(request).await.unwrap();
+        assert_eq!(result.gas_limit, 21000.into());
+        assert_eq!(result.method, "static");
+    }
+
+    #[tokio::test]
+    async fn test_estimate_with_default_margin() {
+        let estimator = eoa_receiver(
+            GasEstimator::new(mock_transport())
+                .with_margin_bps(1000)
+                .unwrap(),
+        );
+        let request = EstimateGasRequest {
+            tx: simple_transfer_request(),
+            ..Default::default()
+        };
+        let result = estimator.estimate(request.clone()).await.unwrap();
+        assert_eq!(result.gas_limit, 23100.into());
+
+        // The request's margin replaces the default
+        let request = EstimateGasRequest {
+            margin_bps: Some(0),
+            ..request
+        };
+        let result = estimator.estimate(request).await.unwrap();
+        assert_eq!(result.gas_limit, 21000.into());
+        assert!(
+            GasEstimator::new(mock_transport())
+                .with_margin_bps(MAX_MARGIN_BPS + 1)
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_estimate_rejects_excessive_margin() {
+        let estimator = GasEstimator::new(mock_transport());
+        let request = EstimateGasRequest {
+            tx: simple_transfer_request(),
+            margin_bps: Some(MAX_MARGIN_BPS + 1),
+            ..Default::default()
+        };
+
+        let result = estimator.estimate(request).await;
+        assert!(matches!(result, Err(ApiError::BadRequest(_))));
+    }
+
+    #[tokio::test]
+    async fn test_estimate_gas_with_margin() {
+        let estimator = eoa_receiver(GasEstimator::new(mock_transport()));
+        let result = estimator
+            .estimate_gas_with_margin(simple_transfer_request(), 1000)
+            .await
+            .unwrap();
+        assert_eq!(result.gas_limit, 23100.into());
+        assert_eq!(result.method, "static+margin");
+
+        // Replaces the estimator's default margin
+        let estimator = eoa_receiver(
+            GasEstimator::new(mock_transport())
+                .with_margin_bps(1000)
+                .unwrap(),
+        );
+        let result = estimator
+            .estimate_gas_with_margin(simple_transfer_request(), 0)
+            .await
+            .unwrap();
+        assert_eq!(result.gas_limit, 21000.into());
+        assert_eq!(result.method, "static");
+    }
+
+    #[tokio::test]
+    async fn test_estimate_gas_with_margin_above_cap() {
+        // Rejected before anything is sent to the node
+        let transport = TestTransport::default();
+        let estimator = GasEstimator::new(transport.clone());
+        let result = estimator
+            .estimate_gas_with_margin(simple_transfer_request(), MAX_MARGIN_BPS + 1)
+            .await;
+        assert!(matches!(result, Err(ApiError::BadRequest(_))));
+        transport.assert_no_more_requests();
+
+        let estimator = eoa_receiver(GasEstimator::new(mock_transport()));
+        let result = estimator
+            .estimate_gas_with_margin(simple_transfer_request(), MAX_MARGIN_BPS)
+            .await
+            .unwrap();
+        assert_eq!(result.gas_limit, 31500.into());
+    }
+
+    #[tokio::test]
+    async fn test_estimate_rpc_with_default_buffer() {
+        let estimator = GasEstimator::new(mock_transport())
+            .with_buffer_percent(10)
+            .unwrap();
+        let tx = CallRequest {
+            data: Some(Bytes::from(vec![0x01])),
+            ..Default::default()
+        };
+
+        let result = estimator.estimate_gas(tx).await.unwrap();
+        assert_eq!(result.gas_limit, 23100.into());
+        assert_eq!(result.raw_gas_limit, Some(21000.into()));
+        assert_eq!(result.buffer_percent, Some(10));
+    }
+
+    #[tokio::test]
+    async fn test_estimate_rpc_with_request_buffer() {
+        let estimator = GasEstimator::new(mock_transport())
+            .with_buffer_percent(10)
+            .unwrap();
+        let request = EstimateGasRequest {
+            tx: CallRequest {
+                data: Some(Bytes::from(vec![0x01])),
+                ..Default::default()
+            },
+            buffer_percent: Some(15),
+            ..Default::default()
+        };
+
+        let result = estimator.estimate(request).await.unwrap();
+        assert_eq!(result.gas_limit, 24150.into());
+        assert_eq!(result.buffer_percent, Some(15));
+    }
+
+    #[tokio::test]
+    async fn test_estimate_static_is_never_buffered() {
+        let estimator = eoa_receiver(GasEstimator::new(mock_transport()))
+            .with_buffer_percent(20)
+            .unwrap();
+
+        let result = estimator
+            .estimate_gas(simple_transfer_request())
+            .await
+            .unwrap();
+        assert_eq!(result.gas_limit, GAS_TX_BASE.into());
+        assert_eq!(result.raw_gas_limit, None);
+        assert_eq!(result.buffer_percent, None);
+    }
+
+    #[tokio::test]
+    async fn test_estimate_buffer_above_cap() {
+        assert!(
+            GasEstimator::new(mock_transport())
+                .with_buffer_percent(MAX_BUFFER_PERCENT + 1)
+                .is_err()
+        );
+
+        let estimator = GasEstimator::new(mock_transport());
+        let request = EstimateGasRequest {
+            tx: simple_transfer_request(),
+            buffer_percent: Some(MAX_BUFFER_PERCENT + 1),
+            ..Default::default()
+        };
+        assert!(matches!(
+            estimator.estimate(request).await,
+            Err(ApiError::BadRequest(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_circuit_breaker_fails_fast_then_probes() {
+        let mut transport = TestTransport::default();
+        let estimator =
+            GasEstimator::new(transport.clone()).with_circuit_breaker(2, Duration::from_millis(50));
+        let tx = CallRequest {
+            data: Some(Bytes::from(vec![0x01])),
+            ..Default::default()
+        };
+
+        // No responses queued, so the node is unreachable
+        for _ in 0..2 {
+            assert!(matches!(
+                estimator.estimate_gas(tx.clone()).await,
+                Err(ApiError::UpstreamUnavailable(_))
+            ));
+        }
+        let error = estimator.estimate_gas(tx.clone()).await.unwrap_err();
+        assert_eq!(error.to_string(), "circuit open");
+        transport.assert_request("eth_estimateGas", &[r#"{"data":"0x01"}"#.into()]);
+        transport.assert_request("eth_estimateGas", &[r#"{"data":"0x01"}"#.into()]);
+        transport.assert_no_more_requests();
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        transport.add_response("0x5208".into());
+        let result = estimator.estimate_gas(tx).await.unwrap();
+        assert_eq!(result.gas_limit, 21000.into());
+        transport.assert_request("eth_estimateGas", &[r#"{"data":"0x01"}"#.into()]);
+        assert_eq!(
+            estimator.circuit_breaker.as_ref().unwrap().state(),
+            crate::circuit_breaker::CircuitState::Closed { failures: 0 }
+        );
+    }
+}
@@ -0,0 +1,404 @@
+//! Priority fee suggestions from the reward percentiles of recent blocks
+
+use crate::access_list::is_method_unsupported;
+use crate::estimator::{ApiError, GasEstimator};
+use lru::LruCache;
+use serde::{Deserialize, Serialize};
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use utoipa::ToSchema;
+use web3::{
+    Transport,
+    api::Namespace,
+    helpers::{self, CallFuture},
+    types::{BlockNumber, U64, U256},
+};
+
+pub const DEFAULT_FEE_HISTORY_BLOCKS: u64 = 20;
+/// Most blocks nodes return from one `eth_feeHistory` call
+pub const MAX_FEE_HISTORY_BLOCKS: u64 = 1024;
+pub const DEFAULT_FEE_HISTORY_PERCENTILES: [f64; 3] = [10.0, 50.0, 90.0];
+/// The history only changes once per block
+pub const DEFAULT_FEE_HISTORY_CACHE_TTL_SECS: u64 = 6;
+/// Distinct block count and percentile combinations kept in the cache
+const FEE_HISTORY_CACHE_ENTRIES: usize = 64;
+
+/// `eth_feeHistory` result. Kept loose since providers differ, e.g. in omitting
+/// `reward` or `gasUsedRatio`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawFeeHistory {
+    oldest_block: U64,
+    /// One entry per block, plus the base fee of the block after the newest
+    #[serde(default)]
+    base_fee_per_gas: Vec<U256>,
+    #[serde(default)]
+    gas_used_ratio: Vec<f64>,
+    /// Priority fee at each requested percentile, per block
+    #[serde(default)]
+    reward: Vec<Vec<U256>>,
+}
+
+/// Response for the fee history endpoint
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct FeeHistoryResponse {
+    pub oldest_block: u64,
+    /// Base fee of each block, oldest first
+    pub base_fee_per_gas: Vec<U256>,
+    /// Base fee of the block after the newest
+    pub next_base_fee_per_gas: Option<U256>,
+    pub gas_used_ratio: Vec<f64>,
+    pub reward_percentiles: Vec<f64>,
+    /// Priority fee paid at each percentile, per block
+    pub reward: Vec<Vec<U256>>,
+    /// One suggestion per percentile, slowest first
+    pub suggestions: Vec<FeeHistorySuggestion>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct FeeHistorySuggestion {
+    pub percentile: f64,
+    /// Median across the blocks of the priority fee paid at the percentile
+    pub max_priority_fee_per_gas: U256,
+    /// `2 * latest base fee + max_priority_fee_per_gas`
+    pub max_fee_per_gas: U256,
+}
+
+/// Priority fees paid at each percentile of recent blocks, for offering slow,
+/// standard and fast options
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct FeePriorityPercentiles {
+    /// Blocks the node returned rewards for
+    pub block_count: u64,
+    pub percentiles: Vec<u8>,
+    /// Median across the blocks of the priority fee paid at each percentile
+    pub rewards: Vec<U256>,
+}
+
+/// Block count and the bits of each percentile
+type FeeHistoryKey = (u64, Vec<u64>);
+
+/// Recent fee histories, keyed by block count and percentiles
+pub struct FeeHistoryCache {
+    entries: Mutex<LruCache<FeeHistoryKey, (Instant, FeeHistoryResponse)>>,
+    ttl: Duration,
+}
+
+impl FeeHistoryCache {
+    pub fn new(ttl: Duration) -> Self {
+        let capacity = NonZeroUsize::new(FEE_HISTORY_CACHE_ENTRIES).expect("non-zero capacity");
+        Self {
+            entries: Mutex::new(LruCache::new(capacity)),
+            ttl,
+        }
+    }
+
+    fn key(blocks: u64, percentiles: &[f64]) -> FeeHistoryKey {
+        (blocks, percentiles.iter().map(|p| p.to_bits()).collect())
+    }
+
+    fn get(&self, blocks: u64, percentiles: &[f64]) -> Option<FeeHistoryResponse> {
+        let key = Self::key(blocks, percentiles);
+        let mut entries = self.entries.lock().unwrap();
+        match entries.get(&key) {
+            Some((inserted, history)) if inserted.elapsed() < self.ttl => Some(history.clone()),
+            Some(_) => {
+                entries.pop(&key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn insert(&self, blocks: u64, percentiles: &[f64], history: FeeHistoryResponse) {
+        self.entries
+            .lock()
+            .unwrap()
+            .put(Self::key(blocks, percentiles), (Instant::now(), history));
+    }
+}
+
+impl Default for FeeHistoryCache {
+    fn default() -> Self {
+        Self::new(Duration::from_secs(DEFAULT_FEE_HISTORY_CACHE_TTL_SECS))
+    }
+}
+
+impl<T: Transport> GasEstimator<T> {
+    /// Fetches the fee history of the latest `blocks` blocks and suggests fees at
+    /// each reward percentile. Results are cached for a few seconds.
+    pub async fn fee_history(
+        &self,
+        blocks: u64,
+        percentiles: Vec<f64>,
+    ) -> Result<FeeHistoryResponse, ApiError> {
+        check_fee_history_request(blocks, &percentiles)?;
+        if let Some(history) = self.fee_history_cache.get(blocks, &percentiles) {
+            return Ok(history);
+        }
+
+        let raw: RawFeeHistory = CallFuture::new(self.eth.transport().execute(
+            "eth_feeHistory",
+            vec![
+                helpers::serialize(&U64::from(blocks)),
+                helpers::serialize(&BlockNumber::Latest),
+                helpers::serialize(&percentiles),
+            ],
+        ))
+        .await
+        .map_err(|e| {
+            if is_method_unsupported(&e) {
+                ApiError::ServiceUnavailable(format!(
+                    "The configured RPC doesn't support eth_feeHistory (pre-London chain?): {e}"
+                ))
+            } else {
+                ApiError::InternalServerError(format!("RPC call failed: {e}"))
+            }
+        })?;
+        let history = fee_history_response(raw, percentiles)?;
+
+        self.fee_history_cache
+            .insert(blocks, &history.reward_percentiles, history.clone());
+        Ok(history)
+    }
+
+    /// Median priority fee paid at each percentile over the latest `blocks` blocks
+    pub async fn priority_fee_percentiles(
+        &self,
+        blocks: u64,
+        percentiles: Vec<u8>,
+    ) -> Result<FeePriorityPercentiles, ApiError> {
+        let history = self
+            .fee_history(blocks, percentiles.iter().map(|&p| p.into()).collect())
+            .await?;
+        Ok(FeePriorityPercentiles {
+            block_count: history.reward.len() as u64,
+            percentiles,
+            rewards: history
+                .suggestions
+                .iter()
+                .map(|suggestion| suggestion.max_priority_fee_per_gas)
+                .collect(),
+        })
+    }
+}
+
+/// Validates the block count and percentiles the way nodes do, so bad input is
+/// a `400` rather than an upstream error
+pub fn check_fee_history_request(blocks: u64, percentiles: &[f64]) -> Result<(), ApiError> {
+    if !(1..=MAX_FEE_HISTORY_BLOCKS).contains(&blocks) {
+        return Err(ApiError::BadRequest(format!(
+            "blocks of {blocks} must be between 1 and {MAX_FEE_HISTORY_BLOCKS}"
+        )));
+    }
+    if percentiles.is_empty() {
+        return Err(ApiError::BadRequest(
+            "At least one percentile is required".to_string(),
+        ));
+    }
+    if let Some(percentile) = percentiles.iter().find(|p| !(0.0..=100.0).contains(*p)) {
+        return Err(ApiError::BadRequest(format!(
+            "Percentile {percentile} must be between 0 and 100"
+        )));
+    }
+    if percentiles.windows(2).any(|pair| pair[1] < pair[0]) {
+        return Err(ApiError::BadRequest(
+            "Percentiles must be in ascending order".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+fn fee_history_response(
+    raw: RawFeeHistory,
+    percentiles: Vec<f64>,
+) -> Result<FeeHistoryResponse, ApiError> {
+    let blocks = raw.reward.len();
+    if blocks == 0
+        || raw
+            .reward
+            .iter()
+            .any(|rewards| rewards.len() != percentiles.len())
+    {
+        return Err(ApiError::ServiceUnavailable(
+            "Node returned no reward percentiles in its fee history".to_string(),
+        ));
+    }
+    // The newest block's base fee precedes the next block's, when the node sends it
+    let latest_base_fee = match raw.base_fee_per_gas.len() {
+        len if len > blocks => raw.base_fee_per_gas[blocks - 1],
+        _ => raw.base_fee_per_gas.last().copied().ok_or_else(|| {
+            ApiError::ServiceUnavailable(
+                "Node did not return EIP-1559 fee fields (pre-London chain?)".to_string(),
+            )
+        })?,
+    };
+
+    let suggestions = percentiles
+        .iter()
+        .enumerate()
+        .map(|(index, &percentile)| {
+            let max_priority_fee_per_gas =
+                median(raw.reward.iter().map(|rewards| rewards[index]).collect());
+            FeeHistorySuggestion {
+                percentile,
+                max_priority_fee_per_gas,
+                max_fee_per_gas: latest_base_fee
+                    .saturating_mul(2.into())
+                    .saturating_add(max_priority_fee_per_gas),
+            }
+        })
+        .collect();
+
+    let mut base_fee_per_gas = raw.base_fee_per_gas;
+    let next_base_fee_per_gas = (base_fee_per_gas.len() > blocks)
+        .then(|| base_fee_per_gas.pop())
+        .flatten();
+    Ok(FeeHistoryResponse {
+        oldest_block: raw.oldest_block.as_u64(),
+        base_fee_per_gas,
+        next_base_fee_per_gas,
+        gas_used_ratio: raw.gas_used_ratio,
+        reward_percentiles: percentiles,
+        reward: raw.reward,
+        suggestions,
+    })
+}
+
+/// Median of a non-empty list, averaging the middle two of an even one
+fn median(mut values: Vec<U256>) -> U256 {
+    values.sort();
+    let middle = values.len() / 2;
+    if values.len().is_multiple_of(2) {
+        (values[middle - 1] + values[middle]) / 2
+    } else {
+        values[middle]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use web3::transports::test::TestTransport;
+
+    fn fee_history() -> serde_json::Value {
+        json!({
+            "oldestBlock": "0x10",
+            "baseFeePerGas": ["0x64", "0x6e", "0x78", "0x82"],
+            "gasUsedRatio": [0.5, 0.9, 0.7],
+            "reward": [["0x1", "0x5", "0xa"], ["0x2", "0x3", "0x14"], ["0x1", "0x4", "0xf"]]
+        })
+    }
+
+    #[tokio::test]
+    async fn test_fee_history() {
+        let mut transport = TestTransport::default();
+        transport.add_response(fee_history());
+        let estimator = GasEstimator::new(transport.clone());
+
+        let history = estimator
+            .fee_history(3, vec![10.0, 50.0, 90.0])
+            .await
+            .unwrap();
+        transport.assert_request(
+            "eth_feeHistory",
+            &[
+                r#""0x3""#.to_string(),
+                r#""latest""#.to_string(),
+                "[10.0,50.0,90.0]".to_string(),
+            ],
+        );
+        assert_eq!(history.oldest_block, 16);
+        assert_eq!(
+            history.base_fee_per_gas,
+            vec![100.into(), 110.into(), 120.into()]
+        );
+        assert_eq!(history.next_base_fee_per_gas, Some(130.into()));
+
+        let fees: Vec<_> = history
+            .suggestions
+            .iter()
+            .map(|s| (s.max_priority_fee_per_gas, s.max_fee_per_gas))
+            .collect();
+        // Medians of the rewards, on top of twice the newest block's base fee of 120
+        assert_eq!(
+            fees,
+            vec![
+                (1.into(), 241.into()),
+                (4.into(), 244.into()),
+                (15.into(), 255.into()),
+            ]
+        );
+
+        // Served from the cache until the next block
+        let cached = estimator
+            .fee_history(3, vec![10.0, 50.0, 90.0])
+            .await
+            .unwrap();
+        assert_eq!(cached, history);
+        transport.assert_no_more_requests();
+    }
+
+    #[tokio::test]
+    async fn test_priority_fee_percentiles() {
+        let mut transport = TestTransport::default();
+        transport.add_response(fee_history());
+        let estimator = GasEstimator::new(transport.clone());
+
+        let percentiles = estimator
+            .priority_fee_percentiles(3, vec![10, 50, 90])
+            .await
+            .unwrap();
+        transport.assert_request(
+            "eth_feeHistory",
+            &[
+                r#""0x3""#.to_string(),
+                r#""latest""#.to_string(),
+                "[10.0,50.0,90.0]".to_string(),
+            ],
+        );
+        assert_eq!(
+            percentiles,
+            FeePriorityPercentiles {
+                block_count: 3,
+                percentiles: vec![10, 50, 90],
+                rewards: vec![1.into(), 4.into(), 15.into()],
+            }
+        );
+    }
+
+    #[test]
+    fn test_check_fee_history_request() {
+        assert!(check_fee_history_request(20, &[10.0, 50.0, 90.0]).is_ok());
+        assert!(check_fee_history_request(0, &[50.0]).is_err());
+        assert!(check_fee_history_request(1025, &[50.0]).is_err());
+        assert!(check_fee_history_request(20, &[]).is_err());
+        assert!(check_fee_history_request(20, &[50.0, 10.0]).is_err());
+        assert!(check_fee_history_request(20, &[101.0]).is_err());
+    }
+
+    #[test]
+    fn test_missing_rewards() {
+        let raw: RawFeeHistory = serde_json::from_value(json!({
+            "oldestBlock": "0x10",
+            "baseFeePerGas": ["0x64", "0x6e"],
+        }))
+        .unwrap();
+        let error = fee_history_response(raw, vec![50.0]).unwrap_err();
+        assert!(
+            matches!(error, ApiError::ServiceUnavailable(_)),
+            "{error:?}"
+        );
+    }
+
+    #[test]
+    fn test_median() {
+        assert_eq!(median(vec![3.into(), 1.into(), 2.into()]), 2.into());
+        assert_eq!(
+            median(vec![4.into(), 1.into(), 2.into(), 3.into()]),
+            2.into()
+        );
+    }
+}
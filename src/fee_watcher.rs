@@ -0,0 +1,280 @@
+//! Background watcher of the default chain's fees, which `/api/fees` serves and
+//! `/api/fees/stream` pushes without each client polling the node
+
+use crate::base_fee::BaseFeeResponse;
+use crate::estimator::{ApiError, GasEstimator};
+use crate::upstream_error::classify_rpc_error;
+use axum::response::sse::Event;
+use futures::{Stream, stream};
+use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::watch;
+use utoipa::ToSchema;
+use web3::{Transport, types::U256};
+
+/// One Ethereum block
+pub const DEFAULT_BASE_FEE_POLL_SECS: u64 = 12;
+/// How often `/api/fees/stream` sends a comment, so proxies don't close idle
+/// connections
+pub const FEE_STREAM_HEARTBEAT: Duration = Duration::from_secs(15);
+
+/// Fees of the latest block the watcher saw
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct FeeSnapshot {
+    /// Number of the latest block
+    pub block_number: u64,
+    /// Base fee of the latest block
+    pub base_fee_per_gas: U256,
+    /// Base fee of the block after it
+    pub next_base_fee_per_gas: U256,
+    /// Priority fee the node suggests
+    pub max_priority_fee_per_gas: U256,
+    /// `2 * base_fee_per_gas + max_priority_fee_per_gas`
+    pub max_fee_per_gas: U256,
+    /// Unix time the snapshot was fetched, in milliseconds
+    pub timestamp_ms: u64,
+    /// Whether the node failed to answer since, so fees may have moved
+    pub stale: bool,
+    /// Seconds since the snapshot was fetched, when it was served
+    pub age_secs: u64,
+}
+
+impl FeeSnapshot {
+    fn new(base_fee: BaseFeeResponse, max_priority_fee_per_gas: U256) -> Self {
+        Self {
+            block_number: base_fee.block_number,
+            base_fee_per_gas: base_fee.current,
+            next_base_fee_per_gas: base_fee.predicted_next,
+            max_priority_fee_per_gas,
+            max_fee_per_gas: base_fee
+                .current
+                .saturating_mul(2.into())
+                .saturating_add(max_priority_fee_per_gas),
+            timestamp_ms: now_ms(),
+            stale: false,
+            age_secs: 0,
+        }
+    }
+
+    /// Whether the snapshots have the same block and fees
+    fn same_fees(&self, other: &Self) -> bool {
+        (
+            self.block_number,
+            self.base_fee_per_gas,
+            self.next_base_fee_per_gas,
+            self.max_priority_fee_per_gas,
+        ) == (
+            other.block_number,
+            other.base_fee_per_gas,
+            other.next_base_fee_per_gas,
+            other.max_priority_fee_per_gas,
+        )
+    }
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+/// Latest [`FeeSnapshot`], refreshed by [`FeeWatcher::run`], with a channel
+/// notifying subscribers whenever the fees change or turn stale
+#[derive(Debug)]
+pub struct FeeWatcher {
+    snapshot: Arc<RwLock<Option<FeeSnapshot>>>,
+    changes: watch::Sender<()>,
+}
+
+impl Default for FeeWatcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FeeWatcher {
+    pub fn new() -> Self {
+        Self {
+            snapshot: Arc::default(),
+            changes: watch::Sender::new(()),
+        }
+    }
+
+    /// Latest snapshot with its current age, unset until the node first answered
+    pub fn snapshot(&self) -> Option<FeeSnapshot> {
+        let mut snapshot = self.snapshot.read().unwrap().clone()?;
+        snapshot.age_secs = now_ms().saturating_sub(snapshot.timestamp_ms) / 1000;
+        Some(snapshot)
+    }
+
+    /// Stores the outcome of a refresh. A failed one keeps the last snapshot,
+    /// marked stale. Subscribers are only notified of changed fees and of a
+    /// snapshot turning stale, not of every refresh.
+    pub fn record(&self, fetched: Result<FeeSnapshot, ApiError>) {
+        let changed = {
+            let mut current = self.snapshot.write().unwrap();
+            match fetched {
+                Ok(snapshot) => {
+                    let changed = current
+                        .as_ref()
+                        .is_none_or(|current| current.stale || !current.same_fees(&snapshot));
+                    *current = Some(snapshot);
+                    changed
+                }
+                Err(error) => {
+                    tracing::warn!(%error, "Failed to refresh fees, serving the last ones");
+                    current
+                        .as_mut()
+                        .is_some_and(|current| !std::mem::replace(&mut current.stale, true))
+                }
+            }
+        };
+        if changed {
+            self.changes.send_replace(());
+        }
+    }
+
+    /// Refreshes the snapshot from `estimator` every `interval`, forever
+    pub async fn run<T: Transport>(
+        self: Arc<Self>,
+        estimator: Arc<GasEstimator<T>>,
+        interval: Duration,
+    ) {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+            self.record(estimator.fee_snapshot().await);
+        }
+    }
+
+    /// Server-sent events of the snapshot as JSON: the current one, then one
+    /// per change
+    pub fn events(self: Arc<Self>) -> impl Stream<Item = Result<Event, Infallible>> {
+        let mut changes = self.changes.subscribe();
+        changes.mark_changed();
+        stream::unfold((self, changes), |(watcher, mut changes)| async move {
+            loop {
+                // The watcher owns the sender, so it is never dropped
+                changes.changed().await.ok()?;
+                if let Some(snapshot) = watcher.snapshot() {
+                    let event = Event::default()
+                        .json_data(&snapshot)
+                        .expect("fee snapshots serialize to JSON");
+                    return Some((Ok(event), (watcher, changes)));
+                }
+            }
+        })
+    }
+}
+
+impl<T: Transport> GasEstimator<T> {
+    /// Fetches the latest block's fees and the suggested priority fee
+    pub async fn fee_snapshot(&self) -> Result<FeeSnapshot, ApiError> {
+        let (base_fee, max_priority_fee) =
+            futures::join!(self.next_base_fee(), self.max_priority_fee_per_gas());
+        Ok(FeeSnapshot::new(
+            base_fee?,
+            max_priority_fee.map_err(classify_rpc_error)?,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+    use serde_json::json;
+    use web3::transports::test::TestTransport;
+    use web3::types::{Block, H256, U64};
+
+    fn snapshot(block_number: u64, base_fee: u64) -> FeeSnapshot {
+        FeeSnapshot::new(
+            BaseFeeResponse {
+                current: base_fee.into(),
+                predicted_next: base_fee.into(),
+                block_number,
+            },
+            1.into(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_fee_snapshot() {
+        let mut transport = TestTransport::default();
+        // The priority fee request is sent first, when its future is created
+        transport.add_response(json!("0x3b9aca00"));
+        transport.add_response(
+            serde_json::to_value(Block::<H256> {
+                number: Some(U64::from(16)),
+                base_fee_per_gas: Some(10_000_000_000u64.into()),
+                gas_used: 30_000_000.into(),
+                gas_limit: 30_000_000.into(),
+                ..Default::default()
+            })
+            .unwrap(),
+        );
+        let estimator = GasEstimator::new(transport);
+
+        let snapshot = estimator.fee_snapshot().await.unwrap();
+        assert_eq!(snapshot.block_number, 16);
+        assert_eq!(snapshot.base_fee_per_gas, 10_000_000_000u64.into());
+        // A full block raises the base fee by an eighth
+        assert_eq!(snapshot.next_base_fee_per_gas, 11_250_000_000u64.into());
+        assert_eq!(snapshot.max_priority_fee_per_gas, 1_000_000_000u64.into());
+        assert_eq!(snapshot.max_fee_per_gas, 21_000_000_000u64.into());
+        assert!(!snapshot.stale);
+    }
+
+    #[tokio::test]
+    async fn test_record_notifies_changes() {
+        let watcher = Arc::new(FeeWatcher::new());
+        let mut changes = watcher.changes.subscribe();
+        assert_eq!(watcher.snapshot(), None);
+
+        // Nothing to serve before the node first answered
+        watcher.record(Err(ApiError::UpstreamUnavailable("RPC call failed".into())));
+        assert_eq!(watcher.snapshot(), None);
+        assert!(!changes.has_changed().unwrap());
+
+        watcher.record(Ok(snapshot(1, 100)));
+        assert!(changes.has_changed().unwrap());
+        changes.mark_unchanged();
+        // A refresh without new fees only renews the snapshot
+        watcher.record(Ok(snapshot(1, 100)));
+        assert!(!changes.has_changed().unwrap());
+
+        // Outages keep the last fees, marked stale once
+        watcher.record(Err(ApiError::UpstreamTimeout("RPC call timed out".into())));
+        assert!(changes.has_changed().unwrap());
+        changes.mark_unchanged();
+        watcher.record(Err(ApiError::UpstreamTimeout("RPC call timed out".into())));
+        assert!(!changes.has_changed().unwrap());
+        let stale = watcher.snapshot().unwrap();
+        assert!(stale.stale);
+        assert_eq!(stale.block_number, 1);
+
+        // Recovering clears the flag, even with the same fees
+        watcher.record(Ok(snapshot(1, 100)));
+        assert!(changes.has_changed().unwrap());
+        assert!(!watcher.snapshot().unwrap().stale);
+    }
+
+    #[tokio::test]
+    async fn test_events() {
+        let watcher = Arc::new(FeeWatcher::new());
+        watcher.record(Ok(snapshot(1, 100)));
+        let mut events = Box::pin(watcher.clone().events());
+
+        // The current snapshot comes first, then each change
+        assert!(events.next().await.is_some());
+        watcher.record(Ok(snapshot(2, 110)));
+        assert!(events.next().await.is_some());
+        watcher.record(Ok(snapshot(2, 110)));
+        let pending = tokio::time::timeout(Duration::from_millis(50), events.next()).await;
+        assert!(pending.is_err(), "unchanged fees sent no event");
+    }
+}
@@ -0,0 +1,208 @@
+//! Gas constants based on the Ethereum Yellow Paper and EIPs
+
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use utoipa::ToSchema;
+
+pub const GAS_TX_BASE: u64 = 21000;
+pub const GAS_TX_DATA_ZERO: u64 = 4;
+pub const GAS_TX_DATA_NON_ZERO: u64 = 16;
+pub const GAS_TX_CREATE: u64 = 32000;
+
+/// Intrinsic transaction costs used by static estimates. Mainnet values by default;
+/// chains that price transactions differently can override them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GasConstants {
+    pub tx_base: u64,
+    pub tx_data_zero: u64,
+    pub tx_data_non_zero: u64,
+    pub tx_create: u64,
+}
+
+impl Default for GasConstants {
+    fn default() -> Self {
+        Self {
+            tx_base: GAS_TX_BASE,
+            tx_data_zero: GAS_TX_DATA_ZERO,
+            tx_data_non_zero: GAS_TX_DATA_NON_ZERO,
+            tx_create: GAS_TX_CREATE,
+        }
+    }
+}
+
+impl GasConstants {
+    /// Reads `GAS_TX_BASE`, `GAS_TX_DATA_ZERO`, `GAS_TX_DATA_NON_ZERO` and
+    /// `GAS_TX_CREATE`, keeping the mainnet value of those that aren't set
+    pub fn from_env() -> Result<Self, String> {
+        Self::default().with_vars(|name| std::env::var(name).ok())
+    }
+
+    /// Replaces the constants whose variable is set, keeping the others
+    pub fn with_vars(self, var: impl Fn(&str) -> Option<String>) -> Result<Self, String> {
+        let read = |name: &str, default: u64| -> Result<u64, String> {
+            let value = match var(name) {
+                Some(value) => value
+                    .trim()
+                    .parse()
+                    .map_err(|e| format!("Failed to parse {name}: {e}"))?,
+                None => default,
+            };
+            // Every transaction pays for these, so zero can only be a misconfiguration
+            if value == 0 {
+                return Err(format!("{name} must be greater than zero"));
+            }
+            Ok(value)
+        };
+
+        Ok(Self {
+            tx_base: read("GAS_TX_BASE", self.tx_base)?,
+            tx_data_zero: read("GAS_TX_DATA_ZERO", self.tx_data_zero)?,
+            tx_data_non_zero: read("GAS_TX_DATA_NON_ZERO", self.tx_data_non_zero)?,
+            tx_create: read("GAS_TX_CREATE", self.tx_create)?,
+        })
+    }
+}
+
+/// Transactions that predate EIP-2718 typed envelopes
+pub const LEGACY_TX_TYPE: u8 = 0x00;
+
+// EIP-2930: Optional access lists
+pub const ACCESS_LIST_TX_TYPE: u8 = 0x01;
+pub const GAS_ACCESS_LIST_ADDRESS: u64 = 2400;
+pub const GAS_ACCESS_LIST_STORAGE_KEY: u64 = 1900;
+
+// EIP-2028: Transaction data gas cost reduction (Istanbul, mainnet)
+pub const EIP2028_ACTIVATION_BLOCK: u64 = 9_069_000;
+
+// EIP-3860: Limit and meter initcode (Shanghai, mainnet)
+pub const EIP3860_ACTIVATION_BLOCK: u64 = 17_034_870;
+pub const INITCODE_WORD_COST: u64 = 2;
+pub const MAX_INITCODE_SIZE: usize = 49152;
+
+/// Default limit on the calldata of estimated transactions: the largest
+/// transaction Geth and most clients accept into their pools (128 KiB), so larger
+/// calldata can never make it into a block. Unlike [`MAX_INITCODE_SIZE`], which
+/// EIP-3860 sets for contract creations only, it applies to every transaction.
+pub const MAX_CALLDATA_SIZE: usize = 131_072;
+
+// EIP-4844: Shard Blob Transactions
+pub const BLOB_TX_TYPE: u8 = 0x03;
+pub const GAS_PER_BLOB: u64 = 131_072;
+pub const MAX_BLOBS_PER_TX: u64 = 6;
+pub const MIN_BASE_FEE_PER_BLOB_GAS: u64 = 1;
+/// Blob base fee update fraction since Prague (EIP-7691)
+pub const BLOB_BASE_FEE_UPDATE_FRACTION: u64 = 5_007_716;
+
+// EIP-7702: Set Code Transactions
+pub const SET_CODE_TX_TYPE: u8 = 0x04;
+/// Intrinsic cost of each authorization, charged as if its authority were empty
+pub const PER_EMPTY_ACCOUNT_COST: u64 = 25_000;
+
+// EIP-7623: Increase calldata cost (Prague)
+/// Floor price of each calldata token: a zero byte is one token, a non-zero byte four
+pub const TOTAL_COST_FLOOR_PER_TOKEN: u64 = 10;
+/// Tokens of a non-zero calldata byte
+pub const TOKENS_PER_NON_ZERO_BYTE: u64 = 4;
+/// Chains pricing calldata with the EIP-7623 floor since Prague: Ethereum mainnet,
+/// Sepolia, Holesky, Hoodi and Gnosis
+pub const EIP7623_CHAIN_IDS: [u64; 5] = [1, 11_155_111, 17_000, 560_048, 100];
+
+/// How a chain prices calldata, and so which intrinsic cost static estimates charge
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PricingRule {
+    /// 4 gas per zero and 16 per non-zero calldata byte
+    #[default]
+    Eip2028,
+    /// As before, but calldata pays at least 10 gas per token, however little
+    /// the transaction executes
+    Eip7623,
+}
+
+impl FromStr for PricingRule {
+    type Err = String;
+
+    fn from_str(rule: &str) -> Result<Self, Self::Err> {
+        match rule.trim() {
+            "eip2028" => Ok(Self::Eip2028),
+            "eip7623" => Ok(Self::Eip7623),
+            other => Err(format!(
+                "Unknown pricing rule {other}; expected eip2028 or eip7623"
+            )),
+        }
+    }
+}
+
+impl PricingRule {
+    /// Rule of a chain known to have activated EIP-7623, the older one otherwise
+    pub fn for_chain(chain_id: u64) -> Self {
+        if EIP7623_CHAIN_IDS.contains(&chain_id) {
+            Self::Eip7623
+        } else {
+            Self::Eip2028
+        }
+    }
+}
+
+/// Least gas an EIP-7623 transaction pays for its calldata and base cost
+pub fn calldata_floor(tx_base: u64, zero_bytes: u64, non_zero_bytes: u64) -> u64 {
+    let tokens = zero_bytes + non_zero_bytes * TOKENS_PER_NON_ZERO_BYTE;
+    tx_base + tokens * TOTAL_COST_FLOOR_PER_TOKEN
+}
+
+// EIP-1559: Fee market change (London)
+pub const EIP1559_TX_TYPE: u8 = 0x02;
+pub const BASE_FEE_MAX_CHANGE_DENOMINATOR: u64 = 8;
+pub const ELASTICITY_MULTIPLIER: u64 = 2;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gas_constants_with_vars() {
+        let constants = GasConstants::default()
+            .with_vars(|name| match name {
+                "GAS_TX_BASE" => Some("53000".to_string()),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(
+            constants,
+            GasConstants {
+                tx_base: 53000,
+                ..Default::default()
+            }
+        );
+
+        let error = GasConstants::default()
+            .with_vars(|name| (name == "GAS_TX_DATA_ZERO").then(|| "0".to_string()))
+            .unwrap_err();
+        assert_eq!(error, "GAS_TX_DATA_ZERO must be greater than zero");
+
+        let error = GasConstants::default()
+            .with_vars(|name| (name == "GAS_TX_CREATE").then(|| "lots".to_string()))
+            .unwrap_err();
+        assert!(
+            error.starts_with("Failed to parse GAS_TX_CREATE"),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn test_pricing_rule() {
+        assert_eq!("eip7623".parse(), Ok(PricingRule::Eip7623));
+        assert_eq!(" eip2028".parse(), Ok(PricingRule::Eip2028));
+        assert!("prague".parse::<PricingRule>().is_err());
+        assert_eq!(PricingRule::for_chain(1), PricingRule::Eip7623);
+        assert_eq!(PricingRule::for_chain(137), PricingRule::Eip2028);
+    }
+
+    #[test]
+    fn test_calldata_floor() {
+        // 10 zero bytes and 10 non-zero bytes are 50 tokens
+        assert_eq!(calldata_floor(GAS_TX_BASE, 10, 10), 21_500);
+        assert_eq!(calldata_floor(GAS_TX_BASE, 0, 0), GAS_TX_BASE);
+    }
+}
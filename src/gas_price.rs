@@ -0,0 +1,81 @@
+//! Gas price snapshots streamed to WebSocket clients
+
+use crate::estimator::{ApiError, ErrorResponse, GasEstimator};
+use axum::extract::ws::{Message, WebSocket};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use utoipa::ToSchema;
+use web3::{Transport, types::U256};
+
+/// One Ethereum block
+pub const DEFAULT_GAS_PRICE_POLL_INTERVAL_SECS: u64 = 12;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct GasPriceUpdate {
+    pub gas_price: U256,
+    pub max_priority_fee: U256,
+    /// Unix time of the snapshot, in milliseconds
+    pub timestamp_ms: u64,
+}
+
+impl<T: Transport> GasEstimator<T> {
+    /// Fetches the current gas price and priority fee suggestions
+    pub async fn gas_price_update(&self) -> Result<GasPriceUpdate, ApiError> {
+        let rpc_error = |e| ApiError::InternalServerError(format!("RPC call failed: {e}"));
+        let (gas_price, max_priority_fee) =
+            futures::join!(self.eth.gas_price(), self.max_priority_fee_per_gas());
+
+        Ok(GasPriceUpdate {
+            gas_price: gas_price.map_err(rpc_error)?,
+            max_priority_fee: max_priority_fee.map_err(rpc_error)?,
+            timestamp_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64,
+        })
+    }
+
+    /// Sends a [`GasPriceUpdate`] every `interval` until the client closes the
+    /// connection. Failed fetches are reported as error messages and polling continues.
+    pub async fn stream_gas_prices(&self, mut socket: WebSocket, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {
+                    let message = match self.gas_price_update().await {
+                        Ok(update) => serde_json::to_string(&update),
+                        Err(e) => serde_json::to_string(&ErrorResponse::from(e)),
+                    }
+                    .expect("gas price messages serialize to JSON");
+                    if socket.send(Message::Text(message)).await.is_err() {
+                        return;
+                    }
+                }
+                message = socket.recv() => match message {
+                    // The close reply is sent by the socket itself
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+                    Some(Ok(_)) => {}
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use web3::transports::test::TestTransport;
+
+    #[tokio::test]
+    async fn test_gas_price_update() {
+        let mut transport = TestTransport::default();
+        transport.add_response("0x4a817c800".into()); // 20 gwei
+        transport.add_response("0x3b9aca00".into()); // 1 gwei
+        let estimator = GasEstimator::new(transport);
+
+        let update = estimator.gas_price_update().await.unwrap();
+        assert_eq!(update.gas_price, 20_000_000_000u64.into());
+        assert_eq!(update.max_priority_fee, 1_000_000_000u64.into());
+        assert!(update.timestamp_ms > 0);
+    }
+}
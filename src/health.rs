@@ -0,0 +1,241 @@
+//! Readiness of the upstream node, from cached `eth_blockNumber` probes and the
+//! outcome of recent estimates
+
+use crate::estimator::{ApiError, ErrorResponse, GasEstimator};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::{Duration, Instant};
+use web3::Transport;
+
+/// How long a readiness probe is reused before the node is asked again
+pub const DEFAULT_READINESS_CHECK_INTERVAL_SECS: u64 = 5;
+/// Consecutive failed estimates that mark the instance as not ready
+pub const DEFAULT_READINESS_FAILURE_THRESHOLD: u32 = 5;
+
+/// Outcome of asking the node for its latest block
+#[derive(Debug, Clone, PartialEq)]
+struct Probe {
+    checked_at: Instant,
+    result: Result<(u64, Duration), String>,
+}
+
+/// Whether the instance can serve RPC estimates
+#[derive(Debug, Clone, PartialEq)]
+pub enum Readiness {
+    Ready {
+        latest_block: u64,
+        /// Time the node took to answer the probe
+        rpc_latency: Duration,
+    },
+    /// The node didn't answer the probe
+    Unreachable(String),
+    /// The node answers the probe, but this many estimates in a row failed
+    FailingEstimates(u32),
+}
+
+/// Readiness state shared by the estimator, which reports the outcome of its RPC
+/// estimates, and the readiness endpoint
+#[derive(Debug)]
+pub struct UpstreamHealth {
+    failure_threshold: u32,
+    consecutive_failures: AtomicU32,
+    /// When the failed estimates reached the threshold. Cleared by a successful
+    /// estimate, or by a successful probe a check interval later.
+    tripped_at: Mutex<Option<Instant>>,
+    /// Held while probing, so concurrent readiness checks share one probe
+    last_probe: tokio::sync::Mutex<Option<Probe>>,
+}
+
+impl UpstreamHealth {
+    pub fn new(failure_threshold: u32) -> Self {
+        Self {
+            failure_threshold: failure_threshold.max(1),
+            consecutive_failures: AtomicU32::new(0),
+            tripped_at: Mutex::new(None),
+            last_probe: tokio::sync::Mutex::new(None),
+        }
+    }
+
+    /// Records an estimate the node answered
+    pub fn record_success(&self) {
+        self.consecutive_failures.store(0, Ordering::Relaxed);
+        *self.tripped_at.lock().unwrap() = None;
+    }
+
+    /// Records an estimate that failed upstream
+    pub fn record_failure(&self) {
+        let failures = self.consecutive_failures.fetch_add(1, Ordering::Relaxed) + 1;
+        if failures == self.failure_threshold {
+            tracing::warn!(
+                failures,
+                "Estimates keep failing, marking the service not ready"
+            );
+            self.tripped_at
+                .lock()
+                .unwrap()
+                .get_or_insert_with(Instant::now);
+        }
+    }
+
+    /// Consecutive failed estimates, while they keep the instance out of rotation
+    fn failing_estimates(&self) -> Option<u32> {
+        self.tripped_at
+            .lock()
+            .unwrap()
+            .map(|_| self.consecutive_failures.load(Ordering::Relaxed))
+    }
+
+    /// Clears a trip once a probe made at least `interval` after it succeeded
+    fn recover(&self, probe: &Probe, interval: Duration) {
+        let mut tripped_at = self.tripped_at.lock().unwrap();
+        if probe.result.is_ok()
+            && tripped_at.is_some_and(|tripped| probe.checked_at >= tripped + interval)
+        {
+            *tripped_at = None;
+            self.consecutive_failures.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+impl Default for UpstreamHealth {
+    fn default() -> Self {
+        Self::new(DEFAULT_READINESS_FAILURE_THRESHOLD)
+    }
+}
+
+impl<T: Transport> GasEstimator<T> {
+    /// Checks that the node answers `eth_blockNumber` within `timeout` and that
+    /// estimates aren't failing. Probes are reused for `interval`.
+    pub async fn readiness(&self, interval: Duration, timeout: Duration) -> Readiness {
+        let probe = {
+            let mut last_probe = self.health.last_probe.lock().await;
+            match &*last_probe {
+                Some(probe) if probe.checked_at.elapsed() < interval => probe.clone(),
+                _ => {
+                    let probe = self.probe(timeout).await;
+                    *last_probe = Some(probe.clone());
+                    probe
+                }
+            }
+        };
+        self.health.recover(&probe, interval);
+
+        match (probe.result, self.health.failing_estimates()) {
+            (Err(error), _) => Readiness::Unreachable(error),
+            (Ok(_), Some(failures)) => Readiness::FailingEstimates(failures),
+            (Ok((latest_block, rpc_latency)), None) => Readiness::Ready {
+                latest_block,
+                rpc_latency,
+            },
+        }
+    }
+
+    async fn probe(&self, timeout: Duration) -> Probe {
+        let checked_at = Instant::now();
+        let result = tokio::time::timeout(timeout, self.latest_block_number())
+            .await
+            .unwrap_or_else(|_| {
+                Err(ApiError::ServiceUnavailable(format!(
+                    "RPC didn't answer within {} seconds",
+                    timeout.as_secs_f64()
+                )))
+            })
+            .map(|block| (block, checked_at.elapsed()))
+            .map_err(|e| ErrorResponse::from(e).error);
+        Probe { checked_at, result }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use web3::transports::test::TestTransport;
+
+    const INTERVAL: Duration = Duration::from_secs(60);
+    const TIMEOUT: Duration = Duration::from_secs(1);
+
+    #[tokio::test]
+    async fn test_probe_is_cached() {
+        let mut transport = TestTransport::default();
+        transport.add_response(json!("0x10"));
+        let estimator = GasEstimator::new(transport.clone());
+
+        for _ in 0..2 {
+            let readiness = estimator.readiness(INTERVAL, TIMEOUT).await;
+            assert!(
+                matches!(
+                    readiness,
+                    Readiness::Ready {
+                        latest_block: 16,
+                        ..
+                    }
+                ),
+                "{readiness:?}"
+            );
+        }
+        transport.assert_request("eth_blockNumber", &[]);
+        transport.assert_no_more_requests();
+    }
+
+    #[tokio::test]
+    async fn test_failed_probe() {
+        let mut transport = TestTransport::default();
+        transport.add_response(json!({ "unexpected": true }));
+        let estimator = GasEstimator::new(transport);
+
+        let readiness = estimator.readiness(INTERVAL, TIMEOUT).await;
+        assert!(
+            matches!(readiness, Readiness::Unreachable(_)),
+            "{readiness:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_failing_estimates() {
+        let mut transport = TestTransport::default();
+        transport.add_response(json!("0x10"));
+        let estimator = GasEstimator::new(transport);
+        let health = &estimator.health;
+
+        for _ in 0..DEFAULT_READINESS_FAILURE_THRESHOLD - 1 {
+            health.record_failure();
+        }
+        let readiness = estimator.readiness(Duration::ZERO, TIMEOUT).await;
+        assert!(
+            matches!(readiness, Readiness::Ready { .. }),
+            "{readiness:?}"
+        );
+
+        health.record_failure();
+        let readiness = estimator.readiness(INTERVAL, TIMEOUT).await;
+        assert_eq!(
+            readiness,
+            Readiness::FailingEstimates(DEFAULT_READINESS_FAILURE_THRESHOLD)
+        );
+
+        health.record_success();
+        let readiness = estimator.readiness(INTERVAL, TIMEOUT).await;
+        assert!(
+            matches!(readiness, Readiness::Ready { .. }),
+            "{readiness:?}"
+        );
+    }
+
+    #[test]
+    fn test_probe_recovers_after_an_interval() {
+        let health = UpstreamHealth::new(1);
+        health.record_failure();
+        let tripped_at = health.tripped_at.lock().unwrap().unwrap();
+        let probe = |checked_at| Probe {
+            checked_at,
+            result: Ok((16, Duration::ZERO)),
+        };
+
+        // A probe right after the failures doesn't clear them
+        health.recover(&probe(tripped_at), INTERVAL);
+        assert_eq!(health.failing_estimates(), Some(1));
+        health.recover(&probe(tripped_at + INTERVAL), INTERVAL);
+        assert_eq!(health.failing_estimates(), None);
+    }
+}
@@ -0,0 +1,359 @@
+//! Estimates of token transfers and approvals learned from earlier RPC results.
+//!
+//! ERC-20 and ERC-721 calls cost about the same every time for a given token, so
+//! once enough consistent node estimates of a token's call have been seen, further
+//! ones are answered from the highest of them without calling the node.
+
+use crate::eip7702;
+use crate::estimator::{EstimateGasRequest, GasEstimator};
+use lru::LruCache;
+use std::collections::VecDeque;
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use web3::Transport;
+use web3::types::{Address, BlockNumber};
+
+/// `transfer(address,uint256)`
+pub const TRANSFER_SELECTOR: [u8; 4] = [0xa9, 0x05, 0x9c, 0xbb];
+/// `approve(address,uint256)`
+pub const APPROVE_SELECTOR: [u8; 4] = [0x09, 0x5e, 0xa7, 0xb3];
+/// `transferFrom(address,address,uint256)`, shared by ERC-20 and ERC-721
+pub const TRANSFER_FROM_SELECTOR: [u8; 4] = [0x23, 0xb8, 0x72, 0xdd];
+/// ERC-721 `safeTransferFrom(address,address,uint256)`
+pub const SAFE_TRANSFER_FROM_SELECTOR: [u8; 4] = [0x42, 0x84, 0x2e, 0x0e];
+/// ERC-721 `safeTransferFrom(address,address,uint256,bytes)`
+pub const SAFE_TRANSFER_FROM_WITH_DATA_SELECTOR: [u8; 4] = [0xb8, 0x8d, 0x4f, 0xde];
+
+const SELECTORS: [[u8; 4]; 5] = [
+    TRANSFER_SELECTOR,
+    APPROVE_SELECTOR,
+    TRANSFER_FROM_SELECTOR,
+    SAFE_TRANSFER_FROM_SELECTOR,
+    SAFE_TRANSFER_FROM_WITH_DATA_SELECTOR,
+];
+
+pub const DEFAULT_HEURISTIC_MIN_SAMPLES: usize = 20;
+pub const DEFAULT_HEURISTIC_MAX_DEVIATION_PERCENT: u64 = 10;
+pub const DEFAULT_HEURISTIC_MARGIN_PERCENT: u64 = 10;
+/// Tokens without estimates for this long are forgotten
+pub const DEFAULT_HEURISTIC_TTL_SECS: u64 = 3600;
+pub const DEFAULT_HEURISTIC_MAX_ENTRIES: usize = 10_000;
+/// Most recent estimates kept per token call for the deviation check
+const MAX_SAMPLES: usize = 100;
+
+/// When learned estimates are trusted
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HeuristicConfig {
+    /// Node estimates of a token call needed before answering it from them
+    pub min_samples: usize,
+    /// Largest standard deviation of the estimates, in percent of their mean
+    pub max_deviation_percent: u64,
+    /// Added to the highest estimate seen, in percent
+    pub margin_percent: u64,
+    /// How long a token call is remembered after its last node estimate
+    pub ttl: Duration,
+    /// Token calls remembered at most, least recently used first out
+    pub max_entries: usize,
+}
+
+impl Default for HeuristicConfig {
+    fn default() -> Self {
+        Self {
+            min_samples: DEFAULT_HEURISTIC_MIN_SAMPLES,
+            max_deviation_percent: DEFAULT_HEURISTIC_MAX_DEVIATION_PERCENT,
+            margin_percent: DEFAULT_HEURISTIC_MARGIN_PERCENT,
+            ttl: Duration::from_secs(DEFAULT_HEURISTIC_TTL_SECS),
+            max_entries: DEFAULT_HEURISTIC_MAX_ENTRIES,
+        }
+    }
+}
+
+/// Function called on a token contract
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TokenCall {
+    pub token: Address,
+    pub selector: [u8; 4],
+}
+
+impl TokenCall {
+    /// The token call of requests whose cost only depends on the token: a known
+    /// selector sent without value or state overrides, against the latest block
+    pub fn of(request: &EstimateGasRequest, block: Option<BlockNumber>) -> Option<Self> {
+        let tx = &request.tx;
+        let selector = tx.data.as_ref()?.0.get(..4)?.try_into().ok()?;
+        let plain = request.state_overrides.is_none()
+            && tx.value.is_none_or(|value| value.is_zero())
+            && tx.transaction_type.is_none_or(|kind| kind.as_u64() <= 2)
+            && !eip7702::is_set_code_transaction(tx)
+            && matches!(block, None | Some(BlockNumber::Latest));
+        (plain && SELECTORS.contains(&selector)).then_some(Self {
+            token: tx.to?,
+            selector,
+        })
+    }
+}
+
+/// Estimate answered from earlier node estimates
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LearnedEstimate {
+    /// Highest node estimate seen, which covers calls writing fresh storage slots,
+    /// e.g. transfers to holders without a balance
+    pub high_water: u64,
+    pub gas_limit: u64,
+}
+
+struct Samples {
+    recent: VecDeque<u64>,
+    high_water: u64,
+    updated: Instant,
+}
+
+impl Samples {
+    /// Standard deviation of the recent estimates, in percent of their mean
+    fn deviation_percent(&self) -> f64 {
+        let count = self.recent.len() as f64;
+        let mean = self.recent.iter().sum::<u64>() as f64 / count;
+        let variance = self
+            .recent
+            .iter()
+            .map(|&gas| (gas as f64 - mean).powi(2))
+            .sum::<f64>()
+            / count;
+        variance.sqrt() / mean * 100.0
+    }
+}
+
+struct Entries {
+    samples: LruCache<TokenCall, Samples>,
+    last_pruned: Instant,
+}
+
+/// Node estimates of token calls, in memory
+pub struct HeuristicStore {
+    entries: Mutex<Entries>,
+    config: HeuristicConfig,
+}
+
+impl HeuristicStore {
+    pub fn new(config: HeuristicConfig) -> Self {
+        let capacity = NonZeroUsize::new(config.max_entries).unwrap_or(NonZeroUsize::MIN);
+        Self {
+            entries: Mutex::new(Entries {
+                samples: LruCache::new(capacity),
+                last_pruned: Instant::now(),
+            }),
+            config,
+        }
+    }
+
+    /// Learned estimate of the call, once it has enough consistent samples
+    pub fn estimate(&self, call: &TokenCall) -> Option<LearnedEstimate> {
+        let mut entries = self.entries.lock().unwrap();
+        let samples = entries.samples.get(call)?;
+        if samples.updated.elapsed() >= self.config.ttl {
+            entries.samples.pop(call);
+            return None;
+        }
+        if samples.recent.len() < self.config.min_samples.max(1)
+            || samples.deviation_percent() > self.config.max_deviation_percent as f64
+        {
+            return None;
+        }
+        let high_water = samples.high_water;
+        Some(LearnedEstimate {
+            high_water,
+            gas_limit: high_water.saturating_mul(100 + self.config.margin_percent) / 100,
+        })
+    }
+
+    /// Adds a node estimate of the call. Expired calls are dropped at most once per
+    /// TTL, so tokens no longer estimated don't stay around.
+    pub fn record(&self, call: TokenCall, gas: u64) {
+        let mut entries = self.entries.lock().unwrap();
+        let ttl = self.config.ttl;
+        if entries.last_pruned.elapsed() >= ttl {
+            let expired: Vec<_> = entries
+                .samples
+                .iter()
+                .filter(|(_, samples)| samples.updated.elapsed() >= ttl)
+                .map(|(call, _)| *call)
+                .collect();
+            for call in expired {
+                entries.samples.pop(&call);
+            }
+            entries.last_pruned = Instant::now();
+        }
+
+        let samples = entries.samples.get_or_insert_mut(call, || Samples {
+            recent: VecDeque::new(),
+            high_water: 0,
+            updated: Instant::now(),
+        });
+        if samples.recent.len() == MAX_SAMPLES {
+            samples.recent.pop_front();
+        }
+        samples.recent.push_back(gas);
+        samples.high_water = samples.high_water.max(gas);
+        samples.updated = Instant::now();
+    }
+
+    /// Number of token calls remembered
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T: Transport> GasEstimator<T> {
+    /// Token call of the request when learned estimates are enabled
+    pub(crate) fn heuristic_call(
+        &self,
+        request: &EstimateGasRequest,
+        block: Option<BlockNumber>,
+    ) -> Option<(&HeuristicStore, TokenCall)> {
+        let store = self.heuristics.as_ref()?;
+        Some((store, TokenCall::of(request, block)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use web3::transports::test::TestTransport;
+    use web3::types::{Bytes, CallRequest, U64};
+
+    fn transfer() -> EstimateGasRequest {
+        let mut data = TRANSFER_SELECTOR.to_vec();
+        data.extend([0; 64]);
+        CallRequest {
+            to: Some(Address::repeat_byte(0x0d)),
+            data: Some(Bytes::from(data)),
+            ..Default::default()
+        }
+        .into()
+    }
+
+    fn store(min_samples: usize) -> HeuristicStore {
+        HeuristicStore::new(HeuristicConfig {
+            min_samples,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_token_call() {
+        let call = TokenCall::of(&transfer(), None).unwrap();
+        assert_eq!(call.token, Address::repeat_byte(0x0d));
+        assert_eq!(call.selector, TRANSFER_SELECTOR);
+        assert!(TokenCall::of(&transfer(), Some(BlockNumber::Latest)).is_some());
+
+        // Historical blocks, value, overrides and unknown selectors aren't learned
+        assert_eq!(TokenCall::of(&transfer(), Some(BlockNumber::Pending)), None);
+        let mut with_value = transfer();
+        with_value.tx.value = Some(1.into());
+        assert_eq!(TokenCall::of(&with_value, None), None);
+        let mut overridden = transfer();
+        overridden.state_overrides = Some(Default::default());
+        assert_eq!(TokenCall::of(&overridden, None), None);
+        let mut blob = transfer();
+        blob.tx.transaction_type = Some(U64::from(3));
+        assert_eq!(TokenCall::of(&blob, None), None);
+        let mut unknown = transfer();
+        unknown.tx.data = Some(Bytes::from(vec![0x70, 0xa0, 0x82, 0x31]));
+        assert_eq!(TokenCall::of(&unknown, None), None);
+        unknown.tx.data = Some(Bytes::from(vec![0xa9, 0x05]));
+        assert_eq!(TokenCall::of(&unknown, None), None);
+    }
+
+    #[test]
+    fn test_needs_min_samples() {
+        let store = store(3);
+        let call = TokenCall::of(&transfer(), None).unwrap();
+        store.record(call, 50_000);
+        store.record(call, 50_000);
+        assert_eq!(store.estimate(&call), None);
+        store.record(call, 51_000);
+        assert_eq!(
+            store.estimate(&call),
+            Some(LearnedEstimate {
+                high_water: 51_000,
+                gas_limit: 56_100,
+            })
+        );
+    }
+
+    #[test]
+    fn test_high_deviation_is_not_trusted() {
+        let store = store(2);
+        let call = TokenCall::of(&transfer(), None).unwrap();
+        store.record(call, 30_000);
+        store.record(call, 50_000);
+        assert_eq!(store.estimate(&call), None);
+
+        // Once consistent again, the fresh-slot estimate still sets the answer
+        for _ in 0..40 {
+            store.record(call, 34_000);
+        }
+        assert_eq!(store.estimate(&call).unwrap().high_water, 50_000);
+    }
+
+    #[tokio::test]
+    async fn test_estimates_from_learned_samples() {
+        let mut transport = TestTransport::default();
+        transport.add_response(json!("0xc350")); // 50000 gas
+        transport.add_response(json!("0xc738")); // 51000 gas
+        let estimator = GasEstimator::new(transport.clone()).with_heuristics(HeuristicConfig {
+            min_samples: 2,
+            ..Default::default()
+        });
+        for _ in 0..2 {
+            let result = estimator.estimate(transfer()).await.unwrap();
+            assert_eq!(result.method, "rpc");
+        }
+
+        let result = estimator.estimate(transfer()).await.unwrap();
+        assert_eq!(result.method, "heuristic");
+        assert_eq!(result.gas_limit, 56_100.into());
+        assert_eq!(result.raw_gas_limit, Some(51_000.into()));
+        let tx = web3::helpers::serialize(&transfer().tx).to_string();
+        transport.assert_request("eth_estimateGas", std::slice::from_ref(&tx));
+        transport.assert_request("eth_estimateGas", &[tx]);
+        transport.assert_no_more_requests();
+
+        // The escape hatch asks the node, which adds another sample
+        transport.add_response(json!("0xc350"));
+        let request = EstimateGasRequest {
+            no_heuristic: true,
+            ..transfer()
+        };
+        let result = estimator.estimate(request).await.unwrap();
+        assert_eq!(result.method, "rpc");
+        assert_eq!(result.gas_limit, 50_000.into());
+    }
+
+    #[test]
+    fn test_expired_calls_are_dropped() {
+        let store = HeuristicStore::new(HeuristicConfig {
+            min_samples: 1,
+            ttl: Duration::ZERO,
+            ..Default::default()
+        });
+        let call = TokenCall::of(&transfer(), None).unwrap();
+        store.record(call, 50_000);
+        assert_eq!(store.estimate(&call), None);
+        assert!(store.is_empty());
+
+        store.record(call, 50_000);
+        let other = TokenCall {
+            token: Address::repeat_byte(0x0e),
+            ..call
+        };
+        store.record(other, 50_000);
+        assert_eq!(store.len(), 1);
+    }
+}
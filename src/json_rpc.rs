@@ -0,0 +1,381 @@
+//! JSON-RPC 2.0 endpoint for clients that already speak the node's protocol:
+//! `eth_estimateGas` is answered by the estimator, so plain transfers never reach
+//! the node, and a few read-only fee methods are forwarded upstream
+
+use crate::estimator::{ApiError, ErrorResponse, EstimateGasRequest, GasEstimator};
+use crate::metrics::Metrics;
+use crate::transport::BatchSender;
+use futures::future::join_all;
+use jsonrpc_core::{Call, Error, ErrorCode, Id, MethodCall, Output, Params, Response, Version};
+use serde_json::Value;
+use std::time::Duration;
+use web3::{RequestId, Transport};
+
+/// Methods sent on to the node as they are
+pub const FORWARDED_METHODS: [&str; 4] = [
+    "eth_createAccessList",
+    "eth_gasPrice",
+    "eth_maxPriorityFeePerGas",
+    "eth_feeHistory",
+];
+
+/// Calls of a JSON-RPC request body
+#[derive(Debug)]
+pub enum Payload {
+    Single(Call),
+    Batch(Vec<Call>),
+}
+
+impl Payload {
+    /// Parses a request body. Bodies that aren't JSON, empty batches and batches
+    /// of more than `max_batch_size` calls get the error response to send back.
+    pub fn parse(body: &[u8], max_batch_size: usize) -> Result<Self, Response> {
+        let rejected = |error| Err(Response::from(error, Some(Version::V2)));
+        let Ok(value) = serde_json::from_slice::<Value>(body) else {
+            return rejected(Error::parse_error());
+        };
+        match value {
+            Value::Array(calls) if calls.is_empty() => rejected(Error::invalid_request()),
+            Value::Array(calls) if calls.len() > max_batch_size => rejected(Error {
+                code: ErrorCode::InvalidRequest,
+                message: format!(
+                    "Batch size {} exceeds maximum of {max_batch_size}",
+                    calls.len()
+                ),
+                data: None,
+            }),
+            Value::Array(calls) => Ok(Self::Batch(calls.into_iter().map(call).collect())),
+            value => Ok(Self::Single(call(value))),
+        }
+    }
+
+    pub fn calls(&self) -> &[Call] {
+        match self {
+            Self::Single(call) => std::slice::from_ref(call),
+            Self::Batch(calls) => calls,
+        }
+    }
+
+    /// Response carrying the outputs of the calls, `None` when they were all
+    /// notifications, which get no response
+    pub fn respond(&self, outputs: Vec<Option<Output>>) -> Option<Response> {
+        let mut outputs = outputs.into_iter().flatten();
+        match self {
+            Self::Single(_) => outputs.next().map(Response::Single),
+            Self::Batch(_) => {
+                let outputs: Vec<_> = outputs.collect();
+                (!outputs.is_empty()).then_some(Response::Batch(outputs))
+            }
+        }
+    }
+}
+
+/// Call of a batch item or body, invalid when it isn't a JSON-RPC 2.0 call
+fn call(value: Value) -> Call {
+    serde_json::from_value(value).unwrap_or(Call::Invalid { id: Id::Null })
+}
+
+impl<T: Transport> GasEstimator<T> {
+    /// Answers each call, in order. Estimates run concurrently, while forwarded calls
+    /// go to the node together as one batch through `batch_sender`, or one by one
+    /// without it. A failed call only fails its own output; notifications get none.
+    pub async fn answer_calls(
+        &self,
+        calls: &[Call],
+        batch_sender: Option<&BatchSender>,
+        rpc_timeout: Option<Duration>,
+        metrics: &Metrics,
+    ) -> Vec<Option<Output>> {
+        let mut outputs: Vec<Option<Output>> = calls.iter().map(|_| None).collect();
+        let mut estimates = Vec::new();
+        let mut forwarded = Vec::new();
+        for (index, call) in calls.iter().enumerate() {
+            let call = match call {
+                Call::MethodCall(call) => call,
+                Call::Notification(_) => continue,
+                Call::Invalid { id } => {
+                    outputs[index] = Some(Output::invalid_request(id.clone(), Some(Version::V2)));
+                    continue;
+                }
+            };
+            let request = if call.method == "eth_estimateGas" {
+                estimate_request(&call.params).map(|mut request| {
+                    request.rpc_timeout = rpc_timeout;
+                    estimates.push((index, call, request));
+                })
+            } else if FORWARDED_METHODS.contains(&call.method.as_str()) {
+                params(&call.params).map(|params| {
+                    forwarded.push((index, call, self.transport().prepare(&call.method, params)));
+                })
+            } else {
+                Err(Error::method_not_found())
+            };
+            if let Err(error) = request {
+                outputs[index] = Some(output(call, Err(error)));
+            }
+        }
+
+        let (estimated, forwarded_results) = tokio::join!(
+            join_all(
+                estimates
+                    .iter()
+                    .map(|(_, _, request)| self.estimate(request.clone()))
+            ),
+            self.forward(
+                forwarded
+                    .iter()
+                    .map(|(_, _, request)| request.clone())
+                    .collect(),
+                batch_sender
+            )
+        );
+        for ((index, call, _), result) in estimates.iter().zip(estimated) {
+            metrics.record(&result, None);
+            let result = result
+                .map(|estimate| Value::from(format!("{:#x}", estimate.gas_limit)))
+                .map_err(estimate_error);
+            outputs[*index] = Some(output(call, result));
+        }
+        for ((index, call, _), result) in forwarded.iter().zip(forwarded_results) {
+            outputs[*index] = Some(output(call, result));
+        }
+        outputs
+    }
+
+    /// Sends the calls to the node, as one batch when there are several to send
+    async fn forward(
+        &self,
+        requests: Vec<(RequestId, Call)>,
+        batch_sender: Option<&BatchSender>,
+    ) -> Vec<Result<Value, Error>> {
+        match batch_sender {
+            Some(send_batch) if requests.len() > 1 => {
+                let len = requests.len();
+                match send_batch(requests).await {
+                    Ok(results) => results
+                        .into_iter()
+                        .map(|result| result.map_err(upstream_error))
+                        .collect(),
+                    Err(error) => vec![Err(upstream_error(error)); len],
+                }
+            }
+            _ => join_all(
+                requests
+                    .into_iter()
+                    .map(|(id, request)| self.transport().send(id, request)),
+            )
+            .await
+            .into_iter()
+            .map(|result| result.map_err(upstream_error))
+            .collect(),
+        }
+    }
+}
+
+fn output(call: &MethodCall, result: Result<Value, Error>) -> Output {
+    Output::from(result, call.id.clone(), call.jsonrpc)
+}
+
+/// Positional params, which is all `eth_` methods take
+fn params(params: &Params) -> Result<Vec<Value>, Error> {
+    match params {
+        Params::Array(params) => Ok(params.clone()),
+        Params::None => Ok(Vec::new()),
+        Params::Map(_) => Err(Error::invalid_params("params must be an array")),
+    }
+}
+
+/// Estimate request of `eth_estimateGas` params: the transaction, then optionally
+/// the block and state overrides
+fn estimate_request(call_params: &Params) -> Result<EstimateGasRequest, Error> {
+    let mut params = params(call_params)?.into_iter();
+    let tx = params
+        .next()
+        .ok_or_else(|| Error::invalid_params("missing transaction object"))?;
+    let mut request: EstimateGasRequest = serde_json::from_value(tx)
+        .map_err(|e| Error::invalid_params(format!("invalid transaction object: {e}")))?;
+    match params.next() {
+        None | Some(Value::Null) => {}
+        Some(Value::String(block)) => request.block = Some(block),
+        Some(_) => {
+            return Err(Error::invalid_params(
+                "block must be a tag or a hex block number",
+            ));
+        }
+    }
+    if let Some(overrides) = params.next().filter(|overrides| !overrides.is_null()) {
+        request.state_overrides = Some(
+            serde_json::from_value(overrides)
+                .map_err(|e| Error::invalid_params(format!("invalid state overrides: {e}")))?,
+        );
+    }
+    if params.next().is_some() {
+        return Err(Error::invalid_params("too many params, expected at most 3"));
+    }
+    Ok(request)
+}
+
+/// JSON-RPC error of a failed estimate, in the form nodes report them
+fn estimate_error(error: ApiError) -> Error {
+    match error {
+        ApiError::BadRequest(message) => Error::invalid_params(message),
+        ApiError::ExecutionReverted { reason, data } => Error {
+            code: ErrorCode::ServerError(3),
+            message: ApiError::ExecutionReverted { reason, data: None }.to_string(),
+            data: data.map(Value::from),
+        },
+        error @ (ApiError::ExceedsBlockGasLimit { .. } | ApiError::InvalidTransaction(_)) => {
+            Error {
+                code: ErrorCode::ServerError(-32000),
+                message: error.to_string(),
+                data: None,
+            }
+        }
+        error => Error {
+            code: ErrorCode::InternalError,
+            message: ErrorResponse::from(error).error,
+            data: None,
+        },
+    }
+}
+
+/// Node errors are passed through; failures to reach the node are only logged in
+/// full, as they can name the endpoint
+fn upstream_error(error: web3::Error) -> Error {
+    match error {
+        web3::Error::Rpc(error) => error,
+        error => estimate_error(ApiError::InternalServerError(format!(
+            "RPC call failed: {error}"
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use web3::transports::test::TestTransport;
+
+    fn parse(body: Value) -> Result<Payload, Response> {
+        Payload::parse(body.to_string().as_bytes(), 4)
+    }
+
+    fn error_code(response: Response) -> i64 {
+        match response {
+            Response::Single(Output::Failure(failure)) => failure.error.code.code(),
+            response => panic!("unexpected response: {response:?}"),
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        assert!(matches!(
+            parse(json!({ "jsonrpc": "2.0", "id": 1, "method": "eth_gasPrice" })),
+            Ok(Payload::Single(Call::MethodCall(_)))
+        ));
+        let Ok(Payload::Batch(calls)) = parse(json!([
+            { "jsonrpc": "2.0", "id": 1, "method": "eth_gasPrice" },
+            { "jsonrpc": "2.0", "method": "eth_gasPrice" },
+            { "id": 3 },
+            1,
+        ])) else {
+            panic!("expected a batch");
+        };
+        assert!(matches!(calls[1], Call::Notification(_)));
+        assert_eq!(calls[2], Call::Invalid { id: Id::Num(3) });
+        assert_eq!(calls[3], Call::Invalid { id: Id::Null });
+
+        assert_eq!(
+            error_code(Payload::parse(b"{", 4).unwrap_err()),
+            ErrorCode::ParseError.code()
+        );
+        assert_eq!(
+            error_code(parse(json!([])).unwrap_err()),
+            ErrorCode::InvalidRequest.code()
+        );
+        assert_eq!(
+            error_code(parse(json!([1, 2, 3, 4, 5])).unwrap_err()),
+            ErrorCode::InvalidRequest.code()
+        );
+    }
+
+    #[test]
+    fn test_estimate_request() {
+        let params = Params::Array(vec![
+            json!({ "to": "0x0000000000000000000000000000000000000b0b", "value": "0x1" }),
+            json!("0x10"),
+            json!({ "0x0000000000000000000000000000000000000001": { "balance": "0x1" } }),
+        ]);
+        let request = estimate_request(&params).unwrap();
+        assert_eq!(request.tx.value, Some(1.into()));
+        assert_eq!(request.block.as_deref(), Some("0x10"));
+        assert_eq!(request.state_overrides.unwrap().len(), 1);
+
+        for invalid in [
+            Params::None,
+            Params::Array(vec![json!("0x1")]),
+            Params::Array(vec![json!({}), json!(16)]),
+            Params::Array(vec![json!({}), json!(null), json!(null), json!(null)]),
+        ] {
+            assert_eq!(
+                estimate_request(&invalid).unwrap_err().code,
+                ErrorCode::InvalidParams
+            );
+        }
+    }
+
+    #[test]
+    fn test_estimate_error() {
+        let reverted = estimate_error(ApiError::ExecutionReverted {
+            reason: Some("paused".to_string()),
+            data: Some("0x08c379a0".to_string()),
+        });
+        assert_eq!(reverted.code, ErrorCode::ServerError(3));
+        assert_eq!(reverted.message, "execution reverted: paused");
+        assert_eq!(reverted.data, Some(json!("0x08c379a0")));
+
+        let failed = upstream_error(web3::Error::Transport(
+            web3::error::TransportError::Message("http://secret.example down".to_string()),
+        ));
+        assert_eq!(failed.code, ErrorCode::InternalError);
+        assert_eq!(failed.message, "RPC call failed");
+    }
+
+    #[tokio::test]
+    async fn test_answer_calls() {
+        let mut transport = TestTransport::default();
+        // eth_getCode for the estimate, then the forwarded eth_gasPrice
+        transport.add_response(json!("0x"));
+        transport.add_response(json!("0x3b9aca00"));
+        let estimator = GasEstimator::new(transport);
+        let Ok(payload) = parse(json!([
+            {
+                "jsonrpc": "2.0",
+                "id": "a",
+                "method": "eth_estimateGas",
+                "params": [{ "to": "0x0000000000000000000000000000000000000b0b", "value": "0x1" }]
+            },
+            { "jsonrpc": "2.0", "id": 2, "method": "eth_gasPrice" },
+            { "jsonrpc": "2.0", "id": 3, "method": "eth_sendRawTransaction", "params": ["0x"] },
+        ])) else {
+            panic!("expected a batch");
+        };
+
+        let outputs = estimator
+            .answer_calls(payload.calls(), None, None, &Metrics::new())
+            .await;
+        let response = serde_json::to_value(payload.respond(outputs).unwrap()).unwrap();
+        assert_eq!(
+            response,
+            json!([
+                { "jsonrpc": "2.0", "id": "a", "result": "0x5208" },
+                { "jsonrpc": "2.0", "id": 2, "result": "0x3b9aca00" },
+                {
+                    "jsonrpc": "2.0",
+                    "id": 3,
+                    "error": { "code": -32601, "message": "Method not found" }
+                },
+            ])
+        );
+    }
+}
@@ -0,0 +1,402 @@
+//! L1 data fees of rollup transactions, which their execution gas doesn't cover
+
+use crate::abi::encode_call;
+use crate::estimator::{ApiError, ErrorResponse, GasEstimateResponse, GasEstimator};
+use rlp::RlpStream;
+use std::collections::HashMap;
+use std::str::FromStr;
+use web3::{
+    Transport,
+    ethabi::{self, ParamType, Token},
+    types::{Address, Bytes, CallRequest, H160, U256},
+};
+
+/// OP-stack `GasPriceOracle` predeploy
+pub const OP_GAS_PRICE_ORACLE: Address = H160([
+    0x42, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x0f,
+]);
+
+/// Arbitrum's `NodeInterface`, a virtual contract only reachable through calls
+pub const ARBITRUM_NODE_INTERFACE: Address = H160([
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0xc8,
+]);
+
+/// Chain id of OP Mainnet, priced as an OP-stack rollup unless `L2_KIND` says otherwise
+pub const OP_MAINNET_CHAIN_ID: u64 = 10;
+
+/// Rollup stack of a chain, which decides how its L1 fee is computed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum L2Kind {
+    /// Optimism, Base and other OP-stack chains, priced by the `GasPriceOracle`
+    OpStack,
+    /// Arbitrum chains, whose `eth_estimateGas` already includes the L1 component
+    Arbitrum,
+}
+
+impl FromStr for L2Kind {
+    type Err = String;
+
+    fn from_str(kind: &str) -> Result<Self, Self::Err> {
+        match kind.trim() {
+            "op-stack" => Ok(Self::OpStack),
+            "arbitrum" => Ok(Self::Arbitrum),
+            other => Err(format!(
+                "Unknown L2 kind {other}; expected op-stack or arbitrum"
+            )),
+        }
+    }
+}
+
+/// Rollup stacks of the configured chains, from `L2_KIND`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct L2Config {
+    /// Stack of the default chain
+    pub default: Option<L2Kind>,
+    /// Stacks of the chains selectable per request
+    pub chains: HashMap<u64, L2Kind>,
+}
+
+impl L2Config {
+    /// Parses either a stack for the default chain, such as `op-stack`, or
+    /// `chain_id=kind` entries separated by `;`, such as
+    /// `8453=op-stack;42161=arbitrum`. Chains not listed are L1s.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let mut config = Self::default();
+        for entry in value.split(';').map(str::trim).filter(|e| !e.is_empty()) {
+            match entry.split_once('=') {
+                Some((chain_id, kind)) => {
+                    let chain_id = chain_id
+                        .trim()
+                        .parse()
+                        .map_err(|e| format!("Failed to parse chain id {chain_id}: {e}"))?;
+                    config.chains.insert(chain_id, kind.parse()?);
+                }
+                None => config.default = Some(entry.parse()?),
+            }
+        }
+        Ok(config)
+    }
+}
+
+/// Stack of a well-known rollup, for chains `L2_KIND` doesn't list
+pub fn known_kind(chain_id: u64) -> Option<L2Kind> {
+    (chain_id == OP_MAINNET_CHAIN_ID).then_some(L2Kind::OpStack)
+}
+
+impl<T: Transport> GasEstimator<T> {
+    /// Adds the L1 fee of the estimated transaction to the response. A fee that
+    /// can't be computed leaves the estimate as it is, with `l1_fee_warning` set.
+    pub(crate) async fn add_l1_fee(
+        &self,
+        kind: L2Kind,
+        tx: &CallRequest,
+        response: &mut GasEstimateResponse,
+    ) {
+        let (result, gas_price) = match kind {
+            L2Kind::OpStack => {
+                let (result, gas_price) =
+                    tokio::join!(self.op_stack_l1_fee(tx, response.gas_limit), async {
+                        self.eth.gas_price().await
+                    });
+                (result, gas_price.ok())
+            }
+            L2Kind::Arbitrum => (self.arbitrum_l1_fee(tx).await, None),
+        };
+        match result {
+            Ok((l1_fee, l1_gas_used)) => {
+                response.l1_fee = Some(l1_fee);
+                response.l1_gas_used = Some(l1_gas_used);
+                if kind == L2Kind::OpStack {
+                    response.l2_execution_fee_wei =
+                        gas_price.map(|gas_price| response.gas_limit.saturating_mul(gas_price));
+                }
+            }
+            Err(error) => {
+                tracing::warn!(%error, ?kind, "Failed to compute the L1 fee");
+                response.l1_fee_warning = Some(format!(
+                    "L1 fee unavailable: {}",
+                    ErrorResponse::from(error).error
+                ));
+            }
+        }
+    }
+
+    /// Asks the `GasPriceOracle` for the fee and gas of posting the transaction
+    /// to L1, with `gas_limit` as its gas
+    async fn op_stack_l1_fee(
+        &self,
+        tx: &CallRequest,
+        gas_limit: U256,
+    ) -> Result<(U256, U256), ApiError> {
+        let unsigned = unsigned_eip1559(tx, gas_limit);
+        let oracle_call = |name| {
+            let data = encode_call(name, &[ParamType::Bytes], &[Token::Bytes(unsigned.clone())]);
+            self.l1_call(
+                CallRequest {
+                    to: Some(OP_GAS_PRICE_ORACLE),
+                    data: Some(Bytes(data)),
+                    ..Default::default()
+                },
+                &[ParamType::Uint(256)],
+            )
+        };
+        let (fee, gas_used) = tokio::join!(oracle_call("getL1Fee"), oracle_call("getL1GasUsed"));
+        Ok((uint(&fee?[0]), uint(&gas_used?[0])))
+    }
+
+    /// Asks the `NodeInterface` for the L2 gas that pays for the transaction's
+    /// L1 data, priced at the L2 base fee
+    async fn arbitrum_l1_fee(&self, tx: &CallRequest) -> Result<(U256, U256), ApiError> {
+        let data = encode_call(
+            "gasEstimateL1Component",
+            &[ParamType::Address, ParamType::Bool, ParamType::Bytes],
+            &[
+                Token::Address(tx.to.unwrap_or_default()),
+                Token::Bool(tx.to.is_none()),
+                Token::Bytes(tx.data.clone().unwrap_or_default().0),
+            ],
+        );
+        let output = self
+            .l1_call(
+                CallRequest {
+                    from: tx.from,
+                    to: Some(ARBITRUM_NODE_INTERFACE),
+                    value: tx.value,
+                    data: Some(Bytes(data)),
+                    ..Default::default()
+                },
+                &[
+                    ParamType::Uint(64),
+                    ParamType::Uint(256),
+                    ParamType::Uint(256),
+                ],
+            )
+            .await?;
+        let (gas_for_l1, base_fee) = (uint(&output[0]), uint(&output[1]));
+        Ok((gas_for_l1.saturating_mul(base_fee), gas_for_l1))
+    }
+
+    /// Performs an `eth_call` and decodes its output
+    async fn l1_call(
+        &self,
+        request: CallRequest,
+        outputs: &[ParamType],
+    ) -> Result<Vec<Token>, ApiError> {
+        let to = request.to;
+        let output = self
+            .eth
+            .call(request, None)
+            .await
+            .map_err(|e| ApiError::InternalServerError(format!("RPC call failed: {e}")))?;
+        ethabi::decode(outputs, &output.0).map_err(|e| {
+            ApiError::InternalServerError(format!("Unexpected call result from {to:?}: {e}"))
+        })
+    }
+}
+
+/// Value of a decoded `uint`
+fn uint(token: &Token) -> U256 {
+    token.clone().into_uint().unwrap_or_default()
+}
+
+/// Unsigned EIP-1559 encoding of the transaction, which `getL1Fee` prices. Requests
+/// carry no chain id or nonce, so both are encoded as zero, a byte or two shorter
+/// than the signed transaction; the oracle accounts for the signature itself.
+fn unsigned_eip1559(tx: &CallRequest, gas_limit: U256) -> Vec<u8> {
+    let mut stream = RlpStream::new_list(9);
+    stream
+        .append(&U256::zero())
+        .append(&U256::zero())
+        .append(&tx.max_priority_fee_per_gas.unwrap_or_default())
+        .append(&tx.max_fee_per_gas.or(tx.gas_price).unwrap_or_default())
+        .append(&gas_limit);
+    match &tx.to {
+        Some(to) => stream.append(to),
+        None => stream.append_empty_data(),
+    };
+    stream
+        .append(&tx.value.unwrap_or_default())
+        .append(&tx.data.clone().unwrap_or_default().0);
+    let access_list = tx.access_list.as_deref().unwrap_or_default();
+    stream.begin_list(access_list.len());
+    for item in access_list {
+        stream
+            .begin_list(2)
+            .append(&item.address)
+            .append_list(&item.storage_keys);
+    }
+
+    let mut encoded = vec![0x02];
+    encoded.extend_from_slice(&stream.out());
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::estimator::EstimateGasRequest;
+    use serde_json::json;
+    use web3::transports::test::TestTransport;
+
+    fn uint_response(values: &[u64]) -> serde_json::Value {
+        let tokens: Vec<Token> = values.iter().map(|v| Token::Uint((*v).into())).collect();
+        serde_json::to_value(Bytes(ethabi::encode(&tokens))).unwrap()
+    }
+
+    fn transfer() -> EstimateGasRequest {
+        CallRequest {
+            to: Some(Address::repeat_byte(2)),
+            value: Some(1.into()),
+            ..Default::default()
+        }
+        .into()
+    }
+
+    #[test]
+    fn test_parse() {
+        let config = L2Config::parse("op-stack").unwrap();
+        assert_eq!(config.default, Some(L2Kind::OpStack));
+        assert!(config.chains.is_empty());
+
+        let config = L2Config::parse("8453=op-stack; 42161=arbitrum").unwrap();
+        assert_eq!(config.default, None);
+        assert_eq!(config.chains[&8453], L2Kind::OpStack);
+        assert_eq!(config.chains[&42161], L2Kind::Arbitrum);
+
+        assert_eq!(L2Config::parse("").unwrap(), L2Config::default());
+        assert!(L2Config::parse("zksync").is_err());
+        assert!(L2Config::parse("base=op-stack").is_err());
+    }
+
+    #[test]
+    fn test_known_kind() {
+        assert_eq!(known_kind(OP_MAINNET_CHAIN_ID), Some(L2Kind::OpStack));
+        assert_eq!(known_kind(1), None);
+    }
+
+    #[test]
+    fn test_unsigned_eip1559() {
+        let tx = CallRequest {
+            to: Some(Address::repeat_byte(2)),
+            value: Some(1.into()),
+            max_fee_per_gas: Some(100.into()),
+            ..Default::default()
+        };
+        let encoded = unsigned_eip1559(&tx, 21_000.into());
+        assert_eq!(encoded[0], 0x02);
+
+        let fields = rlp::Rlp::new(&encoded[1..]);
+        assert_eq!(fields.item_count().unwrap(), 9);
+        assert_eq!(fields.val_at::<U256>(3).unwrap(), 100.into());
+        assert_eq!(fields.val_at::<U256>(4).unwrap(), 21_000.into());
+        assert_eq!(
+            fields.val_at::<Address>(5).unwrap(),
+            Address::repeat_byte(2)
+        );
+        assert_eq!(fields.at(8).unwrap().item_count().unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_op_stack_l1_fee() {
+        let mut transport = TestTransport::default();
+        // eth_getCode: the receiver has no code
+        transport.add_response(json!("0x"));
+        transport.add_response(uint_response(&[42_000_000_000]));
+        transport.add_response(uint_response(&[1_600]));
+        // eth_gasPrice: 0.001 gwei
+        transport.add_response(json!("0xf4240"));
+        let estimator =
+            GasEstimator::new(transport.clone()).with_l2_kind(known_kind(OP_MAINNET_CHAIN_ID));
+
+        let response = estimator.estimate(transfer()).await.unwrap();
+        assert_eq!(response.gas_limit, 21_000.into());
+        assert_eq!(response.l1_fee, Some(42_000_000_000u64.into()));
+        assert_eq!(response.l1_gas_used, Some(1_600.into()));
+        assert_eq!(response.l1_fee_warning, None);
+        assert_eq!(
+            response.l2_execution_fee_wei,
+            Some(21_000_000_000u64.into())
+        );
+
+        // The oracle prices the EIP-2718 envelope of the transaction
+        transport.assert_request(
+            "eth_getCode",
+            &[
+                r#""0x0202020202020202020202020202020202020202""#.to_string(),
+                r#""latest""#.to_string(),
+            ],
+        );
+        let calldata = encode_call(
+            "getL1Fee",
+            &[ParamType::Bytes],
+            &[Token::Bytes(unsigned_eip1559(
+                &transfer().tx,
+                21_000.into(),
+            ))],
+        );
+        transport.assert_request(
+            "eth_call",
+            &[
+                serde_json::to_string(&json!({
+                    "to": OP_GAS_PRICE_ORACLE,
+                    "data": Bytes(calldata),
+                }))
+                .unwrap(),
+                r#""latest""#.to_string(),
+            ],
+        );
+    }
+
+    #[tokio::test]
+    async fn test_arbitrum_l1_fee() {
+        let mut transport = TestTransport::default();
+        transport.add_response(json!("0x"));
+        // gasEstimateForL1, baseFee, l1BaseFeeEstimate
+        transport.add_response(uint_response(&[3_000, 10_000_000, 30_000_000_000]));
+        let estimator = GasEstimator::new(transport.clone()).with_l2_kind(Some(L2Kind::Arbitrum));
+
+        let response = estimator.estimate(transfer()).await.unwrap();
+        assert_eq!(response.l1_fee, Some(30_000_000_000u64.into()));
+        assert_eq!(response.l1_gas_used, Some(3_000.into()));
+        // Arbitrum's gas limit already pays for L1 data
+        assert_eq!(response.l2_execution_fee_wei, None);
+    }
+
+    #[tokio::test]
+    async fn test_failed_l1_fee_keeps_estimate() {
+        let mut transport = TestTransport::default();
+        transport.add_response(json!("0x"));
+        transport.add_response(json!("0x"));
+        transport.add_response(json!("0x"));
+        let estimator = GasEstimator::new(transport.clone()).with_l2_kind(Some(L2Kind::OpStack));
+
+        let response = estimator.estimate(transfer()).await.unwrap();
+        assert_eq!(response.gas_limit, 21_000.into());
+        assert_eq!(response.l1_fee, None);
+        assert!(
+            response
+                .l1_fee_warning
+                .as_deref()
+                .is_some_and(|warning| warning.starts_with("L1 fee unavailable")),
+            "{:?}",
+            response.l1_fee_warning
+        );
+    }
+
+    #[tokio::test]
+    async fn test_l1_chains_skip_the_fee() {
+        let mut transport = TestTransport::default();
+        transport.add_response(json!("0x"));
+        let estimator = GasEstimator::new(transport.clone());
+
+        let response = estimator.estimate(transfer()).await.unwrap();
+        assert_eq!(response.l1_fee, None);
+        assert_eq!(response.l1_fee_warning, None);
+        assert_eq!(response.l2_execution_fee_wei, None);
+
+        let json = serde_json::to_value(&response).unwrap();
+        assert!(json["l2_execution_fee_wei"].is_null());
+    }
+}
@@ -0,0 +1,65 @@
+//! Gas estimation service for Ethereum transactions.
+//!
+//! The [`estimator`] module holds the estimation logic and can be used on its own;
+//! [`server::build_router`] exposes it over HTTP.
+
+pub mod abi;
+pub mod access_list;
+pub mod auth;
+pub mod balance;
+pub mod base_fee;
+pub mod binary_search;
+pub mod block_gas_limit;
+pub mod blur;
+pub mod bundle;
+#[cfg(feature = "cache")]
+pub mod cache;
+pub mod calldata;
+pub mod chains;
+pub mod circuit_breaker;
+pub mod code_cache;
+pub mod config;
+pub mod cors;
+pub mod eigenlayer;
+pub mod eip7702;
+pub mod estimator;
+pub mod fee_history;
+pub mod fee_watcher;
+pub mod gas_costs;
+pub mod gas_price;
+pub mod health;
+pub mod heuristic;
+pub mod json_rpc;
+pub mod l2;
+pub mod memory;
+pub mod metrics;
+pub mod openapi;
+pub mod precompile;
+pub mod provider;
+pub mod rate_limit;
+pub mod recorder;
+pub mod request;
+pub mod request_id;
+pub mod request_log;
+pub mod revert;
+pub mod rpc_batch;
+pub mod rpc_override;
+pub mod rpc_timeout;
+pub mod safe;
+pub mod server;
+pub mod shutdown;
+pub mod simulate;
+pub mod state_override;
+pub mod static_estimator;
+pub mod transport;
+pub mod units;
+pub mod upstream_error;
+pub mod user_operation;
+pub mod wallet_calls;
+pub mod warmup;
+pub mod websocket;
+
+pub use estimator::{ApiError, GasEstimateResponse, GasEstimator};
+pub use metrics::Metrics;
+pub use server::{AppState, build_router};
+pub use transport::MultiTransport;
@@ -1,371 +1,532 @@
-use axum::{
-    Json, Router,
-    extract::State,
-    http::StatusCode,
-    response::IntoResponse,
-    routing::{get, post},
+use gas_estimator::{
+    AppState, GasEstimator, Metrics, MultiTransport, auth,
+    binary_search::DEFAULT_BINARY_SEARCH_BUDGET,
+    block_gas_limit::DEFAULT_BLOCK_GAS_LIMIT_TTL_SECS,
+    build_router,
+    bundle::DEFAULT_MAX_BUNDLE_SIZE,
+    chains::ChainConfig,
+    circuit_breaker::{DEFAULT_CIRCUIT_FAILURE_THRESHOLD, DEFAULT_CIRCUIT_OPEN_SECS},
+    code_cache::{DEFAULT_CODE_CACHE_MAX_ENTRIES, DEFAULT_CODE_CACHE_TTL_SECS},
+    config::Config,
+    cors,
+    estimator::DEFAULT_CONFIDENCE_THRESHOLD_PERCENT,
+    fee_history::DEFAULT_FEE_HISTORY_CACHE_TTL_SECS,
+    fee_watcher::{DEFAULT_BASE_FEE_POLL_SECS, FeeWatcher},
+    gas_costs::{MAX_CALLDATA_SIZE, PricingRule},
+    gas_price::DEFAULT_GAS_PRICE_POLL_INTERVAL_SECS,
+    health::{DEFAULT_READINESS_CHECK_INTERVAL_SECS, DEFAULT_READINESS_FAILURE_THRESHOLD},
+    heuristic::{
+        DEFAULT_HEURISTIC_MARGIN_PERCENT, DEFAULT_HEURISTIC_MAX_DEVIATION_PERCENT,
+        DEFAULT_HEURISTIC_MAX_ENTRIES, DEFAULT_HEURISTIC_MIN_SAMPLES, DEFAULT_HEURISTIC_TTL_SECS,
+        HeuristicConfig,
+    },
+    l2::{self, L2Config, L2Kind},
+    rate_limit::RateLimiter,
+    recorder::{DEFAULT_RECORDING_PATH, Recorder},
+    rpc_override::{DEFAULT_RPC_OVERRIDE_TIMEOUT_SECS, RpcOverride},
+    server::{
+        DEFAULT_BATCH_CONCURRENCY, DEFAULT_HEALTH_CHECK_TIMEOUT_SECS, DEFAULT_MAX_BODY_BYTES,
+        DEFAULT_REQUEST_TIMEOUT_SECS,
+    },
+    shutdown::DEFAULT_SHUTDOWN_TIMEOUT_SECS,
+    transport::{
+        DEFAULT_COOLDOWN_SECS, DEFAULT_FAILURE_THRESHOLD, DEFAULT_MAX_ATTEMPTS,
+        DEFAULT_RETRY_BASE_DELAY_MS, DEFAULT_RETRY_MAX_DELAY_MS, EndpointTransport,
+    },
+    user_operation::DEFAULT_VERIFICATION_GAS_LIMIT,
+    warmup::DEFAULT_WARMUP_GRACE_SECS,
 };
 use reqwest::{Client as ReqwestClient, Url};
-use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::future::IntoFuture;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::str::FromStr;
 use std::sync::Arc;
 use std::time::Duration;
-use tower_http::{
-    cors::{Any, CorsLayer},
-    trace::TraceLayer,
-};
-use web3::{
-    Transport, Web3,
-    api::Eth,
-    transports::Http,
-    types::{CallRequest, U256},
-};
-
-const BIND_ADDRESS: &str = "0.0.0.0:3000";
-const DEFAULT_ETH_RPC_URL: &str = "https://ethereum-rpc.publicnode.com";
-const RPC_TIMEOUT_SECS: u64 = 10;
-const KEEP_ALIVE_SECS: u64 = 30;
-const MAX_IDLE_CONNECTIONS: usize = 10;
-
-// Gas constants based on Ethereum Yellow Paper and EIPs
-const GAS_TX_BASE: u64 = 21000;
-const GAS_TX_DATA_ZERO: u64 = 4;
-const GAS_TX_DATA_NON_ZERO: u64 = 16;
-const GAS_TX_CREATE: u64 = 32000;
-const GAS_CODE_DEPOSIT: u64 = 200;
-
-// EIP-4844: Shard Blob Transactions
-const BLOB_TX_TYPE: u8 = 0x03;
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct GasEstimateResponse {
-    pub gas_limit: U256,
-    pub method: String, // "static" or "rpc"
-}
-
-#[derive(Debug, Serialize)]
-pub struct ErrorResponse {
-    pub error: String,
+use tracing_subscriber::EnvFilter;
+use web3::BatchTransport;
+
+/// Reads and parses an environment variable, falling back to a default when unset
+fn env_or<V: FromStr>(name: &str, default: V) -> Result<V, String>
+where
+    V::Err: std::fmt::Display,
+{
+    match std::env::var(name) {
+        Ok(value) => value
+            .parse()
+            .map_err(|e| format!("Failed to parse {name}: {e}")),
+        Err(_) => Ok(default),
+    }
 }
 
-/// Custom error type for our API
-#[derive(Debug)]
-pub enum ApiError {
-    BadRequest(String),
-    InternalServerError(String),
+/// Like [`env_or`], reading the variable's former name only when the current one
+/// isn't set
+fn env_or_former<V: FromStr>(name: &str, former: &str, default: V) -> Result<V, String>
+where
+    V::Err: std::fmt::Display,
+{
+    if std::env::var(name).is_ok() {
+        env_or(name, default)
+    } else {
+        env_or(former, default)
+    }
 }
 
-/// Implement IntoResponse for our error type
-impl IntoResponse for ApiError {
-    fn into_response(self) -> axum::response::Response {
-        match self {
-            ApiError::BadRequest(error) => {
-                (StatusCode::BAD_REQUEST, Json(ErrorResponse { error })).into_response()
-            }
-            ApiError::InternalServerError(error) => (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse { error }),
+/// Builds an estimator failing over between the given RPC endpoints. HTTP endpoints
+/// share the client's connection pool, while WebSocket endpoints keep their own
+/// connection with requests limited to the configured RPC timeout. Retries stop in
+/// time for `request_timeout`.
+fn build_estimator(
+    urls: Vec<Url>,
+    client: &ReqwestClient,
+    metrics: &Metrics,
+    config: &Config,
+    request_timeout: Duration,
+    l2_kind: Option<L2Kind>,
+    recorder: Option<&Recorder>,
+) -> Result<GasEstimator<MultiTransport>, String> {
+    let endpoints = urls
+        .into_iter()
+        .map(|url| {
+            (
+                url.clone(),
+                EndpointTransport::new(url, client, config.rpc_timeout),
             )
-                .into_response(),
-        }
+        })
+        .collect();
+    let mut transport = MultiTransport::new(endpoints)
+        .with_circuit_breaker(
+            env_or("RPC_FAILURE_THRESHOLD", DEFAULT_FAILURE_THRESHOLD)?,
+            Duration::from_secs(env_or("RPC_COOLDOWN_SECS", DEFAULT_COOLDOWN_SECS)?),
+        )
+        .with_retries(
+            env_or("RPC_MAX_ATTEMPTS", DEFAULT_MAX_ATTEMPTS)?,
+            Duration::from_millis(env_or(
+                "RPC_RETRY_BASE_DELAY_MS",
+                DEFAULT_RETRY_BASE_DELAY_MS,
+            )?),
+        )
+        .with_max_retry_delay(Duration::from_millis(env_or(
+            "RPC_RETRY_MAX_DELAY_MS",
+            DEFAULT_RETRY_MAX_DELAY_MS,
+        )?))
+        .with_retry_budget(request_timeout)
+        .with_failure_counter(metrics.rpc_endpoint_failures.clone());
+    if let Some(recorder) = recorder {
+        transport = transport.with_recorder(recorder.clone());
     }
-}
 
-// Our application state
-#[derive(Clone)]
-struct AppState<T: Transport + Send + Sync + 'static> {
-    estimator: Arc<GasEstimator<T>>,
+    let batch_transport = transport.clone();
+    let estimator = GasEstimator::new(transport)
+        .with_batch_sender(Arc::new(move |requests| {
+            batch_transport.send_batch(requests)
+        }))
+        .with_buffer_percent(env_or("GAS_BUFFER_PERCENT", 0)?)
+        .map_err(|e| format!("Invalid GAS_BUFFER_PERCENT: {e}"))?
+        .with_code_cache(
+            env_or("CODE_CACHE_MAX_ENTRIES", DEFAULT_CODE_CACHE_MAX_ENTRIES)?,
+            Duration::from_secs(env_or("CODE_CACHE_TTL_SECS", DEFAULT_CODE_CACHE_TTL_SECS)?),
+        )
+        .with_fee_history_cache_ttl(Duration::from_secs(env_or(
+            "FEE_HISTORY_CACHE_TTL_SECS",
+            DEFAULT_FEE_HISTORY_CACHE_TTL_SECS,
+        )?))
+        .with_margin_bps(config.safety_margin_bps)
+        .map_err(|e| format!("Invalid SAFETY_MARGIN_BPS: {e}"))?
+        .with_gas_constants(config.gas_constants)
+        .with_verification_gas_limit(env_or(
+            "USEROP_VERIFICATION_GAS_LIMIT",
+            DEFAULT_VERIFICATION_GAS_LIMIT,
+        )?)
+        .with_max_calldata_size(env_or("MAX_CALLDATA_SIZE", MAX_CALLDATA_SIZE)?)
+        .with_l2_kind(l2_kind)
+        .with_block_gas_limit_ttl(Duration::from_secs(env_or(
+            "BLOCK_GAS_LIMIT_TTL_SECS",
+            DEFAULT_BLOCK_GAS_LIMIT_TTL_SECS,
+        )?))
+        .with_circuit_breaker(
+            env_or(
+                "CIRCUIT_BREAKER_FAILURE_THRESHOLD",
+                DEFAULT_CIRCUIT_FAILURE_THRESHOLD,
+            )?,
+            Duration::from_secs(env_or(
+                "CIRCUIT_BREAKER_OPEN_SECS",
+                DEFAULT_CIRCUIT_OPEN_SECS,
+            )?),
+        )
+        .with_confidence_threshold_percent(env_or(
+            "CONFIDENCE_THRESHOLD_PERCENT",
+            DEFAULT_CONFIDENCE_THRESHOLD_PERCENT,
+        )?)
+        .with_readiness_failure_threshold(env_or(
+            "READINESS_FAILURE_THRESHOLD",
+            DEFAULT_READINESS_FAILURE_THRESHOLD,
+        )?);
+    let estimator = if env_or("FALLBACK_BINARY_SEARCH", false)? {
+        estimator.with_binary_search_fallback(DEFAULT_BINARY_SEARCH_BUDGET.min(request_timeout))
+    } else {
+        estimator
+    };
+    Ok(match heuristic_config()? {
+        Some(config) => estimator.with_heuristics(config),
+        None => estimator,
+    })
 }
 
-pub struct GasEstimator<T: Transport> {
-    eth: Eth<T>,
+/// Settings of learned token call estimates, when `HEURISTIC_ESTIMATES` is set
+fn heuristic_config() -> Result<Option<HeuristicConfig>, String> {
+    if !env_or("HEURISTIC_ESTIMATES", false)? {
+        return Ok(None);
+    }
+    Ok(Some(HeuristicConfig {
+        min_samples: env_or("HEURISTIC_MIN_SAMPLES", DEFAULT_HEURISTIC_MIN_SAMPLES)?,
+        max_deviation_percent: env_or(
+            "HEURISTIC_MAX_DEVIATION_PERCENT",
+            DEFAULT_HEURISTIC_MAX_DEVIATION_PERCENT,
+        )?,
+        margin_percent: env_or("HEURISTIC_MARGIN_PERCENT", DEFAULT_HEURISTIC_MARGIN_PERCENT)?,
+        ttl: Duration::from_secs(env_or("HEURISTIC_TTL_SECS", DEFAULT_HEURISTIC_TTL_SECS)?),
+        max_entries: env_or("HEURISTIC_MAX_ENTRIES", DEFAULT_HEURISTIC_MAX_ENTRIES)?,
+    }))
 }
 
-impl<T: Transport> GasEstimator<T> {
-    pub fn new(transport: T) -> Self {
-        Self {
-            eth: Web3::new(transport).eth(),
-        }
+/// Recorder of upstream traffic into `RECORD_UPSTREAM_PATH` when `RECORD_UPSTREAM=1`.
+/// Debug builds only, so production binaries never write request data to disk.
+fn upstream_recorder() -> Result<Option<Recorder>, String> {
+    let enabled = std::env::var("RECORD_UPSTREAM")
+        .is_ok_and(|value| value == "1" || value.eq_ignore_ascii_case("true"));
+    if !enabled {
+        return Ok(None);
     }
-
-    /// Main estimation logic
-    pub async fn estimate_gas(&self, tx: CallRequest) -> Result<GasEstimateResponse, ApiError> {
-        // Determine estimation method
-        if Self::is_blob_transaction(&tx) || self.needs_simulation(&tx) {
-            // Use RPC for complex transactions
-            let gas_limit = self
-                .eth
-                .estimate_gas(tx, None)
-                .await
-                .map_err(|e| ApiError::InternalServerError(format!("RPC call failed: {e}")))?;
-
-            return Ok(GasEstimateResponse {
-                gas_limit,
-                method: "rpc".to_string(),
-            });
-        }
-
-        // Use static calculation for simple transactions
-        let gas_limit = self.calculate_static_gas(&tx).into();
-        Ok(GasEstimateResponse {
-            gas_limit,
-            method: "static".to_string(),
-        })
+    if !cfg!(debug_assertions) {
+        tracing::warn!("RECORD_UPSTREAM is ignored in release builds");
+        return Ok(None);
     }
+    let path = std::env::var("RECORD_UPSTREAM_PATH")
+        .unwrap_or_else(|_| DEFAULT_RECORDING_PATH.to_string());
+    let recorder = Recorder::create(Path::new(&path))
+        .map_err(|e| format!("Failed to open RECORD_UPSTREAM_PATH {path}: {e}"))?;
+    tracing::warn!(path, "Recording upstream JSON-RPC traffic");
+    Ok(Some(recorder))
+}
 
-    /// Check if this is a blob transaction (EIP-4844)
-    fn is_blob_transaction(tx: &CallRequest) -> bool {
-        tx.transaction_type == Some(BLOB_TX_TYPE.into())
+/// Lets single estimates choose a node from `RPC_OVERRIDE_ALLOWED_HOSTS` when
+/// `ALLOW_RPC_OVERRIDE=true`
+fn rpc_override() -> Result<Option<Arc<RpcOverride>>, String> {
+    if !env_or("ALLOW_RPC_OVERRIDE", false)? {
+        return Ok(None);
     }
-
-    /// Determines if transaction needs EVM simulation
-    fn needs_simulation(&self, tx: &CallRequest) -> bool {
-        if let Some(data) = &tx.data {
-            // Contract calls with data (function or constructor calls)
-            if !data.0.is_empty() {
-                return true;
-            }
-            // Has value and data (might trigger receive/fallback functions)
-            if let Some(value) = &tx.value {
-                if !value.is_zero() {
-                    return true;
-                }
-            }
-        }
-
-        false
+    let allowed_hosts = std::env::var("RPC_OVERRIDE_ALLOWED_HOSTS").unwrap_or_default();
+    let rpc_override = RpcOverride::new(
+        allowed_hosts.split(',').map(str::to_string),
+        Duration::from_secs(env_or(
+            "RPC_OVERRIDE_TIMEOUT_SECS",
+            DEFAULT_RPC_OVERRIDE_TIMEOUT_SECS,
+        )?),
+    );
+    if allowed_hosts.trim().is_empty() {
+        tracing::warn!(
+            "ALLOW_RPC_OVERRIDE is set without RPC_OVERRIDE_ALLOWED_HOSTS, so every override is rejected"
+        );
     }
+    Ok(Some(Arc::new(rpc_override)))
+}
 
-    /// Static gas calculation for simple transactions
-    fn calculate_static_gas(&self, tx: &CallRequest) -> u64 {
-        let mut gas = GAS_TX_BASE;
-
-        // Contract creation vs regular transaction
-        if tx.to.is_none() {
-            gas += GAS_TX_CREATE;
-        }
-
-        // Calculate data gas (calldata)
-        if let Some(data) = &tx.data {
-            let data_bytes = &data.0;
-            for &byte in data_bytes.iter() {
-                if byte == 0 {
-                    gas += GAS_TX_DATA_ZERO;
-                } else {
-                    gas += GAS_TX_DATA_NON_ZERO;
-                }
-            }
-
-            // For contract creation, add code deposit cost
-            if tx.to.is_none() {
-                gas += data_bytes.len() as u64 * GAS_CODE_DEPOSIT;
-            }
-        }
-
-        gas
+/// Per-client rate limiter, when `RATE_LIMIT_PER_MINUTE` is set. The burst
+/// defaults to a minute's worth of requests.
+fn rate_limiter() -> Result<Option<Arc<RateLimiter>>, String> {
+    let per_minute: u32 = env_or("RATE_LIMIT_PER_MINUTE", 0)?;
+    if per_minute == 0 {
+        return Ok(None);
     }
+    let limiter = RateLimiter::new(per_minute, env_or("RATE_LIMIT_BURST", per_minute)?)
+        .with_trusted_proxy_headers(env_or("TRUST_PROXY_HEADERS", false)?);
+    Ok(Some(Arc::new(limiter)))
 }
 
-// API Handlers
-
-/// Handles HTTP requests for gas estimation
-/// POST: /api/estimate-gas
-async fn estimate_gas_handler(
-    State(state): State<AppState<Http>>,
-    Json(payload): Json<CallRequest>,
-) -> Result<Json<GasEstimateResponse>, ApiError> {
-    state.estimator.estimate_gas(payload).await.map(Json)
+/// Logs to stdout, filtered by `RUST_LOG` (default `info`), as JSON lines when
+/// `LOG_FORMAT=json`
+fn init_tracing() {
+    let subscriber = tracing_subscriber::fmt().with_env_filter(
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
+    );
+    if std::env::var("LOG_FORMAT").is_ok_and(|format| format.eq_ignore_ascii_case("json")) {
+        subscriber.json().init();
+    } else {
+        subscriber.init();
+    }
 }
 
-/// Handles HTTP requests for health check
-/// GET: /health
-async fn health_handler() -> impl IntoResponse {
-    Json(serde_json::json!({
-        "status": "healthy",
-        "service": "gas-estimator"
-    }))
+/// Resolves when the process receives SIGINT or SIGTERM
+async fn shutdown_signal() {
+    let interrupt = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("Failed to listen for SIGINT");
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to listen for SIGTERM")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = interrupt => {}
+        _ = terminate => {}
+    }
 }
 
 #[tokio::main]
 async fn main() -> Result<(), String> {
+    init_tracing();
+    let server_config = Config::load()?;
+    tracing::info!(config = %server_config, "Starting");
+
     // Create a Reqwest client with connection pooling
     let reqwest_client = ReqwestClient::builder()
-        .timeout(Duration::from_secs(RPC_TIMEOUT_SECS))
-        .tcp_keepalive(Some(Duration::from_secs(KEEP_ALIVE_SECS)))
-        .pool_max_idle_per_host(MAX_IDLE_CONNECTIONS) // Maximum number of idle connections per host
+        .timeout(server_config.rpc_timeout)
+        .tcp_keepalive(Some(server_config.keep_alive))
+        .pool_max_idle_per_host(server_config.max_idle_connections) // Maximum number of idle connections per host
         .build()
         .map_err(|e| format!("Failed to create HTTP client: {e}"))?;
 
-    // Create Web3 transport with the configured client
-    let rpc_url = Url::parse(
-        &std::env::var("ETH_RPC_URL").unwrap_or_else(|_| DEFAULT_ETH_RPC_URL.to_string()),
-    )
-    .map_err(|e| format!("Failed to parse RPC URL: {e}"))?;
-    let transport = Http::with_client(reqwest_client, rpc_url);
-
-    // Create the gas estimator
-    let estimator = GasEstimator::new(transport);
-    let state = AppState {
-        estimator: Arc::new(estimator),
+    let config = ChainConfig::parse(
+        &server_config.rpc_urls,
+        &std::env::var("CHAINS").unwrap_or_default(),
+    )?;
+    let metrics = Metrics::new();
+    let request_timeout = Duration::from_secs(env_or(
+        "REQUEST_TIMEOUT_SECS",
+        DEFAULT_REQUEST_TIMEOUT_SECS,
+    )?);
+
+    let recorder = upstream_recorder()?;
+    let l2 = L2Config::parse(&std::env::var("L2_KIND").unwrap_or_default())
+        .map_err(|e| format!("Invalid L2_KIND: {e}"))?;
+    let pricing_rule = std::env::var("CALLDATA_PRICING")
+        .ok()
+        .map(|rule| rule.parse::<PricingRule>())
+        .transpose()
+        .map_err(|e| format!("Invalid CALLDATA_PRICING: {e}"))?;
+
+    // Chains selectable per request, each checked to serve the configured chain
+    let mut chains = HashMap::new();
+    for (chain_id, urls) in config.chains {
+        let l2_kind = match l2.chains.get(&chain_id) {
+            Some(kind) => Some(*kind),
+            None if config.default_chain_id == Some(chain_id) => {
+                l2.default.or(l2::known_kind(chain_id))
+            }
+            None => l2::known_kind(chain_id),
+        };
+        let estimator = build_estimator(
+            urls,
+            &reqwest_client,
+            &metrics,
+            &server_config,
+            request_timeout,
+            l2_kind,
+            recorder.as_ref(),
+        )?
+        .with_pricing_rule(pricing_rule.unwrap_or(PricingRule::for_chain(chain_id)));
+        let estimator = match estimator.chain_id().await {
+            Ok(id) if id == chain_id => estimator.with_chain_id(chain_id),
+            Ok(id) => {
+                return Err(format!(
+                    "RPC configured for chain {chain_id} serves chain {id}"
+                ));
+            }
+            // The node may just be down for now; requests will fail until it recovers
+            Err(e) => {
+                tracing::warn!(chain_id, error = %e, "Failed to verify the chain's RPC");
+                estimator
+            }
+        };
+        chains.insert(chain_id, Arc::new(estimator));
+    }
+    let estimator = match config.default_chain_id {
+        Some(chain_id) => chains[&chain_id].clone(),
+        None => {
+            let estimator = build_estimator(
+                config.default_urls,
+                &reqwest_client,
+                &metrics,
+                &server_config,
+                request_timeout,
+                l2.default,
+                recorder.as_ref(),
+            )?
+            .with_pricing_rule(pricing_rule.unwrap_or_default());
+            // Without CHAINS, well-known rollups and chains pricing calldata with
+            // the EIP-7623 floor are recognized by the id their node reports
+            let estimator = match (l2.default, pricing_rule) {
+                (Some(_), Some(_)) => estimator,
+                _ => match estimator.chain_id().await {
+                    Ok(chain_id) => estimator
+                        .with_chain_id(chain_id)
+                        .with_l2_kind(l2.default.or(l2::known_kind(chain_id)))
+                        .with_pricing_rule(
+                            pricing_rule.unwrap_or(PricingRule::for_chain(chain_id)),
+                        ),
+                    Err(e) => {
+                        tracing::warn!(error = %e, "Failed to read the chain id of the RPC");
+                        estimator
+                    }
+                },
+            };
+            Arc::new(estimator)
+        }
     };
-
-    // Set up CORS
-    let cors = CorsLayer::new()
-        .allow_origin(Any)
-        .allow_methods(Any)
-        .allow_headers(Any);
-
-    // Build our application with a route
-    let app = Router::new()
-        .route("/api/estimate-gas", post(estimate_gas_handler))
-        .route("/health", get(health_handler))
-        .layer(cors)
-        .layer(TraceLayer::new_for_http())
-        .with_state(state);
-
-    let listener = tokio::net::TcpListener::bind(BIND_ADDRESS)
-        .await
-        .map_err(|e| format!("Failed to bind to address ({BIND_ADDRESS}): {e}"))?;
-
-    println!("Running server on {BIND_ADDRESS}");
-
-    axum::serve(listener, app)
-        .await
-        .map_err(|e| format!("Server error: {e}"))?;
-
-    Ok(())
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use web3::{
-        transports::test::TestTransport,
-        types::{Address, Bytes, U256},
+    let transport = estimator.transport().clone();
+    let batch_transport = transport.clone();
+    let mut state = AppState {
+        max_batch_size: server_config.max_batch_size,
+        batch_concurrency: env_or("BATCH_CONCURRENCY", DEFAULT_BATCH_CONCURRENCY)?,
+        max_bundle_size: env_or("MAX_BUNDLE_SIZE", DEFAULT_MAX_BUNDLE_SIZE)?,
+        metrics: Arc::new(metrics),
+        max_body_bytes: env_or_former(
+            "MAX_REQUEST_BODY_BYTES",
+            "MAX_BODY_BYTES",
+            DEFAULT_MAX_BODY_BYTES,
+        )?,
+        request_timeout,
+        health_check_timeout: Duration::from_secs(env_or(
+            "HEALTH_CHECK_TIMEOUT_SECS",
+            DEFAULT_HEALTH_CHECK_TIMEOUT_SECS,
+        )?),
+        readiness_check_interval: Duration::from_secs(env_or(
+            "READINESS_CHECK_INTERVAL_SECS",
+            DEFAULT_READINESS_CHECK_INTERVAL_SECS,
+        )?),
+        api_keys: Arc::new(server_config.api_keys.clone()),
+        open_paths: Arc::new(match std::env::var("AUTH_OPEN_PATHS") {
+            Ok(paths) => paths
+                .split(',')
+                .map(str::trim)
+                .filter(|path| !path.is_empty())
+                .map(str::to_string)
+                .collect(),
+            Err(_) => auth::default_open_paths(),
+        }),
+        gas_price_poll_interval: Duration::from_secs(
+            env_or(
+                "GAS_PRICE_POLL_INTERVAL_SECS",
+                DEFAULT_GAS_PRICE_POLL_INTERVAL_SECS,
+            )?
+            .max(1),
+        ),
+        rpc_connections: Some(Arc::new(move || transport.websocket_connections())),
+        rpc_batch: Some(Arc::new(move |requests| {
+            batch_transport.send_batch(requests)
+        })),
+        rate_limiter: rate_limiter()?,
+        rpc_override: rpc_override()?,
+        log_request_bodies: env_or("LOG_REQUESTS", cfg!(feature = "debug_logging"))?,
+        ..AppState::with_chains(estimator, chains)
     };
 
-    /// Helper function to create a mock transport that returns fixed gas values
-    fn mock_transport() -> impl Transport {
-        let mut mock = TestTransport::default();
-        mock.set_response("0x5208".into()); // 21000 gas
-        mock
-    }
-
-    /// Helper function to create an address
-    fn address_from() -> Address {
-        "0xc0ffee254729296a45a3885639AC7E10F9d54979"
-            .parse()
-            .unwrap()
-    }
-
-    /// Helper function to create an address
-    fn address_to() -> Address {
-        "0xc0ffee254729296a45a3885639AC7E10F9d54979"
-            .parse()
-            .unwrap()
+    #[cfg(feature = "cache")]
+    {
+        use gas_estimator::cache::{
+            CachedGasEstimator, DEFAULT_CACHE_MAX_ENTRIES, DEFAULT_CACHE_TTL_SECS,
+        };
+        state.cached_estimator = Some(Arc::new(CachedGasEstimator::new(
+            state.estimator.clone(),
+            env_or("ESTIMATE_CACHE_MAX_ENTRIES", DEFAULT_CACHE_MAX_ENTRIES)?,
+            Duration::from_secs(env_or("ESTIMATE_CACHE_TTL_SECS", DEFAULT_CACHE_TTL_SECS)?),
+        )));
     }
 
-    /// Helper function to create a simple transfer request
-    fn simple_transfer_request() -> CallRequest {
-        CallRequest {
-            from: Some(address_from()),
-            to: Some(address_to()),
-            value: Some(U256::one()),
-            ..Default::default()
+    // Connect to the node and fill caches before taking traffic. Startup waits
+    // out a grace period for a node that isn't up yet, then either gives up or
+    // starts not ready and warms up from the readiness endpoint.
+    if env_or("WARMUP", true)? {
+        let grace = Duration::from_secs(env_or("WARMUP_GRACE_SECS", DEFAULT_WARMUP_GRACE_SECS)?);
+        match state.estimator.warm_up_within(grace).await {
+            Ok(warmup) => tracing::info!(
+                chain_id = warmup.chain_id,
+                rpc_latency_ms = warmup.latency.as_millis() as u64,
+                "Warmed up the RPC connection"
+            ),
+            Err(e) if env_or("WARMUP_REQUIRED", false)? => {
+                return Err(format!("Warmup failed: {e}"));
+            }
+            Err(e) => {
+                tracing::warn!(error = %e, "Warmup failed, starting degraded");
+                state.warmup.set_pending();
+            }
         }
     }
 
-    #[test]
-    fn test_calculate_static_gas_simple_transfer() {
-        let estimator = GasEstimator::new(mock_transport());
-        let tx = simple_transfer_request();
-
-        let gas = estimator.calculate_static_gas(&tx);
-        assert_eq!(gas, GAS_TX_BASE);
-    }
-
-    #[test]
-    fn test_calculate_static_gas_contract_creation() {
-        let estimator = GasEstimator::new(mock_transport());
-        let tx = CallRequest {
-            to: None, // Contract creation
-            ..Default::default()
-        };
-
-        let gas = estimator.calculate_static_gas(&tx);
-        assert_eq!(gas, GAS_TX_BASE + GAS_TX_CREATE);
+    let base_fee_poll_secs = env_or("BASE_FEE_POLL_SECS", DEFAULT_BASE_FEE_POLL_SECS)?;
+    if base_fee_poll_secs > 0 {
+        let watcher = Arc::new(FeeWatcher::new());
+        tokio::spawn(watcher.clone().run(
+            state.estimator.clone(),
+            Duration::from_secs(base_fee_poll_secs),
+        ));
+        state.fee_watcher = Some(watcher);
     }
 
-    #[test]
-    fn test_calculate_static_gas_with_data() {
-        let estimator = GasEstimator::new(mock_transport());
-        let tx = CallRequest {
-            to: Some(address_to()),
-            data: Some(Bytes::from(vec![0x01, 0x00, 0x02])), // 2 non-zero, 1 zero byte
-            ..Default::default()
-        };
-
-        let gas = estimator.calculate_static_gas(&tx);
-        assert_eq!(
-            gas,
-            GAS_TX_BASE + (GAS_TX_DATA_NON_ZERO * 2) + GAS_TX_DATA_ZERO
+    // Other chains probe on their first bundle
+    if state.estimator.probe_simulate_v1().await {
+        tracing::info!("Bundles are simulated with eth_simulateV1");
+    } else {
+        tracing::info!(
+            "Node lacks eth_simulateV1, bundles are estimated one transaction at a time"
         );
     }
 
-    #[test]
-    fn test_needs_simulation_with_data() {
-        let estimator = GasEstimator::new(mock_transport());
-        let tx = CallRequest {
-            data: Some(Bytes::from(vec![0x01])),
-            ..Default::default()
-        };
-
-        assert!(estimator.needs_simulation(&tx));
-    }
+    let shutdown = state.shutdown.clone();
+    let shutdown_timeout = Duration::from_secs(env_or(
+        "SHUTDOWN_TIMEOUT_SECS",
+        DEFAULT_SHUTDOWN_TIMEOUT_SECS,
+    )?);
 
-    #[test]
-    fn test_needs_simulation_with_value() {
-        let estimator = GasEstimator::new(mock_transport());
-        let tx = CallRequest {
-            data: Some(Bytes::default()),
-            value: Some(U256::from(1)),
-            ..Default::default()
-        };
+    let cors = cors::cors_layer(|name| std::env::var(name).ok())?;
 
-        assert!(estimator.needs_simulation(&tx));
-    }
+    // Build our application with a route
+    let app = build_router(state).layer(cors);
 
-    #[tokio::test]
-    async fn test_estimate_gas_static() {
-        let estimator = GasEstimator::new(mock_transport());
-        let tx = simple_transfer_request();
+    let bind_address = server_config.bind_address;
+    let listener = tokio::net::TcpListener::bind(bind_address)
+        .await
+        .map_err(|e| format!("Failed to bind to address ({bind_address}): {e}"))?;
 
-        let result = estimator.estimate_gas(tx).await.unwrap();
-        assert_eq!(result.gas_limit, GAS_TX_BASE.into());
-        assert_eq!(result.method, "static");
-    }
+    tracing::info!(%bind_address, "Running server");
 
-    #[tokio::test]
-    async fn test_estimate_gas_rpc() {
-        let estimator = GasEstimator::new(mock_transport());
-        let tx = CallRequest {
-            data: Some(Bytes::from(vec![0x01])), // Forces RPC path
-            ..Default::default()
-        };
+    // Stop accepting connections on SIGINT/SIGTERM and let in-flight requests finish,
+    // giving up once the drain timeout elapses
+    // Peer addresses identify clients for rate limiting
+    let server = axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown({
+        let shutdown = shutdown.clone();
+        async move {
+            shutdown_signal().await;
+            tracing::info!("Shutdown signal received, draining in-flight requests");
+            shutdown.initiate();
+        }
+    });
+    let drain_deadline = async {
+        shutdown.initiated().await;
+        tokio::time::sleep(shutdown_timeout).await;
+    };
 
-        let result = estimator.estimate_gas(tx).await.unwrap();
-        assert_eq!(result.gas_limit, 21000.into()); // From mock response
-        assert_eq!(result.method, "rpc");
+    tokio::select! {
+        result = server.into_future() => result.map_err(|e| format!("Server error: {e}"))?,
+        _ = drain_deadline => tracing::warn!(
+            in_flight = shutdown.in_flight(),
+            "Shutdown timeout elapsed with requests still in flight"
+        ),
     }
 
-    #[tokio::test]
-    async fn test_estimate_gas_blob_transaction() {
-        let estimator = GasEstimator::new(mock_transport());
-        let tx = CallRequest {
-            transaction_type: Some(BLOB_TX_TYPE.into()),
-            ..Default::default()
-        };
-
-        let result = estimator.estimate_gas(tx).await.unwrap();
-        assert_eq!(result.method, "rpc");
-    }
+    Ok(())
 }
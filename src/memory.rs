@@ -0,0 +1,90 @@
+//! Process memory and resource statistics reported by the health endpoint
+
+use serde::Serialize;
+use utoipa::ToSchema;
+
+#[cfg(feature = "jemalloc")]
+#[global_allocator]
+static GLOBAL: tikv_jemallocator::Jemalloc = tikv_jemallocator::Jemalloc;
+
+#[derive(Debug, Default, Serialize, ToSchema)]
+pub struct MemoryStats {
+    /// Bytes currently allocated by the application, in KiB
+    pub heap_allocated_kb: Option<u64>,
+    /// Bytes the allocator holds in physically resident pages, in KiB
+    pub heap_resident_kb: Option<u64>,
+    /// Resident / allocated ratio; values well above 1.0 indicate fragmentation
+    pub fragmentation_ratio: Option<f64>,
+    pub open_file_descriptors: Option<u64>,
+    /// Number of alive tokio tasks
+    pub goroutines_equivalent: Option<usize>,
+}
+
+impl MemoryStats {
+    /// Collects the current statistics. Fields that can't be read on this platform are `None`.
+    pub fn collect() -> Self {
+        let (heap_allocated_kb, heap_resident_kb) = match heap_stats() {
+            Some((allocated, resident)) => (Some(allocated / 1024), Some(resident / 1024)),
+            None => (None, None),
+        };
+        let fragmentation_ratio = match (heap_allocated_kb, heap_resident_kb) {
+            (Some(allocated), Some(resident)) if allocated > 0 => {
+                Some(resident as f64 / allocated as f64)
+            }
+            _ => None,
+        };
+
+        Self {
+            heap_allocated_kb,
+            heap_resident_kb,
+            fragmentation_ratio,
+            open_file_descriptors: open_file_descriptors(),
+            goroutines_equivalent: tokio::runtime::Handle::try_current()
+                .ok()
+                .map(|handle| handle.metrics().num_alive_tasks()),
+        }
+    }
+}
+
+/// Returns `(allocated, resident)` heap bytes from jemalloc
+#[cfg(feature = "jemalloc")]
+fn heap_stats() -> Option<(u64, u64)> {
+    use tikv_jemalloc_ctl::{epoch, stats};
+
+    // Statistics are cached by jemalloc until the epoch is advanced
+    epoch::advance().ok()?;
+    let allocated = stats::allocated::read().ok()? as u64;
+    let resident = stats::resident::read().ok()? as u64;
+    Some((allocated, resident))
+}
+
+/// Returns `(allocated, resident)` heap bytes from glibc malloc
+#[cfg(all(not(feature = "jemalloc"), target_os = "linux", target_env = "gnu"))]
+fn heap_stats() -> Option<(u64, u64)> {
+    // SAFETY: mallinfo2 has no preconditions and only reads allocator state
+    let info = unsafe { libc::mallinfo2() };
+    let allocated = (info.uordblks + info.hblkhd) as u64;
+    // Memory obtained from the system: main arena plus mmapped chunks
+    let resident = (info.arena + info.hblkhd) as u64;
+    Some((allocated, resident))
+}
+
+#[cfg(all(
+    not(feature = "jemalloc"),
+    not(all(target_os = "linux", target_env = "gnu"))
+))]
+fn heap_stats() -> Option<(u64, u64)> {
+    None
+}
+
+#[cfg(target_os = "linux")]
+fn open_file_descriptors() -> Option<u64> {
+    std::fs::read_dir("/proc/self/fd")
+        .ok()
+        .map(|entries| entries.count() as u64)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn open_file_descriptors() -> Option<u64> {
+    None
+}
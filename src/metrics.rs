@@ -0,0 +1,171 @@
+//! Prometheus metrics for gas estimates
+
+use crate::estimator::{ApiError, GasEstimateResponse};
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, Opts, Registry, TextEncoder,
+};
+use std::time::Duration;
+
+/// Counters and histograms exposed on `GET /metrics`
+pub struct Metrics {
+    registry: Registry,
+    estimates: IntCounterVec,
+    estimate_duration: HistogramVec,
+    errors: IntCounterVec,
+    api_key_requests: IntCounterVec,
+    /// Transport failures of individual RPC endpoints, shared with
+    /// [`MultiTransport`](crate::MultiTransport)
+    pub rpc_endpoint_failures: IntCounter,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let registry = Registry::new();
+        let estimates = IntCounterVec::new(
+            Opts::new("gas_estimate_total", "Gas estimates served"),
+            &["method"],
+        )
+        .unwrap();
+        let estimate_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "gas_estimate_duration_seconds",
+                "Time taken to produce a gas estimate",
+            ),
+            &["method"],
+        )
+        .unwrap();
+        let errors = IntCounterVec::new(
+            Opts::new("gas_estimate_errors_total", "Failed gas estimates"),
+            &["kind"],
+        )
+        .unwrap();
+        let api_key_requests = IntCounterVec::new(
+            Opts::new(
+                "api_key_requests_total",
+                "Authenticated requests by hashed API key",
+            ),
+            &["key_id"],
+        )
+        .unwrap();
+        let rpc_endpoint_failures = IntCounter::new(
+            "rpc_endpoint_failures_total",
+            "Transport failures of individual RPC endpoints",
+        )
+        .unwrap();
+
+        // Registration only fails on duplicate names, which are fixed above
+        registry.register(Box::new(estimates.clone())).unwrap();
+        registry
+            .register(Box::new(estimate_duration.clone()))
+            .unwrap();
+        registry.register(Box::new(errors.clone())).unwrap();
+        registry
+            .register(Box::new(api_key_requests.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(rpc_endpoint_failures.clone()))
+            .unwrap();
+
+        Self {
+            registry,
+            estimates,
+            estimate_duration,
+            errors,
+            api_key_requests,
+            rpc_endpoint_failures,
+        }
+    }
+
+    /// Records the outcome of an estimate. Batch items share one request and
+    /// pass no duration.
+    pub fn record(
+        &self,
+        result: &Result<GasEstimateResponse, ApiError>,
+        elapsed: Option<Duration>,
+    ) {
+        match result {
+            Ok(response) => {
+                let method = method_label(&response.method);
+                self.estimates.with_label_values(&[method]).inc();
+                if let Some(elapsed) = elapsed {
+                    self.estimate_duration
+                        .with_label_values(&[method])
+                        .observe(elapsed.as_secs_f64());
+                }
+            }
+            Err(e) => self.errors.with_label_values(&[error_kind(e)]).inc(),
+        }
+    }
+
+    /// Counts a request authenticated with the key identified by `key_id`
+    pub fn record_api_key(&self, key_id: &str) {
+        self.api_key_requests.with_label_values(&[key_id]).inc();
+    }
+
+    /// Content type of [`Metrics::render`] output
+    pub fn content_type(&self) -> String {
+        TextEncoder::new().format_type().to_string()
+    }
+
+    /// Renders all metrics in the Prometheus text format
+    pub fn render(&self) -> String {
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .expect("text encoding into a Vec can't fail");
+        String::from_utf8(buffer).expect("Prometheus text format is UTF-8")
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Estimation path without the `+margin` suffix, keeping label cardinality fixed
+fn method_label(method: &str) -> &str {
+    method.strip_suffix("+margin").unwrap_or(method)
+}
+
+/// Client errors (including reverts) versus failures on our side or the node's
+fn error_kind(error: &ApiError) -> &'static str {
+    if error.status_code().is_client_error() {
+        "bad_request"
+    } else {
+        "internal"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_and_render() {
+        let metrics = Metrics::new();
+        let estimate = GasEstimateResponse {
+            method: "static+margin".to_string(),
+            ..Default::default()
+        };
+        metrics.record(&Ok(estimate), Some(Duration::from_millis(5)));
+        metrics.record(&Err(ApiError::BadRequest("bad".to_string())), None);
+        metrics.record(
+            &Err(ApiError::ExecutionReverted {
+                reason: None,
+                data: None,
+            }),
+            None,
+        );
+        metrics.rpc_endpoint_failures.inc();
+        metrics.record_api_key("0123abcd");
+
+        let body = metrics.render();
+        assert!(body.contains(r#"gas_estimate_total{method="static"} 1"#));
+        assert!(body.contains(r#"gas_estimate_duration_seconds_count{method="static"} 1"#));
+        assert!(body.contains(r#"gas_estimate_errors_total{kind="bad_request"} 2"#));
+        assert!(body.contains("rpc_endpoint_failures_total 1"));
+        assert!(body.contains(r#"api_key_requests_total{key_id="0123abcd"} 1"#));
+        assert_eq!(metrics.content_type(), "text/plain; version=0.0.4");
+    }
+}
@@ -0,0 +1,227 @@
+//! OpenAPI description of the HTTP API, served at `/api/openapi.json` with a
+//! Swagger UI at `/api/docs`, also reachable as `/docs`
+
+use crate::access_list::{AccessListEstimate, CreateAccessListResponse};
+use crate::balance::BalanceCheck;
+use crate::base_fee::BaseFeeResponse;
+use crate::blur::{BlurExecution, BlurFee, BlurOrder, BlurPurchaseEstimate, BlurPurchaseParams};
+use crate::bundle::{BundleRequest, BundleTxEstimate};
+use crate::calldata::CalldataStats;
+use crate::eigenlayer::{EigenLayerParams, EigenLayerRestakeEstimate};
+use crate::eip7702::SignedAuthorization;
+use crate::estimator::{
+    ErrorResponse, EstimateReason, FeeEstimate, FeeSuggestionResponse, GasBreakdown,
+    GasEstimateResponse,
+};
+use crate::fee_history::{FeeHistoryResponse, FeeHistorySuggestion, FeePriorityPercentiles};
+use crate::fee_watcher::FeeSnapshot;
+use crate::gas_costs::PricingRule;
+use crate::gas_price::GasPriceUpdate;
+use crate::request::EstimateRequest;
+use crate::safe::{SafeWithGuardEstimate, SafeWithGuardParams};
+use crate::server::{self, BatchItemError, BatchItemResult};
+use crate::state_override::AccountOverride;
+use crate::units::GasEstimateResponseV2;
+use crate::user_operation::{UserOperationGasResponse, UserOperationRequest};
+use crate::wallet_calls::{WalletCall, WalletSendCallsEstimate, WalletSendCallsRequest};
+use utoipa::openapi::{ArrayBuilder, ObjectBuilder, OneOfBuilder, RefOr, Schema, SchemaType};
+use utoipa::{Modify, OpenApi, ToSchema};
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Gas Estimator",
+        description = "Ethereum gas estimation API. Every `/api/...` route is also served \
+                       as `/api/{chain_id}/...` for the chains the server is configured with."
+    ),
+    paths(
+        server::estimate_gas_handler,
+        server::estimate_gas_v2_handler,
+        server::estimate_transfer_handler,
+        server::estimate_gas_batch_handler,
+        server::estimate_bundle_handler,
+        server::create_access_list_handler,
+        server::fee_suggestion_handler,
+        server::next_base_fee_handler,
+        server::fees_handler,
+        server::fees_stream_handler,
+        server::fee_history_handler,
+        server::gas_price_percentiles_handler,
+        server::estimate_safe_with_guard_handler,
+        server::estimate_eigenlayer_restake_handler,
+        server::estimate_blur_purchase_handler,
+        server::estimate_wallet_send_calls_handler,
+        server::estimate_user_operation_handler,
+        server::json_rpc_handler,
+        server::gas_price_ws_handler,
+        server::health_handler,
+        server::liveness_handler,
+        server::readiness_handler,
+        server::metrics_handler,
+    ),
+    components(schemas(
+        EstimateRequest,
+        SignedAuthorization,
+        AccountOverride,
+        GasEstimateResponse,
+        GasEstimateResponseV2,
+        EstimateMethod,
+        EstimateReason,
+        PricingRule,
+        GasBreakdown,
+        FeeEstimate,
+        AccessListEstimate,
+        BalanceCheck,
+        CalldataStats,
+        ErrorResponse,
+        BatchItemResult,
+        BatchItemError,
+        BundleRequest,
+        BundleTxEstimate,
+        CreateAccessListResponse,
+        FeeSuggestionResponse,
+        BaseFeeResponse,
+        FeeSnapshot,
+        FeeHistoryResponse,
+        FeeHistorySuggestion,
+        FeePriorityPercentiles,
+        SafeWithGuardParams,
+        SafeWithGuardEstimate,
+        EigenLayerParams,
+        EigenLayerRestakeEstimate,
+        BlurPurchaseParams,
+        BlurExecution,
+        BlurOrder,
+        BlurFee,
+        BlurPurchaseEstimate,
+        WalletSendCallsRequest,
+        WalletCall,
+        WalletSendCallsEstimate,
+        UserOperationRequest,
+        UserOperationGasResponse,
+        GasPriceUpdate,
+    )),
+    modifiers(&EthereumTypes),
+    tags(
+        (name = "estimate", description = "Gas estimates of transactions"),
+        (name = "protocols", description = "Gas estimates of protocol-specific operations"),
+        (name = "fees", description = "Fee suggestions"),
+        (name = "service", description = "Health and metrics"),
+    )
+)]
+pub struct ApiDoc;
+
+/// Values of the `method` of estimates, which is a plain string in responses
+pub struct EstimateMethod;
+
+impl<'s> ToSchema<'s> for EstimateMethod {
+    fn schema() -> (&'s str, RefOr<Schema>) {
+        (
+            "EstimateMethod",
+            ObjectBuilder::new()
+                .schema_type(SchemaType::String)
+                .enum_values(Some([
+                    "static",
+                    "static+margin",
+                    "static-blob",
+                    "static-blob+margin",
+                    "rpc",
+                    "rpc+margin",
+                    "rpc-forced",
+                    "rpc-forced+margin",
+                    "rpc-cached",
+                    "rpc-cached+margin",
+                    "heuristic",
+                    "heuristic+margin",
+                    "binary_search",
+                    "binary_search+margin",
+                ]))
+                .description(Some(
+                    "How the estimate was computed: from intrinsic costs (`static`, or \
+                     `static-blob` for a blob transaction with a `blob_count`), by the \
+                     node (`rpc`, or `rpc-forced` when `has_transient_storage` sent a \
+                     transaction the node otherwise wouldn't see), from the estimate cache (`rpc-cached`) or from earlier node \
+                     estimates of the same token call (`heuristic`), or by probing gas limits \
+                     with `eth_call` after the node failed to estimate (`binary_search`), with `+margin` when \
+                     `margin_bps` padded it",
+                ))
+                .into(),
+        )
+    }
+}
+
+/// Adds the web3 types that schemas refer to by name, in their JSON-RPC encoding
+struct EthereumTypes;
+
+impl Modify for EthereumTypes {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let hex = |description: &str, example: &str| -> RefOr<Schema> {
+            ObjectBuilder::new()
+                .schema_type(SchemaType::String)
+                .description(Some(description))
+                .example(Some(example.into()))
+                .into()
+        };
+        let access_list = ArrayBuilder::new()
+            .items(
+                ObjectBuilder::new()
+                    .property(
+                        "address",
+                        RefOr::Ref(utoipa::openapi::Ref::from_schema_name("Address")),
+                    )
+                    .required("address")
+                    .property(
+                        "storageKeys",
+                        ArrayBuilder::new()
+                            .items(RefOr::Ref(utoipa::openapi::Ref::from_schema_name("H256"))),
+                    )
+                    .required("storageKeys"),
+            )
+            .description(Some("EIP-2930 access list"))
+            .into();
+        let quantity = OneOfBuilder::new()
+            .item(hex("Hex quantity", "0x5208"))
+            .item(hex("Decimal quantity", "21000"))
+            .item(ObjectBuilder::new().schema_type(SchemaType::Integer))
+            .description(Some(
+                "Quantity as a hex string, a decimal string or a number",
+            ))
+            .into();
+        let state_override = ObjectBuilder::new()
+            .additional_properties(Some(RefOr::Ref(utoipa::openapi::Ref::from_schema_name(
+                "AccountOverride",
+            ))))
+            .description(Some("Overrides keyed by account address"))
+            .into();
+
+        let components = openapi.components.get_or_insert_with(Default::default);
+        for (name, schema) in [
+            ("U256", hex("256-bit unsigned integer in hex", "0xc350")),
+            ("U64", hex("64-bit unsigned integer in hex", "0x1")),
+            (
+                "H256",
+                hex(
+                    "32-byte hash in hex",
+                    "0x0000000000000000000000000000000000000000000000000000000000000001",
+                ),
+            ),
+            (
+                "Address",
+                hex(
+                    "20-byte address in hex",
+                    "0x6b175474e89094c44da98b954eedeac495271d0f",
+                ),
+            ),
+            ("Bytes", hex("Byte string in hex", "0xa9059cbb")),
+            (
+                "BlockNumber",
+                hex("Block tag or hex block number", "latest"),
+            ),
+            ("AccessList", access_list),
+            ("Quantity", quantity),
+            ("StateOverride", state_override),
+        ] {
+            components.schemas.insert(name.to_string(), schema);
+        }
+    }
+}
@@ -0,0 +1,204 @@
+//! Gas of calls to precompiles whose cost follows from the input's length or
+//! header alone, so transactions calling them can be estimated statically
+
+use web3::types::{Address, CallRequest, U256};
+
+pub const ECRECOVER_GAS: u64 = 3000;
+pub const SHA256_BASE_GAS: u64 = 60;
+pub const SHA256_WORD_GAS: u64 = 12;
+pub const RIPEMD160_BASE_GAS: u64 = 600;
+pub const RIPEMD160_WORD_GAS: u64 = 120;
+pub const IDENTITY_BASE_GAS: u64 = 15;
+pub const IDENTITY_WORD_GAS: u64 = 3;
+/// EIP-7883 minimum cost of a modexp call
+pub const MODEXP_MIN_GAS: u64 = 500;
+/// EIP-7823 limit on each of the base, exponent and modulus lengths; longer
+/// inputs make the call fail
+pub const MODEXP_MAX_INPUT_SIZE: u64 = 1024;
+/// Longest exponent priced statically. Longer ones are priced by their length
+/// and can cost more than a block holds, so the node estimates them.
+const MODEXP_MAX_STATIC_EXPONENT_SIZE: u64 = 32;
+
+/// Gas `tx` spends in the precompile it calls, when it calls one with a cost
+/// that doesn't depend on the input's content: ecrecover (0x01), sha256 (0x02),
+/// ripemd160 (0x03), identity (0x04), and modexp (0x05) with an exponent of at
+/// most 32 bytes. These calls can't fail for lack of valid input. The other
+/// precompiles, up to the point evaluation at 0x0a, reject malformed input by
+/// consuming all gas, so only the node can tell whether a call succeeds.
+pub fn call_gas(tx: &CallRequest) -> Option<u64> {
+    let to = tx.to?;
+    let input = tx.data.as_ref().map_or(&[][..], |data| &data.0[..]);
+    precompile_gas(to, input)
+}
+
+/// Gas of calling the precompile at `address` with `input`, if it is one
+/// [`call_gas`] prices
+pub fn precompile_gas(address: Address, input: &[u8]) -> Option<u64> {
+    let index = precompile_index(address)?;
+    let words = (input.len() as u64).div_ceil(32);
+    match index {
+        0x01 => Some(ECRECOVER_GAS),
+        0x02 => Some(SHA256_BASE_GAS + SHA256_WORD_GAS * words),
+        0x03 => Some(RIPEMD160_BASE_GAS + RIPEMD160_WORD_GAS * words),
+        0x04 => Some(IDENTITY_BASE_GAS + IDENTITY_WORD_GAS * words),
+        0x05 => modexp_gas(input),
+        _ => None,
+    }
+}
+
+/// Low byte of a precompile address, `0x00…0001` to `0x00…000a`
+fn precompile_index(address: Address) -> Option<u8> {
+    let (prefix, index) = address.0.split_at(19);
+    (prefix.iter().all(|&byte| byte == 0) && (0x01..=0x0a).contains(&index[0])).then_some(index[0])
+}
+
+/// EIP-7883 (Osaka) cost of a modexp call, read from the lengths in its
+/// 96-byte header and the exponent's leading bytes. Earlier forks charge less,
+/// so chains before Osaka get an estimate above what they charge.
+fn modexp_gas(input: &[u8]) -> Option<u64> {
+    let base_size = word(input, 0);
+    let exponent_size = word(input, 32);
+    let modulus_size = word(input, 64);
+    if [base_size, exponent_size, modulus_size]
+        .iter()
+        .any(|&size| size > MODEXP_MAX_INPUT_SIZE.into())
+        || exponent_size > MODEXP_MAX_STATIC_EXPONENT_SIZE.into()
+    {
+        return None;
+    }
+    let (base_size, exponent_size, modulus_size) = (
+        base_size.as_usize(),
+        exponent_size.as_usize(),
+        modulus_size.as_usize(),
+    );
+
+    let max_size = base_size.max(modulus_size) as u64;
+    let complexity = if max_size <= 32 {
+        16
+    } else {
+        2 * max_size.div_ceil(8).pow(2)
+    };
+    // The exponent follows the header and the base, and input missing past the
+    // end reads as zeros
+    let exponent = U256::from_big_endian(&padded(input, 96 + base_size, exponent_size));
+    let iterations = (exponent.bits() as u64).saturating_sub(1).max(1);
+    Some((complexity * iterations).max(MODEXP_MIN_GAS))
+}
+
+/// 32-byte big-endian word of `input` at `offset`
+fn word(input: &[u8], offset: usize) -> U256 {
+    U256::from_big_endian(&padded(input, offset, 32))
+}
+
+/// `len` bytes of `input` from `offset`, with zeros past its end
+fn padded(input: &[u8], offset: usize, len: usize) -> Vec<u8> {
+    let mut bytes = vec![0; len];
+    if offset < input.len() {
+        let available = &input[offset..input.len().min(offset + len)];
+        bytes[..available.len()].copy_from_slice(available);
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn precompile(index: u8) -> Address {
+        Address::from_low_u64_be(index.into())
+    }
+
+    /// modexp input with the given lengths, a base and modulus of 0xff bytes and
+    /// the given exponent
+    fn modexp_input(base_size: usize, exponent: &[u8], modulus_size: usize) -> Vec<u8> {
+        let mut input = Vec::new();
+        for size in [base_size, exponent.len(), modulus_size] {
+            let mut word = [0; 32];
+            U256::from(size).to_big_endian(&mut word);
+            input.extend_from_slice(&word);
+        }
+        input.extend(std::iter::repeat_n(0xff, base_size));
+        input.extend_from_slice(exponent);
+        input.extend(std::iter::repeat_n(0xff, modulus_size));
+        input
+    }
+
+    #[test]
+    fn test_fixed_and_linear_precompiles() {
+        let cases = [
+            (0x01, 128, 3000),
+            (0x01, 0, 3000),
+            (0x02, 0, 60),
+            (0x02, 32, 72),
+            (0x02, 33, 84),
+            (0x03, 0, 600),
+            (0x03, 32, 720),
+            (0x03, 64, 840),
+            (0x04, 0, 15),
+            (0x04, 32, 18),
+            (0x04, 33, 21),
+            (0x04, 1024, 111),
+        ];
+        for (index, len, gas) in cases {
+            assert_eq!(
+                precompile_gas(precompile(index), &vec![0xab; len]),
+                Some(gas),
+                "precompile {index:#04x} with {len} bytes"
+            );
+        }
+    }
+
+    #[test]
+    fn test_modexp() {
+        // Test cases listed in EIP-7883
+        // nagydani-1-square
+        assert_eq!(modexp_gas(&modexp_input(64, &[0x02], 64)), Some(500));
+        // nagydani-1-pow0x10001
+        assert_eq!(
+            modexp_gas(&modexp_input(64, &[0x01, 0x00, 0x01], 64)),
+            Some(2048)
+        );
+        // nagydani-2-pow0x10001
+        assert_eq!(
+            modexp_gas(&modexp_input(128, &[0x01, 0x00, 0x01], 128)),
+            Some(8192)
+        );
+        // Operands of up to 32 bytes have a complexity of 16, and zero exponents
+        // pay the minimum
+        assert_eq!(
+            modexp_gas(&modexp_input(1, &[0xff; 32], 32)),
+            Some(16 * 255)
+        );
+        assert_eq!(modexp_gas(&modexp_input(32, &[0x00], 32)), Some(500));
+        // Empty input reads as all-zero lengths
+        assert_eq!(modexp_gas(&[]), Some(500));
+        // A truncated exponent reads as zeros past the end
+        let mut truncated = modexp_input(64, &[0x80, 0x00], 64);
+        truncated.truncate(96 + 64 + 1);
+        assert_eq!(modexp_gas(&truncated), Some(128 * 15));
+
+        // Long exponents and inputs over the EIP-7823 limit go to the node
+        assert_eq!(modexp_gas(&modexp_input(32, &[0x01; 33], 32)), None);
+        assert_eq!(modexp_gas(&modexp_input(1025, &[0x01], 32)), None);
+        let mut huge_length = modexp_input(32, &[0x01], 32);
+        huge_length[..32].fill(0xff);
+        assert_eq!(modexp_gas(&huge_length), None);
+    }
+
+    #[test]
+    fn test_unpriced_addresses() {
+        for index in [0x00, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x100] {
+            assert_eq!(
+                precompile_gas(Address::from_low_u64_be(index), &[]),
+                None,
+                "{index:#x}"
+            );
+        }
+        assert_eq!(
+            precompile_gas(Address::repeat_byte(0x01), &[]),
+            None,
+            "not a precompile"
+        );
+        assert_eq!(call_gas(&CallRequest::default()), None, "contract creation");
+    }
+}
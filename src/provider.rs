@@ -0,0 +1,105 @@
+//! Estimates behind a trait, so applications and tests can answer
+//! `/api/estimate-gas` without a node
+
+use crate::estimator::{ApiError, EstimateGasRequest, GasEstimateResponse, GasEstimator};
+use futures::future::BoxFuture;
+use web3::Transport;
+
+/// Source of single gas estimates. Implemented by [`GasEstimator`] over any
+/// transport; set [`AppState::estimate_provider`](crate::AppState::estimate_provider)
+/// to answer the default chain's estimates with another implementation.
+pub trait GasEstimateProvider {
+    /// Estimates a transaction with its options. A bare
+    /// [`CallRequest`](web3::types::CallRequest) converts with `.into()`.
+    fn estimate_gas(
+        &self,
+        request: EstimateGasRequest,
+    ) -> BoxFuture<'_, Result<GasEstimateResponse, ApiError>>;
+}
+
+impl<T> GasEstimateProvider for GasEstimator<T>
+where
+    T: Transport + Send + Sync,
+    T::Out: Send,
+{
+    fn estimate_gas(
+        &self,
+        request: EstimateGasRequest,
+    ) -> BoxFuture<'_, Result<GasEstimateResponse, ApiError>> {
+        Box::pin(self.estimate(request))
+    }
+}
+
+#[cfg(any(test, feature = "testing"))]
+pub use mock::MockGasEstimator;
+
+#[cfg(any(test, feature = "testing"))]
+mod mock {
+    use super::*;
+    use std::collections::VecDeque;
+    use std::sync::Mutex;
+
+    /// Provider answering with queued results, in order, and recording the requests
+    /// it was asked to estimate
+    #[derive(Debug, Default)]
+    pub struct MockGasEstimator {
+        responses: Mutex<VecDeque<Result<GasEstimateResponse, ApiError>>>,
+        requests: Mutex<Vec<EstimateGasRequest>>,
+    }
+
+    impl MockGasEstimator {
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        /// Queues the result of the next estimate
+        pub fn push_response(&self, response: Result<GasEstimateResponse, ApiError>) {
+            self.responses.lock().unwrap().push_back(response);
+        }
+
+        /// Requests estimated so far
+        pub fn requests(&self) -> Vec<EstimateGasRequest> {
+            self.requests.lock().unwrap().clone()
+        }
+    }
+
+    impl GasEstimateProvider for MockGasEstimator {
+        fn estimate_gas(
+            &self,
+            request: EstimateGasRequest,
+        ) -> BoxFuture<'_, Result<GasEstimateResponse, ApiError>> {
+            self.requests.lock().unwrap().push(request);
+            let response = self.responses.lock().unwrap().pop_front();
+            Box::pin(async move {
+                response.unwrap_or_else(|| {
+                    Err(ApiError::ServiceUnavailable(
+                        "No mocked estimate left".to_string(),
+                    ))
+                })
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_mock_answers_in_order() {
+        let mock = MockGasEstimator::new();
+        mock.push_response(Ok(GasEstimateResponse {
+            gas_limit: 21_000.into(),
+            ..Default::default()
+        }));
+        mock.push_response(Err(ApiError::BadRequest("invalid".to_string())));
+
+        let first = mock.estimate_gas(Default::default()).await.unwrap();
+        assert_eq!(first.gas_limit, 21_000.into());
+        let second = mock.estimate_gas(Default::default()).await;
+        assert!(matches!(second, Err(ApiError::BadRequest(_))));
+        let exhausted = mock.estimate_gas(Default::default()).await;
+        assert!(matches!(exhausted, Err(ApiError::ServiceUnavailable(_))));
+        assert_eq!(mock.requests().len(), 3);
+    }
+}
@@ -0,0 +1,232 @@
+//! Per-client rate limiting with token buckets keyed by client IP
+
+use crate::estimator::ApiError;
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{HeaderMap, HeaderValue, Method},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+pub const RATE_LIMIT_REMAINING_HEADER: &str = "x-ratelimit-remaining";
+/// How often buckets that refilled completely are dropped
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+#[derive(Debug)]
+struct Buckets {
+    by_client: HashMap<IpAddr, Bucket>,
+    last_sweep: Instant,
+}
+
+/// Token buckets refilling `per_minute` requests a minute, each holding up to
+/// `burst` requests
+#[derive(Debug)]
+pub struct RateLimiter {
+    per_minute: u32,
+    burst: u32,
+    /// Whether to take the client IP from `X-Real-IP` and `X-Forwarded-For`,
+    /// which clients can forge unless a proxy sets them
+    trust_proxy_headers: bool,
+    buckets: Mutex<Buckets>,
+}
+
+impl RateLimiter {
+    /// Limits each client to `per_minute` requests a minute, of which `burst` can
+    /// be made at once
+    pub fn new(per_minute: u32, burst: u32) -> Self {
+        Self {
+            per_minute: per_minute.max(1),
+            burst: burst.max(1),
+            trust_proxy_headers: false,
+            buckets: Mutex::new(Buckets {
+                by_client: HashMap::new(),
+                last_sweep: Instant::now(),
+            }),
+        }
+    }
+
+    /// Identifies clients by the proxy headers rather than the peer address, for
+    /// deployments behind a reverse proxy
+    pub fn with_trusted_proxy_headers(mut self, trust_proxy_headers: bool) -> Self {
+        self.trust_proxy_headers = trust_proxy_headers;
+        self
+    }
+
+    /// Tokens gained per elapsed second
+    fn refill_rate(&self) -> f64 {
+        f64::from(self.per_minute) / 60.0
+    }
+
+    /// Takes a token from the client's bucket, returning the tokens left, or how
+    /// long until the next one when the bucket is empty
+    fn acquire(&self, client: IpAddr, now: Instant) -> Result<u32, Duration> {
+        let burst = f64::from(self.burst);
+        let mut buckets = self.buckets.lock().unwrap();
+        if now.duration_since(buckets.last_sweep) >= SWEEP_INTERVAL {
+            // A full bucket is the same as a missing one
+            let refill_rate = self.refill_rate();
+            buckets.by_client.retain(|_, bucket| {
+                bucket.tokens + now.duration_since(bucket.updated).as_secs_f64() * refill_rate
+                    < burst
+            });
+            buckets.last_sweep = now;
+        }
+
+        let bucket = buckets.by_client.entry(client).or_insert(Bucket {
+            tokens: burst,
+            updated: now,
+        });
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.refill_rate()).min(burst);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(bucket.tokens as u32)
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - bucket.tokens) / self.refill_rate(),
+            ))
+        }
+    }
+
+    /// Clients with a bucket that hasn't refilled yet
+    pub fn tracked_clients(&self) -> usize {
+        self.buckets.lock().unwrap().by_client.len()
+    }
+
+    /// Address the request is counted against
+    fn client_ip(&self, request: &Request) -> IpAddr {
+        let forwarded = self
+            .trust_proxy_headers
+            .then(|| forwarded_ip(request.headers()))
+            .flatten();
+        forwarded
+            .or_else(|| {
+                request
+                    .extensions()
+                    .get::<ConnectInfo<SocketAddr>>()
+                    .map(|ConnectInfo(address)| address.ip())
+            })
+            // Without connection info, e.g. when the router is served in-process,
+            // every request shares one bucket
+            .unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED))
+    }
+}
+
+/// Client address set by a reverse proxy: `X-Real-IP`, or the last address of
+/// `X-Forwarded-For`, which the proxy appended itself
+fn forwarded_ip(headers: &HeaderMap) -> Option<IpAddr> {
+    let header = |name| headers.get(name).and_then(|value| value.to_str().ok());
+    header("x-real-ip")
+        .and_then(|ip| ip.trim().parse().ok())
+        .or_else(|| {
+            header("x-forwarded-for")?
+                .rsplit(',')
+                .next()?
+                .trim()
+                .parse()
+                .ok()
+        })
+}
+
+/// Middleware rejecting clients over their limit with `429`, a `Retry-After` and
+/// `X-RateLimit-Remaining` of `0`. Does nothing without a limiter; health checks
+/// are never limited.
+pub async fn limit_rate(
+    State(limiter): State<Option<Arc<RateLimiter>>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(limiter) = limiter else {
+        return next.run(request).await;
+    };
+    let is_health_check = request.method() == Method::GET
+        && matches!(
+            request.uri().path(),
+            "/health" | "/health/live" | "/health/ready"
+        );
+    if is_health_check {
+        return next.run(request).await;
+    }
+
+    let client = limiter.client_ip(&request);
+    match limiter.acquire(client, Instant::now()) {
+        Ok(remaining) => {
+            let mut response = next.run(request).await;
+            response
+                .headers_mut()
+                .insert(RATE_LIMIT_REMAINING_HEADER, HeaderValue::from(remaining));
+            response
+        }
+        Err(retry_after) => {
+            let retry_after = retry_after.as_secs_f64().ceil().max(1.0) as u64;
+            tracing::debug!(%client, retry_after, "Rate limit exceeded");
+            let mut response = ApiError::TooManyRequests(format!(
+                "Rate limit of {} requests per minute exceeded",
+                limiter.per_minute
+            ))
+            .into_response();
+            let headers = response.headers_mut();
+            headers.insert("retry-after", HeaderValue::from(retry_after));
+            headers.insert(RATE_LIMIT_REMAINING_HEADER, HeaderValue::from(0));
+            response
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(last: u8) -> IpAddr {
+        IpAddr::V4(Ipv4Addr::new(192, 0, 2, last))
+    }
+
+    #[test]
+    fn test_acquire() {
+        let limiter = RateLimiter::new(60, 2);
+        let now = Instant::now();
+        assert_eq!(limiter.acquire(ip(1), now), Ok(1));
+        assert_eq!(limiter.acquire(ip(1), now), Ok(0));
+        assert_eq!(limiter.acquire(ip(1), now), Err(Duration::from_secs(1)));
+        // Other clients have their own bucket
+        assert_eq!(limiter.acquire(ip(2), now), Ok(1));
+        // One request a second
+        let later = now + Duration::from_secs(1);
+        assert_eq!(limiter.acquire(ip(1), later), Ok(0));
+    }
+
+    #[test]
+    fn test_full_buckets_are_evicted() {
+        let limiter = RateLimiter::new(60, 10);
+        let now = Instant::now();
+        for last in 0..=255 {
+            limiter.acquire(ip(last), now).unwrap();
+        }
+        assert_eq!(limiter.tracked_clients(), 256);
+
+        // By the next sweep every bucket refilled, so only the new client is kept
+        limiter.acquire(ip(1), now + SWEEP_INTERVAL).unwrap();
+        assert_eq!(limiter.tracked_clients(), 1);
+    }
+
+    #[test]
+    fn test_forwarded_ip() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(forwarded_ip(&headers), None);
+        headers.insert("x-forwarded-for", "203.0.113.7, 192.0.2.1".parse().unwrap());
+        assert_eq!(forwarded_ip(&headers), Some(ip(1)));
+        headers.insert("x-real-ip", "192.0.2.9".parse().unwrap());
+        assert_eq!(forwarded_ip(&headers), Some(ip(9)));
+    }
+}
@@ -0,0 +1,159 @@
+//! Recording of upstream JSON-RPC traffic, to turn real requests into test fixtures
+
+use jsonrpc_core::Call;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use web3::error::TransportError;
+
+/// Default file of `RECORD_UPSTREAM`
+pub const DEFAULT_RECORDING_PATH: &str = "upstream-recording.jsonl";
+
+/// One JSON-RPC request with the response it got, a line of a recording
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Exchange {
+    pub method: String,
+    pub params: Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    /// Error returned by the node
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<jsonrpc_core::Error>,
+    /// Failure to reach the node, after retries and failover
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transport_error: Option<String>,
+}
+
+impl Exchange {
+    /// Exchange of a method call, `None` for notifications
+    pub fn new(request: &Call, response: &web3::Result<Value>) -> Option<Self> {
+        let Call::MethodCall(call) = request else {
+            return None;
+        };
+        let mut exchange = Self {
+            method: call.method.clone(),
+            params: serde_json::to_value(&call.params).unwrap_or_default(),
+            result: None,
+            error: None,
+            transport_error: None,
+        };
+        match response {
+            Ok(result) => exchange.result = Some(result.clone()),
+            Err(web3::Error::Rpc(error)) => exchange.error = Some(error.clone()),
+            Err(error) => exchange.transport_error = Some(error.to_string()),
+        }
+        Some(exchange)
+    }
+
+    /// The recorded response, for replaying it
+    pub fn response(&self) -> web3::Result<Value> {
+        match (&self.error, &self.transport_error) {
+            (Some(error), _) => Err(web3::Error::Rpc(error.clone())),
+            (None, Some(error)) => Err(web3::Error::Transport(TransportError::Message(
+                error.clone(),
+            ))),
+            (None, None) => Ok(self.result.clone().unwrap_or(Value::Null)),
+        }
+    }
+
+    /// Reads a recording, one exchange per line
+    pub fn read_all(recording: &str) -> Result<Vec<Self>, String> {
+        recording
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .enumerate()
+            .map(|(index, line)| {
+                serde_json::from_str(line)
+                    .map_err(|e| format!("Invalid exchange on line {}: {e}", index + 1))
+            })
+            .collect()
+    }
+}
+
+/// Appends every exchange to a file as a JSON line. Meant for debugging: requests
+/// and responses are written as they are, including addresses and calldata.
+#[derive(Debug, Clone)]
+pub struct Recorder {
+    file: Arc<Mutex<File>>,
+}
+
+impl Recorder {
+    /// Records into `path`, appending to an existing recording
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let file = File::options().create(true).append(true).open(path)?;
+        Ok(Self {
+            file: Arc::new(Mutex::new(file)),
+        })
+    }
+
+    /// Writes the exchange. Failures are logged rather than failing the request.
+    pub fn record(&self, request: &Call, response: &web3::Result<Value>) {
+        let Some(exchange) = Exchange::new(request, response) else {
+            return;
+        };
+        let mut line = serde_json::to_string(&exchange).expect("exchanges serialize to JSON");
+        line.push('\n');
+        if let Err(error) = self.file.lock().unwrap().write_all(line.as_bytes()) {
+            tracing::warn!(%error, "Failed to record upstream exchange");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn call(method: &str, params: Vec<Value>) -> Call {
+        web3::helpers::build_request(1, method, params)
+    }
+
+    #[test]
+    fn test_exchange_round_trip() {
+        let request = call("eth_estimateGas", vec![json!({ "to": "0x01" })]);
+        let ok = Exchange::new(&request, &Ok(json!("0x5208"))).unwrap();
+        assert_eq!(ok.method, "eth_estimateGas");
+        assert_eq!(ok.params, json!([{ "to": "0x01" }]));
+        assert_eq!(ok.response().unwrap(), json!("0x5208"));
+
+        let reverted = jsonrpc_core::Error {
+            code: jsonrpc_core::ErrorCode::ServerError(3),
+            message: "execution reverted".to_string(),
+            data: Some(json!("0x")),
+        };
+        let failed = Exchange::new(&request, &Err(web3::Error::Rpc(reverted.clone()))).unwrap();
+        assert!(matches!(failed.response(), Err(web3::Error::Rpc(error)) if error == reverted));
+        let unreachable = Exchange::new(&request, &Err(web3::Error::Unreachable)).unwrap();
+        assert!(matches!(
+            unreachable.response(),
+            Err(web3::Error::Transport(_))
+        ));
+
+        let recording = [ok, failed, unreachable]
+            .map(|exchange| serde_json::to_string(&exchange).unwrap())
+            .join("\n");
+        assert_eq!(Exchange::read_all(&recording).unwrap().len(), 3);
+        assert!(Exchange::read_all("{").unwrap_err().contains("line 1"));
+    }
+
+    #[test]
+    fn test_records_json_lines() {
+        let path = std::env::temp_dir().join(format!(
+            "gas-estimator-recording-{}.jsonl",
+            std::process::id()
+        ));
+        let recorder = Recorder::create(&path).unwrap();
+        recorder.record(&call("eth_blockNumber", vec![]), &Ok(json!("0x10")));
+        recorder.record(&call("eth_gasPrice", vec![]), &Ok(json!("0x1")));
+
+        let recording = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let exchanges = Exchange::read_all(&recording).unwrap();
+        assert_eq!(exchanges.len(), 2);
+        assert_eq!(exchanges[0].method, "eth_blockNumber");
+        assert_eq!(exchanges[1].result, Some(json!("0x1")));
+    }
+}
@@ -0,0 +1,263 @@
+//! Wire format of estimation requests, as sent by wallets and dapps
+
+use crate::eip7702::SignedAuthorization;
+use crate::estimator::EstimateGasRequest;
+use crate::state_override::StateOverride;
+use serde::de::{self, Deserializer, Unexpected, Visitor};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use utoipa::ToSchema;
+use web3::types::{AccessList, Address, Bytes, CallRequest, H256, U64, U256};
+
+/// A quantity given as a hex string, a decimal string or a JSON number
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Quantity(pub U256);
+
+impl<'de> Deserialize<'de> for Quantity {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(QuantityVisitor)
+    }
+}
+
+impl Quantity {
+    /// Narrows the quantity to `u64`, naming `field` when it doesn't fit
+    fn to_u64(self, field: &str) -> Result<u64, String> {
+        if self.0 > U256::from(u64::MAX) {
+            return Err(format!("{field} {} is out of range", self.0));
+        }
+        Ok(self.0.as_u64())
+    }
+}
+
+struct QuantityVisitor;
+
+impl Visitor<'_> for QuantityVisitor {
+    type Value = Quantity;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a hex string, a decimal string or a non-negative integer")
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<Quantity, E> {
+        Ok(Quantity(value.into()))
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<Quantity, E> {
+        u64::try_from(value)
+            .map(|value| Quantity(value.into()))
+            .map_err(|_| E::invalid_value(Unexpected::Signed(value), &self))
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Quantity, E> {
+        parse_quantity(value)
+            .map(Quantity)
+            .ok_or_else(|| E::invalid_value(Unexpected::Str(value), &self))
+    }
+}
+
+/// Parses a `0x`-prefixed hex or a decimal quantity
+fn parse_quantity(value: &str) -> Option<U256> {
+    match value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+    {
+        Some(hex) if !hex.is_empty() => U256::from_str_radix(hex, 16).ok(),
+        Some(_) => None,
+        None if !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit()) => {
+            U256::from_dec_str(value).ok()
+        }
+        None => None,
+    }
+}
+
+/// Estimation request body: a JSON-RPC transaction object plus estimation options.
+/// Accepts `input` as an alias of `data`, quantities in hex, decimal or as numbers,
+/// and ignores fields it doesn't use (e.g. `nonce` or `chainId`).
+#[derive(Debug, Clone, Default, Deserialize, ToSchema)]
+#[schema(example = json!({
+    "from": "0x28c6c06298d514db089934071355e5743bf21d60",
+    "to": "0x6b175474e89094c44da98b954eedeac495271d0f",
+    "data": "0xa9059cbb000000000000000000000000000000000000000000000000000000000000dead0000000000000000000000000000000000000000000000000de0b6b3a7640000"
+}))]
+pub struct EstimateRequest {
+    pub from: Option<Address>,
+    pub to: Option<Address>,
+    pub gas: Option<Quantity>,
+    #[serde(alias = "gasPrice")]
+    pub gas_price: Option<Quantity>,
+    pub value: Option<Quantity>,
+    pub data: Option<Bytes>,
+    pub input: Option<Bytes>,
+    #[serde(rename = "type", alias = "transaction_type")]
+    pub transaction_type: Option<Quantity>,
+    #[serde(alias = "accessList")]
+    pub access_list: Option<AccessList>,
+    #[serde(alias = "maxFeePerGas")]
+    pub max_fee_per_gas: Option<Quantity>,
+    #[serde(alias = "maxPriorityFeePerGas")]
+    pub max_priority_fee_per_gas: Option<Quantity>,
+    #[serde(alias = "blockNumber")]
+    pub block_number: Option<Quantity>,
+    pub block: Option<String>,
+    #[serde(default, alias = "blobVersionedHashes")]
+    pub blob_versioned_hashes: Vec<H256>,
+    #[serde(alias = "blobCount")]
+    pub blob_count: Option<u64>,
+    #[serde(default, alias = "authorizationList")]
+    pub authorization_list: Vec<SignedAuthorization>,
+    #[serde(alias = "authorizationCount")]
+    pub authorization_count: Option<u64>,
+    #[serde(alias = "marginBps")]
+    pub margin_bps: Option<u16>,
+    #[serde(alias = "stateOverrides")]
+    pub state_overrides: Option<StateOverride>,
+    /// Chain to estimate on. Wallets' `chainId` is deliberately not an alias: it
+    /// describes the transaction rather than choosing where to estimate it.
+    pub chain_id: Option<Quantity>,
+    #[serde(default, alias = "includeCost")]
+    pub include_cost: bool,
+    #[serde(default, alias = "includeAccessList")]
+    pub include_access_list: bool,
+    #[serde(default, alias = "checkBalance")]
+    pub check_balance: bool,
+    #[serde(default)]
+    pub simulate: bool,
+    #[serde(default)]
+    pub confidence: bool,
+    #[serde(default, alias = "hasTransientStorage")]
+    pub has_transient_storage: bool,
+    #[serde(alias = "rpcUrl")]
+    pub rpc_url: Option<String>,
+}
+
+impl EstimateRequest {
+    /// Calldata from `data` or `input`, which must agree when both are given
+    fn calldata(&mut self) -> Result<Option<Bytes>, String> {
+        match (self.data.take(), self.input.take()) {
+            (Some(data), Some(input)) if data != input => {
+                Err("data and input are both set but differ".to_string())
+            }
+            (data, input) => Ok(input.or(data)),
+        }
+    }
+}
+
+impl TryFrom<EstimateRequest> for EstimateGasRequest {
+    type Error = String;
+
+    fn try_from(mut request: EstimateRequest) -> Result<Self, Self::Error> {
+        let data = request.calldata()?;
+        let transaction_type = request
+            .transaction_type
+            .map(|quantity| quantity.to_u64("type").map(U64::from))
+            .transpose()?;
+        let chain_id = request
+            .chain_id
+            .map(|quantity| quantity.to_u64("chain_id"))
+            .transpose()?;
+
+        Ok(EstimateGasRequest {
+            tx: CallRequest {
+                from: request.from,
+                to: request.to,
+                gas: request.gas.map(|q| q.0),
+                gas_price: request.gas_price.map(|q| q.0),
+                value: request.value.map(|q| q.0),
+                data,
+                transaction_type,
+                access_list: request.access_list,
+                max_fee_per_gas: request.max_fee_per_gas.map(|q| q.0),
+                max_priority_fee_per_gas: request.max_priority_fee_per_gas.map(|q| q.0),
+            },
+            block_number: request.block_number.map(|q| q.0),
+            block: request.block,
+            blob_versioned_hashes: request.blob_versioned_hashes,
+            blob_count: request.blob_count,
+            authorization_list: request.authorization_list,
+            authorization_count: request.authorization_count,
+            buffer_percent: None,
+            margin_bps: request.margin_bps,
+            state_overrides: request.state_overrides,
+            chain_id,
+            include_cost: request.include_cost,
+            include_access_list: request.include_access_list,
+            check_balance: request.check_balance,
+            simulate: request.simulate,
+            rpc_timeout: None,
+            no_heuristic: false,
+            confidence: request.confidence,
+            has_transient_storage: request.has_transient_storage,
+            rpc_url: request.rpc_url,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn parse(body: serde_json::Value) -> Result<EstimateGasRequest, String> {
+        serde_json::from_value(body).map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn test_parse_quantity() {
+        assert_eq!(parse_quantity("0x5208"), Some(21000.into()));
+        assert_eq!(parse_quantity("21000"), Some(21000.into()));
+        assert_eq!(parse_quantity("0x"), None);
+        assert_eq!(parse_quantity("-1"), None);
+        assert_eq!(parse_quantity("1e18"), None);
+    }
+
+    #[test]
+    fn test_metamask_style() {
+        let request = parse(json!({
+            "from": "0x0000000000000000000000000000000000000001",
+            "to": "0x6b175474e89094c44da98b954eedeac495271d0f",
+            "value": "0x0",
+            "data": "0xa9059cbb",
+            "gas": "0x5208",
+            "maxFeePerGas": "0x4e3b29200",
+            "maxPriorityFeePerGas": "0x3b9aca00",
+            "chainId": "0x1"
+        }))
+        .unwrap();
+        assert_eq!(request.tx.data, Some(vec![0xa9, 0x05, 0x9c, 0xbb].into()));
+        assert_eq!(request.tx.max_fee_per_gas, Some(21_000_000_000u64.into()));
+    }
+
+    #[test]
+    fn test_ethers_style() {
+        let request = parse(json!({
+            "from": "0x0000000000000000000000000000000000000001",
+            "to": "0x6b175474e89094c44da98b954eedeac495271d0f",
+            "value": "1000000000000000000",
+            "input": "0xa9059cbb",
+            "type": 2,
+            "nonce": 7,
+            "gasPrice": 20000000000u64
+        }))
+        .unwrap();
+        assert_eq!(request.tx.value, Some(1_000_000_000_000_000_000u64.into()));
+        assert_eq!(request.tx.data, Some(vec![0xa9, 0x05, 0x9c, 0xbb].into()));
+        assert_eq!(request.tx.transaction_type, Some(2.into()));
+        assert_eq!(request.tx.gas_price, Some(20_000_000_000u64.into()));
+    }
+
+    #[test]
+    fn test_data_and_input() {
+        let same = parse(json!({ "data": "0x01", "input": "0x01" })).unwrap();
+        assert_eq!(same.tx.data, Some(vec![0x01].into()));
+
+        let error = parse(json!({ "data": "0x01", "input": "0x02" })).unwrap_err();
+        assert!(error.contains("data and input"));
+    }
+
+    #[test]
+    fn test_invalid_quantity() {
+        let error = parse(json!({ "value": "lots" })).unwrap_err();
+        assert!(error.contains("a hex string, a decimal string"), "{error}");
+        assert!(parse(json!({ "value": -1 })).is_err());
+    }
+}
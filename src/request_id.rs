@@ -0,0 +1,79 @@
+//! Correlation ids shared between clients and the server's logs
+
+use axum::{
+    extract::Request,
+    http::{HeaderMap, HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use std::fmt;
+use uuid::Uuid;
+
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Id of one request, attached to its log lines and echoed in `x-request-id`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RequestId(pub Uuid);
+
+impl RequestId {
+    pub fn new() -> Self {
+        Self(Uuid::new_v4())
+    }
+
+    /// The UUID the client sent in `x-request-id`, or a new one when the header is
+    /// missing or isn't a UUID, so logs are never keyed by arbitrary client input
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        headers
+            .get(REQUEST_ID_HEADER)
+            .and_then(|id| id.to_str().ok())
+            .and_then(|id| Uuid::parse_str(id).ok())
+            .map_or_else(Self::new, Self)
+    }
+}
+
+impl Default for RequestId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// Replaces the request's `x-request-id` with its [`RequestId`], which handlers
+/// can also take from the request extensions
+pub async fn set_request_id(mut request: Request, next: Next) -> Response {
+    let id = RequestId::from_headers(request.headers());
+    let value = HeaderValue::from_str(&id.to_string()).expect("UUIDs are valid header values");
+    request.headers_mut().insert(REQUEST_ID_HEADER, value);
+    request.extensions_mut().insert(id);
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(id: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(REQUEST_ID_HEADER, HeaderValue::from_str(id).unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_client_uuid_is_kept() {
+        let id = RequestId::from_headers(&headers("5F0C6F3E-8D2A-4D4B-9A55-3B1F2F6F7E01"));
+        assert_eq!(id.to_string(), "5f0c6f3e-8d2a-4d4b-9a55-3b1f2f6f7e01");
+    }
+
+    #[test]
+    fn test_malformed_or_missing_ids_are_replaced() {
+        for headers in [headers("client-supplied-id"), headers(""), HeaderMap::new()] {
+            let id = RequestId::from_headers(&headers);
+            assert_eq!(id.0.get_version_num(), 4, "{headers:?}");
+        }
+    }
+}
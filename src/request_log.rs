@@ -0,0 +1,39 @@
+//! Debug logging of request bodies, for seeing the raw transactions a local
+//! instance is asked to estimate
+
+use axum::{
+    body::{self, Body},
+    extract::{Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+/// Middleware logging each non-empty request body at debug level, within the
+/// request's span, when `enabled`. The body is buffered to be logged and handed
+/// on to the handler whole.
+pub async fn log_request_body(
+    State(enabled): State<bool>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if !enabled {
+        return next.run(request).await;
+    }
+    let (parts, body) = request.into_parts();
+    // The body limit layer outside already caps the body, so reading it only
+    // fails for bodies over the limit
+    let Ok(bytes) = body::to_bytes(body, usize::MAX).await else {
+        return StatusCode::PAYLOAD_TOO_LARGE.into_response();
+    };
+    if !bytes.is_empty() {
+        tracing::debug!(
+            method = %parts.method,
+            path = parts.uri.path(),
+            body = %String::from_utf8_lossy(&bytes),
+            "Request body"
+        );
+    }
+    next.run(Request::from_parts(parts, Body::from(bytes)))
+        .await
+}
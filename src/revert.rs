@@ -0,0 +1,151 @@
+//! Decoding of revert data returned by the node when a transaction would revert
+
+use crate::estimator::ApiError;
+use crate::upstream_error::classify_rpc_error;
+use web3::ethabi::{self, ParamType, Token};
+
+/// Selector of `Error(string)`
+pub(crate) const ERROR_SELECTOR: [u8; 4] = [0x08, 0xc3, 0x79, 0xa0];
+/// Selector of `Panic(uint256)`
+const PANIC_SELECTOR: [u8; 4] = [0x4e, 0x48, 0x7b, 0x71];
+
+/// Converts a node error into [`ApiError::ExecutionReverted`] if it reports a revert,
+/// or `None` for any other failure
+pub fn execution_reverted(error: &web3::Error) -> Option<ApiError> {
+    let web3::Error::Rpc(error) = error else {
+        return None;
+    };
+    // Geth and most clients use code 3 for reverts with data; some only say so in the message
+    if error.code.code() != 3 && !error.message.contains("revert") {
+        return None;
+    }
+
+    let data = error
+        .data
+        .as_ref()
+        .and_then(|data| data.as_str())
+        .and_then(|data| hex_decode(data.strip_prefix("0x").unwrap_or(data)));
+    let reason = data.as_deref().and_then(decode_revert);
+
+    Some(ApiError::ExecutionReverted {
+        reason,
+        data: data.map(|data| format!("0x{}", hex_encode(&data))),
+    })
+}
+
+/// Maps a failed `eth_estimateGas` to [`ApiError::ExecutionReverted`] when the
+/// transaction would revert, or to whatever else the node reported through
+/// [`classify_rpc_error`]
+pub fn estimate_error(error: web3::Error) -> ApiError {
+    classify_rpc_error(error)
+}
+
+/// Decodes `Error(string)` and `Panic(uint256)` revert data into a readable reason.
+/// Custom errors can't be decoded without the contract ABI and return `None`.
+pub fn decode_revert(data: &[u8]) -> Option<String> {
+    let (selector, payload) = data.split_first_chunk::<4>()?;
+    match *selector {
+        ERROR_SELECTOR => match ethabi::decode(&[ParamType::String], payload).ok()?.pop()? {
+            Token::String(reason) => Some(reason),
+            _ => None,
+        },
+        PANIC_SELECTOR => match ethabi::decode(&[ParamType::Uint(256)], payload)
+            .ok()?
+            .pop()?
+        {
+            Token::Uint(code) if code <= u8::MAX.into() => Some(panic_reason(code.low_u32() as u8)),
+            Token::Uint(code) => Some(format!("panic: unknown code {code:#x}")),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Describes a Solidity panic code
+fn panic_reason(code: u8) -> String {
+    let description = match code {
+        0x00 => "generic compiler panic",
+        0x01 => "assertion failed",
+        0x11 => "arithmetic overflow or underflow",
+        0x12 => "division or modulo by zero",
+        0x21 => "invalid enum value",
+        0x22 => "invalid storage byte array encoding",
+        0x31 => "pop on empty array",
+        0x32 => "array index out of bounds",
+        0x41 => "out of memory",
+        0x51 => "call to uninitialized function",
+        _ => return format!("panic: unknown code {code:#04x}"),
+    };
+    format!("panic: {description} ({code:#04x})")
+}
+
+fn hex_decode(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn hex_encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(selector: [u8; 4], token: Token) -> Vec<u8> {
+        let mut data = selector.to_vec();
+        data.extend(ethabi::encode(&[token]));
+        data
+    }
+
+    #[test]
+    fn test_decode_error_string() {
+        let data = encode(
+            ERROR_SELECTOR,
+            Token::String("ERC20: transfer amount exceeds balance".to_string()),
+        );
+        assert_eq!(
+            decode_revert(&data).as_deref(),
+            Some("ERC20: transfer amount exceeds balance")
+        );
+    }
+
+    #[test]
+    fn test_decode_panic() {
+        let data = encode(PANIC_SELECTOR, Token::Uint(0x11.into()));
+        assert_eq!(
+            decode_revert(&data).as_deref(),
+            Some("panic: arithmetic overflow or underflow (0x11)")
+        );
+    }
+
+    #[test]
+    fn test_estimate_error() {
+        let data = encode(ERROR_SELECTOR, Token::String("Ownable: caller".to_string()));
+        let reverted = estimate_error(web3::Error::Rpc(jsonrpc_core::Error {
+            code: jsonrpc_core::ErrorCode::ServerError(3),
+            message: "execution reverted: Ownable: caller".to_string(),
+            data: Some(format!("0x{}", hex_encode(&data)).into()),
+        }));
+        assert!(matches!(
+            reverted,
+            ApiError::ExecutionReverted { reason: Some(reason), .. } if reason == "Ownable: caller"
+        ));
+
+        assert!(matches!(
+            estimate_error(web3::Error::Unreachable),
+            ApiError::UpstreamUnavailable(_)
+        ));
+    }
+
+    #[test]
+    fn test_custom_error_is_not_decoded() {
+        assert_eq!(decode_revert(&[0xde, 0xad, 0xbe, 0xef, 0x01]), None);
+        assert_eq!(decode_revert(&[0x08]), None);
+    }
+}
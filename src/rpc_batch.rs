@@ -0,0 +1,114 @@
+//! Node estimates of a batch's items fetched with one JSON-RPC batch request
+//! rather than one request each
+
+use crate::circuit_breaker::CircuitState;
+use crate::eip7702;
+use crate::estimator::{
+    ApiError, EstimateGasRequest, EstimateReason, GasEstimator, rpc_timeout_error,
+};
+use crate::revert;
+use crate::transport::BatchSender;
+use std::time::Duration;
+use tokio::time::Instant;
+use web3::{
+    Transport, helpers,
+    types::{BlockNumber, U256},
+};
+
+/// Node estimate of one item of a batch
+pub(crate) struct NodeEstimate {
+    pub(crate) result: Result<U256, ApiError>,
+    /// Round trip of the whole batch
+    pub(crate) latency: Duration,
+}
+
+impl<T: Transport> GasEstimator<T> {
+    /// Fetches the node estimates of the items that need a plain `eth_estimateGas`
+    /// as one batch. Other items, and every item when fewer than two need one or
+    /// the batch fails as a whole, get `None` and ask the node on their own.
+    pub(crate) async fn prefetch_node_estimates(
+        &self,
+        batch_sender: &BatchSender,
+        requests: &[EstimateGasRequest],
+        reasons: &[Result<EstimateReason, ApiError>],
+    ) -> Vec<Option<NodeEstimate>> {
+        let mut node_estimates: Vec<_> = requests.iter().map(|_| None).collect();
+        let batched: Vec<_> = requests
+            .iter()
+            .zip(reasons)
+            .enumerate()
+            .filter_map(|(index, (request, reason))| {
+                let reason = *reason.as_ref().ok()?;
+                let block = request.requested_block().ok()?;
+                self.is_batchable(request, reason, block).then(|| {
+                    let mut params = vec![helpers::serialize(&request.tx)];
+                    params.extend(block.map(|block| helpers::serialize(&block)));
+                    (index, self.transport().prepare("eth_estimateGas", params))
+                })
+            })
+            .collect();
+        // While the circuit isn't closed, items are admitted one by one
+        let closed = self
+            .circuit_breaker
+            .as_ref()
+            .is_none_or(|breaker| matches!(breaker.state(), CircuitState::Closed { .. }));
+        if batched.len() < 2 || !closed {
+            return node_estimates;
+        }
+
+        // The handler gives every item the same timeout
+        let rpc_timeout = requests
+            .iter()
+            .filter_map(|request| request.rpc_timeout)
+            .min();
+        let (indices, calls): (Vec<_>, Vec<_>) = batched.into_iter().unzip();
+        let batch = batch_sender(calls);
+        let started = Instant::now();
+        let results = match rpc_timeout {
+            Some(timeout) => match tokio::time::timeout(timeout, batch).await {
+                Ok(results) => results,
+                Err(_) => {
+                    for index in indices {
+                        node_estimates[index] = Some(NodeEstimate {
+                            result: Err(rpc_timeout_error(timeout)),
+                            latency: started.elapsed(),
+                        });
+                    }
+                    return node_estimates;
+                }
+            },
+            None => batch.await,
+        };
+        let results = match results {
+            Ok(results) => results,
+            Err(error) => {
+                tracing::warn!(%error, "Batched estimates failed, estimating items one by one");
+                return node_estimates;
+            }
+        };
+        let latency = started.elapsed();
+        for (index, result) in indices.into_iter().zip(results) {
+            let result = result.map_err(revert::estimate_error).and_then(|gas| {
+                serde_json::from_value(gas)
+                    .map_err(|e| ApiError::InternalServerError(format!("RPC call failed: {e}")))
+            });
+            node_estimates[index] = Some(NodeEstimate { result, latency });
+        }
+        node_estimates
+    }
+
+    /// Whether the node estimate is a plain `eth_estimateGas` that a learned
+    /// estimate won't answer instead
+    fn is_batchable(
+        &self,
+        request: &EstimateGasRequest,
+        reason: EstimateReason,
+        block: Option<BlockNumber>,
+    ) -> bool {
+        reason.requires_rpc()
+            && request.state_overrides.is_none()
+            && !eip7702::is_set_code_transaction(&request.tx)
+            && !request.confidence
+            && (request.no_heuristic || self.heuristic_call(request, block).is_none())
+    }
+}
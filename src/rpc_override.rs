@@ -0,0 +1,122 @@
+//! Single estimates against a node chosen by the request, e.g. a private fork,
+//! instead of the configured endpoints
+
+use crate::estimator::{ApiError, EstimateGasRequest, GasEstimator};
+use reqwest::Url;
+use std::collections::HashSet;
+use std::time::Duration;
+use web3::transports::Http;
+
+pub const UPSTREAM_RPC_HEADER: &str = "x-upstream-rpc";
+pub const DEFAULT_RPC_OVERRIDE_TIMEOUT_SECS: u64 = 10;
+
+/// Which nodes requests may estimate against, and for how long
+#[derive(Debug, Clone)]
+pub struct RpcOverride {
+    /// Host names accepted in override URLs, lowercase
+    allowed_hosts: HashSet<String>,
+    /// Longest the override node may take, whatever `X-Timeout-Ms` asks for
+    timeout: Duration,
+    client: reqwest::Client,
+}
+
+impl RpcOverride {
+    pub fn new(allowed_hosts: impl IntoIterator<Item = String>, timeout: Duration) -> Self {
+        Self {
+            allowed_hosts: allowed_hosts
+                .into_iter()
+                .map(|host| host.trim().to_ascii_lowercase())
+                .filter(|host| !host.is_empty())
+                .collect(),
+            timeout,
+            client: reqwest::Client::builder()
+                .timeout(timeout)
+                // A redirect could lead anywhere, past the allowlist
+                .redirect(reqwest::redirect::Policy::none())
+                .build()
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Parses an override URL, accepting only `http` and `https` URLs to an
+    /// allowed host
+    pub fn parse_url(&self, url: &str) -> Result<Url, ApiError> {
+        let parsed = Url::parse(url)
+            .map_err(|e| ApiError::BadRequest(format!("rpc_url is not a valid URL: {e}")))?;
+        if !matches!(parsed.scheme(), "http" | "https") {
+            return Err(ApiError::BadRequest(format!(
+                "rpc_url scheme {} is not http or https",
+                parsed.scheme()
+            )));
+        }
+        match parsed.host_str() {
+            Some(host) if self.allowed_hosts.contains(&host.to_ascii_lowercase()) => Ok(parsed),
+            host => Err(ApiError::BadRequest(format!(
+                "rpc_url host {} is not allowed",
+                host.unwrap_or_default()
+            ))),
+        }
+    }
+
+    /// Estimator of the node at `url`, with default settings
+    pub fn estimator(&self, url: Url) -> GasEstimator<Http> {
+        GasEstimator::new(Http::with_client(self.client.clone(), url))
+    }
+
+    /// `requested` capped at the override timeout
+    pub fn rpc_timeout(&self, requested: Option<Duration>) -> Duration {
+        requested.map_or(self.timeout, |requested| requested.min(self.timeout))
+    }
+}
+
+/// Rejects requests naming an override node where it isn't supported
+pub(crate) fn reject_rpc_url(request: &EstimateGasRequest) -> Result<(), ApiError> {
+    match request.rpc_url {
+        Some(_) => Err(ApiError::BadRequest(
+            "rpc_url is only accepted by single estimates".to_string(),
+        )),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_url() {
+        let rpc_override = RpcOverride::new(
+            ["Fork.internal".to_string(), "localhost".to_string()],
+            Duration::from_secs(5),
+        );
+        assert!(rpc_override.parse_url("http://fork.internal:8545").is_ok());
+        assert!(rpc_override.parse_url("https://localhost/rpc").is_ok());
+
+        for url in [
+            "http://169.254.169.254/latest",
+            "ws://fork.internal:8545",
+            "file:///etc/passwd",
+            "fork.internal:8545",
+            "http://fork.internal.evil.com",
+        ] {
+            assert!(
+                matches!(rpc_override.parse_url(url), Err(ApiError::BadRequest(_))),
+                "{url}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_rpc_timeout_is_capped() {
+        let rpc_override = RpcOverride::new([], Duration::from_secs(5));
+        assert_eq!(rpc_override.rpc_timeout(None), Duration::from_secs(5));
+        assert_eq!(
+            rpc_override.rpc_timeout(Some(Duration::from_secs(1))),
+            Duration::from_secs(1)
+        );
+        assert_eq!(
+            rpc_override.rpc_timeout(Some(Duration::from_secs(60))),
+            Duration::from_secs(5)
+        );
+    }
+}
@@ -0,0 +1,63 @@
+//! Per-request RPC timeouts, chosen by clients with the `X-Timeout-Ms` header
+
+use crate::estimator::ApiError;
+use axum::{
+    extract::Request,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::time::Duration;
+
+pub const TIMEOUT_HEADER: &str = "x-timeout-ms";
+/// Shortest timeout a client may ask for; anything less can't reach a node
+pub const MIN_TIMEOUT_MS: u64 = 100;
+
+/// Time the node may take to estimate the request, from `X-Timeout-Ms`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RequestTimeout(pub Duration);
+
+/// Middleware storing a valid `X-Timeout-Ms` header as a [`RequestTimeout`]
+/// extension. Values that aren't a number of milliseconds of at least
+/// [`MIN_TIMEOUT_MS`] are rejected with `400`.
+pub async fn read_timeout_header(mut request: Request, next: Next) -> Response {
+    if let Some(value) = request.headers().get(TIMEOUT_HEADER) {
+        match parse_timeout(value.to_str().unwrap_or_default()) {
+            Ok(timeout) => {
+                request.extensions_mut().insert(timeout);
+            }
+            Err(error) => return error.into_response(),
+        }
+    }
+    next.run(request).await
+}
+
+fn parse_timeout(value: &str) -> Result<RequestTimeout, ApiError> {
+    let millis: u64 = value.trim().parse().map_err(|_| {
+        ApiError::BadRequest(format!(
+            "X-Timeout-Ms of {value:?} must be a number of milliseconds"
+        ))
+    })?;
+    if millis < MIN_TIMEOUT_MS {
+        return Err(ApiError::BadRequest(format!(
+            "X-Timeout-Ms of {millis} is below the minimum of {MIN_TIMEOUT_MS} ms"
+        )));
+    }
+    Ok(RequestTimeout(Duration::from_millis(millis)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_timeout() {
+        assert_eq!(
+            parse_timeout("250").unwrap(),
+            RequestTimeout(Duration::from_millis(250))
+        );
+        assert!(parse_timeout("100").is_ok());
+        assert!(parse_timeout("99").is_err());
+        assert!(parse_timeout("1s").is_err());
+        assert!(parse_timeout("").is_err());
+    }
+}
@@ -0,0 +1,240 @@
+//! Gas estimation for Gnosis Safe transactions guarded by an `ITransactionGuard`
+
+use crate::abi::encode_call;
+use crate::estimator::{ApiError, GasEstimator};
+use crate::revert;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use web3::{
+    Transport,
+    ethabi::{ParamType, Token},
+    signing::keccak256,
+    types::{Address, Bytes, CallRequest, U256},
+};
+
+/// Fixed cost of `execTransaction` itself (tx hash computation, nonce update, events)
+const SAFE_EXEC_BASE_GAS: u64 = 30000;
+/// Cost of verifying one owner signature (ecrecover, owner lookup, signature calldata)
+const SAFE_SIGNATURE_GAS: u64 = 8000;
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SafeWithGuardParams {
+    pub safe: Address,
+    pub to: Address,
+    pub data: Bytes,
+    pub value: U256,
+    /// Number of owner signatures that will be verified
+    pub n_owners: u8,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct SafeWithGuardEstimate {
+    pub safe_execution_gas: U256,
+    pub guard_check_gas: Option<U256>,
+    pub guard_address: Option<Address>,
+    pub total_gas: U256,
+}
+
+impl<T: Transport> GasEstimator<T> {
+    /// Estimates a Safe transaction including the overhead of its transaction guard hooks
+    pub async fn estimate_safe_with_guard(
+        &self,
+        params: SafeWithGuardParams,
+    ) -> Result<SafeWithGuardEstimate, ApiError> {
+        if params.n_owners == 0 {
+            return Err(ApiError::BadRequest(
+                "n_owners must be at least 1".to_string(),
+            ));
+        }
+
+        let guard_address = self.safe_guard(params.safe).await?;
+
+        // Inner call as executed by the Safe, plus Safe execution and signature overhead
+        let inner_gas = self
+            .eth
+            .estimate_gas(
+                CallRequest {
+                    from: Some(params.safe),
+                    to: Some(params.to),
+                    value: Some(params.value),
+                    data: Some(params.data.clone()),
+                    ..Default::default()
+                },
+                None,
+            )
+            .await
+            .map_err(revert::estimate_error)?;
+        let safe_execution_gas = inner_gas
+            .saturating_add(SAFE_EXEC_BASE_GAS.into())
+            .saturating_add((params.n_owners as u64 * SAFE_SIGNATURE_GAS).into());
+
+        let guard_check_gas = match guard_address {
+            Some(guard) => Some(self.estimate_guard_hooks(guard, &params).await?),
+            None => None,
+        };
+
+        Ok(SafeWithGuardEstimate {
+            safe_execution_gas,
+            guard_check_gas,
+            guard_address,
+            total_gas: safe_execution_gas.saturating_add(guard_check_gas.unwrap_or_default()),
+        })
+    }
+
+    /// Reads the guard address of a Safe. Safe has no public getter for the guard,
+    /// so it is read directly from its dedicated storage slot.
+    async fn safe_guard(&self, safe: Address) -> Result<Option<Address>, ApiError> {
+        let slot = U256::from_big_endian(&keccak256(b"guard_manager.guard.address"));
+        let value = self
+            .eth
+            .storage(safe, slot, None)
+            .await
+            .map_err(|e| ApiError::InternalServerError(format!("RPC call failed: {e}")))?;
+
+        let guard = Address::from_slice(&value.as_bytes()[12..]);
+        Ok((!guard.is_zero()).then_some(guard))
+    }
+
+    /// Simulates `checkTransaction` and `checkAfterExecution` on the guard, called by the Safe
+    async fn estimate_guard_hooks(
+        &self,
+        guard: Address,
+        params: &SafeWithGuardParams,
+    ) -> Result<U256, ApiError> {
+        let check_transaction = encode_check_transaction(params);
+        let check_after_execution = encode_check_after_execution();
+
+        let mut total = U256::zero();
+        for data in [check_transaction, check_after_execution] {
+            let gas = self
+                .eth
+                .estimate_gas(
+                    CallRequest {
+                        from: Some(params.safe),
+                        to: Some(guard),
+                        data: Some(data.into()),
+                        ..Default::default()
+                    },
+                    None,
+                )
+                .await
+                .map_err(|e| {
+                    revert::execution_reverted(&e).unwrap_or_else(|| {
+                        ApiError::InternalServerError(format!("Guard simulation failed: {e}"))
+                    })
+                })?;
+            // Hooks are internal calls, so the intrinsic transaction cost isn't paid again
+            total = total.saturating_add(gas.saturating_sub(self.gas_constants.tx_base.into()));
+        }
+
+        Ok(total)
+    }
+}
+
+/// ABI-encodes `checkTransaction(address,uint256,bytes,uint8,uint256,uint256,uint256,address,address,bytes,address)`
+fn encode_check_transaction(params: &SafeWithGuardParams) -> Vec<u8> {
+    let param_types = [
+        ParamType::Address,
+        ParamType::Uint(256),
+        ParamType::Bytes,
+        ParamType::Uint(8),
+        ParamType::Uint(256),
+        ParamType::Uint(256),
+        ParamType::Uint(256),
+        ParamType::Address,
+        ParamType::Address,
+        ParamType::Bytes,
+        ParamType::Address,
+    ];
+    let tokens = [
+        Token::Address(params.to),
+        Token::Uint(params.value),
+        Token::Bytes(params.data.0.clone()),
+        Token::Uint(U256::zero()), // operation: CALL
+        Token::Uint(U256::zero()), // safeTxGas
+        Token::Uint(U256::zero()), // baseGas
+        Token::Uint(U256::zero()), // gasPrice
+        Token::Address(Address::zero()),
+        Token::Address(Address::zero()),
+        // One 65-byte signature per owner
+        Token::Bytes(vec![0u8; params.n_owners as usize * 65]),
+        Token::Address(params.safe),
+    ];
+
+    encode_call("checkTransaction", &param_types, &tokens)
+}
+
+/// ABI-encodes `checkAfterExecution(bytes32,bool)`
+fn encode_check_after_execution() -> Vec<u8> {
+    let param_types = [ParamType::FixedBytes(32), ParamType::Bool];
+    let tokens = [Token::FixedBytes(vec![0u8; 32]), Token::Bool(true)];
+
+    encode_call("checkAfterExecution", &param_types, &tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use web3::{transports::test::TestTransport, types::H256};
+
+    fn params(n_owners: u8) -> SafeWithGuardParams {
+        SafeWithGuardParams {
+            safe: Address::repeat_byte(0x5a),
+            to: Address::repeat_byte(0x01),
+            data: Bytes::from(vec![0x01, 0x02]),
+            value: U256::zero(),
+            n_owners,
+        }
+    }
+
+    #[test]
+    fn test_selectors() {
+        assert_eq!(
+            encode_check_transaction(&params(1))[..4],
+            [0x75, 0xf0, 0xbb, 0x52]
+        );
+        assert_eq!(
+            encode_check_after_execution()[..4],
+            [0x93, 0x27, 0x13, 0x68]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_estimate_safe_without_guard() {
+        let mut transport = TestTransport::default();
+        transport.add_response(serde_json::to_value(H256::zero()).unwrap());
+        transport.add_response("0xc350".into()); // 50000
+        let estimator = GasEstimator::new(transport);
+
+        let estimate = estimator.estimate_safe_with_guard(params(2)).await.unwrap();
+        let expected = 50000 + SAFE_EXEC_BASE_GAS + 2 * SAFE_SIGNATURE_GAS;
+        assert_eq!(estimate.safe_execution_gas, expected.into());
+        assert_eq!(estimate.guard_address, None);
+        assert_eq!(estimate.guard_check_gas, None);
+        assert_eq!(estimate.total_gas, expected.into());
+    }
+
+    #[tokio::test]
+    async fn test_estimate_safe_with_guard() {
+        let guard = Address::repeat_byte(0x9a);
+        let mut transport = TestTransport::default();
+        transport.add_response(serde_json::to_value(H256::from(guard)).unwrap());
+        transport.add_response("0xc350".into()); // 50000
+        transport.add_response("0x6590".into()); // 26000 for checkTransaction
+        transport.add_response("0x5dc0".into()); // 24000 for checkAfterExecution
+        let estimator = GasEstimator::new(transport);
+
+        let estimate = estimator.estimate_safe_with_guard(params(1)).await.unwrap();
+        let safe_execution_gas = 50000 + SAFE_EXEC_BASE_GAS + SAFE_SIGNATURE_GAS;
+        assert_eq!(estimate.guard_address, Some(guard));
+        assert_eq!(estimate.guard_check_gas, Some(8000.into()));
+        assert_eq!(estimate.total_gas, (safe_execution_gas + 8000).into());
+    }
+
+    #[tokio::test]
+    async fn test_estimate_safe_requires_owners() {
+        let estimator = GasEstimator::new(TestTransport::default());
+        let result = estimator.estimate_safe_with_guard(params(0)).await;
+        assert!(matches!(result, Err(ApiError::BadRequest(_))));
+    }
+}
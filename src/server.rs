@@ -0,0 +1,1629 @@
+//! HTTP surface: application state, handlers and router construction
+
+use crate::access_list::CreateAccessListResponse;
+use crate::auth;
+use crate::base_fee::BaseFeeResponse;
+use crate::blur::{BlurPurchaseEstimate, BlurPurchaseParams};
+use crate::bundle::{BundleRequest, BundleTxEstimate, DEFAULT_MAX_BUNDLE_SIZE};
+#[cfg(feature = "cache")]
+use crate::cache::CachedGasEstimator;
+use crate::chains::{self, ChainRegistry, PathChainId};
+use crate::config::redact_urls;
+use crate::eigenlayer::{EigenLayerParams, EigenLayerRestakeEstimate};
+use crate::estimator::{
+    ApiError, ErrorResponse, EstimateGasRequest, FeeSuggestionResponse, GasEstimateResponse,
+    GasEstimator,
+};
+use crate::fee_history::{
+    DEFAULT_FEE_HISTORY_BLOCKS, DEFAULT_FEE_HISTORY_PERCENTILES, FeeHistoryResponse,
+    FeePriorityPercentiles,
+};
+use crate::fee_watcher::{FEE_STREAM_HEARTBEAT, FeeSnapshot, FeeWatcher};
+use crate::gas_price::DEFAULT_GAS_PRICE_POLL_INTERVAL_SECS;
+use crate::health::{DEFAULT_READINESS_CHECK_INTERVAL_SECS, Readiness};
+use crate::json_rpc::Payload;
+use crate::memory;
+use crate::metrics::Metrics;
+use crate::openapi::ApiDoc;
+use crate::provider::GasEstimateProvider;
+use crate::rate_limit::{self, RateLimiter};
+use crate::request::Quantity;
+use crate::request_id::{self, RequestId};
+use crate::request_log;
+use crate::rpc_override::{self, RpcOverride, UPSTREAM_RPC_HEADER};
+use crate::rpc_timeout::{self, RequestTimeout};
+use crate::safe::{SafeWithGuardEstimate, SafeWithGuardParams};
+use crate::shutdown::{self, Shutdown};
+use crate::transport::{BatchSender, ConnectionStatus};
+use crate::units::GasEstimateResponseV2;
+use crate::user_operation::{UserOperationGasResponse, UserOperationRequest};
+use crate::wallet_calls::{WalletSendCallsEstimate, WalletSendCallsRequest};
+use crate::warmup::WarmupState;
+use axum::{
+    Extension, Json, Router, async_trait,
+    body::{Body, Bytes},
+    extract::{
+        DefaultBodyLimit, FromRequest, Query, Request, State, WebSocketUpgrade,
+        rejection::QueryRejection,
+    },
+    http::{HeaderMap, StatusCode, header},
+    middleware,
+    response::{
+        IntoResponse, Redirect, Response,
+        sse::{KeepAlive, Sse},
+    },
+    routing::{get, post},
+};
+use futures::{Stream, StreamExt};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use std::collections::HashSet;
+use std::convert::Infallible;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tower_http::{
+    limit::RequestBodyLimitLayer,
+    request_id::PropagateRequestIdLayer,
+    timeout::TimeoutLayer,
+    trace::TraceLayer,
+};
+use tracing::Span;
+use utoipa::ToSchema;
+use utoipa::{IntoParams, OpenApi};
+use utoipa_swagger_ui::SwaggerUi;
+use web3::{Transport, types::Address};
+
+pub const DEFAULT_MAX_BATCH_SIZE: usize = 50;
+pub const DEFAULT_BATCH_CONCURRENCY: usize = 10;
+pub const DEFAULT_MAX_BODY_BYTES: usize = 1024 * 1024;
+pub const DEFAULT_REQUEST_TIMEOUT_SECS: u64 = 30;
+pub const DEFAULT_HEALTH_CHECK_TIMEOUT_SECS: u64 = 3;
+
+/// Error for a single item in a batch estimation request
+#[derive(Debug, Serialize, ToSchema)]
+pub struct BatchItemError {
+    pub index: usize,
+    /// The error as the single estimate endpoint reports it
+    #[serde(flatten)]
+    pub error: ErrorResponse,
+}
+
+/// Result of a single item in a batch estimation request
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(untagged)]
+pub enum BatchItemResult {
+    Ok(Box<GasEstimateResponse>),
+    Err(BatchItemError),
+}
+
+impl BatchItemResult {
+    pub fn new(index: usize, result: Result<GasEstimateResponse, ApiError>) -> Self {
+        match result {
+            Ok(response) => BatchItemResult::Ok(Box::new(response)),
+            Err(e) => BatchItemResult::Err(BatchItemError {
+                index,
+                error: ErrorResponse::from(e),
+            }),
+        }
+    }
+}
+
+/// JSON body extractor that reports malformed bodies as a `400` [`ApiError`] naming
+/// the offending field, instead of axum's plain-text rejection
+pub struct ApiJson<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequest<S> for ApiJson<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(request: Request, state: &S) -> Result<Self, Self::Rejection> {
+        Json::<T>::from_request(request, state)
+            .await
+            .map(|Json(value)| ApiJson(value))
+            .map_err(|rejection| match rejection.status() {
+                StatusCode::PAYLOAD_TOO_LARGE => ApiError::PayloadTooLarge(rejection.body_text()),
+                _ => ApiError::BadRequest(rejection.body_text()),
+            })
+    }
+}
+
+/// Shared application state
+pub struct AppState<T: Transport + Send + Sync + 'static> {
+    /// Estimator for requests that don't select a chain
+    pub estimator: Arc<GasEstimator<T>>,
+    /// Estimators selectable by chain id, through `/api/{chain_id}/...` or `chain_id`
+    pub chains: Arc<ChainRegistry<T>>,
+    pub max_batch_size: usize,
+    pub batch_concurrency: usize,
+    /// Most transactions `/api/estimate-bundle` accepts
+    pub max_bundle_size: usize,
+    pub metrics: Arc<Metrics>,
+    pub shutdown: Arc<Shutdown>,
+    /// How often `/ws/gas-price` clients receive an update
+    pub gas_price_poll_interval: Duration,
+    /// Keys accepted in the `X-Api-Key` header or as `Authorization: Bearer`
+    /// tokens; empty leaves the API open
+    pub api_keys: Arc<HashSet<String>>,
+    /// `GET` paths served without a key, [`auth::DEFAULT_OPEN_PATHS`] by default
+    pub open_paths: Arc<HashSet<String>>,
+    /// Limits requests per client IP, when set
+    pub rate_limiter: Option<Arc<RateLimiter>>,
+    /// Largest request body accepted, in bytes
+    pub max_body_bytes: usize,
+    /// How long a request may take before it is answered with `408`
+    pub request_timeout: Duration,
+    /// How long `/health` and `/health/ready` wait for the node's latest block
+    pub health_check_timeout: Duration,
+    /// How long `/health/ready` reuses its last check of the node
+    pub readiness_check_interval: Duration,
+    /// Reports the default chain's WebSocket connections in `/health`, when set
+    pub rpc_connections: Option<ConnectionStatus>,
+    /// Sends the calls `/rpc` forwards to the default chain's node as one batch,
+    /// when set; otherwise they are sent one by one
+    pub rpc_batch: Option<BatchSender>,
+    /// When set, answers single estimates for the default chain in place of
+    /// `estimator`, e.g. with a [`MockGasEstimator`](crate::provider) in tests
+    pub estimate_provider: Option<Arc<dyn GasEstimateProvider + Send + Sync>>,
+    /// Nodes single estimates may choose with `rpc_url` or `X-Upstream-RPC`, when
+    /// set; otherwise choosing one is rejected
+    pub rpc_override: Option<Arc<RpcOverride>>,
+    /// Logs request bodies at debug level, by default only with the
+    /// `debug_logging` feature
+    pub log_request_bodies: bool,
+    /// Fees of the default chain served by `/api/fees`, when the background
+    /// watcher runs
+    pub fee_watcher: Option<Arc<FeeWatcher>>,
+    /// Whether the default chain's node still has to be warmed up, after startup
+    /// went ahead without reaching it
+    pub warmup: Arc<WarmupState>,
+    /// When set, single estimates are answered through this cache
+    #[cfg(feature = "cache")]
+    pub cached_estimator: Option<Arc<CachedGasEstimator<T>>>,
+}
+
+impl<T: Transport + Send + Sync + 'static> AppState<T> {
+    /// Creates state with default limits
+    pub fn new(estimator: GasEstimator<T>) -> Self {
+        Self::with_chains(Arc::new(estimator), ChainRegistry::new())
+    }
+
+    /// Creates state with default limits serving several chains. `estimator` answers
+    /// requests that don't select a chain and may also be one of `chains`.
+    pub fn with_chains(estimator: Arc<GasEstimator<T>>, chains: ChainRegistry<T>) -> Self {
+        Self {
+            estimator,
+            chains: Arc::new(chains),
+            max_batch_size: DEFAULT_MAX_BATCH_SIZE,
+            batch_concurrency: DEFAULT_BATCH_CONCURRENCY,
+            max_bundle_size: DEFAULT_MAX_BUNDLE_SIZE,
+            metrics: Arc::new(Metrics::new()),
+            shutdown: Arc::new(Shutdown::new()),
+            gas_price_poll_interval: Duration::from_secs(DEFAULT_GAS_PRICE_POLL_INTERVAL_SECS),
+            api_keys: Arc::default(),
+            open_paths: Arc::new(auth::default_open_paths()),
+            rate_limiter: None,
+            max_body_bytes: DEFAULT_MAX_BODY_BYTES,
+            request_timeout: Duration::from_secs(DEFAULT_REQUEST_TIMEOUT_SECS),
+            health_check_timeout: Duration::from_secs(DEFAULT_HEALTH_CHECK_TIMEOUT_SECS),
+            readiness_check_interval: Duration::from_secs(DEFAULT_READINESS_CHECK_INTERVAL_SECS),
+            rpc_connections: None,
+            rpc_batch: None,
+            estimate_provider: None,
+            rpc_override: None,
+            log_request_bodies: cfg!(feature = "debug_logging"),
+            fee_watcher: None,
+            warmup: Arc::default(),
+            #[cfg(feature = "cache")]
+            cached_estimator: None,
+        }
+    }
+}
+
+impl<T: Transport + Send + Sync + 'static> AppState<T> {
+    /// Estimator for the given chain, or the default one when no chain is given
+    pub fn estimator_for(&self, chain_id: Option<u64>) -> Result<Arc<GasEstimator<T>>, ApiError> {
+        match chain_id {
+            None => Ok(self.estimator.clone()),
+            Some(chain_id) => self
+                .chains
+                .get(&chain_id)
+                .cloned()
+                .ok_or_else(|| chains::unknown_chain(chain_id, &self.chains)),
+        }
+    }
+}
+
+impl<T: Transport + Send + Sync + 'static> Clone for AppState<T> {
+    fn clone(&self) -> Self {
+        Self {
+            estimator: self.estimator.clone(),
+            chains: self.chains.clone(),
+            max_batch_size: self.max_batch_size,
+            batch_concurrency: self.batch_concurrency,
+            max_bundle_size: self.max_bundle_size,
+            metrics: self.metrics.clone(),
+            shutdown: self.shutdown.clone(),
+            gas_price_poll_interval: self.gas_price_poll_interval,
+            api_keys: self.api_keys.clone(),
+            open_paths: self.open_paths.clone(),
+            rate_limiter: self.rate_limiter.clone(),
+            max_body_bytes: self.max_body_bytes,
+            request_timeout: self.request_timeout,
+            health_check_timeout: self.health_check_timeout,
+            readiness_check_interval: self.readiness_check_interval,
+            rpc_connections: self.rpc_connections.clone(),
+            rpc_batch: self.rpc_batch.clone(),
+            estimate_provider: self.estimate_provider.clone(),
+            rpc_override: self.rpc_override.clone(),
+            log_request_bodies: self.log_request_bodies,
+            fee_watcher: self.fee_watcher.clone(),
+            warmup: self.warmup.clone(),
+            #[cfg(feature = "cache")]
+            cached_estimator: self.cached_estimator.clone(),
+        }
+    }
+}
+
+/// Builds the API router. Downstream users can mount it under their own axum app
+/// or add their own layers on top.
+pub fn build_router<T>(state: AppState<T>) -> Router
+where
+    T: Transport + Send + Sync + 'static,
+    T::Out: Send,
+{
+    Router::new()
+        .nest("/api", api_routes())
+        .nest("/api/:chain_id", api_routes())
+        // The watcher only follows the default chain, so these aren't served per chain
+        .route("/api/fees", get(fees_handler::<T>))
+        .route("/api/fees/stream", get(fees_stream_handler::<T>))
+        .route("/rpc", post(json_rpc_handler::<T>))
+        .route("/ws/gas-price", get(gas_price_ws_handler::<T>))
+        .route("/health", get(health_handler::<T>))
+        .route("/health/live", get(liveness_handler))
+        .route("/health/ready", get(readiness_handler::<T>))
+        .route("/metrics", get(metrics_handler::<T>))
+        .merge(SwaggerUi::new("/api/docs").url("/api/openapi.json", ApiDoc::openapi()))
+        .route("/docs", get(|| async { Redirect::permanent("/api/docs/") }))
+        // Inside the trace span and the body limit
+        .layer(middleware::from_fn_with_state(
+            state.log_request_bodies,
+            request_log::log_request_body,
+        ))
+        // Replaces axum's own 2 MB limit on extracted bodies
+        .layer(DefaultBodyLimit::disable())
+        .layer(RequestBodyLimitLayer::new(state.max_body_bytes))
+        .layer(TimeoutLayer::new(state.request_timeout))
+        .layer(middleware::map_response_with_state(
+            (state.max_body_bytes, state.request_timeout),
+            json_limit_errors,
+        ))
+        .layer(middleware::from_fn(rpc_timeout::read_timeout_header))
+        .layer(middleware::from_fn_with_state(
+            auth::AuthState {
+                api_keys: state.api_keys.clone(),
+                open_paths: state.open_paths.clone(),
+                metrics: state.metrics.clone(),
+            },
+            auth::require_api_key,
+        ))
+        // Outside authentication, so guessing keys counts against the limit
+        .layer(middleware::from_fn_with_state(
+            state.rate_limiter.clone(),
+            rate_limit::limit_rate,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.shutdown.clone(),
+            shutdown::track_in_flight,
+        ))
+        .layer(
+            TraceLayer::new_for_http()
+                .make_span_with(make_span)
+                .on_response(on_response),
+        )
+        // Outermost, so every log line of the request carries its id. UUIDs sent by
+        // clients are kept, anything else is replaced.
+        .layer(PropagateRequestIdLayer::x_request_id())
+        .layer(middleware::from_fn(request_id::set_request_id))
+        .with_state(state)
+}
+
+/// Request span carrying the request id and, once authenticated, the API key's
+/// [`auth::key_id`], disabled for Prometheus scrapes so they
+/// don't flood the logs
+fn make_span(request: &Request<Body>) -> Span {
+    if request.uri().path() == "/metrics" {
+        return Span::none();
+    }
+    let request_id = request
+        .extensions()
+        .get::<RequestId>()
+        .copied()
+        .unwrap_or_default();
+    tracing::info_span!(
+        "request",
+        method = %request.method(),
+        uri = %request.uri(),
+        request_id = %request_id,
+        api_key_id = tracing::field::Empty,
+    )
+}
+
+/// Logs each response at info level, except those of disabled spans
+fn on_response(response: &Response, latency: Duration, span: &Span) {
+    if !span.is_none() {
+        tracing::info!(status = %response.status(), ?latency, "finished processing request");
+    }
+}
+
+/// Replaces the responses of the body limit and timeout layers, and of bodies found
+/// too large while being read, with JSON [`ApiError`]s naming the limit
+async fn json_limit_errors(
+    State((max_body_bytes, request_timeout)): State<(usize, Duration)>,
+    response: Response,
+) -> Response {
+    match response.status() {
+        StatusCode::PAYLOAD_TOO_LARGE => ApiError::PayloadTooLarge(format!(
+            "Request body exceeds the limit of {max_body_bytes} bytes"
+        ))
+        .into_response(),
+        StatusCode::REQUEST_TIMEOUT => ApiError::RequestTimeout(format!(
+            "Request didn't complete within {} seconds",
+            request_timeout.as_secs_f64()
+        ))
+        .into_response(),
+        _ => response,
+    }
+}
+
+/// Routes served under `/api`, for the default chain, and under `/api/{chain_id}`
+fn api_routes<T>() -> Router<AppState<T>>
+where
+    T: Transport + Send + Sync + 'static,
+    T::Out: Send,
+{
+    Router::new()
+        .route(
+            "/estimate-gas",
+            post(estimate_gas_handler::<T>).get(estimate_transfer_handler::<T>),
+        )
+        .route("/estimate-gas/batch", post(estimate_gas_batch_handler::<T>))
+        .route("/estimate-bundle", post(estimate_bundle_handler::<T>))
+        .route("/v2/estimate-gas", post(estimate_gas_v2_handler::<T>))
+        .route(
+            "/estimate-gas/eigenlayer-restake",
+            post(estimate_eigenlayer_restake_handler::<T>),
+        )
+        .route("/create-access-list", post(create_access_list_handler::<T>))
+        .route("/fee-suggestion", get(fee_suggestion_handler::<T>))
+        .route("/base-fee/next", get(next_base_fee_handler::<T>))
+        .route("/fee-history", get(fee_history_handler::<T>))
+        .route(
+            "/gas-price/percentiles",
+            get(gas_price_percentiles_handler::<T>),
+        )
+        .route(
+            "/estimate-gas/safe-with-guard",
+            post(estimate_safe_with_guard_handler::<T>),
+        )
+        .route(
+            "/estimate-gas/blur-purchase",
+            post(estimate_blur_purchase_handler::<T>),
+        )
+        .route(
+            "/estimate-gas/wallet-send-calls",
+            post(estimate_wallet_send_calls_handler::<T>),
+        )
+        .route(
+            "/estimate-gas/userop",
+            post(estimate_user_operation_handler::<T>),
+        )
+}
+
+/// Query parameters for the estimate endpoint
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct EstimateGasQuery {
+    /// Safety buffer in percent applied to RPC estimates
+    buffer: Option<u64>,
+    /// Block to estimate against, overriding the body's `block`
+    block: Option<String>,
+    /// Safety margin in basis points, overriding the body's `margin_bps`
+    margin_bps: Option<u16>,
+    /// Whether to include the gas breakdown of static estimates
+    #[serde(default)]
+    detail: bool,
+    /// Whether to ask the node even when a learned estimate is available
+    #[serde(default)]
+    no_heuristic: bool,
+}
+
+/// Query parameters of a plain transfer estimate
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct TransferQuery {
+    from: Option<Address>,
+    to: Address,
+    /// Amount in wei, in hex or decimal
+    value: Option<Quantity>,
+    /// Whether to include the gas breakdown
+    #[serde(default)]
+    detail: bool,
+}
+
+/// Query parameters for the fee history endpoint
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct FeeHistoryQuery {
+    /// Number of latest blocks to look at
+    blocks: Option<u64>,
+    /// Comma-separated reward percentiles, in ascending order
+    percentiles: Option<String>,
+}
+
+/// Query parameters for the batch estimate endpoint
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct BatchQuery {
+    /// Whether to include the gas breakdown of static estimates
+    #[serde(default)]
+    detail: bool,
+}
+
+/// Handles HTTP requests for gas estimation
+/// POST: /api/estimate-gas
+#[utoipa::path(
+    post,
+    path = "/api/estimate-gas",
+    tag = "estimate",
+    params(
+        EstimateGasQuery,
+        ("X-Timeout-Ms" = Option<u64>, Header,
+            description = "Time the node may take to estimate, at least 100 ms"),
+        ("X-Upstream-RPC" = Option<String>, Header,
+            description = "Node to estimate against, when the server allows it; same as `rpc_url`"),
+    ),
+    request_body = EstimateRequest,
+    responses(
+        (status = 200, description = "Gas estimate", body = GasEstimateResponse,
+            headers(("Age" = u64, description = "Seconds since a cached estimate was computed"))),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+        (status = 408, description = "Request timed out", body = ErrorResponse),
+        (status = 413, description = "Request body too large", body = ErrorResponse),
+        (status = 422, description = "Execution reverted", body = ErrorResponse),
+        (status = 500, description = "RPC call failed", body = ErrorResponse),
+        (status = 502, description = "Node unreachable", body = ErrorResponse),
+        (status = 503, description = "Node rate limiting us, with Retry-After", body = ErrorResponse),
+        (status = 504, description = "Node didn't answer in time", body = ErrorResponse),
+    )
+)]
+async fn estimate_gas_handler<T>(
+    State(state): State<AppState<T>>,
+    PathChainId(path_chain_id): PathChainId,
+    Query(query): Query<EstimateGasQuery>,
+    timeout: Option<Extension<RequestTimeout>>,
+    headers: HeaderMap,
+    ApiJson(payload): ApiJson<EstimateGasRequest>,
+) -> Result<Response, ApiError>
+where
+    T: Transport + Send + Sync + 'static,
+    T::Out: Send,
+{
+    let estimate =
+        estimate_with_fees(&state, path_chain_id, query, timeout, &headers, payload).await?;
+    let age = estimate.cache_age_secs;
+    Ok(with_age(Json(estimate), age))
+}
+
+/// Handles HTTP requests for gas estimation, answering with the gas limit and its
+/// cost in decimal units
+/// POST: /api/v2/estimate-gas
+#[utoipa::path(
+    post,
+    path = "/api/v2/estimate-gas",
+    tag = "estimate",
+    params(
+        EstimateGasQuery,
+        ("X-Timeout-Ms" = Option<u64>, Header,
+            description = "Time the node may take to estimate, at least 100 ms"),
+        ("X-Upstream-RPC" = Option<String>, Header,
+            description = "Node to estimate against, when the server allows it; same as `rpc_url`"),
+    ),
+    request_body = EstimateRequest,
+    responses(
+        (status = 200, description = "Gas estimate in decimal units", body = GasEstimateResponseV2,
+            headers(("Age" = u64, description = "Seconds since a cached estimate was computed"))),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+        (status = 408, description = "Request timed out", body = ErrorResponse),
+        (status = 413, description = "Request body too large", body = ErrorResponse),
+        (status = 422, description = "Execution reverted", body = ErrorResponse),
+        (status = 500, description = "RPC call failed", body = ErrorResponse),
+        (status = 502, description = "Node unreachable", body = ErrorResponse),
+        (status = 503, description = "Node rate limiting us, with Retry-After", body = ErrorResponse),
+        (status = 504, description = "Node didn't answer in time", body = ErrorResponse),
+    )
+)]
+async fn estimate_gas_v2_handler<T>(
+    State(state): State<AppState<T>>,
+    PathChainId(path_chain_id): PathChainId,
+    Query(query): Query<EstimateGasQuery>,
+    timeout: Option<Extension<RequestTimeout>>,
+    headers: HeaderMap,
+    ApiJson(payload): ApiJson<EstimateGasRequest>,
+) -> Result<Response, ApiError>
+where
+    T: Transport + Send + Sync + 'static,
+    T::Out: Send,
+{
+    let estimate =
+        estimate_with_fees(&state, path_chain_id, query, timeout, &headers, payload).await?;
+    let age = estimate.cache_age_secs;
+    Ok(with_age(Json(GasEstimateResponseV2::from(estimate)), age))
+}
+
+/// Sets the `Age` header of cached estimates
+fn with_age(body: impl IntoResponse, age: Option<u64>) -> Response {
+    let mut response = body.into_response();
+    if let Some(age) = age {
+        response.headers_mut().insert(header::AGE, age.into());
+    }
+    response
+}
+
+/// Estimates a single transaction with fee suggestions, applying the chain of the
+/// path and the query options
+async fn estimate_with_fees<T>(
+    state: &AppState<T>,
+    path_chain_id: Option<u64>,
+    query: EstimateGasQuery,
+    timeout: Option<Extension<RequestTimeout>>,
+    headers: &HeaderMap,
+    mut payload: EstimateGasRequest,
+) -> Result<GasEstimateResponse, ApiError>
+where
+    T: Transport + Send + Sync + 'static,
+    T::Out: Send,
+{
+    payload.chain_id = match (path_chain_id, payload.chain_id) {
+        (Some(path), Some(body)) if path != body => {
+            return Err(ApiError::BadRequest(format!(
+                "chain_id {body} doesn't match chain {path} of the request path"
+            )));
+        }
+        (path, body) => path.or(body),
+    };
+    // An unknown chain in the path is a missing resource, in the body an invalid field
+    let estimator = match state.estimator_for(payload.chain_id) {
+        Err(ApiError::NotFound(error)) if path_chain_id.is_none() => {
+            return Err(ApiError::BadRequest(error));
+        }
+        result => result?,
+    };
+    payload.buffer_percent = query.buffer;
+    payload.no_heuristic = query.no_heuristic;
+    payload.rpc_timeout = timeout.map(|Extension(RequestTimeout(timeout))| timeout);
+    if query.block.is_some() {
+        payload.block = query.block;
+    }
+    if query.margin_bps.is_some() {
+        payload.margin_bps = query.margin_bps;
+    }
+    if let Some(rpc_url) = requested_rpc_url(&mut payload, headers)? {
+        return estimate_with_rpc_override(state, rpc_url, query.detail, payload).await;
+    }
+    let started = Instant::now();
+    let (estimate, fees) = tokio::join!(estimate_single(state, payload), estimator.estimate_fees());
+    state.metrics.record(&estimate, Some(started.elapsed()));
+
+    let mut estimate = estimate?;
+    estimate.fees = fees;
+    if !query.detail {
+        estimate.breakdown = None;
+    }
+    Ok(estimate)
+}
+
+/// Takes the node a single estimate chose, from `rpc_url` or `X-Upstream-RPC`
+fn requested_rpc_url(
+    payload: &mut EstimateGasRequest,
+    headers: &HeaderMap,
+) -> Result<Option<String>, ApiError> {
+    let header = headers
+        .get(UPSTREAM_RPC_HEADER)
+        .map(|value| {
+            value
+                .to_str()
+                .map(str::to_string)
+                .map_err(|_| ApiError::BadRequest("X-Upstream-RPC is not a valid URL".to_string()))
+        })
+        .transpose()?;
+    match (payload.rpc_url.take(), header) {
+        (Some(body), Some(header)) if body != header => Err(ApiError::BadRequest(
+            "rpc_url and X-Upstream-RPC are both set but differ".to_string(),
+        )),
+        (body, header) => Ok(body.or(header)),
+    }
+}
+
+/// Estimates a single transaction with fee suggestions on the node the request
+/// chose, bypassing the estimate provider and the cache. `endpoint` names the node.
+async fn estimate_with_rpc_override<T>(
+    state: &AppState<T>,
+    rpc_url: String,
+    detail: bool,
+    mut payload: EstimateGasRequest,
+) -> Result<GasEstimateResponse, ApiError>
+where
+    T: Transport + Send + Sync + 'static,
+    T::Out: Send,
+{
+    let Some(rpc_override) = &state.rpc_override else {
+        return Err(ApiError::BadRequest(
+            "rpc_url and X-Upstream-RPC are disabled on this server".to_string(),
+        ));
+    };
+    let url = rpc_override.parse_url(&rpc_url)?;
+    let endpoint = redact_urls(url.as_str());
+    let estimator = rpc_override.estimator(url);
+    payload.rpc_timeout = Some(rpc_override.rpc_timeout(payload.rpc_timeout));
+    tracing::info!(%endpoint, "Estimating against a requested node");
+
+    let started = Instant::now();
+    let (estimate, fees) = tokio::join!(estimator.estimate(payload), estimator.estimate_fees());
+    state.metrics.record(&estimate, Some(started.elapsed()));
+
+    let mut estimate = estimate?;
+    estimate.fees = fees;
+    estimate.endpoint = Some(endpoint);
+    if !detail {
+        estimate.breakdown = None;
+    }
+    Ok(estimate)
+}
+
+/// Handles HTTP requests for plain transfers given as query parameters. These are
+/// always estimated statically, without contacting the node.
+/// GET: /api/estimate-gas?to=0x...&from=0x...&value=<wei>
+#[utoipa::path(
+    get,
+    path = "/api/estimate-gas",
+    tag = "estimate",
+    params(TransferQuery),
+    responses(
+        (status = 200, description = "Static estimate of a plain transfer", body = GasEstimateResponse),
+        (status = 400, description = "Invalid query", body = ErrorResponse),
+    )
+)]
+async fn estimate_transfer_handler<T>(
+    State(state): State<AppState<T>>,
+    PathChainId(chain_id): PathChainId,
+    query: Result<Query<TransferQuery>, QueryRejection>,
+) -> Result<Json<GasEstimateResponse>, ApiError>
+where
+    T: Transport + Send + Sync + 'static,
+    T::Out: Send,
+{
+    let Query(query) = query.map_err(|rejection| ApiError::BadRequest(rejection.body_text()))?;
+    let started = Instant::now();
+    let estimate = state.estimator_for(chain_id).map(|estimator| {
+        estimator.estimate_transfer(query.from, query.to, query.value.map(|value| value.0))
+    });
+    state.metrics.record(&estimate, Some(started.elapsed()));
+
+    let mut estimate = estimate?;
+    if !query.detail {
+        estimate.breakdown = None;
+    }
+    Ok(Json(estimate))
+}
+
+/// Estimates a single request on its chain. Requests for the default chain go
+/// to the estimate provider or through the cache when one is configured.
+async fn estimate_single<T>(
+    state: &AppState<T>,
+    request: EstimateGasRequest,
+) -> Result<GasEstimateResponse, ApiError>
+where
+    T: Transport + Send + Sync + 'static,
+    T::Out: Send,
+{
+    if request.chain_id.is_none()
+        && let Some(provider) = &state.estimate_provider
+    {
+        return provider.estimate_gas(request).await;
+    }
+    #[cfg(feature = "cache")]
+    if request.chain_id.is_none()
+        && let Some(cached) = &state.cached_estimator
+    {
+        return cached.estimate(request).await;
+    }
+    state
+        .estimator_for(request.chain_id)?
+        .estimate(request)
+        .await
+}
+
+/// Handles HTTP requests for access list suggestions
+/// POST: /api/create-access-list
+#[utoipa::path(
+    post,
+    path = "/api/create-access-list",
+    tag = "estimate",
+    request_body = EstimateRequest,
+    responses(
+        (status = 200, description = "Suggested access list", body = CreateAccessListResponse),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+        (status = 408, description = "Request timed out", body = ErrorResponse),
+        (status = 413, description = "Request body too large", body = ErrorResponse),
+        (status = 422, description = "Execution reverted", body = ErrorResponse),
+        (status = 500, description = "RPC call failed", body = ErrorResponse),
+        (status = 501, description = "Node doesn't support eth_createAccessList", body = ErrorResponse),
+    )
+)]
+async fn create_access_list_handler<T>(
+    State(state): State<AppState<T>>,
+    PathChainId(chain_id): PathChainId,
+    ApiJson(payload): ApiJson<EstimateGasRequest>,
+) -> Result<Json<CreateAccessListResponse>, ApiError>
+where
+    T: Transport + Send + Sync + 'static,
+    T::Out: Send,
+{
+    rpc_override::reject_rpc_url(&payload)?;
+    state
+        .estimator_for(chain_id.or(payload.chain_id))?
+        .create_access_list(&payload.tx, payload.requested_block()?)
+        .await
+        .map(Json)
+}
+
+/// Handles HTTP requests for EIP-1559 fee suggestions
+/// GET: /api/fee-suggestion
+#[utoipa::path(
+    get,
+    path = "/api/fee-suggestion",
+    tag = "fees",
+    responses(
+        (status = 200, description = "EIP-1559 fee suggestion", body = FeeSuggestionResponse),
+        (status = 500, description = "RPC call failed", body = ErrorResponse),
+        (status = 503, description = "Chain without EIP-1559 fee fields", body = ErrorResponse),
+    )
+)]
+async fn fee_suggestion_handler<T>(
+    State(state): State<AppState<T>>,
+    PathChainId(chain_id): PathChainId,
+) -> Result<Json<FeeSuggestionResponse>, ApiError>
+where
+    T: Transport + Send + Sync + 'static,
+    T::Out: Send,
+{
+    state
+        .estimator_for(chain_id)?
+        .fee_suggestion()
+        .await
+        .map(Json)
+}
+
+/// Handles HTTP requests for the next block's base fee
+/// GET: /api/base-fee/next
+#[utoipa::path(
+    get,
+    path = "/api/base-fee/next",
+    tag = "fees",
+    responses(
+        (status = 200, description = "Predicted base fee of the next block", body = BaseFeeResponse),
+        (status = 500, description = "RPC call failed", body = ErrorResponse),
+        (status = 503, description = "Chain without EIP-1559 fee fields", body = ErrorResponse),
+    )
+)]
+async fn next_base_fee_handler<T>(
+    State(state): State<AppState<T>>,
+    PathChainId(chain_id): PathChainId,
+) -> Result<Json<BaseFeeResponse>, ApiError>
+where
+    T: Transport + Send + Sync + 'static,
+    T::Out: Send,
+{
+    state
+        .estimator_for(chain_id)?
+        .next_base_fee()
+        .await
+        .map(Json)
+}
+
+/// Handles HTTP requests for the default chain's fees, as last seen by the
+/// background watcher
+/// GET: /api/fees
+#[utoipa::path(
+    get,
+    path = "/api/fees",
+    tag = "fees",
+    responses(
+        (status = 200, description = "Latest fee snapshot, marked stale while the node fails", body = FeeSnapshot),
+        (status = 501, description = "Fee watcher disabled", body = ErrorResponse),
+        (status = 503, description = "No snapshot fetched yet", body = ErrorResponse),
+    )
+)]
+async fn fees_handler<T>(State(state): State<AppState<T>>) -> Result<Json<FeeSnapshot>, ApiError>
+where
+    T: Transport + Send + Sync + 'static,
+{
+    fee_watcher(&state)?.snapshot().map(Json).ok_or_else(|| {
+        ApiError::ServiceUnavailable("No fees fetched from the node yet".to_string())
+    })
+}
+
+/// Streams the default chain's fees as server-sent events, sending the current
+/// snapshot and then every change, with a heartbeat comment every 15 seconds
+/// GET: /api/fees/stream
+#[utoipa::path(
+    get,
+    path = "/api/fees/stream",
+    tag = "fees",
+    responses(
+        (status = 200, description = "Event stream of FeeSnapshot JSON", body = FeeSnapshot, content_type = "text/event-stream"),
+        (status = 501, description = "Fee watcher disabled", body = ErrorResponse),
+    )
+)]
+async fn fees_stream_handler<T>(
+    State(state): State<AppState<T>>,
+) -> Result<Sse<impl Stream<Item = Result<axum::response::sse::Event, Infallible>>>, ApiError>
+where
+    T: Transport + Send + Sync + 'static,
+{
+    let shutdown = state.shutdown.clone();
+    let events = fee_watcher(&state)?
+        .events()
+        // Let draining finish rather than hold the connection open
+        .take_until(async move { shutdown.initiated().await });
+    Ok(Sse::new(events).keep_alive(
+        KeepAlive::new()
+            .interval(FEE_STREAM_HEARTBEAT)
+            .text("heartbeat"),
+    ))
+}
+
+fn fee_watcher<T>(state: &AppState<T>) -> Result<Arc<FeeWatcher>, ApiError>
+where
+    T: Transport + Send + Sync + 'static,
+{
+    state.fee_watcher.clone().ok_or_else(|| {
+        ApiError::NotImplemented("Fee watcher is disabled; set BASE_FEE_POLL_SECS".to_string())
+    })
+}
+
+/// Handles HTTP requests for fee history and percentile fee suggestions
+/// GET: /api/fee-history?blocks=20&percentiles=10,50,90
+#[utoipa::path(
+    get,
+    path = "/api/fee-history",
+    tag = "fees",
+    params(FeeHistoryQuery),
+    responses(
+        (status = 200, description = "Fee history and percentile suggestions", body = FeeHistoryResponse),
+        (status = 400, description = "Invalid block count or percentiles", body = ErrorResponse),
+        (status = 500, description = "RPC call failed", body = ErrorResponse),
+        (status = 503, description = "Chain without EIP-1559 fee fields", body = ErrorResponse),
+    )
+)]
+async fn fee_history_handler<T>(
+    State(state): State<AppState<T>>,
+    PathChainId(chain_id): PathChainId,
+    query: Result<Query<FeeHistoryQuery>, QueryRejection>,
+) -> Result<Json<FeeHistoryResponse>, ApiError>
+where
+    T: Transport + Send + Sync + 'static,
+    T::Out: Send,
+{
+    let Query(query) = query.map_err(|rejection| ApiError::BadRequest(rejection.body_text()))?;
+    let percentiles = parse_percentiles(query.percentiles.as_deref())?
+        .unwrap_or_else(|| DEFAULT_FEE_HISTORY_PERCENTILES.to_vec());
+    state
+        .estimator_for(chain_id)?
+        .fee_history(
+            query.blocks.unwrap_or(DEFAULT_FEE_HISTORY_BLOCKS),
+            percentiles,
+        )
+        .await
+        .map(Json)
+}
+
+/// Handles HTTP requests for the priority fees paid at percentiles of recent
+/// blocks, for slow, standard and fast options
+/// GET: /api/gas-price/percentiles?blocks=20&percentiles=10,50,90
+#[utoipa::path(
+    get,
+    path = "/api/gas-price/percentiles",
+    tag = "fees",
+    params(FeeHistoryQuery),
+    responses(
+        (status = 200, description = "Priority fee at each percentile", body = FeePriorityPercentiles),
+        (status = 400, description = "Invalid block count or percentiles", body = ErrorResponse),
+        (status = 500, description = "RPC call failed", body = ErrorResponse),
+        (status = 503, description = "Node without eth_feeHistory (pre-London)", body = ErrorResponse),
+    )
+)]
+async fn gas_price_percentiles_handler<T>(
+    State(state): State<AppState<T>>,
+    PathChainId(chain_id): PathChainId,
+    query: Result<Query<FeeHistoryQuery>, QueryRejection>,
+) -> Result<Json<FeePriorityPercentiles>, ApiError>
+where
+    T: Transport + Send + Sync + 'static,
+    T::Out: Send,
+{
+    let Query(query) = query.map_err(|rejection| ApiError::BadRequest(rejection.body_text()))?;
+    let percentiles = parse_percentiles(query.percentiles.as_deref())?
+        .unwrap_or_else(|| DEFAULT_FEE_HISTORY_PERCENTILES.map(|p| p as u8).to_vec());
+    state
+        .estimator_for(chain_id)?
+        .priority_fee_percentiles(
+            query.blocks.unwrap_or(DEFAULT_FEE_HISTORY_BLOCKS),
+            percentiles,
+        )
+        .await
+        .map(Json)
+}
+
+/// Parses a comma-separated list of percentiles
+fn parse_percentiles<P: FromStr>(percentiles: Option<&str>) -> Result<Option<Vec<P>>, ApiError> {
+    percentiles
+        .map(|percentiles| {
+            percentiles
+                .split(',')
+                .map(|percentile| {
+                    percentile.trim().parse().map_err(|_| {
+                        ApiError::BadRequest(format!("Invalid percentile: {percentile}"))
+                    })
+                })
+                .collect()
+        })
+        .transpose()
+}
+
+/// Handles HTTP requests for batch gas estimation
+/// POST: /api/estimate-gas/batch
+#[utoipa::path(
+    post,
+    path = "/api/estimate-gas/batch",
+    tag = "estimate",
+    params(
+        BatchQuery,
+        ("X-Timeout-Ms" = Option<u64>, Header,
+            description = "Time the node may take to estimate each transaction, at least 100 ms"),
+    ),
+    request_body = Vec<EstimateRequest>,
+    responses(
+        (status = 200, description = "One estimate or error per transaction, in order", body = [BatchItemResult]),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+        (status = 408, description = "Request timed out", body = ErrorResponse),
+        (status = 413, description = "Request body too large", body = ErrorResponse),
+    )
+)]
+async fn estimate_gas_batch_handler<T>(
+    State(state): State<AppState<T>>,
+    PathChainId(chain_id): PathChainId,
+    Query(query): Query<BatchQuery>,
+    timeout: Option<Extension<RequestTimeout>>,
+    ApiJson(mut payload): ApiJson<Vec<EstimateGasRequest>>,
+) -> Result<Json<Vec<BatchItemResult>>, ApiError>
+where
+    T: Transport + Send + Sync + 'static,
+    T::Out: Send,
+{
+    check_batch_size(payload.len(), state.max_batch_size)?;
+    // The whole batch runs on one chain, selected by the path
+    if let Some(index) = payload
+        .iter()
+        .position(|request| request.chain_id.is_some_and(|id| Some(id) != chain_id))
+    {
+        return Err(ApiError::BadRequest(format!(
+            "Item {index} selects another chain; use /api/{{chain_id}}/estimate-gas/batch"
+        )));
+    }
+    if let Some(index) = payload.iter().position(|request| request.rpc_url.is_some()) {
+        return Err(ApiError::BadRequest(format!(
+            "Item {index} sets rpc_url, which only single estimates accept"
+        )));
+    }
+    let estimator = state.estimator_for(chain_id)?;
+    if let Some(Extension(RequestTimeout(timeout))) = timeout {
+        for request in &mut payload {
+            request.rpc_timeout = Some(timeout);
+        }
+    }
+
+    let (results, fees) = tokio::join!(
+        estimator.estimate_gas_batch(payload, state.batch_concurrency),
+        estimator.estimate_fees()
+    );
+
+    let items = results
+        .into_iter()
+        .enumerate()
+        .map(|(index, result)| {
+            state.metrics.record(&result, None);
+            let result = result.map(|mut estimate| {
+                estimate.fees = fees.clone();
+                if !query.detail {
+                    estimate.breakdown = None;
+                }
+                estimate
+            });
+            BatchItemResult::new(index, result)
+        })
+        .collect();
+    Ok(Json(items))
+}
+
+/// Rejects batches larger than the configured maximum
+fn check_batch_size(len: usize, max_batch_size: usize) -> Result<(), ApiError> {
+    if len > max_batch_size {
+        return Err(ApiError::BadRequest(format!(
+            "Batch size {len} exceeds maximum of {max_batch_size}"
+        )));
+    }
+    Ok(())
+}
+
+/// Handles HTTP requests for bundles of dependent transactions, each estimated
+/// against the state left by the ones before it
+/// POST: /api/estimate-bundle
+#[utoipa::path(
+    post,
+    path = "/api/estimate-bundle",
+    tag = "estimate",
+    request_body = BundleRequest,
+    responses(
+        (status = 200, description = "Estimate of each transaction, in order", body = Vec<BundleTxEstimate>),
+        (status = 400, description = "Invalid request or too many transactions", body = ErrorResponse),
+        (status = 408, description = "Request timed out", body = ErrorResponse),
+        (status = 413, description = "Request body too large", body = ErrorResponse),
+        (status = 500, description = "RPC call failed", body = ErrorResponse),
+        (status = 501, description = "Node supports neither eth_simulateV1 nor debug_traceCall", body = ErrorResponse),
+    )
+)]
+async fn estimate_bundle_handler<T>(
+    State(state): State<AppState<T>>,
+    PathChainId(chain_id): PathChainId,
+    ApiJson(payload): ApiJson<BundleRequest>,
+) -> Result<Json<Vec<BundleTxEstimate>>, ApiError>
+where
+    T: Transport + Send + Sync + 'static,
+    T::Out: Send,
+{
+    let len = payload.transactions.len();
+    if len > state.max_bundle_size {
+        return Err(ApiError::BadRequest(format!(
+            "Bundle of {len} transactions exceeds maximum of {}",
+            state.max_bundle_size
+        )));
+    }
+    state
+        .estimator_for(chain_id)?
+        .estimate_bundle(payload)
+        .await
+        .map(Json)
+}
+
+/// Handles HTTP requests for Safe transactions with a transaction guard
+/// POST: /api/estimate-gas/safe-with-guard
+#[utoipa::path(
+    post,
+    path = "/api/estimate-gas/safe-with-guard",
+    tag = "protocols",
+    request_body = SafeWithGuardParams,
+    responses(
+        (status = 200, description = "Safe execution and guard gas", body = SafeWithGuardEstimate),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+        (status = 408, description = "Request timed out", body = ErrorResponse),
+        (status = 413, description = "Request body too large", body = ErrorResponse),
+        (status = 422, description = "Execution reverted", body = ErrorResponse),
+        (status = 500, description = "RPC call failed", body = ErrorResponse),
+    )
+)]
+async fn estimate_safe_with_guard_handler<T>(
+    State(state): State<AppState<T>>,
+    PathChainId(chain_id): PathChainId,
+    ApiJson(payload): ApiJson<SafeWithGuardParams>,
+) -> Result<Json<SafeWithGuardEstimate>, ApiError>
+where
+    T: Transport + Send + Sync + 'static,
+    T::Out: Send,
+{
+    state
+        .estimator_for(chain_id)?
+        .estimate_safe_with_guard(payload)
+        .await
+        .map(Json)
+}
+
+/// Handles HTTP requests for EigenLayer restaking
+/// POST: /api/estimate-gas/eigenlayer-restake
+#[utoipa::path(
+    post,
+    path = "/api/estimate-gas/eigenlayer-restake",
+    tag = "protocols",
+    request_body = EigenLayerParams,
+    responses(
+        (status = 200, description = "Approval and deposit gas", body = EigenLayerRestakeEstimate),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+        (status = 408, description = "Request timed out", body = ErrorResponse),
+        (status = 413, description = "Request body too large", body = ErrorResponse),
+        (status = 422, description = "Execution reverted", body = ErrorResponse),
+        (status = 500, description = "RPC call failed", body = ErrorResponse),
+    )
+)]
+async fn estimate_eigenlayer_restake_handler<T>(
+    State(state): State<AppState<T>>,
+    PathChainId(chain_id): PathChainId,
+    ApiJson(payload): ApiJson<EigenLayerParams>,
+) -> Result<Json<EigenLayerRestakeEstimate>, ApiError>
+where
+    T: Transport + Send + Sync + 'static,
+    T::Out: Send,
+{
+    state
+        .estimator_for(chain_id)?
+        .estimate_eigenlayer_restake(payload)
+        .await
+        .map(Json)
+}
+
+/// Handles HTTP requests for Blur marketplace purchases
+/// POST: /api/estimate-gas/blur-purchase
+#[utoipa::path(
+    post,
+    path = "/api/estimate-gas/blur-purchase",
+    tag = "protocols",
+    request_body = BlurPurchaseParams,
+    responses(
+        (status = 200, description = "Single and bulk purchase gas", body = BlurPurchaseEstimate),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+        (status = 408, description = "Request timed out", body = ErrorResponse),
+        (status = 413, description = "Request body too large", body = ErrorResponse),
+        (status = 422, description = "Execution reverted", body = ErrorResponse),
+        (status = 500, description = "RPC call failed", body = ErrorResponse),
+    )
+)]
+async fn estimate_blur_purchase_handler<T>(
+    State(state): State<AppState<T>>,
+    PathChainId(chain_id): PathChainId,
+    ApiJson(payload): ApiJson<BlurPurchaseParams>,
+) -> Result<Json<BlurPurchaseEstimate>, ApiError>
+where
+    T: Transport + Send + Sync + 'static,
+    T::Out: Send,
+{
+    state
+        .estimator_for(chain_id)?
+        .estimate_blur_purchase(payload)
+        .await
+        .map(Json)
+}
+
+/// Handles HTTP requests for EIP-5792 `wallet_sendCalls` batches
+/// POST: /api/estimate-gas/wallet-send-calls
+#[utoipa::path(
+    post,
+    path = "/api/estimate-gas/wallet-send-calls",
+    tag = "protocols",
+    request_body = WalletSendCallsRequest,
+    responses(
+        (status = 200, description = "Gas of each call and of the batch", body = WalletSendCallsEstimate),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+        (status = 408, description = "Request timed out", body = ErrorResponse),
+        (status = 413, description = "Request body too large", body = ErrorResponse),
+        (status = 422, description = "Execution reverted", body = ErrorResponse),
+        (status = 500, description = "RPC call failed", body = ErrorResponse),
+    )
+)]
+async fn estimate_wallet_send_calls_handler<T>(
+    State(state): State<AppState<T>>,
+    PathChainId(chain_id): PathChainId,
+    ApiJson(payload): ApiJson<WalletSendCallsRequest>,
+) -> Result<Json<WalletSendCallsEstimate>, ApiError>
+where
+    T: Transport + Send + Sync + 'static,
+    T::Out: Send,
+{
+    state
+        .estimator_for(chain_id)?
+        .estimate_wallet_send_calls(payload)
+        .await
+        .map(Json)
+}
+
+/// Handles HTTP requests for EIP-4337 user operations
+/// POST: /api/estimate-gas/userop
+#[utoipa::path(
+    post,
+    path = "/api/estimate-gas/userop",
+    tag = "protocols",
+    request_body = UserOperationRequest,
+    responses(
+        (status = 200, description = "Gas fields of the user operation", body = UserOperationGasResponse),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+        (status = 408, description = "Request timed out", body = ErrorResponse),
+        (status = 413, description = "Request body too large", body = ErrorResponse),
+        (status = 422, description = "Execution reverted", body = ErrorResponse),
+        (status = 500, description = "RPC call failed", body = ErrorResponse),
+    )
+)]
+async fn estimate_user_operation_handler<T>(
+    State(state): State<AppState<T>>,
+    PathChainId(chain_id): PathChainId,
+    ApiJson(payload): ApiJson<UserOperationRequest>,
+) -> Result<Json<UserOperationGasResponse>, ApiError>
+where
+    T: Transport + Send + Sync + 'static,
+    T::Out: Send,
+{
+    state
+        .estimator_for(chain_id)?
+        .estimate_user_operation(payload)
+        .await
+        .map(Json)
+}
+
+/// Answers JSON-RPC 2.0 calls and batches on the default chain: `eth_estimateGas`
+/// through the estimator, and [`json_rpc::FORWARDED_METHODS`](crate::json_rpc::FORWARDED_METHODS)
+/// by the node. Errors are JSON-RPC error objects in a `200` response.
+/// POST: /rpc
+#[utoipa::path(
+    post,
+    path = "/rpc",
+    tag = "estimate",
+    request_body(content = Object, description = "JSON-RPC 2.0 call, or a batch of calls"),
+    responses(
+        (status = 200, description = "JSON-RPC 2.0 response, or a batch of responses in the order of the calls", body = Object),
+        (status = 204, description = "The request only held notifications"),
+        (status = 413, description = "Request body too large", body = ErrorResponse),
+    )
+)]
+async fn json_rpc_handler<T>(
+    State(state): State<AppState<T>>,
+    timeout: Option<Extension<RequestTimeout>>,
+    body: Bytes,
+) -> Response
+where
+    T: Transport + Send + Sync + 'static,
+    T::Out: Send,
+{
+    let payload = match Payload::parse(&body, state.max_batch_size) {
+        Ok(payload) => payload,
+        Err(response) => return Json(response).into_response(),
+    };
+    let outputs = state
+        .estimator
+        .answer_calls(
+            payload.calls(),
+            state.rpc_batch.as_ref(),
+            timeout.map(|Extension(RequestTimeout(timeout))| timeout),
+            &state.metrics,
+        )
+        .await;
+    match payload.respond(outputs) {
+        Some(response) => Json(response).into_response(),
+        None => StatusCode::NO_CONTENT.into_response(),
+    }
+}
+
+/// Streams gas price updates to each WebSocket client from its own polling task
+/// GET: /ws/gas-price
+#[utoipa::path(
+    get,
+    path = "/ws/gas-price",
+    tag = "fees",
+    responses(
+        (status = 101, description = "WebSocket streaming a GasPriceUpdate every poll interval", body = GasPriceUpdate),
+    )
+)]
+async fn gas_price_ws_handler<T>(
+    State(state): State<AppState<T>>,
+    ws: WebSocketUpgrade,
+) -> impl IntoResponse
+where
+    T: Transport + Send + Sync + 'static,
+    T::Out: Send,
+{
+    ws.on_upgrade(move |socket| async move {
+        state
+            .estimator
+            .stream_gas_prices(socket, state.gas_price_poll_interval)
+            .await
+    })
+}
+
+/// Handles HTTP requests for health check. Reports `503` once shutdown has begun,
+/// so load balancers stop routing new traffic while requests drain, and while the
+/// default chain's node doesn't answer.
+/// GET: /health
+#[utoipa::path(
+    get,
+    path = "/health",
+    tag = "service",
+    responses(
+        (status = 200, description = "Service and node are healthy", body = Object),
+        (status = 503, description = "Node unreachable or shutting down", body = Object),
+    )
+)]
+async fn health_handler<T>(State(state): State<AppState<T>>) -> impl IntoResponse
+where
+    T: Transport + Send + Sync + 'static,
+    T::Out: Send,
+{
+    if state.shutdown.is_initiated() {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(serde_json::json!({
+                "status": "shutting_down",
+                "service": "gas-estimator",
+            })),
+        );
+    }
+
+    let latest_block = tokio::time::timeout(
+        state.health_check_timeout,
+        state.estimator.latest_block_number(),
+    )
+    .await
+    .unwrap_or_else(|_| {
+        Err(ApiError::ServiceUnavailable(format!(
+            "RPC didn't answer within {} seconds",
+            state.health_check_timeout.as_secs_f64()
+        )))
+    });
+
+    let memory = memory::MemoryStats::collect();
+    let mut body = serde_json::json!({
+        "status": "healthy",
+        "service": "gas-estimator",
+        "heap_allocated_kb": memory.heap_allocated_kb,
+        "heap_resident_kb": memory.heap_resident_kb,
+        "fragmentation_ratio": memory.fragmentation_ratio,
+        "open_file_descriptors": memory.open_file_descriptors,
+        "goroutines_equivalent": memory.goroutines_equivalent,
+    });
+    // Checked after the probe, which reconnects dropped WebSockets
+    if let Some(connections) = &state.rpc_connections {
+        let connections: Vec<_> = connections()
+            .into_iter()
+            .map(|(url, connected)| {
+                serde_json::json!({
+                    "endpoint": redact_urls(url.as_str()),
+                    "connected": connected,
+                })
+            })
+            .collect();
+        if !connections.is_empty() {
+            body["rpc_connections"] = connections.into();
+        }
+    }
+    match latest_block {
+        Ok(block) => {
+            body["rpc_status"] = "ok".into();
+            body["latest_block"] = block.into();
+            (StatusCode::OK, Json(body))
+        }
+        Err(e) => {
+            body["status"] = "unhealthy".into();
+            body["rpc_status"] = "error".into();
+            body["rpc_error"] = ErrorResponse::from(e).error.into();
+            (StatusCode::SERVICE_UNAVAILABLE, Json(body))
+        }
+    }
+}
+
+/// Handles liveness probes: the process is up and serving requests, whatever the
+/// state of the node
+/// GET: /health/live
+#[utoipa::path(
+    get,
+    path = "/health/live",
+    tag = "service",
+    responses(
+        (status = 200, description = "Process is up", body = Object),
+    )
+)]
+async fn liveness_handler() -> impl IntoResponse {
+    Json(serde_json::json!({
+        "status": "alive",
+        "service": "gas-estimator",
+    }))
+}
+
+/// Handles readiness probes. Reports `503` while the default chain's node doesn't
+/// answer `eth_blockNumber`, after repeated failed estimates, and once shutdown has
+/// begun. Node checks are reused for `readiness_check_interval`.
+/// GET: /health/ready
+#[utoipa::path(
+    get,
+    path = "/health/ready",
+    tag = "service",
+    responses(
+        (status = 200, description = "Node answers, with its latest block and latency", body = Object),
+        (status = 503, description = "Node unreachable or not warmed up yet, estimates failing or shutting down", body = Object),
+    )
+)]
+async fn readiness_handler<T>(State(state): State<AppState<T>>) -> impl IntoResponse
+where
+    T: Transport + Send + Sync + 'static,
+    T::Out: Send,
+{
+    if state.shutdown.is_initiated() {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(serde_json::json!({
+                "status": "shutting_down",
+                "service": "gas-estimator",
+            })),
+        );
+    }
+
+    if state.warmup.is_pending() {
+        match state.estimator.warm_up().await {
+            Ok(warmup) => {
+                tracing::info!(chain_id = warmup.chain_id, "Warmed up after startup");
+                state.warmup.complete();
+            }
+            Err(error) => {
+                return (
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    Json(serde_json::json!({
+                        "status": "not_ready",
+                        "service": "gas-estimator",
+                        "rpc_status": "warming_up",
+                        "rpc_error": ErrorResponse::from(error).error,
+                    })),
+                );
+            }
+        }
+    }
+
+    let readiness = state
+        .estimator
+        .readiness(state.readiness_check_interval, state.health_check_timeout)
+        .await;
+    match readiness {
+        Readiness::Ready {
+            latest_block,
+            rpc_latency,
+        } => (
+            StatusCode::OK,
+            Json(serde_json::json!({
+                "status": "ready",
+                "service": "gas-estimator",
+                "rpc_status": "ok",
+                "latest_block": latest_block,
+                "rpc_latency_ms": rpc_latency.as_millis() as u64,
+                "chain_id": state.estimator.known_chain_id(),
+            })),
+        ),
+        Readiness::Unreachable(error) => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(serde_json::json!({
+                "status": "not_ready",
+                "service": "gas-estimator",
+                "rpc_status": "error",
+                "rpc_error": error,
+            })),
+        ),
+        Readiness::FailingEstimates(failures) => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(serde_json::json!({
+                "status": "not_ready",
+                "service": "gas-estimator",
+                "rpc_status": "estimates_failing",
+                "rpc_error": format!("{failures} estimates in a row failed upstream"),
+            })),
+        ),
+    }
+}
+
+/// Handles Prometheus scrapes
+/// GET: /metrics
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    tag = "service",
+    responses(
+        (status = 200, description = "Prometheus metrics", body = String, content_type = "text/plain"),
+    )
+)]
+async fn metrics_handler<T>(State(state): State<AppState<T>>) -> impl IntoResponse
+where
+    T: Transport + Send + Sync + 'static,
+{
+    (
+        [(header::CONTENT_TYPE, state.metrics.content_type())],
+        state.metrics.render(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use web3::transports::Http;
+
+    fn state() -> AppState<Http> {
+        // Nothing listens here, so the node is unreachable
+        AppState::new(GasEstimator::new(
+            Http::new("http://localhost:8545").unwrap(),
+        ))
+    }
+
+    #[test]
+    fn test_check_batch_size() {
+        assert!(check_batch_size(DEFAULT_MAX_BATCH_SIZE, DEFAULT_MAX_BATCH_SIZE).is_ok());
+        assert!(matches!(
+            check_batch_size(DEFAULT_MAX_BATCH_SIZE + 1, DEFAULT_MAX_BATCH_SIZE),
+            Err(ApiError::BadRequest(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_health_reports_memory_stats() {
+        let response = health_handler(State(state())).await.into_response();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        // Reported even while the node is unreachable
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["status"], "unhealthy");
+        for key in [
+            "heap_allocated_kb",
+            "heap_resident_kb",
+            "fragmentation_ratio",
+            "open_file_descriptors",
+        ] {
+            assert!(json.get(key).is_some(), "missing {key}");
+        }
+        assert!(json["goroutines_equivalent"].as_u64().is_some());
+    }
+
+    #[test]
+    fn test_batch_item_result_serialization() {
+        let items = vec![
+            BatchItemResult::new(
+                0,
+                Ok(GasEstimateResponse {
+                    gas_limit: 21000.into(),
+                    method: "static".to_string(),
+                    ..Default::default()
+                }),
+            ),
+            BatchItemResult::new(
+                1,
+                Err(ApiError::UpstreamUnavailable(
+                    "RPC call failed: https://rpc.example/v3/secret-key: connection refused"
+                        .to_string(),
+                )),
+            ),
+            BatchItemResult::new(
+                2,
+                Err(ApiError::ExecutionReverted {
+                    reason: Some("Dai/insufficient-balance".to_string()),
+                    data: Some("0x08c379a0".to_string()),
+                }),
+            ),
+        ];
+
+        let json = serde_json::to_value(&items).unwrap();
+        assert_eq!(json[0]["method"], "static");
+        assert_eq!(json[1]["index"], 1);
+        assert_eq!(json[1]["error"], "RPC call failed");
+        assert_eq!(json[1]["code"], "upstream_unavailable");
+        assert_eq!(json[2]["index"], 2);
+        assert_eq!(json[2]["error"], "execution reverted");
+        assert_eq!(json[2]["reason"], "Dai/insufficient-balance");
+        assert_eq!(json[2]["data"], "0x08c379a0");
+    }
+}
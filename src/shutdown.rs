@@ -0,0 +1,90 @@
+//! Shutdown state shared between the server and its handlers
+
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tokio::sync::watch;
+
+pub const DEFAULT_SHUTDOWN_TIMEOUT_SECS: u64 = 30;
+
+/// Tracks whether shutdown has begun and how many requests are still being served
+#[derive(Debug)]
+pub struct Shutdown {
+    initiated: watch::Sender<bool>,
+    in_flight: AtomicUsize,
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        Self {
+            initiated: watch::Sender::new(false),
+            in_flight: AtomicUsize::new(0),
+        }
+    }
+
+    /// Marks shutdown as started; `/health` reports unavailable from now on
+    pub fn initiate(&self) {
+        self.initiated.send_replace(true);
+    }
+
+    pub fn is_initiated(&self) -> bool {
+        *self.initiated.borrow()
+    }
+
+    /// Resolves once shutdown has been initiated
+    pub async fn initiated(&self) {
+        let mut receiver = self.initiated.subscribe();
+        // The sender lives in `self`, so the channel can't close while borrowed
+        let _ = receiver.wait_for(|initiated| *initiated).await;
+    }
+
+    /// Number of requests currently being handled
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::Relaxed)
+    }
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Decrements the in-flight count when a request finishes or is dropped
+struct InFlightGuard(Arc<Shutdown>);
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.0.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Middleware counting requests in flight
+pub async fn track_in_flight(
+    State(shutdown): State<Arc<Shutdown>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    shutdown.in_flight.fetch_add(1, Ordering::Relaxed);
+    let _guard = InFlightGuard(shutdown);
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_initiate() {
+        let shutdown = Shutdown::new();
+        assert!(!shutdown.is_initiated());
+        shutdown.initiate();
+        assert!(shutdown.is_initiated());
+        // Resolves immediately once initiated
+        shutdown.initiated().await;
+    }
+}
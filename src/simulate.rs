@@ -0,0 +1,211 @@
+//! Simulation of RPC-path transactions with `eth_call` at their estimated gas
+//! limit, returning whether they succeed and their output alongside the estimate
+
+use crate::eip7702;
+use crate::estimator::{ApiError, EstimateGasRequest, EstimateReason, GasEstimator};
+use crate::revert;
+use crate::state_override;
+use web3::Transport;
+use web3::helpers::{self, CallFuture};
+use web3::types::{BlockNumber, Bytes, CallRequest, U256};
+
+/// Outcome of executing the transaction at its estimated gas limit
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Simulation {
+    /// Whether the call returned without reverting
+    pub success: bool,
+    /// What the call returned, when it succeeded
+    pub return_data: Option<Bytes>,
+    /// Decoded reason of a reverted call
+    pub revert_reason: Option<String>,
+}
+
+/// Rejects `simulate` for transactions estimated from their intrinsic cost, which
+/// don't run any code whose output could be returned
+pub(crate) fn check_simulate(
+    request: &EstimateGasRequest,
+    reason: EstimateReason,
+) -> Result<(), ApiError> {
+    let static_set_code =
+        eip7702::is_set_code_transaction(&request.tx) && request.authorization_list.is_empty();
+    if !reason.requires_rpc() || static_set_code {
+        return Err(ApiError::BadRequest(
+            "Nothing to simulate for a plain transfer or an empty contract creation; \
+             simulate needs calldata or a contract receiver"
+                .to_string(),
+        ));
+    }
+    Ok(())
+}
+
+impl<T: Transport> GasEstimator<T> {
+    /// Executes the transaction with `eth_call` at `gas_limit`, against the same
+    /// block, state overrides and authorizations as its estimate. An estimate can
+    /// succeed while the call still reverts at the limit it suggests, which is
+    /// reported as an unsuccessful [`Simulation`] rather than an error, whether the
+    /// node fails the call or returns `Error(string)` revert data as its output.
+    pub(crate) async fn simulate(
+        &self,
+        request: &EstimateGasRequest,
+        gas_limit: U256,
+    ) -> Result<Simulation, ApiError> {
+        let block = request.requested_block()?;
+        let mut tx = helpers::serialize(&CallRequest {
+            gas: Some(gas_limit),
+            ..request.tx.clone()
+        });
+        if !request.authorization_list.is_empty() {
+            tx["authorizationList"] = helpers::serialize(&request.authorization_list);
+        }
+        let mut params = vec![
+            tx,
+            helpers::serialize(&block.unwrap_or(BlockNumber::Latest)),
+        ];
+        let call_error: fn(web3::Error) -> ApiError = match &request.state_overrides {
+            Some(overrides) => {
+                params.push(helpers::serialize(overrides));
+                state_override::estimate_error
+            }
+            None => revert::estimate_error,
+        };
+
+        let call = CallFuture::<Bytes, _>::new(self.transport().execute("eth_call", params));
+        let result = match request.rpc_timeout {
+            Some(timeout) => tokio::time::timeout(timeout, call).await.map_err(|_| {
+                ApiError::GatewayTimeout(format!(
+                    "Node didn't simulate within the requested {} ms",
+                    timeout.as_millis()
+                ))
+            })?,
+            None => call.await,
+        }
+        .map_err(call_error);
+
+        match result {
+            Ok(output) if output.0.starts_with(&revert::ERROR_SELECTOR) => Ok(Simulation {
+                success: false,
+                return_data: None,
+                revert_reason: revert::decode_revert(&output.0),
+            }),
+            Ok(output) => Ok(Simulation {
+                success: true,
+                return_data: Some(output),
+                revert_reason: None,
+            }),
+            Err(ApiError::ExecutionReverted { reason, .. }) => Ok(Simulation {
+                success: false,
+                return_data: None,
+                revert_reason: reason,
+            }),
+            Err(error) => Err(error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use web3::transports::test::TestTransport;
+    use web3::types::Address;
+
+    fn call_request() -> EstimateGasRequest {
+        EstimateGasRequest {
+            tx: CallRequest {
+                to: Some(Address::repeat_byte(2)),
+                data: Some(Bytes::from(vec![0x18, 0x16, 0x0d, 0xdd])),
+                ..Default::default()
+            },
+            block: Some("0x10".to_string()),
+            simulate: true,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_simulate_with_estimate() {
+        let mut transport = TestTransport::default();
+        // eth_estimateGas, then eth_call at the estimated gas limit
+        transport.add_response(json!("0xc350"));
+        transport.add_response(json!(
+            "0x00000000000000000000000000000000000000000000000000000000000003e8"
+        ));
+        let estimator = GasEstimator::new(transport.clone());
+
+        let result = estimator.estimate(call_request()).await.unwrap();
+        assert_eq!(result.gas_limit, 50_000.into());
+        assert_eq!(result.method, "rpc");
+        assert_eq!(result.simulation_success, Some(true));
+        assert_eq!(result.revert_reason, None);
+        let return_data = result.return_data.unwrap();
+        assert_eq!(return_data.0.len(), 32);
+        assert_eq!(return_data.0[30..], [0x03, 0xe8]);
+
+        // Both calls run against the requested block, the call with the estimate
+        let tx = call_request().tx;
+        let call = CallRequest {
+            gas: Some(50_000.into()),
+            ..tx.clone()
+        };
+        let block = r#""0x10""#.to_string();
+        transport.assert_request(
+            "eth_estimateGas",
+            &[helpers::serialize(&tx).to_string(), block.clone()],
+        );
+        transport.assert_request("eth_call", &[helpers::serialize(&call).to_string(), block]);
+        transport.assert_no_more_requests();
+    }
+
+    /// ABI encoding of `Error(string)` with the given reason
+    fn error_string(reason: &str) -> String {
+        let mut data = revert::ERROR_SELECTOR.to_vec();
+        data.extend(web3::ethabi::encode(&[web3::ethabi::Token::String(
+            reason.to_string(),
+        )]));
+        format!(
+            "0x{}",
+            data.iter().map(|b| format!("{b:02x}")).collect::<String>()
+        )
+    }
+
+    #[tokio::test]
+    async fn test_simulate_reverting_output() {
+        let mut transport = TestTransport::default();
+        transport.add_response(json!("0xc350"));
+        // Some nodes return the revert data as the call's output
+        transport.add_response(json!(error_string("Out of gas in callback")));
+        let estimator = GasEstimator::new(transport);
+
+        let result = estimator.estimate(call_request()).await.unwrap();
+        assert_eq!(result.gas_limit, 50_000.into());
+        assert_eq!(result.simulation_success, Some(false));
+        assert_eq!(
+            result.revert_reason.as_deref(),
+            Some("Out of gas in callback")
+        );
+        assert_eq!(result.return_data, None);
+    }
+
+    #[tokio::test]
+    async fn test_simulate_static_transfer_is_rejected() {
+        let mut transport = TestTransport::default();
+        // eth_getCode: the receiver has no code
+        transport.add_response(json!("0x"));
+        let estimator = GasEstimator::new(transport);
+        let request = EstimateGasRequest {
+            tx: CallRequest {
+                to: Some(Address::repeat_byte(2)),
+                value: Some(1.into()),
+                ..Default::default()
+            },
+            simulate: true,
+            ..Default::default()
+        };
+
+        let error = estimator.estimate(request).await.unwrap_err();
+        assert!(
+            matches!(&error, ApiError::BadRequest(message) if message.contains("Nothing to simulate")),
+            "{error:?}"
+        );
+    }
+}
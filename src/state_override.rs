@@ -0,0 +1,190 @@
+//! State overrides passed as the third parameter of `eth_estimateGas`
+
+use crate::estimator::{ApiError, GasEstimator};
+use crate::revert;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use utoipa::ToSchema;
+use web3::{
+    Transport,
+    api::Namespace,
+    helpers::{self, CallFuture},
+    types::{Address, BlockNumber, Bytes, CallRequest, H256, U64, U256},
+};
+
+/// Per-account overrides applied on top of the estimation block's state.
+/// Ordered maps keep the serialized form stable for cache keys.
+pub type StateOverride = BTreeMap<Address, AccountOverride>;
+
+/// Fields of an account to replace for the estimate, in Geth's format
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct AccountOverride {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub balance: Option<U256>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<U64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<Bytes>,
+    /// Replaces the whole storage of the account
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub state: Option<BTreeMap<H256, H256>>,
+    /// Replaces individual storage slots, keeping the rest
+    #[serde(
+        default,
+        rename = "stateDiff",
+        alias = "state_diff",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub state_diff: Option<BTreeMap<H256, H256>>,
+}
+
+/// Rejects overrides the node would refuse anyway, and overrides against a block
+/// given by number, which not every node supports together
+pub fn check_state_overrides(
+    overrides: &StateOverride,
+    block: Option<BlockNumber>,
+) -> Result<(), ApiError> {
+    if let Some(BlockNumber::Number(number)) = block {
+        return Err(ApiError::BadRequest(format!(
+            "State overrides can't be combined with block number {number}; \
+             estimate against a block tag such as latest instead"
+        )));
+    }
+    match overrides
+        .iter()
+        .find(|(_, account)| account.state.is_some() && account.state_diff.is_some())
+    {
+        Some((address, _)) => Err(ApiError::BadRequest(format!(
+            "State override for {address:?} sets both state and stateDiff"
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Maps a failed `eth_estimateGas` with overrides like [`revert::estimate_error`],
+/// except that a node refusing the override parameter is reported as a client error
+pub fn estimate_error(error: web3::Error) -> ApiError {
+    if revert::execution_reverted(&error).is_none() && rejects_overrides(&error) {
+        return ApiError::BadRequest(format!(
+            "State overrides are not supported by the configured RPC: {error}"
+        ));
+    }
+    revert::estimate_error(error)
+}
+
+/// Whether the node refused the call because of the extra override parameter.
+/// Nodes without override support answer with invalid params
+/// (e.g. "too many arguments, want at most 2").
+fn rejects_overrides(error: &web3::Error) -> bool {
+    let web3::Error::Rpc(error) = error else {
+        return false;
+    };
+    let message = error.message.to_lowercase();
+    error.code == jsonrpc_core::ErrorCode::InvalidParams
+        || message.contains("too many arguments")
+        || message.contains("override")
+}
+
+impl<T: Transport> GasEstimator<T> {
+    /// Calls `eth_estimateGas` with state overrides, which web3's typed helper can't pass
+    pub(crate) fn estimate_gas_with_overrides(
+        &self,
+        tx: &CallRequest,
+        block: Option<BlockNumber>,
+        overrides: &StateOverride,
+    ) -> CallFuture<U256, T::Out> {
+        CallFuture::new(self.eth.transport().execute(
+            "eth_estimateGas",
+            vec![
+                helpers::serialize(tx),
+                helpers::serialize(&block.unwrap_or(BlockNumber::Latest)),
+                helpers::serialize(overrides),
+            ],
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn rpc_error(code: i64, message: &str) -> web3::Error {
+        web3::Error::Rpc(jsonrpc_core::Error {
+            code: jsonrpc_core::ErrorCode::from(code),
+            message: message.to_string(),
+            data: None,
+        })
+    }
+
+    #[test]
+    fn test_serializes_in_geth_format() {
+        let overrides: StateOverride = serde_json::from_value(json!({
+            "0x6b175474e89094c44da98b954eedeac495271d0f": {
+                "balance": "0xde0b6b3a7640000",
+                "state_diff": {
+                    "0x0000000000000000000000000000000000000000000000000000000000000001":
+                        "0x00000000000000000000000000000000000000000000000000000000000000ff"
+                }
+            }
+        }))
+        .unwrap();
+
+        assert_eq!(
+            serde_json::to_value(&overrides).unwrap(),
+            json!({
+                "0x6b175474e89094c44da98b954eedeac495271d0f": {
+                    "balance": "0xde0b6b3a7640000",
+                    "stateDiff": {
+                        "0x0000000000000000000000000000000000000000000000000000000000000001":
+                            "0x00000000000000000000000000000000000000000000000000000000000000ff"
+                    }
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn test_state_and_state_diff_are_exclusive() {
+        let overrides = StateOverride::from([(
+            Address::zero(),
+            AccountOverride {
+                state: Some(BTreeMap::new()),
+                state_diff: Some(BTreeMap::new()),
+                ..Default::default()
+            },
+        )]);
+        assert!(matches!(
+            check_state_overrides(&overrides, None),
+            Err(ApiError::BadRequest(_))
+        ));
+    }
+
+    #[test]
+    fn test_block_number_is_rejected() {
+        let overrides = StateOverride::from([(Address::zero(), AccountOverride::default())]);
+        assert!(check_state_overrides(&overrides, None).is_ok());
+        assert!(check_state_overrides(&overrides, Some(BlockNumber::Latest)).is_ok());
+        assert!(matches!(
+            check_state_overrides(&overrides, Some(BlockNumber::Number(16.into()))),
+            Err(ApiError::BadRequest(message)) if message.contains("block number 16")
+        ));
+    }
+
+    #[test]
+    fn test_estimate_error() {
+        let unsupported = rpc_error(-32602, "too many arguments, want at most 2");
+        assert!(matches!(
+            estimate_error(unsupported),
+            ApiError::BadRequest(message) if message.contains("not supported")
+        ));
+        assert!(matches!(
+            estimate_error(rpc_error(3, "execution reverted")),
+            ApiError::ExecutionReverted { .. }
+        ));
+        assert!(matches!(
+            estimate_error(rpc_error(-32000, "header not found")),
+            ApiError::UpstreamUnavailable(_)
+        ));
+    }
+}
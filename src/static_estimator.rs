@@ -0,0 +1,136 @@
+//! Intrinsic gas of transactions, computed from the transaction alone without
+//! a node or an estimator
+
+use crate::estimator::GasBreakdown;
+use crate::gas_costs::*;
+use web3::types::{BlockNumber, CallRequest};
+
+/// Static estimate of `tx`: its intrinsic gas with EIP-2028 calldata pricing, as
+/// of the latest block
+pub fn static_gas_estimate(tx: &CallRequest, constants: &GasConstants) -> u64 {
+    static_gas_breakdown(tx, constants, PricingRule::Eip2028, None).total()
+}
+
+/// Intrinsic gas of `tx` itemized per component, with calldata priced by
+/// `pricing_rule` and initcode metered if `block` is after Shanghai
+pub fn static_gas_breakdown(
+    tx: &CallRequest,
+    constants: &GasConstants,
+    pricing_rule: PricingRule,
+    block: Option<BlockNumber>,
+) -> GasBreakdown {
+    let mut breakdown = GasBreakdown {
+        base: constants.tx_base,
+        ..Default::default()
+    };
+
+    // Contract creation vs regular transaction
+    if tx.to.is_none() {
+        breakdown.create = constants.tx_create;
+    }
+
+    // Calculate data gas (calldata)
+    if let Some(data) = &tx.data {
+        let data_bytes = &data.0;
+        breakdown.calldata_zero_bytes = data_bytes.iter().filter(|&&byte| byte == 0).count() as u64;
+        breakdown.calldata_nonzero_bytes = data_bytes.len() as u64 - breakdown.calldata_zero_bytes;
+        breakdown.calldata_zero = breakdown.calldata_zero_bytes * constants.tx_data_zero;
+        breakdown.calldata_nonzero = breakdown.calldata_nonzero_bytes * constants.tx_data_non_zero;
+
+        // Code deposit depends on the runtime code returned by the constructor, which
+        // is only known after execution, so only the initcode itself is charged here
+        if tx.to.is_none() && !predates_eip3860(block) {
+            breakdown.initcode = (data_bytes.len() as u64).div_ceil(32) * INITCODE_WORD_COST;
+        }
+    }
+
+    // Access list costs (EIP-2930)
+    if let Some(access_list) = &tx.access_list {
+        for item in access_list {
+            breakdown.access_list += GAS_ACCESS_LIST_ADDRESS;
+            breakdown.access_list += item.storage_keys.len() as u64 * GAS_ACCESS_LIST_STORAGE_KEY;
+        }
+    }
+
+    if pricing_rule == PricingRule::Eip7623 {
+        let floor = calldata_floor(
+            constants.tx_base,
+            breakdown.calldata_zero_bytes,
+            breakdown.calldata_nonzero_bytes,
+        );
+        breakdown.calldata_floor = floor.saturating_sub(breakdown.total());
+    }
+
+    breakdown
+}
+
+/// Initcode wasn't metered or limited before Shanghai
+pub(crate) fn predates_eip3860(block: Option<BlockNumber>) -> bool {
+    match block {
+        Some(BlockNumber::Earliest) => true,
+        Some(BlockNumber::Number(block)) => block < EIP3860_ACTIVATION_BLOCK.into(),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use web3::types::{AccessListItem, Address, Bytes, H256};
+
+    #[test]
+    fn test_static_gas_estimate() {
+        let constants = GasConstants::default();
+        let transfer = CallRequest {
+            to: Some(Address::repeat_byte(0x02)),
+            value: Some(1.into()),
+            ..Default::default()
+        };
+        assert_eq!(static_gas_estimate(&transfer, &constants), GAS_TX_BASE);
+
+        let creation = CallRequest::default();
+        assert_eq!(
+            static_gas_estimate(&creation, &constants),
+            GAS_TX_BASE + GAS_TX_CREATE
+        );
+
+        // 2 non-zero and 1 zero byte
+        let with_data = CallRequest {
+            to: Some(Address::repeat_byte(0x02)),
+            data: Some(Bytes::from(vec![0x01, 0x00, 0x02])),
+            ..Default::default()
+        };
+        assert_eq!(
+            static_gas_estimate(&with_data, &constants),
+            GAS_TX_BASE + (GAS_TX_DATA_NON_ZERO * 2) + GAS_TX_DATA_ZERO
+        );
+
+        let with_access_list = CallRequest {
+            to: Some(Address::repeat_byte(0x02)),
+            access_list: Some(vec![
+                AccessListItem {
+                    address: Address::repeat_byte(0x01),
+                    storage_keys: vec![H256::zero(), H256::repeat_byte(0x01)],
+                },
+                AccessListItem {
+                    address: Address::repeat_byte(0x02),
+                    storage_keys: vec![H256::repeat_byte(0x02)],
+                },
+            ]),
+            ..Default::default()
+        };
+        assert_eq!(
+            static_gas_estimate(&with_access_list, &constants),
+            21000 + 2 * 2400 + 3 * 1900
+        );
+
+        let initcode = CallRequest {
+            data: Some(Bytes::from(vec![0x01; 33])),
+            ..Default::default()
+        };
+        assert_eq!(
+            static_gas_estimate(&initcode, &constants),
+            21000 + 32000 + 33 * 16 + 2 * INITCODE_WORD_COST
+        );
+    }
+}
@@ -0,0 +1,553 @@
+//! RPC transport that fails over between several endpoints and retries transient
+//! failures
+
+use crate::config::redact_urls;
+use crate::recorder::Recorder;
+use crate::websocket::ReconnectingWebSocket;
+use futures::future::{BoxFuture, FutureExt};
+use jsonrpc_core::Call;
+use prometheus::IntCounter;
+use reqwest::Url;
+use serde_json::Value;
+use std::cell::RefCell;
+use std::collections::hash_map::RandomState;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use web3::{
+    BatchTransport, RequestId, Transport, error::TransportError, helpers, transports::Http,
+};
+
+pub const DEFAULT_FAILURE_THRESHOLD: u32 = 3;
+pub const DEFAULT_COOLDOWN_SECS: u64 = 30;
+pub const DEFAULT_MAX_ATTEMPTS: u32 = 3;
+pub const DEFAULT_RETRY_BASE_DELAY_MS: u64 = 100;
+pub const DEFAULT_RETRY_MAX_DELAY_MS: u64 = 5000;
+
+/// How the last request made within [`track_upstream`] was served
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Upstream {
+    /// Endpoint that answered
+    pub endpoint: Option<Url>,
+    /// Attempts it took, counting each pass over the endpoints once
+    pub attempts: Option<u32>,
+}
+
+tokio::task_local! {
+    static UPSTREAM: RefCell<Upstream>;
+}
+
+/// Runs `future` and reports which [`MultiTransport`] endpoint answered the last
+/// request it made, and after how many attempts. Other transports leave both unset.
+pub async fn track_upstream<F: Future>(future: F) -> (F::Output, Upstream) {
+    UPSTREAM
+        .scope(RefCell::new(Upstream::default()), async {
+            let output = future.await;
+            (output, UPSTREAM.with(|upstream| upstream.take()))
+        })
+        .await
+}
+
+#[derive(Debug, Clone)]
+struct Endpoint<T> {
+    url: Url,
+    transport: T,
+    consecutive_failures: u32,
+    /// Set while the circuit breaker keeps the endpoint out of rotation
+    demoted_until: Option<Instant>,
+}
+
+impl<T> Endpoint<T> {
+    fn is_demoted(&self, now: Instant) -> bool {
+        self.demoted_until.is_some_and(|until| until > now)
+    }
+}
+
+/// Transport of a single endpoint, chosen by the scheme of its URL
+#[derive(Debug, Clone)]
+pub enum EndpointTransport {
+    Http(Http),
+    WebSocket(ReconnectingWebSocket),
+}
+
+impl EndpointTransport {
+    /// Connects `ws://` and `wss://` URLs over a WebSocket whose requests time out
+    /// after `timeout`, and sends to others over HTTP through `client`
+    pub fn new(url: Url, client: &reqwest::Client, timeout: Duration) -> Self {
+        match url.scheme() {
+            "ws" | "wss" => Self::WebSocket(ReconnectingWebSocket::new(url, timeout)),
+            _ => Self::Http(Http::with_client(client.clone(), url)),
+        }
+    }
+}
+
+impl Transport for EndpointTransport {
+    type Out = BoxFuture<'static, web3::Result<Value>>;
+
+    fn prepare(&self, method: &str, params: Vec<Value>) -> (RequestId, Call) {
+        match self {
+            Self::Http(transport) => transport.prepare(method, params),
+            Self::WebSocket(transport) => transport.prepare(method, params),
+        }
+    }
+
+    fn send(&self, id: RequestId, request: Call) -> Self::Out {
+        match self {
+            Self::Http(transport) => transport.send(id, request).boxed(),
+            Self::WebSocket(transport) => transport.send(id, request),
+        }
+    }
+}
+
+/// Results of a JSON-RPC batch, in the order of its requests
+pub type BatchResult = web3::Result<Vec<web3::Result<Value>>>;
+
+impl BatchTransport for EndpointTransport {
+    type Batch = BoxFuture<'static, BatchResult>;
+
+    fn send_batch<I>(&self, requests: I) -> Self::Batch
+    where
+        I: IntoIterator<Item = (RequestId, Call)>,
+    {
+        match self {
+            Self::Http(transport) => transport.send_batch(requests),
+            // Requests share the connection, so sending them one by one costs no
+            // extra round trips
+            Self::WebSocket(transport) => {
+                let responses: Vec<_> = requests
+                    .into_iter()
+                    .map(|(id, request)| transport.send(id, request))
+                    .collect();
+                async move { Ok(futures::future::join_all(responses).await) }.boxed()
+            }
+        }
+    }
+}
+
+/// Sends requests to the node as one JSON-RPC batch, for the `/rpc` endpoint
+pub type BatchSender =
+    Arc<dyn Fn(Vec<(RequestId, Call)>) -> BoxFuture<'static, BatchResult> + Send + Sync>;
+
+/// Reports whether each WebSocket endpoint has an open connection, for `/health`
+pub type ConnectionStatus = Arc<dyn Fn() -> Vec<(Url, bool)> + Send + Sync>;
+
+/// Tries each endpoint in order until one succeeds. An endpoint that succeeds after
+/// an earlier one failed is promoted to the front, so later requests try it first.
+/// After `failure_threshold` consecutive transport failures an endpoint is skipped
+/// for `cooldown`, unless every endpoint is cooling down.
+///
+/// Requests failing transiently on every endpoint are retried up to `max_attempts`
+/// times in total, with exponential backoff and jitter, as long as the next attempt
+/// would start within the retry budget.
+#[derive(Debug, Clone)]
+pub struct MultiTransport<T = EndpointTransport> {
+    endpoints: Arc<Mutex<Vec<Endpoint<T>>>>,
+    next_id: Arc<AtomicUsize>,
+    failure_threshold: u32,
+    cooldown: Duration,
+    /// Incremented on every endpoint failure, for metrics
+    failure_counter: Option<IntCounter>,
+    max_attempts: u32,
+    retry_base_delay: Duration,
+    /// Longest delay between two attempts
+    retry_max_delay: Duration,
+    /// Time after the first attempt past which no retry is started
+    retry_budget: Option<Duration>,
+    /// Writes each request with its final response, when recording
+    recorder: Option<Recorder>,
+}
+
+impl<T> MultiTransport<T> {
+    pub fn new(endpoints: Vec<(Url, T)>) -> Self {
+        let endpoints = endpoints
+            .into_iter()
+            .map(|(url, transport)| Endpoint {
+                url,
+                transport,
+                consecutive_failures: 0,
+                demoted_until: None,
+            })
+            .collect();
+        Self {
+            endpoints: Arc::new(Mutex::new(endpoints)),
+            next_id: Arc::new(AtomicUsize::new(1)),
+            failure_threshold: DEFAULT_FAILURE_THRESHOLD,
+            cooldown: Duration::from_secs(DEFAULT_COOLDOWN_SECS),
+            failure_counter: None,
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            retry_base_delay: Duration::from_millis(DEFAULT_RETRY_BASE_DELAY_MS),
+            retry_max_delay: Duration::from_millis(DEFAULT_RETRY_MAX_DELAY_MS),
+            retry_budget: None,
+            recorder: None,
+        }
+    }
+
+    /// Sets how many consecutive failures demote an endpoint, and for how long
+    pub fn with_circuit_breaker(mut self, failure_threshold: u32, cooldown: Duration) -> Self {
+        self.failure_threshold = failure_threshold.max(1);
+        self.cooldown = cooldown;
+        self
+    }
+
+    /// Sets how many attempts a request gets in total, and the delay before the
+    /// first retry, which doubles for each further one
+    pub fn with_retries(mut self, max_attempts: u32, base_delay: Duration) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self.retry_base_delay = base_delay;
+        self
+    }
+
+    /// Caps the delay between two attempts, however many retries came before
+    pub fn with_max_retry_delay(mut self, max_delay: Duration) -> Self {
+        self.retry_max_delay = max_delay;
+        self
+    }
+
+    /// Stops retrying once the next attempt would start later than `budget` after
+    /// the first, so retries fit within the caller's own timeout
+    pub fn with_retry_budget(mut self, budget: Duration) -> Self {
+        self.retry_budget = Some(budget);
+        self
+    }
+
+    /// Records every request with the response it finally got, after retries
+    pub fn with_recorder(mut self, recorder: Recorder) -> Self {
+        self.recorder = Some(recorder);
+        self
+    }
+
+    /// Counts endpoint failures in the given counter
+    pub fn with_failure_counter(mut self, counter: IntCounter) -> Self {
+        self.failure_counter = Some(counter);
+        self
+    }
+
+    /// Endpoint URLs in the order they are currently tried
+    pub fn urls(&self) -> Vec<Url> {
+        self.endpoints
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|endpoint| endpoint.url.clone())
+            .collect()
+    }
+
+    /// Endpoint URLs currently demoted by the circuit breaker
+    pub fn demoted_urls(&self) -> Vec<Url> {
+        let now = Instant::now();
+        self.endpoints
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|endpoint| endpoint.is_demoted(now))
+            .map(|endpoint| endpoint.url.clone())
+            .collect()
+    }
+
+    /// Delay before the given retry: the exponential backoff, capped at the
+    /// longest delay, of which a random half is dropped so that concurrent
+    /// requests don't retry in lockstep
+    fn retry_delay(&self, retry: u32) -> Duration {
+        let backoff = self
+            .retry_base_delay
+            .saturating_mul(1 << (retry - 1).min(16))
+            .min(self.retry_max_delay);
+        // Each `RandomState` is seeded differently, which is enough randomness here
+        let random = RandomState::new().build_hasher().finish();
+        let jitter = backoff.as_nanos() as u64 / 2;
+        backoff - Duration::from_nanos(random.checked_rem(jitter).unwrap_or(0))
+    }
+
+    /// Resets the endpoint's failure count and moves it to the front if requested
+    fn record_success(&self, url: &Url, promote: bool) {
+        let mut endpoints = self.endpoints.lock().unwrap();
+        if let Some(position) = endpoints.iter().position(|e| &e.url == url) {
+            endpoints[position].consecutive_failures = 0;
+            endpoints[position].demoted_until = None;
+            if promote {
+                let endpoint = endpoints.remove(position);
+                endpoints.insert(0, endpoint);
+            }
+        }
+    }
+
+    /// Counts a failure, demoting the endpoint once it reaches the threshold
+    fn record_failure(&self, url: &Url) {
+        if let Some(counter) = &self.failure_counter {
+            counter.inc();
+        }
+        let mut endpoints = self.endpoints.lock().unwrap();
+        if let Some(endpoint) = endpoints.iter_mut().find(|e| &e.url == url) {
+            endpoint.consecutive_failures += 1;
+            if endpoint.consecutive_failures >= self.failure_threshold {
+                endpoint.demoted_until = Some(Instant::now() + self.cooldown);
+            }
+        }
+    }
+}
+
+impl MultiTransport<EndpointTransport> {
+    /// Whether each WebSocket endpoint has an open connection. HTTP endpoints
+    /// connect per request and aren't listed.
+    pub fn websocket_connections(&self) -> Vec<(Url, bool)> {
+        self.endpoints
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|endpoint| match &endpoint.transport {
+                EndpointTransport::WebSocket(socket) => {
+                    Some((endpoint.url.clone(), socket.is_connected()))
+                }
+                EndpointTransport::Http(_) => None,
+            })
+            .collect()
+    }
+}
+
+impl<T: Clone> MultiTransport<T> {
+    /// Endpoints to try for the next request: those not cooling down, or all of
+    /// them as a last resort
+    fn candidates(&self) -> Vec<(Url, T)> {
+        let now = Instant::now();
+        let endpoints = self.endpoints.lock().unwrap();
+        let available: Vec<_> = endpoints
+            .iter()
+            .filter(|endpoint| !endpoint.is_demoted(now))
+            .map(|endpoint| (endpoint.url.clone(), endpoint.transport.clone()))
+            .collect();
+        if !available.is_empty() {
+            return available;
+        }
+        endpoints
+            .iter()
+            .map(|endpoint| (endpoint.url.clone(), endpoint.transport.clone()))
+            .collect()
+    }
+}
+
+/// Whether the error means the endpoint itself is unhealthy. JSON-RPC errors (e.g.
+/// reverts or invalid params) and 4xx responses other than rate limiting are
+/// answers about the request, which another endpoint would repeat.
+fn is_endpoint_failure(error: &web3::Error) -> bool {
+    match error {
+        web3::Error::Rpc(_) => false,
+        web3::Error::Transport(TransportError::Code(status)) => {
+            *status == 429 || !(400..500).contains(status)
+        }
+        _ => true,
+    }
+}
+
+/// Error of a request no endpoint answered, listing each endpoint's failure
+fn all_failed(failures: &[String]) -> web3::Error {
+    web3::Error::Transport(TransportError::Message(if failures.is_empty() {
+        "No RPC endpoints configured".to_string()
+    } else {
+        format!("All RPC endpoints failed: {}", failures.join("; "))
+    }))
+}
+
+/// Whether the request may succeed if sent again: endpoint failures, and node
+/// errors about load rather than the request. Reverts and invalid params never are.
+fn is_retryable(error: &web3::Error) -> bool {
+    match error {
+        web3::Error::Rpc(error) => {
+            // -32603 is an internal error, -32005 exceeded limits (EIP-1474), and
+            // some providers pass the HTTP 429 through as the code
+            matches!(error.code.code(), -32603 | -32005 | 429) && !error.message.contains("revert")
+        }
+        error => is_endpoint_failure(error),
+    }
+}
+
+impl<T> Transport for MultiTransport<T>
+where
+    T: Transport + Send + Sync + 'static,
+    T::Out: Send,
+{
+    type Out = BoxFuture<'static, web3::Result<Value>>;
+
+    fn prepare(&self, method: &str, params: Vec<Value>) -> (RequestId, Call) {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        (id, helpers::build_request(id, method, params))
+    }
+
+    fn send(&self, id: RequestId, request: Call) -> Self::Out {
+        let this = self.clone();
+        async move {
+            let started = Instant::now();
+            let mut attempt = 1;
+            loop {
+                let result = this.send_to_endpoints(id, &request).await;
+                let retry = match &result {
+                    Err(error) if attempt < this.max_attempts && is_retryable(error) => {
+                        let delay = this.retry_delay(attempt);
+                        let within_budget = this
+                            .retry_budget
+                            .is_none_or(|budget| started.elapsed() + delay < budget);
+                        within_budget.then_some(delay)
+                    }
+                    _ => None,
+                };
+                let Some(delay) = retry else {
+                    let _ = UPSTREAM.try_with(|upstream| {
+                        upstream.borrow_mut().attempts = Some(attempt);
+                    });
+                    if let Some(recorder) = &this.recorder {
+                        recorder.record(&request, &result);
+                    }
+                    return result;
+                };
+
+                if let Err(error) = &result {
+                    tracing::warn!(
+                        attempt,
+                        delay_ms = delay.as_millis() as u64,
+                        error = %redact_urls(&error.to_string()),
+                        "Retrying RPC request"
+                    );
+                }
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+        }
+        .boxed()
+    }
+}
+
+impl<T> MultiTransport<T>
+where
+    T: Transport + Send + Sync + 'static,
+    T::Out: Send,
+{
+    /// Makes one attempt at the request, trying each endpoint in turn
+    async fn send_to_endpoints(&self, id: RequestId, request: &Call) -> web3::Result<Value> {
+        let candidates = self.candidates();
+        let mut failures = Vec::new();
+
+        for (position, (url, transport)) in candidates.iter().enumerate() {
+            match transport.send(id, request.clone()).await {
+                Err(e) if is_endpoint_failure(&e) => {
+                    self.record_failure(url);
+                    failures.push(format!("{url}: {e}"));
+                }
+                result => {
+                    self.record_success(url, position > 0);
+                    let _ = UPSTREAM
+                        .try_with(|upstream| upstream.borrow_mut().endpoint = Some(url.clone()));
+                    return result;
+                }
+            }
+        }
+
+        Err(all_failed(&failures))
+    }
+}
+
+/// Sends the whole batch to one endpoint, failing over like single requests. A
+/// batch is made once: retrying it would repeat the requests that did succeed.
+impl<T> BatchTransport for MultiTransport<T>
+where
+    T: BatchTransport + Send + Sync + 'static,
+    T::Out: Send,
+    T::Batch: Send,
+{
+    type Batch = BoxFuture<'static, BatchResult>;
+
+    fn send_batch<I>(&self, requests: I) -> Self::Batch
+    where
+        I: IntoIterator<Item = (RequestId, Call)>,
+    {
+        let this = self.clone();
+        let requests: Vec<_> = requests.into_iter().collect();
+        async move {
+            let mut failures = Vec::new();
+            for (position, (url, transport)) in this.candidates().iter().enumerate() {
+                match transport.send_batch(requests.clone()).await {
+                    Err(e) if is_endpoint_failure(&e) => {
+                        this.record_failure(url);
+                        failures.push(format!("{url}: {e}"));
+                    }
+                    result => {
+                        this.record_success(url, position > 0);
+                        if let (Some(recorder), Ok(responses)) = (&this.recorder, &result) {
+                            for ((_, request), response) in requests.iter().zip(responses) {
+                                recorder.record(request, response);
+                            }
+                        }
+                        return result;
+                    }
+                }
+            }
+            Err(all_failed(&failures))
+        }
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_endpoint_failure() {
+        assert!(is_endpoint_failure(&web3::Error::Unreachable));
+        assert!(is_endpoint_failure(&web3::Error::Transport(
+            TransportError::Code(502)
+        )));
+        assert!(is_endpoint_failure(&web3::Error::Transport(
+            TransportError::Code(429)
+        )));
+        assert!(!is_endpoint_failure(&web3::Error::Transport(
+            TransportError::Code(400)
+        )));
+        assert!(!is_endpoint_failure(&web3::Error::Rpc(
+            jsonrpc_core::Error::invalid_params("bad")
+        )));
+    }
+
+    #[test]
+    fn test_is_retryable() {
+        let rpc_error = |code, message: &str| {
+            web3::Error::Rpc(jsonrpc_core::Error {
+                code: jsonrpc_core::ErrorCode::ServerError(code),
+                message: message.to_string(),
+                data: None,
+            })
+        };
+        assert!(is_retryable(&web3::Error::Unreachable));
+        assert!(is_retryable(&rpc_error(-32005, "limit exceeded")));
+        assert!(is_retryable(&web3::Error::Rpc(
+            jsonrpc_core::Error::internal_error()
+        )));
+        assert!(!is_retryable(&rpc_error(-32603, "execution reverted")));
+        assert!(!is_retryable(&rpc_error(3, "execution reverted")));
+        assert!(!is_retryable(&web3::Error::Rpc(
+            jsonrpc_core::Error::invalid_params("bad")
+        )));
+        assert!(!is_retryable(&web3::Error::Transport(
+            TransportError::Code(400)
+        )));
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        let transport =
+            MultiTransport::<()>::new(vec![]).with_retries(3, Duration::from_millis(100));
+        for (retry, backoff) in [(1, 100), (2, 200), (3, 400)] {
+            let delay = transport.retry_delay(retry);
+            assert!(delay <= Duration::from_millis(backoff), "{delay:?}");
+            assert!(delay >= Duration::from_millis(backoff / 2), "{delay:?}");
+        }
+
+        // Later retries wait at most the longest delay
+        let transport = transport.with_max_retry_delay(Duration::from_millis(300));
+        for retry in [3, 10, 40] {
+            let delay = transport.retry_delay(retry);
+            assert!(delay <= Duration::from_millis(300), "{delay:?}");
+            assert!(delay >= Duration::from_millis(150), "{delay:?}");
+        }
+    }
+}
@@ -0,0 +1,207 @@
+//! Estimate responses in decimal and human-readable units, served by
+//! `/api/v2/estimate-gas`
+
+use crate::access_list::AccessListEstimate;
+use crate::balance::BalanceCheck;
+use crate::calldata::CalldataStats;
+use crate::estimator::{EstimateReason, FeeEstimate, GasBreakdown, GasEstimateResponse};
+use crate::gas_costs::PricingRule;
+use serde::{Serialize, Serializer};
+use utoipa::ToSchema;
+use web3::types::{BlockNumber, Bytes, U256};
+
+pub const GWEI_DECIMALS: usize = 9;
+pub const ETH_DECIMALS: usize = 18;
+
+/// Formats an amount of the smallest unit in a unit `decimals` orders of magnitude
+/// larger, with all `decimals` fractional digits so no precision is lost
+pub fn format_units(value: U256, decimals: usize) -> String {
+    // U256's `Display` ignores padding
+    let digits = format!("{:0>width$}", value.to_string(), width = decimals + 1);
+    let (whole, fraction) = digits.split_at(digits.len() - decimals);
+    if fraction.is_empty() {
+        whole.to_string()
+    } else {
+        format!("{whole}.{fraction}")
+    }
+}
+
+fn decimal<S: Serializer>(value: &U256, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(value)
+}
+
+/// Estimate with `gas_limit` as a decimal string and its cost in wei, gwei and
+/// ether. Other fields are the same as in [`GasEstimateResponse`].
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+#[schema(example = json!({
+    "gas_limit": "64800",
+    "gas_limit_hex": "0xfd20",
+    "estimated_cost_wei": "136080000000000",
+    "estimated_cost_gwei": "136080.000000000",
+    "estimated_cost_eth": "0.000136080000000000",
+    "method": "rpc+margin",
+    "fees": {
+        "base_fee_per_gas": "0x3b9aca00",
+        "max_priority_fee_per_gas": "0x5f5e100",
+        "max_fee_per_gas": "0x7d2b7500"
+    },
+    "raw_gas_limit": "0xd2f0",
+    "buffer_percent": 20,
+    "pricing_rule": "eip7623",
+    "block_gas_limit": 30000000,
+    "blob_gas_used": null,
+    "max_fee_per_blob_gas": null,
+    "reason": "calldata_present",
+    "gas_price_used": null,
+    "l2_execution_fee_wei": null,
+    "cached": false
+}))]
+pub struct GasEstimateResponseV2 {
+    #[serde(serialize_with = "decimal")]
+    #[schema(value_type = String, example = "64800")]
+    pub gas_limit: U256,
+    pub gas_limit_hex: U256,
+    /// `gas_limit * max_fee_per_gas`, or `gas_limit * gas_price_used` without fee
+    /// suggestions, in decimal wei
+    pub estimated_cost_wei: Option<String>,
+    /// The cost in gwei, with 9 decimals
+    pub estimated_cost_gwei: Option<String>,
+    /// The cost in ether, with 18 decimals
+    pub estimated_cost_eth: Option<String>,
+    #[schema(value_type = EstimateMethod)]
+    pub method: String,
+    pub fees: Option<FeeEstimate>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub raw_gas_limit: Option<U256>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub buffer_percent: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pending_gas_limit: Option<U256>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<bool>)]
+    pub stable: Option<Option<bool>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pricing_rule: Option<PricingRule>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub block_gas_limit: Option<u64>,
+    pub blob_gas_used: Option<u64>,
+    pub max_fee_per_blob_gas: Option<U256>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub block: Option<BlockNumber>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub endpoint: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rpc_attempts: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub breakdown: Option<GasBreakdown>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<EstimateReason>,
+    pub gas_price_used: Option<U256>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub access_list_estimate: Option<AccessListEstimate>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub balance_check: Option<BalanceCheck>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub return_data: Option<Bytes>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub l1_fee: Option<U256>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub l1_gas_used: Option<U256>,
+    pub l2_execution_fee_wei: Option<U256>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub l1_fee_warning: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub calldata_stats: Option<CalldataStats>,
+    pub cached: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cache_age_secs: Option<u64>,
+}
+
+impl From<GasEstimateResponse> for GasEstimateResponseV2 {
+    fn from(response: GasEstimateResponse) -> Self {
+        let fee_per_gas = response
+            .fees
+            .as_ref()
+            .map(|fees| fees.max_fee_per_gas)
+            .or(response.gas_price_used);
+        let cost = fee_per_gas.map(|fee| response.gas_limit.saturating_mul(fee));
+        Self {
+            gas_limit: response.gas_limit,
+            gas_limit_hex: response.gas_limit,
+            estimated_cost_wei: cost.map(|cost| cost.to_string()),
+            estimated_cost_gwei: cost.map(|cost| format_units(cost, GWEI_DECIMALS)),
+            estimated_cost_eth: cost.map(|cost| format_units(cost, ETH_DECIMALS)),
+            method: response.method,
+            fees: response.fees,
+            raw_gas_limit: response.raw_gas_limit,
+            buffer_percent: response.buffer_percent,
+            pending_gas_limit: response.pending_gas_limit,
+            stable: response.stable,
+            pricing_rule: response.pricing_rule,
+            block_gas_limit: response.block_gas_limit,
+            blob_gas_used: response.blob_gas_used,
+            max_fee_per_blob_gas: response.max_fee_per_blob_gas,
+            block: response.block,
+            endpoint: response.endpoint,
+            rpc_attempts: response.rpc_attempts,
+            breakdown: response.breakdown,
+            reason: response.reason,
+            gas_price_used: response.gas_price_used,
+            access_list_estimate: response.access_list_estimate,
+            balance_check: response.balance_check,
+            return_data: response.return_data,
+            l1_fee: response.l1_fee,
+            l1_gas_used: response.l1_gas_used,
+            l2_execution_fee_wei: response.l2_execution_fee_wei,
+            l1_fee_warning: response.l1_fee_warning,
+            calldata_stats: response.calldata_stats,
+            cached: response.cached,
+            cache_age_secs: response.cache_age_secs,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_units() {
+        assert_eq!(format_units(0.into(), GWEI_DECIMALS), "0.000000000");
+        assert_eq!(format_units(1.into(), GWEI_DECIMALS), "0.000000001");
+        assert_eq!(
+            format_units(1_500_000_000_000_000_000u64.into(), ETH_DECIMALS),
+            "1.500000000000000000"
+        );
+        assert_eq!(format_units(21_000.into(), 0), "21000");
+    }
+
+    #[test]
+    fn test_cost() {
+        let response = |fees, gas_price_used| {
+            GasEstimateResponseV2::from(GasEstimateResponse {
+                gas_limit: 21_000.into(),
+                fees,
+                gas_price_used,
+                ..Default::default()
+            })
+        };
+        let fees = FeeEstimate {
+            base_fee_per_gas: 1.into(),
+            max_priority_fee_per_gas: 1.into(),
+            max_fee_per_gas: 3.into(),
+        };
+
+        // Priced at the fee cap, falling back to the gas price
+        let priced = response(Some(fees), Some(2.into()));
+        assert_eq!(priced.estimated_cost_wei.as_deref(), Some("63000"));
+        let priced = response(None, Some(2.into()));
+        assert_eq!(priced.estimated_cost_wei.as_deref(), Some("42000"));
+        assert_eq!(priced.estimated_cost_gwei.as_deref(), Some("0.000042000"));
+
+        let unpriced = serde_json::to_value(response(None, None)).unwrap();
+        assert_eq!(unpriced["gas_limit"], "21000");
+        assert!(unpriced["estimated_cost_wei"].is_null());
+        assert!(unpriced["estimated_cost_eth"].is_null());
+    }
+}
@@ -0,0 +1,217 @@
+//! Classification of failed node calls into distinct API errors, so clients can
+//! tell an invalid transaction from a rate-limited or unreachable node
+
+use crate::estimator::ApiError;
+use crate::revert;
+use jsonrpc_core::ErrorCode;
+use web3::error::TransportError;
+
+/// Messages nodes use when refusing the transaction itself
+const INVALID_TRANSACTION_MESSAGES: &[&str] = &[
+    "intrinsic gas too low",
+    "insufficient funds",
+    "nonce too low",
+    "nonce too high",
+    "max fee per gas less than block base fee",
+    "max priority fee per gas higher than max fee per gas",
+    "exceeds block gas limit",
+    "invalid sender",
+    "transaction type not supported",
+];
+
+/// Messages providers use when rate limiting, usually with code -32005
+const RATE_LIMIT_MESSAGES: &[&str] = &[
+    "rate limit",
+    "too many requests",
+    "request limit",
+    "exceeded its compute units",
+    "capacity exceeded",
+];
+
+/// Messages of calls the node gave up on
+const TIMEOUT_MESSAGES: &[&str] = &["timeout", "timed out", "deadline exceeded"];
+
+/// Messages of nodes that can't serve the call right now, e.g. while syncing or
+/// without the state of the requested block
+const UNAVAILABLE_MESSAGES: &[&str] = &[
+    "header not found",
+    "missing trie node",
+    "syncing",
+    "unknown block",
+];
+
+/// EIP-1474 code for a request exceeding a limit, used by providers when rate limiting
+const LIMIT_EXCEEDED: i64 = -32005;
+
+/// Maps a failed node call to the [`ApiError`] describing it: reverts, transactions
+/// the node refuses, and rate limits, timeouts or outages of the node, whether
+/// reported in a JSON-RPC error or by the HTTP transport. Failures that fit none
+/// of these stay internal errors.
+pub fn classify_rpc_error(error: web3::Error) -> ApiError {
+    if let Some(reverted) = revert::execution_reverted(&error) {
+        return reverted;
+    }
+    match &error {
+        web3::Error::Rpc(rpc) => {
+            let message = rpc.message.to_lowercase();
+            let matches = |patterns: &[&str]| patterns.iter().any(|p| message.contains(p));
+            if rpc.code == ErrorCode::ServerError(LIMIT_EXCEEDED) || matches(RATE_LIMIT_MESSAGES) {
+                ApiError::UpstreamRateLimited(format!("RPC rate limited: {error}"))
+            } else if matches(INVALID_TRANSACTION_MESSAGES) {
+                ApiError::InvalidTransaction(format!("Invalid transaction: {}", rpc.message))
+            } else if matches(TIMEOUT_MESSAGES) {
+                ApiError::UpstreamTimeout(format!("RPC call timed out: {error}"))
+            } else if matches(UNAVAILABLE_MESSAGES) {
+                ApiError::UpstreamUnavailable(format!("RPC call failed: {error}"))
+            } else if rpc.code == ErrorCode::MethodNotFound {
+                ApiError::NotImplemented(format!("Node doesn't support the call: {}", rpc.message))
+            } else {
+                ApiError::InternalServerError(format!("RPC call failed: {error}"))
+            }
+        }
+        web3::Error::Transport(TransportError::Code(429)) => {
+            ApiError::UpstreamRateLimited(format!("RPC rate limited: {error}"))
+        }
+        web3::Error::Transport(TransportError::Code(408 | 504)) => {
+            ApiError::UpstreamTimeout(format!("RPC call timed out: {error}"))
+        }
+        web3::Error::Transport(TransportError::Message(message))
+            if TIMEOUT_MESSAGES
+                .iter()
+                .any(|pattern| message.to_lowercase().contains(pattern)) =>
+        {
+            ApiError::UpstreamTimeout(format!("RPC call timed out: {error}"))
+        }
+        web3::Error::Transport(_)
+        | web3::Error::Unreachable
+        | web3::Error::Io(_)
+        | web3::Error::InvalidResponse(_)
+        | web3::Error::Decoder(_) => {
+            ApiError::UpstreamUnavailable(format!("RPC call failed: {error}"))
+        }
+        _ => ApiError::InternalServerError(format!("RPC call failed: {error}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::StatusCode;
+
+    fn rpc_error(code: i64, message: &str) -> web3::Error {
+        web3::Error::Rpc(jsonrpc_core::Error {
+            code: ErrorCode::from(code),
+            message: message.to_string(),
+            data: None,
+        })
+    }
+
+    #[test]
+    fn test_classify_rpc_error_matrix() {
+        let cases = [
+            (
+                rpc_error(3, "execution reverted"),
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "execution_reverted",
+            ),
+            (
+                rpc_error(-32000, "execution reverted: Ownable: caller"),
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "execution_reverted",
+            ),
+            (
+                rpc_error(-32000, "intrinsic gas too low: have 0, want 21000"),
+                StatusCode::BAD_REQUEST,
+                "invalid_transaction",
+            ),
+            (
+                rpc_error(
+                    -32000,
+                    "insufficient funds for gas * price + value: have 0 want 1",
+                ),
+                StatusCode::BAD_REQUEST,
+                "invalid_transaction",
+            ),
+            (
+                rpc_error(-32005, "daily request count exceeded"),
+                StatusCode::SERVICE_UNAVAILABLE,
+                "upstream_rate_limited",
+            ),
+            (
+                rpc_error(-32000, "Too Many Requests"),
+                StatusCode::SERVICE_UNAVAILABLE,
+                "upstream_rate_limited",
+            ),
+            (
+                rpc_error(-32000, "execution aborted (timeout = 5s)"),
+                StatusCode::GATEWAY_TIMEOUT,
+                "upstream_timeout",
+            ),
+            (
+                rpc_error(-32000, "header not found"),
+                StatusCode::BAD_GATEWAY,
+                "upstream_unavailable",
+            ),
+            (
+                rpc_error(-32601, "the method eth_estimateGas does not exist"),
+                StatusCode::NOT_IMPLEMENTED,
+                "not_implemented",
+            ),
+            (
+                rpc_error(-32603, "internal error"),
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "internal_error",
+            ),
+            (
+                web3::Error::Transport(TransportError::Code(429)),
+                StatusCode::SERVICE_UNAVAILABLE,
+                "upstream_rate_limited",
+            ),
+            (
+                web3::Error::Transport(TransportError::Code(503)),
+                StatusCode::BAD_GATEWAY,
+                "upstream_unavailable",
+            ),
+            (
+                web3::Error::Transport(TransportError::Code(504)),
+                StatusCode::GATEWAY_TIMEOUT,
+                "upstream_timeout",
+            ),
+            (
+                web3::Error::Transport(TransportError::Message(
+                    "failed to send request: operation timed out".to_string(),
+                )),
+                StatusCode::GATEWAY_TIMEOUT,
+                "upstream_timeout",
+            ),
+            (
+                web3::Error::Transport(TransportError::Message(
+                    "failed to send request: connection refused".to_string(),
+                )),
+                StatusCode::BAD_GATEWAY,
+                "upstream_unavailable",
+            ),
+            (
+                web3::Error::Unreachable,
+                StatusCode::BAD_GATEWAY,
+                "upstream_unavailable",
+            ),
+        ];
+
+        for (error, status, code) in cases {
+            let description = error.to_string();
+            let classified = classify_rpc_error(error);
+            assert_eq!(classified.status_code(), status, "{description}");
+            assert_eq!(classified.code(), code, "{description}");
+        }
+    }
+
+    #[test]
+    fn test_invalid_transaction_keeps_the_node_message() {
+        let error = classify_rpc_error(rpc_error(-32000, "nonce too low: next nonce 5"));
+        assert_eq!(
+            error.to_string(),
+            "Invalid transaction: nonce too low: next nonce 5"
+        );
+    }
+}
@@ -0,0 +1,221 @@
+//! Gas estimation for EIP-5792 `wallet_sendCalls` batches
+
+use crate::abi::encode_call;
+use crate::estimator::{ApiError, GasEstimator};
+use crate::revert;
+use futures::future::try_join_all;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use web3::{
+    Transport,
+    ethabi::{ParamType, Token},
+    types::{Address, Bytes, CallRequest, U256},
+};
+
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct WalletCall {
+    pub to: Option<Address>,
+    pub data: Option<Bytes>,
+    pub value: Option<U256>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct WalletSendCallsRequest {
+    pub version: String,
+    #[serde(alias = "chainId")]
+    pub chain_id: U256,
+    pub from: Address,
+    pub calls: Vec<WalletCall>,
+    /// Multicall3 or a batcher exposing the same `aggregate3Value` interface
+    #[serde(default, alias = "batchExecutor")]
+    pub batch_executor: Option<Address>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct WalletSendCallsEstimate {
+    pub per_call_gas: Vec<U256>,
+    /// Gas for executing all calls atomically through the batch executor
+    pub batched_total_gas: Option<U256>,
+    pub version: String,
+}
+
+impl<T: Transport> GasEstimator<T> {
+    /// Estimates each call of a `wallet_sendCalls` batch, and the whole batch when
+    /// a batch executor is given
+    pub async fn estimate_wallet_send_calls(
+        &self,
+        request: WalletSendCallsRequest,
+    ) -> Result<WalletSendCallsEstimate, ApiError> {
+        if request.calls.is_empty() {
+            return Err(ApiError::BadRequest(
+                "calls must contain at least one call".to_string(),
+            ));
+        }
+
+        let per_call_gas = try_join_all(request.calls.iter().map(|call| async {
+            let estimate = self
+                .estimate_gas(CallRequest {
+                    from: Some(request.from),
+                    to: call.to,
+                    data: call.data.clone(),
+                    value: call.value,
+                    ..Default::default()
+                })
+                .await?;
+            Ok::<_, ApiError>(estimate.gas_limit)
+        }))
+        .await?;
+
+        let batched_total_gas = match request.batch_executor {
+            Some(executor) => Some(
+                self.estimate_batched_calls(request.from, executor, &request.calls)
+                    .await?,
+            ),
+            None => None,
+        };
+
+        Ok(WalletSendCallsEstimate {
+            per_call_gas,
+            batched_total_gas,
+            version: request.version,
+        })
+    }
+
+    /// Estimates `aggregate3Value` on the batch executor with all calls, sending
+    /// their combined value
+    async fn estimate_batched_calls(
+        &self,
+        from: Address,
+        executor: Address,
+        calls: &[WalletCall],
+    ) -> Result<U256, ApiError> {
+        let value = calls.iter().fold(U256::zero(), |total, call| {
+            total.saturating_add(call.value.unwrap_or_default())
+        });
+
+        self.eth
+            .estimate_gas(
+                CallRequest {
+                    from: Some(from),
+                    to: Some(executor),
+                    value: (!value.is_zero()).then_some(value),
+                    data: Some(Bytes(encode_aggregate3_value(calls)?)),
+                    ..Default::default()
+                },
+                None,
+            )
+            .await
+            .map_err(revert::estimate_error)
+    }
+}
+
+/// ABI-encodes Multicall3 `aggregate3Value((address,bool,uint256,bytes)[])`.
+/// Every call must succeed, matching the atomicity of `wallet_sendCalls`.
+fn encode_aggregate3_value(calls: &[WalletCall]) -> Result<Vec<u8>, ApiError> {
+    let call_type = ParamType::Tuple(vec![
+        ParamType::Address,
+        ParamType::Bool,
+        ParamType::Uint(256),
+        ParamType::Bytes,
+    ]);
+    let tokens = calls
+        .iter()
+        .map(|call| {
+            let to = call.to.ok_or_else(|| {
+                ApiError::BadRequest(
+                    "Contract deployments can't be batched through a batch executor".to_string(),
+                )
+            })?;
+            Ok(Token::Tuple(vec![
+                Token::Address(to),
+                Token::Bool(false),
+                Token::Uint(call.value.unwrap_or_default()),
+                Token::Bytes(call.data.clone().unwrap_or_default().0),
+            ]))
+        })
+        .collect::<Result<Vec<_>, ApiError>>()?;
+
+    Ok(encode_call(
+        "aggregate3Value",
+        &[ParamType::Array(Box::new(call_type))],
+        &[Token::Array(tokens)],
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use web3::transports::test::TestTransport;
+
+    fn request(batch_executor: Option<Address>) -> WalletSendCallsRequest {
+        WalletSendCallsRequest {
+            version: "1.0".to_string(),
+            chain_id: 1.into(),
+            from: Address::repeat_byte(0x01),
+            calls: vec![
+                // Plain transfer to an account without code, estimated statically
+                WalletCall {
+                    to: Some(Address::repeat_byte(0x02)),
+                    data: None,
+                    value: Some(1000.into()),
+                },
+                WalletCall {
+                    to: Some(Address::repeat_byte(0x03)),
+                    data: Some(Bytes::from(vec![0xa9, 0x05, 0x9c, 0xbb])),
+                    value: None,
+                },
+            ],
+            batch_executor,
+        }
+    }
+
+    #[test]
+    fn test_selector() {
+        let data = encode_aggregate3_value(&request(None).calls).unwrap();
+        assert_eq!(data[..4], [0x17, 0x4d, 0xea, 0x71]);
+    }
+
+    #[tokio::test]
+    async fn test_estimate_per_call() {
+        let mut transport = TestTransport::default();
+        transport.add_response("0x".into()); // The transfer receiver has no code
+        transport.add_response("0xc350".into()); // 50000
+        let estimator = GasEstimator::new(transport);
+
+        let estimate = estimator
+            .estimate_wallet_send_calls(request(None))
+            .await
+            .unwrap();
+        assert_eq!(estimate.per_call_gas, vec![21000.into(), 50000.into()]);
+        assert_eq!(estimate.batched_total_gas, None);
+        assert_eq!(estimate.version, "1.0");
+    }
+
+    #[tokio::test]
+    async fn test_estimate_batched() {
+        let mut transport = TestTransport::default();
+        transport.add_response("0x".into()); // The transfer receiver has no code
+        transport.add_response("0xc350".into()); // 50000
+        transport.add_response("0x11170".into()); // 70000
+        let estimator = GasEstimator::new(transport);
+
+        let estimate = estimator
+            .estimate_wallet_send_calls(request(Some(Address::repeat_byte(0xca))))
+            .await
+            .unwrap();
+        assert_eq!(estimate.batched_total_gas, Some(70000.into()));
+    }
+
+    #[tokio::test]
+    async fn test_deployments_cannot_be_batched() {
+        let mut transport = TestTransport::default();
+        transport.add_response("0x".into()); // The transfer receiver has no code
+        transport.add_response("0xc350".into());
+        let estimator = GasEstimator::new(transport);
+        let mut request = request(Some(Address::repeat_byte(0xca)));
+        request.calls[1].to = None;
+
+        let result = estimator.estimate_wallet_send_calls(request).await;
+        assert!(matches!(result, Err(ApiError::BadRequest(_))));
+    }
+}
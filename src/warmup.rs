@@ -0,0 +1,157 @@
+//! Startup warmup: opens the connection to the node before the listener binds,
+//! so the first request doesn't pay for DNS and TLS, and learns the chain id
+//! estimates report
+
+use crate::estimator::{ApiError, GasEstimator};
+use crate::fee_history::{DEFAULT_FEE_HISTORY_BLOCKS, DEFAULT_FEE_HISTORY_PERCENTILES};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use web3::Transport;
+
+/// How long startup keeps retrying a failing warmup
+pub const DEFAULT_WARMUP_GRACE_SECS: u64 = 30;
+/// Delay before the first warmup retry, doubled for each further one
+const WARMUP_RETRY_BASE_DELAY: Duration = Duration::from_millis(500);
+/// Longest delay between two warmup attempts
+const WARMUP_RETRY_MAX_DELAY: Duration = Duration::from_secs(5);
+
+/// Outcome of a successful warmup
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Warmup {
+    /// Chain id the node reported
+    pub chain_id: u64,
+    /// Time the node took to answer `eth_chainId`, including connecting to it
+    pub latency: Duration,
+}
+
+/// Whether the service started without reaching the node, shared with the
+/// readiness endpoint, which warms up again until it succeeds
+#[derive(Debug, Default)]
+pub struct WarmupState {
+    pending: AtomicBool,
+}
+
+impl WarmupState {
+    /// Records that startup went ahead without a successful warmup
+    pub fn set_pending(&self) {
+        self.pending.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether the node hasn't been warmed up yet
+    pub fn is_pending(&self) -> bool {
+        self.pending.load(Ordering::Relaxed)
+    }
+
+    /// Records a successful warmup
+    pub fn complete(&self) {
+        self.pending.store(false, Ordering::Relaxed);
+    }
+}
+
+impl<T: Transport> GasEstimator<T> {
+    /// Asks the node for its chain id, which opens the connection to it and is
+    /// then reported by every estimate, and fills the block gas limit and fee
+    /// history caches. Failing to fill a cache doesn't fail the warmup.
+    pub async fn warm_up(&self) -> Result<Warmup, ApiError> {
+        let started = Instant::now();
+        let chain_id = self.chain_id().await?;
+        let latency = started.elapsed();
+        self.set_known_chain_id(chain_id);
+
+        self.block_gas_limit().await;
+        if let Err(error) = self
+            .fee_history(
+                DEFAULT_FEE_HISTORY_BLOCKS,
+                DEFAULT_FEE_HISTORY_PERCENTILES.to_vec(),
+            )
+            .await
+        {
+            tracing::debug!(%error, "Failed to warm up the fee history cache");
+        }
+        Ok(Warmup { chain_id, latency })
+    }
+
+    /// Warms up, retrying with exponential backoff until `grace` has elapsed
+    pub async fn warm_up_within(&self, grace: Duration) -> Result<Warmup, ApiError> {
+        let deadline = Instant::now() + grace;
+        let mut delay = WARMUP_RETRY_BASE_DELAY;
+        loop {
+            match self.warm_up().await {
+                Err(error) if Instant::now() + delay < deadline => {
+                    tracing::warn!(
+                        %error,
+                        delay_ms = delay.as_millis() as u64,
+                        "Warmup failed, retrying"
+                    );
+                    tokio::time::sleep(delay).await;
+                    delay = (delay * 2).min(WARMUP_RETRY_MAX_DELAY);
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use web3::transports::test::TestTransport;
+
+    #[tokio::test]
+    async fn test_warm_up() {
+        let mut transport = TestTransport::default();
+        transport.add_response(json!("0x1"));
+        transport.add_response(json!({ "gasLimit": "0x1c9c380" }));
+        transport.add_response(json!({
+            "oldestBlock": "0x10",
+            "baseFeePerGas": ["0x3b9aca00", "0x3b9aca00"],
+            "gasUsedRatio": [0.5],
+            "reward": [["0x1", "0x2", "0x3"]]
+        }));
+        let estimator =
+            GasEstimator::new(transport.clone()).with_block_gas_limit_ttl(Duration::from_secs(60));
+        assert_eq!(estimator.known_chain_id(), None);
+
+        let warmup = estimator.warm_up().await.unwrap();
+        assert_eq!(warmup.chain_id, 1);
+        assert_eq!(estimator.known_chain_id(), Some(1));
+        transport.assert_request("eth_chainId", &[]);
+        transport.assert_request(
+            "eth_getBlockByNumber",
+            &[r#""latest""#.into(), "false".into()],
+        );
+        transport.assert_request(
+            "eth_feeHistory",
+            &[
+                r#""0x14""#.into(),
+                r#""latest""#.into(),
+                "[10.0,50.0,90.0]".into(),
+            ],
+        );
+        transport.assert_no_more_requests();
+
+        // Both caches are filled
+        assert_eq!(estimator.block_gas_limit().await, 30_000_000);
+        estimator
+            .fee_history(
+                DEFAULT_FEE_HISTORY_BLOCKS,
+                DEFAULT_FEE_HISTORY_PERCENTILES.to_vec(),
+            )
+            .await
+            .unwrap();
+        transport.assert_no_more_requests();
+    }
+
+    #[tokio::test]
+    async fn test_warm_up_within_gives_up_after_the_grace_period() {
+        let estimator = GasEstimator::new(TestTransport::default());
+
+        let error = estimator
+            .warm_up_within(Duration::from_millis(100))
+            .await
+            .unwrap_err();
+        assert!(error.is_upstream_failure(), "{error:?}");
+        assert_eq!(estimator.known_chain_id(), None);
+    }
+}
@@ -0,0 +1,207 @@
+//! WebSocket RPC transport that reconnects after its connection fails
+
+use crate::config::redact_urls;
+use futures::future::{BoxFuture, FutureExt};
+use jsonrpc_core::Call;
+use reqwest::Url;
+use serde_json::Value;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use web3::{RequestId, Transport, error::TransportError, helpers, transports::WebSocket};
+
+/// Connects on the first request, and again on the first request after the
+/// connection failed or a request timed out. A dropped connection only fails the
+/// requests in flight on it.
+#[derive(Debug, Clone)]
+pub struct ReconnectingWebSocket {
+    url: Url,
+    /// Limit on connecting and on each request
+    timeout: Duration,
+    /// Open connection, numbered so a failure only closes the connection it
+    /// happened on
+    connection: Arc<Mutex<Option<(u64, WebSocket)>>>,
+    connections_opened: Arc<Mutex<u64>>,
+    /// Serializes connection attempts
+    connecting: Arc<tokio::sync::Mutex<()>>,
+    next_id: Arc<AtomicUsize>,
+}
+
+impl ReconnectingWebSocket {
+    pub fn new(url: Url, timeout: Duration) -> Self {
+        Self {
+            url,
+            timeout,
+            connection: Arc::default(),
+            connections_opened: Arc::default(),
+            connecting: Arc::default(),
+            next_id: Arc::new(AtomicUsize::new(1)),
+        }
+    }
+
+    /// Whether a connection is open. Connections are closed by the first request
+    /// that fails on them.
+    pub fn is_connected(&self) -> bool {
+        self.connection.lock().unwrap().is_some()
+    }
+
+    fn current(&self) -> Option<(u64, WebSocket)> {
+        self.connection.lock().unwrap().clone()
+    }
+
+    /// The open connection, connecting first if there is none
+    async fn connect(&self) -> web3::Result<(u64, WebSocket)> {
+        if let Some(connection) = self.current() {
+            return Ok(connection);
+        }
+        let _connecting = self.connecting.lock().await;
+        // Another request may have connected while this one waited
+        if let Some(connection) = self.current() {
+            return Ok(connection);
+        }
+
+        let socket = WebSocket::new(self.url.as_str()).await?;
+        let number = {
+            let mut opened = self.connections_opened.lock().unwrap();
+            *opened += 1;
+            *opened
+        };
+        tracing::info!(
+            endpoint = %redact_urls(self.url.as_str()),
+            "Connected to the RPC WebSocket"
+        );
+        *self.connection.lock().unwrap() = Some((number, socket.clone()));
+        Ok((number, socket))
+    }
+
+    /// Closes the given connection, unless it was already replaced
+    fn disconnect(&self, number: u64) {
+        let mut connection = self.connection.lock().unwrap();
+        if connection.as_ref().is_some_and(|(open, _)| *open == number) {
+            *connection = None;
+        }
+    }
+}
+
+/// Whether the error means the connection can't carry requests any more, as
+/// opposed to an answer from the node
+fn is_connection_failure(error: &web3::Error) -> bool {
+    matches!(
+        error,
+        web3::Error::Transport(_) | web3::Error::Unreachable | web3::Error::Io(_)
+    )
+}
+
+impl Transport for ReconnectingWebSocket {
+    type Out = BoxFuture<'static, web3::Result<Value>>;
+
+    fn prepare(&self, method: &str, params: Vec<Value>) -> (RequestId, Call) {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        (id, helpers::build_request(id, method, params))
+    }
+
+    fn send(&self, id: RequestId, request: Call) -> Self::Out {
+        let this = self.clone();
+        async move {
+            let mut number = None;
+            let request = async {
+                let (connection, socket) = this.connect().await?;
+                number = Some(connection);
+                socket.send(id, request).await
+            };
+            let result = tokio::time::timeout(this.timeout, request)
+                .await
+                .unwrap_or_else(|_| {
+                    Err(web3::Error::Transport(TransportError::Message(format!(
+                        "WebSocket request timed out after {} seconds",
+                        this.timeout.as_secs_f64()
+                    ))))
+                });
+
+            if let Err(error) = &result
+                && is_connection_failure(error)
+                && let Some(number) = number
+            {
+                tracing::warn!(
+                    endpoint = %redact_urls(this.url.as_str()),
+                    %error,
+                    "RPC WebSocket failed, reconnecting on the next request"
+                );
+                this.disconnect(number);
+            }
+            result
+        }
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{SinkExt, StreamExt};
+    use tokio::net::TcpListener;
+    use tokio_tungstenite::tungstenite::Message;
+
+    /// Node answering `eth_blockNumber` over WebSocket with the number of the
+    /// connection, closing each connection after `requests_per_connection` answers
+    async fn spawn_node(requests_per_connection: usize) -> Url {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut connection = 0;
+            while let Ok((stream, _)) = listener.accept().await {
+                connection += 1;
+                let mut socket = tokio_tungstenite::accept_async(stream).await.unwrap();
+                for _ in 0..requests_per_connection {
+                    let Some(Ok(Message::Text(request))) = socket.next().await else {
+                        break;
+                    };
+                    let request: Value = serde_json::from_str(&request).unwrap();
+                    let response = serde_json::json!({
+                        "jsonrpc": "2.0",
+                        "id": request["id"],
+                        "result": format!("{connection:#x}"),
+                    });
+                    socket
+                        .send(Message::Text(response.to_string()))
+                        .await
+                        .unwrap();
+                }
+                let _ = socket.close(None).await;
+            }
+        });
+        format!("ws://{address}").parse().unwrap()
+    }
+
+    async fn block_number(transport: &ReconnectingWebSocket) -> web3::Result<Value> {
+        transport.execute("eth_blockNumber", vec![]).await
+    }
+
+    #[tokio::test]
+    async fn test_reconnects_after_connection_drops() {
+        let transport = ReconnectingWebSocket::new(spawn_node(2).await, Duration::from_secs(5));
+        assert!(!transport.is_connected());
+
+        assert_eq!(block_number(&transport).await.unwrap(), "0x1");
+        assert!(transport.is_connected());
+        assert_eq!(block_number(&transport).await.unwrap(), "0x1");
+
+        // The node closed the connection: the next request fails cleanly and the
+        // one after it is served by a new connection
+        assert!(block_number(&transport).await.is_err());
+        assert!(!transport.is_connected());
+        assert_eq!(block_number(&transport).await.unwrap(), "0x2");
+        assert!(transport.is_connected());
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_node_fails_requests() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        drop(listener);
+
+        let transport = ReconnectingWebSocket::new(url.parse().unwrap(), Duration::from_secs(5));
+        assert!(block_number(&transport).await.is_err());
+        assert!(!transport.is_connected());
+    }
+}
@@ -0,0 +1,84 @@
+//! Chains configured through the environment, each answered by its own transport
+
+mod common;
+
+use axum::http::StatusCode;
+use common::{MockTransport, post_json, send};
+use gas_estimator::{AppState, GasEstimator, build_router, chains::ChainConfig};
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+#[tokio::test]
+async fn requests_reach_the_selected_chain() {
+    // The only test in this binary, so no other test observes the variables
+    unsafe {
+        std::env::set_var(
+            "ETH_RPC_URLS",
+            r#"{"1": "https://mainnet.example", "11155111": "https://sepolia.example"}"#,
+        );
+        std::env::remove_var("CHAINS");
+    }
+    let config = ChainConfig::from_env("https://unused.example").unwrap();
+    assert_eq!(config.default_chain_id, Some(1));
+
+    let transports: HashMap<String, MockTransport> = config
+        .chains
+        .iter()
+        .map(|(_, urls)| (urls[0].to_string(), MockTransport::default()))
+        .collect();
+    let chains: HashMap<_, _> = config
+        .chains
+        .iter()
+        .map(|(chain_id, urls)| {
+            let transport = transports[urls[0].as_str()].clone();
+            (*chain_id, Arc::new(GasEstimator::new(transport)))
+        })
+        .collect();
+    let app = build_router(AppState::with_chains(chains[&1].clone(), chains));
+    let mainnet = &transports["https://mainnet.example/"];
+    let sepolia = &transports["https://sepolia.example/"];
+
+    let call = |chain_id: Option<u64>| {
+        let mut body = json!({
+            "to": "0x6b175474e89094c44da98b954eedeac495271d0f",
+            "data": "0x70a08231"
+        });
+        if let Some(chain_id) = chain_id {
+            body["chain_id"] = json!(chain_id);
+        }
+        post_json("/api/estimate-gas", body)
+    };
+
+    // Each estimate is queued right before its request: the fee lookup that follows
+    // consumes whatever is left in the queue
+    sepolia.add_response(json!("0xc350"));
+    let (status, json) = send(app.clone(), call(Some(11155111))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["gas_limit"], "0xc350");
+
+    mainnet.add_response(json!("0x5208"));
+    let (status, json) = send(app.clone(), call(Some(1))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["gas_limit"], "0x5208");
+
+    // Chain 1 is the default
+    mainnet.add_response(json!("0x5209"));
+    let (status, json) = send(app.clone(), call(None)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["gas_limit"], "0x5209");
+
+    let (status, json) = send(app, call(Some(10))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(json["error"].as_str().unwrap().contains("[1, 11155111]"));
+
+    let estimates = |transport: &MockTransport| {
+        transport
+            .methods()
+            .iter()
+            .filter(|method| *method == "eth_estimateGas")
+            .count()
+    };
+    assert_eq!(estimates(mainnet), 2);
+    assert_eq!(estimates(sepolia), 1);
+}
@@ -0,0 +1,184 @@
+//! Shared helpers for integration tests
+
+#![allow(dead_code)]
+
+use axum::{
+    Router,
+    body::{Body, to_bytes},
+    http::{Request, StatusCode},
+};
+use futures::future::{BoxFuture, FutureExt, ready};
+use gas_estimator::recorder::Exchange;
+use gas_estimator::{AppState, GasEstimator, build_router};
+use jsonrpc_core as rpc;
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tower::ServiceExt;
+use web3::{BatchTransport, RequestId, Transport, helpers};
+
+/// Recorded `(method, params)` pairs
+type Requests = Arc<Mutex<Vec<(String, Vec<Value>)>>>;
+
+/// Thread-safe scripted transport. Unlike web3's `TestTransport` it is `Send + Sync`,
+/// so it can back the axum router state.
+///
+/// Requests matching a replayed exchange get its response, whatever order they are
+/// sent in; others get the queued responses in order.
+#[derive(Debug, Clone, Default)]
+pub struct MockTransport {
+    requests: Requests,
+    responses: Arc<Mutex<VecDeque<web3::Result<Value>>>>,
+    replayed: Arc<Mutex<Vec<Exchange>>>,
+    delay: Arc<Mutex<Option<Duration>>>,
+    /// Sizes of the batches sent so far
+    batches: Arc<Mutex<Vec<usize>>>,
+}
+
+impl MockTransport {
+    /// Transport answering with the exchanges of a recording made with
+    /// `RECORD_UPSTREAM=1`, e.g. a file under `tests/fixtures`
+    pub fn replaying(recording: &str) -> Self {
+        let transport = Self::default();
+        *transport.replayed.lock().unwrap() = Exchange::read_all(recording).unwrap();
+        transport
+    }
+
+    /// Exchanges of the recording not requested so far
+    pub fn unreplayed(&self) -> Vec<Exchange> {
+        self.replayed.lock().unwrap().clone()
+    }
+
+    /// Queues a successful response
+    pub fn add_response(&self, value: Value) {
+        self.responses.lock().unwrap().push_back(Ok(value));
+    }
+
+    /// Queues an error response
+    pub fn add_error(&self, error: web3::Error) {
+        self.responses.lock().unwrap().push_back(Err(error));
+    }
+
+    /// Delays every response by `delay`, simulating a slow node
+    pub fn set_delay(&self, delay: Duration) {
+        *self.delay.lock().unwrap() = Some(delay);
+    }
+
+    /// Returns the methods called so far, in order
+    pub fn methods(&self) -> Vec<String> {
+        self.requests
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(method, _)| method.clone())
+            .collect()
+    }
+
+    /// Returns the number of requests in each batch sent so far
+    pub fn batches(&self) -> Vec<usize> {
+        self.batches.lock().unwrap().clone()
+    }
+
+    /// Returns the params of the n-th request
+    pub fn params(&self, index: usize) -> Vec<Value> {
+        self.requests.lock().unwrap()[index].1.clone()
+    }
+}
+
+impl Transport for MockTransport {
+    type Out = BoxFuture<'static, web3::Result<Value>>;
+
+    fn prepare(&self, method: &str, params: Vec<Value>) -> (RequestId, rpc::Call) {
+        let mut requests = self.requests.lock().unwrap();
+        requests.push((method.to_string(), params.clone()));
+        let id = requests.len();
+        (id, helpers::build_request(id, method, params))
+    }
+
+    fn send(&self, _id: RequestId, request: rpc::Call) -> Self::Out {
+        let replayed = match &request {
+            rpc::Call::MethodCall(call) => {
+                let params = serde_json::to_value(&call.params).unwrap();
+                let mut replayed = self.replayed.lock().unwrap();
+                replayed
+                    .iter()
+                    .position(|exchange| {
+                        exchange.method == call.method && exchange.params == params
+                    })
+                    .map(|index| replayed.remove(index).response())
+            }
+            _ => None,
+        };
+        let response = replayed.unwrap_or_else(|| {
+            self.responses
+                .lock()
+                .unwrap()
+                .pop_front()
+                .unwrap_or(Err(web3::Error::Unreachable))
+        });
+        match *self.delay.lock().unwrap() {
+            Some(delay) => tokio::time::sleep(delay).map(|_| response).boxed(),
+            None => ready(response).boxed(),
+        }
+    }
+}
+
+/// Answers each request of a batch as if sent alone; a queued error fails the batch
+impl BatchTransport for MockTransport {
+    type Batch = BoxFuture<'static, web3::Result<Vec<web3::Result<Value>>>>;
+
+    fn send_batch<I>(&self, requests: I) -> Self::Batch
+    where
+        I: IntoIterator<Item = (RequestId, rpc::Call)>,
+    {
+        let requests: Vec<_> = requests.into_iter().collect();
+        self.batches.lock().unwrap().push(requests.len());
+        let responses: Vec<_> = requests
+            .into_iter()
+            .map(|(id, request)| self.send(id, request))
+            .collect();
+        async move {
+            let responses = futures::future::join_all(responses).await;
+            match responses.iter().position(
+                |response| matches!(response, Err(error) if !matches!(error, web3::Error::Rpc(_))),
+            ) {
+                Some(index) => Err(responses.into_iter().nth(index).unwrap().unwrap_err()),
+                None => Ok(responses),
+            }
+        }
+        .boxed()
+    }
+}
+
+/// Router with default settings estimating through `transport`. Tests needing other
+/// settings build their own [`AppState`].
+pub fn router<T>(transport: T) -> Router
+where
+    T: Transport + Send + Sync + 'static,
+    T::Out: Send,
+{
+    build_router(AppState::new(GasEstimator::new(transport)))
+}
+
+/// Sends a request through the router and returns the status and JSON body
+pub async fn send(app: Router, request: Request<Body>) -> (StatusCode, Value) {
+    let response = app.oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let json = serde_json::from_slice(&body).unwrap_or(Value::Null);
+    (status, json)
+}
+
+/// Builds a JSON POST request
+pub fn post_json(uri: &str, body: Value) -> Request<Body> {
+    Request::post(uri)
+        .header("content-type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+/// Builds a GET request
+pub fn get(uri: &str) -> Request<Body> {
+    Request::get(uri).body(Body::empty()).unwrap()
+}
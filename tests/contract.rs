@@ -0,0 +1,253 @@
+//! The HTTP contract of `/api/estimate-gas`: full response bodies and status codes,
+//! answered from recorded upstream traffic in `tests/fixtures`
+
+mod common;
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+};
+use common::{MockTransport, post_json, router, send};
+use gas_estimator::cors;
+use serde_json::json;
+use tower::ServiceExt;
+
+const SENDER: &str = "0x1111111111111111111111111111111111111111";
+const RECEIVER: &str = "0x2222222222222222222222222222222222222222";
+const DAI: &str = "0x6b175474e89094c44da98b954eedeac495271d0f";
+const BLOB_HASH: &str = "0x0101010101010101010101010101010101010101010101010101010101010101";
+
+/// `fees` of every fixture: 1 gwei base fee and 0.1 gwei priority fee
+fn fees() -> serde_json::Value {
+    json!({
+        "base_fee_per_gas": "0x3b9aca00",
+        "max_priority_fee_per_gas": "0x5f5e100",
+        "max_fee_per_gas": "0x7d2b7500"
+    })
+}
+
+#[tokio::test]
+async fn static_path() {
+    let transport = MockTransport::replaying(include_str!("fixtures/eth_transfer.jsonl"));
+    let body = json!({ "from": SENDER, "to": RECEIVER, "value": "0xde0b6b3a7640000" });
+
+    let (status, json) = send(
+        router(transport.clone()),
+        post_json("/api/estimate-gas", body),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        json,
+        json!({
+            "gas_limit": "0x5208",
+            "method": "static",
+            "fees": fees(),
+            "pricing_rule": "eip2028",
+            "block_gas_limit": 30000000,
+            "blob_gas_used": null,
+            "max_fee_per_blob_gas": null,
+            "reason": "eoa_transfer",
+            "estimated_cost_wei": null,
+            "gas_price_used": null,
+            "l2_execution_fee_wei": null,
+            "cached": false
+        })
+    );
+    assert_eq!(transport.unreplayed(), []);
+}
+
+#[tokio::test]
+async fn rpc_path() {
+    let transport = MockTransport::replaying(include_str!("fixtures/erc20_transfer.jsonl"));
+    let body = json!({
+        "from": SENDER,
+        "to": DAI,
+        "data": "0xa9059cbb000000000000000000000000222222222222222222222222222222222222222200000000000000000000000000000000000000000000000000000000000003e8"
+    });
+
+    let (status, mut json) = send(
+        router(transport.clone()),
+        post_json("/api/estimate-gas", body),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    // The node's latency varies from run to run
+    assert!(
+        json.as_object_mut()
+            .unwrap()
+            .remove("rpc_latency_ms")
+            .unwrap()
+            .is_u64()
+    );
+    assert_eq!(
+        json,
+        json!({
+            "gas_limit": "0xd2f0",
+            "method": "rpc",
+            "fees": fees(),
+            "raw_gas_limit": "0xd2f0",
+            "buffer_percent": 0,
+            "pricing_rule": "eip2028",
+            "block_gas_limit": 30000000,
+            "blob_gas_used": null,
+            "max_fee_per_blob_gas": null,
+            "reason": "calldata_present",
+            "estimated_cost_wei": null,
+            "gas_price_used": null,
+            "l2_execution_fee_wei": null,
+            "calldata_stats": {
+                "total_bytes": 68,
+                "zero_bytes": 42,
+                "non_zero_bytes": 26,
+                "zero_ratio": 0.62,
+                "hint": "high zero-byte ratio; consider calldata compression for L2 deployment"
+            },
+            "cached": false
+        })
+    );
+    assert_eq!(transport.unreplayed(), []);
+}
+
+#[tokio::test]
+async fn blob_transaction() {
+    let transport = MockTransport::replaying(include_str!("fixtures/blob_transaction.jsonl"));
+    let body = json!({
+        "to": RECEIVER,
+        "type": "0x3",
+        "blob_versioned_hashes": [BLOB_HASH, BLOB_HASH],
+        "maxFeePerGas": "0x77359400",
+        "maxPriorityFeePerGas": "0x3b9aca00"
+    });
+
+    let (status, mut json) = send(
+        router(transport.clone()),
+        post_json("/api/estimate-gas", body),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    // The node's latency varies from run to run
+    assert!(
+        json.as_object_mut()
+            .unwrap()
+            .remove("rpc_latency_ms")
+            .unwrap()
+            .is_u64()
+    );
+    assert_eq!(
+        json,
+        json!({
+            "gas_limit": "0x5208",
+            "method": "rpc",
+            "fees": fees(),
+            "raw_gas_limit": "0x5208",
+            "buffer_percent": 0,
+            "pricing_rule": "eip2028",
+            "block_gas_limit": 30000000,
+            "blob_gas_used": 262144,
+            // Twice the blob base fee at the latest block's excess blob gas
+            "max_fee_per_blob_gas": "0xc9c8ac",
+            "reason": "blob_tx",
+            "estimated_cost_wei": null,
+            "gas_price_used": null,
+            "l2_execution_fee_wei": null,
+            "cached": false
+        })
+    );
+    assert_eq!(transport.unreplayed(), []);
+}
+
+#[tokio::test]
+async fn static_blob_path() {
+    let transport = MockTransport::replaying(include_str!("fixtures/blob_transaction.jsonl"));
+    let body = json!({
+        "to": RECEIVER,
+        "type": "0x3",
+        "blob_count": 2,
+        "maxFeePerGas": "0x77359400",
+        "maxPriorityFeePerGas": "0x3b9aca00"
+    });
+
+    let (status, json) = send(
+        router(transport.clone()),
+        post_json("/api/estimate-gas", body),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        json,
+        json!({
+            "gas_limit": "0x5208",
+            "method": "static-blob",
+            "fees": fees(),
+            "pricing_rule": "eip2028",
+            "block_gas_limit": 30000000,
+            "blob_gas_used": 262144,
+            "max_fee_per_blob_gas": "0xc9c8ac",
+            "reason": "zero_value_transfer",
+            "estimated_cost_wei": null,
+            "gas_price_used": null,
+            "l2_execution_fee_wei": null,
+            "cached": false
+        })
+    );
+    // With a blob count, the node isn't asked to estimate
+    let unreplayed: Vec<_> = transport
+        .unreplayed()
+        .into_iter()
+        .map(|exchange| exchange.method)
+        .collect();
+    assert_eq!(unreplayed, ["eth_estimateGas"]);
+}
+
+#[tokio::test]
+async fn upstream_failure() {
+    let transport = MockTransport::default();
+    transport.add_error(web3::Error::Transport(
+        web3::error::TransportError::Message("connection refused".to_string()),
+    ));
+    let body = json!({ "to": DAI, "data": "0x70a08231" });
+
+    let (status, json) = send(router(transport), post_json("/api/estimate-gas", body)).await;
+    assert_eq!(status, StatusCode::BAD_GATEWAY);
+    assert_eq!(
+        json,
+        json!({ "error": "RPC call failed", "code": "upstream_unavailable" })
+    );
+}
+
+#[tokio::test]
+async fn malformed_json() {
+    let request = Request::post("/api/estimate-gas")
+        .header("content-type", "application/json")
+        .body(Body::from(format!(r#"{{"to": "{DAI}","#)))
+        .unwrap();
+
+    let (status, json) = send(router(MockTransport::default()), request).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(
+        json,
+        json!({
+            "error": "Failed to parse the request body as JSON: EOF while parsing a value at line 1 column 52",
+            "code": "bad_request"
+        })
+    );
+}
+
+#[tokio::test]
+async fn cors_preflight() {
+    let app = router(MockTransport::default()).layer(cors::cors_layer(|_| None).unwrap());
+    let request = Request::options("/api/estimate-gas")
+        .header("origin", "https://app.example.com")
+        .header("access-control-request-method", "POST")
+        .header("access-control-request-headers", "content-type")
+        .body(Body::empty())
+        .unwrap();
+
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let headers = response.headers();
+    assert_eq!(headers["access-control-allow-origin"], "*");
+    assert_eq!(headers["access-control-allow-methods"], "*");
+    assert_eq!(headers["access-control-allow-headers"], "*");
+}
@@ -0,0 +1,272 @@
+mod common;
+
+use axum::http::StatusCode;
+use common::{MockTransport, post_json, send};
+use futures::future::{BoxFuture, FutureExt, ready};
+use gas_estimator::{ApiError, AppState, GasEstimator, MultiTransport, build_router};
+use jsonrpc_core::Call;
+use reqwest::Url;
+use serde_json::{Value, json};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use web3::error::TransportError;
+use web3::types::{Bytes, CallRequest};
+use web3::{BatchTransport, RequestId, Transport, helpers};
+
+fn url(name: &str) -> Url {
+    Url::parse(&format!("http://{name}.example")).unwrap()
+}
+
+/// A contract call, so estimation goes through the RPC path
+fn contract_call() -> CallRequest {
+    CallRequest {
+        data: Some(Bytes::from(vec![0x70, 0xa0, 0x82, 0x31])),
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn falls_back_to_next_endpoint() {
+    let primary = MockTransport::default(); // no responses queued, always errors
+    let secondary = MockTransport::default();
+    secondary.add_response(json!("0xc350"));
+    let transport = MultiTransport::new(vec![
+        (url("primary"), primary.clone()),
+        (url("secondary"), secondary.clone()),
+    ]);
+    let estimator = GasEstimator::new(transport.clone());
+
+    let estimate = estimator.estimate_gas(contract_call()).await.unwrap();
+    assert_eq!(estimate.gas_limit, 50000.into());
+    assert_eq!(estimate.method, "rpc");
+    assert_eq!(
+        estimate.endpoint.as_deref(),
+        Some("http://secondary.example/")
+    );
+    // The endpoint that answered is tried first from now on
+    assert_eq!(transport.urls(), vec![url("secondary"), url("primary")]);
+}
+
+#[tokio::test]
+async fn reports_every_failed_endpoint() {
+    let transport = MultiTransport::new(vec![
+        (url("primary"), MockTransport::default()),
+        (url("secondary"), MockTransport::default()),
+    ]);
+    let estimator = GasEstimator::new(transport);
+
+    let error = estimator.estimate_gas(contract_call()).await.unwrap_err();
+    let ApiError::UpstreamUnavailable(message) = error else {
+        panic!("unexpected error: {error:?}");
+    };
+    assert!(message.contains("All RPC endpoints failed"));
+    assert!(message.contains("http://primary.example/"));
+    assert!(message.contains("http://secondary.example/"));
+}
+
+#[tokio::test]
+async fn batch_errors_hide_endpoint_urls_over_http() {
+    let node = MockTransport::default(); // no responses queued, always errors
+    let secret = Url::parse("https://rpc.example/v3/secret-key").unwrap();
+    let transport = MultiTransport::new(vec![(secret, node)]);
+    let app = build_router(AppState::new(GasEstimator::new(transport)));
+
+    let body = json!([{ "to": "0x6b175474e89094c44da98b954eedeac495271d0f", "data": "0x70a08231" }]);
+    let (status, json) = send(app, post_json("/api/estimate-gas/batch", body)).await;
+    assert_eq!(status, StatusCode::OK, "{json}");
+    assert_eq!(json[0]["index"], 0);
+    assert_eq!(json[0]["code"], "upstream_unavailable");
+    assert!(!json.to_string().contains("secret-key"), "{json}");
+}
+
+#[tokio::test]
+async fn node_errors_are_not_retried() {
+    let primary = MockTransport::default();
+    primary.add_error(web3::Error::Rpc(jsonrpc_core::Error::invalid_params("bad")));
+    let secondary = MockTransport::default();
+    secondary.add_response(json!("0xc350"));
+    let transport = MultiTransport::new(vec![
+        (url("primary"), primary),
+        (url("secondary"), secondary),
+    ]);
+    let estimator = GasEstimator::new(transport.clone());
+
+    assert!(estimator.estimate_gas(contract_call()).await.is_err());
+    assert_eq!(transport.urls(), vec![url("primary"), url("secondary")]);
+}
+
+#[tokio::test]
+async fn demotes_failing_endpoint() {
+    let secondary = MockTransport::default();
+    secondary.add_response(json!("0xc350"));
+    let transport = MultiTransport::new(vec![
+        (url("primary"), MockTransport::default()),
+        (url("secondary"), secondary),
+    ])
+    .with_circuit_breaker(1, Duration::from_secs(60));
+    let estimator = GasEstimator::new(transport.clone());
+
+    estimator.estimate_gas(contract_call()).await.unwrap();
+    assert_eq!(transport.demoted_urls(), vec![url("primary")]);
+}
+
+#[tokio::test]
+async fn client_errors_are_not_retried() {
+    let primary = MockTransport::default();
+    primary.add_error(web3::Error::Transport(web3::error::TransportError::Code(
+        400,
+    )));
+    let secondary = MockTransport::default();
+    secondary.add_response(json!("0xc350"));
+    let transport = MultiTransport::new(vec![
+        (url("primary"), primary),
+        (url("secondary"), secondary),
+    ])
+    .with_circuit_breaker(1, Duration::from_secs(60));
+    let estimator = GasEstimator::new(transport.clone());
+
+    assert!(estimator.estimate_gas(contract_call()).await.is_err());
+    assert!(transport.demoted_urls().is_empty());
+}
+
+/// Node failing its first `failures` gas estimates with a 503. Other methods are
+/// unreachable, so fee lookups running alongside don't take scripted responses.
+#[derive(Debug, Clone, Default)]
+struct FlakyNode {
+    failures: usize,
+    estimates: Arc<AtomicUsize>,
+}
+
+impl Transport for FlakyNode {
+    type Out = BoxFuture<'static, web3::Result<Value>>;
+
+    fn prepare(&self, method: &str, params: Vec<Value>) -> (RequestId, Call) {
+        (0, helpers::build_request(0, method, params))
+    }
+
+    fn send(&self, _id: RequestId, request: Call) -> Self::Out {
+        let Call::MethodCall(call) = request else {
+            return ready(Err(web3::Error::Unreachable)).boxed();
+        };
+        if call.method != "eth_estimateGas" {
+            return ready(Err(web3::Error::Unreachable)).boxed();
+        }
+        let response = if self.estimates.fetch_add(1, Ordering::SeqCst) < self.failures {
+            Err(web3::Error::Transport(TransportError::Code(503)))
+        } else {
+            Ok(json!("0xc350"))
+        };
+        ready(response).boxed()
+    }
+}
+
+#[tokio::test]
+async fn retries_transient_failures() {
+    let node = FlakyNode {
+        failures: 2,
+        ..Default::default()
+    };
+    let transport = MultiTransport::new(vec![(url("primary"), node.clone())])
+        .with_retries(3, Duration::from_millis(1));
+    let app = build_router(AppState::new(GasEstimator::new(transport)));
+
+    let body = json!({ "to": "0x6b175474e89094c44da98b954eedeac495271d0f", "data": "0x70a08231" });
+    let (status, json) = send(app, post_json("/api/estimate-gas", body)).await;
+    assert_eq!(status, StatusCode::OK, "{json}");
+    assert_eq!(json["gas_limit"], "0xc350");
+    assert_eq!(json["rpc_attempts"], 3);
+    assert_eq!(node.estimates.load(Ordering::SeqCst), 3);
+}
+
+#[tokio::test]
+async fn gives_up_after_max_attempts() {
+    let node = FlakyNode {
+        failures: 2,
+        ..Default::default()
+    };
+    let transport = MultiTransport::new(vec![(url("primary"), node.clone())])
+        .with_retries(2, Duration::from_millis(1));
+    let estimator = GasEstimator::new(transport);
+
+    assert!(estimator.estimate_gas(contract_call()).await.is_err());
+    assert_eq!(node.estimates.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn rate_limits_are_retried() {
+    let node = MockTransport::default();
+    node.add_error(web3::Error::Rpc(jsonrpc_core::Error {
+        code: jsonrpc_core::ErrorCode::ServerError(-32005),
+        message: "rate limit exceeded".to_string(),
+        data: None,
+    }));
+    node.add_response(json!("0xc350"));
+    let transport =
+        MultiTransport::new(vec![(url("primary"), node)]).with_retries(3, Duration::from_millis(1));
+    let estimator = GasEstimator::new(transport);
+
+    let estimate = estimator.estimate_gas(contract_call()).await.unwrap();
+    assert_eq!(estimate.rpc_attempts, Some(2));
+}
+
+#[tokio::test]
+async fn reverts_are_not_retried() {
+    let node = MockTransport::default();
+    node.add_error(web3::Error::Rpc(jsonrpc_core::Error {
+        code: jsonrpc_core::ErrorCode::ServerError(3),
+        message: "execution reverted".to_string(),
+        data: None,
+    }));
+    node.add_response(json!("0xc350"));
+    let transport =
+        MultiTransport::new(vec![(url("primary"), node)]).with_retries(3, Duration::from_millis(1));
+    let estimator = GasEstimator::new(transport);
+
+    let error = estimator.estimate_gas(contract_call()).await.unwrap_err();
+    assert!(
+        matches!(error, ApiError::ExecutionReverted { .. }),
+        "{error:?}"
+    );
+    // The queued success wasn't used up by a retry
+    let estimate = estimator.estimate_gas(contract_call()).await.unwrap();
+    assert_eq!(estimate.rpc_attempts, Some(1));
+}
+
+#[tokio::test]
+async fn retries_stop_within_budget() {
+    // The first retry would start after the budget is spent
+    let node = FlakyNode {
+        failures: 1,
+        ..Default::default()
+    };
+    let transport = MultiTransport::new(vec![(url("primary"), node.clone())])
+        .with_retries(5, Duration::from_millis(50))
+        .with_retry_budget(Duration::from_millis(20));
+    let estimator = GasEstimator::new(transport);
+
+    assert!(estimator.estimate_gas(contract_call()).await.is_err());
+    assert_eq!(node.estimates.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn batches_fall_back_to_next_endpoint() {
+    let primary = MockTransport::default(); // no responses queued, always errors
+    let secondary = MockTransport::default();
+    secondary.add_response(json!("0x3b9aca00"));
+    secondary.add_response(json!("0x5f5e100"));
+    let transport = MultiTransport::new(vec![
+        (url("primary"), primary.clone()),
+        (url("secondary"), secondary.clone()),
+    ]);
+
+    let requests = ["eth_gasPrice", "eth_maxPriorityFeePerGas"]
+        .map(|method| transport.prepare(method, vec![]));
+    let results = transport.send_batch(requests).await.unwrap();
+    assert_eq!(
+        results.into_iter().map(Result::unwrap).collect::<Vec<_>>(),
+        [json!("0x3b9aca00"), json!("0x5f5e100")]
+    );
+    assert_eq!((primary.batches(), secondary.batches()), (vec![2], vec![2]));
+    assert_eq!(transport.urls(), vec![url("secondary"), url("primary")]);
+}
@@ -0,0 +1,182 @@
+//! The `/rpc` JSON-RPC endpoint: estimates answered by the estimator, fee methods
+//! forwarded to the node in one batch
+
+mod common;
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+};
+use common::{MockTransport, post_json, router, send};
+use gas_estimator::{AppState, GasEstimator, build_router};
+use serde_json::{Value, json};
+use std::sync::Arc;
+use web3::BatchTransport;
+
+const RECEIVER: &str = "0x2222222222222222222222222222222222222222";
+
+/// Router whose forwarded calls go to `transport` as batches
+fn batching_router(transport: &MockTransport) -> axum::Router {
+    let batch_transport = transport.clone();
+    let state = AppState {
+        rpc_batch: Some(Arc::new(move |requests| {
+            batch_transport.send_batch(requests)
+        })),
+        ..AppState::new(GasEstimator::new(transport.clone()))
+    };
+    build_router(state)
+}
+
+fn call(id: Value, method: &str, params: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params })
+}
+
+#[tokio::test]
+async fn transfer_is_estimated_without_the_node_estimating() {
+    let transport = MockTransport::default();
+    // eth_getCode: the receiver has no code
+    transport.add_response(json!("0x"));
+    let body = call(
+        json!(7),
+        "eth_estimateGas",
+        json!([{ "to": RECEIVER, "value": "0xde0b6b3a7640000" }, "latest"]),
+    );
+
+    let (status, json) = send(router(transport.clone()), post_json("/rpc", body)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        json,
+        json!({ "jsonrpc": "2.0", "id": 7, "result": "0x5208" })
+    );
+    assert_eq!(transport.methods(), ["eth_getCode"]);
+}
+
+#[tokio::test]
+async fn batch_forwards_fee_methods_together() {
+    // Only the forwarded calls reach the node: a transfer without value is
+    // estimated statically
+    let transport = MockTransport::replaying(&format!(
+        "{}\n{}",
+        json!({ "method": "eth_gasPrice", "params": [], "result": "0x3b9aca00" }),
+        json!({
+            "method": "eth_maxPriorityFeePerGas",
+            "params": [],
+            "error": { "code": -32000, "message": "method disabled" }
+        }),
+    ));
+    let body = json!([
+        call(json!("fees"), "eth_gasPrice", json!([])),
+        call(json!(2), "eth_estimateGas", json!([{ "to": RECEIVER }])),
+        call(json!(3), "eth_maxPriorityFeePerGas", json!([])),
+        call(json!(4), "eth_sendRawTransaction", json!(["0x02"])),
+        call(json!(5), "eth_estimateGas", json!([])),
+        { "jsonrpc": "2.0", "method": "eth_gasPrice" },
+    ]);
+
+    let (status, json) = send(batching_router(&transport), post_json("/rpc", body)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        json,
+        json!([
+            { "jsonrpc": "2.0", "id": "fees", "result": "0x3b9aca00" },
+            { "jsonrpc": "2.0", "id": 2, "result": "0x5208" },
+            {
+                "jsonrpc": "2.0",
+                "id": 3,
+                "error": { "code": -32000, "message": "method disabled" }
+            },
+            {
+                "jsonrpc": "2.0",
+                "id": 4,
+                "error": { "code": -32601, "message": "Method not found" }
+            },
+            {
+                "jsonrpc": "2.0",
+                "id": 5,
+                "error": { "code": -32602, "message": "missing transaction object" }
+            },
+        ])
+    );
+    assert_eq!(transport.batches(), [2]);
+    assert_eq!(transport.unreplayed(), []);
+}
+
+#[tokio::test]
+async fn reverted_estimate_is_a_json_rpc_error() {
+    let transport = MockTransport::default();
+    transport.add_error(web3::Error::Rpc(jsonrpc_core::Error {
+        code: jsonrpc_core::ErrorCode::ServerError(3),
+        message: "execution reverted".to_string(),
+        data: Some(json!("0x")),
+    }));
+    let body = call(
+        json!(1),
+        "eth_estimateGas",
+        json!([{ "to": RECEIVER, "data": "0x70a08231" }]),
+    );
+
+    let (status, json) = send(router(transport), post_json("/rpc", body)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["id"], 1);
+    assert_eq!(json["error"]["code"], 3);
+    assert_eq!(json["error"]["message"], "execution reverted");
+}
+
+#[tokio::test]
+async fn malformed_payloads_get_error_objects() {
+    let request = Request::post("/rpc")
+        .header("content-type", "application/json")
+        .body(Body::from("{"))
+        .unwrap();
+    let (status, json) = send(router(MockTransport::default()), request).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        json,
+        json!({
+            "jsonrpc": "2.0",
+            "id": null,
+            "error": { "code": -32700, "message": "Parse error" }
+        })
+    );
+
+    let batch: Vec<Value> = (0..51)
+        .map(|id| call(json!(id), "eth_gasPrice", json!([])))
+        .collect();
+    let (_, json) = send(
+        router(MockTransport::default()),
+        post_json("/rpc", batch.into()),
+    )
+    .await;
+    assert_eq!(json["error"]["code"], -32600);
+    assert_eq!(
+        json["error"]["message"],
+        "Batch size 51 exceeds maximum of 50"
+    );
+
+    let (_, json) = send(
+        router(MockTransport::default()),
+        post_json("/rpc", json!([1])),
+    )
+    .await;
+    assert_eq!(
+        json,
+        json!([{
+            "jsonrpc": "2.0",
+            "id": null,
+            "error": { "code": -32600, "message": "Invalid request" }
+        }])
+    );
+}
+
+#[tokio::test]
+async fn notifications_get_no_response() {
+    let transport = MockTransport::default();
+    let body = json!([{ "jsonrpc": "2.0", "method": "eth_gasPrice" }]);
+
+    let app = router(transport.clone());
+    let response = tower::ServiceExt::oneshot(app, post_json("/rpc", body))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    assert_eq!(transport.methods(), Vec::<String>::new());
+}
@@ -0,0 +1,40 @@
+mod common;
+
+use common::MockTransport;
+use futures::{SinkExt, StreamExt};
+use gas_estimator::{AppState, GasEstimator, build_router};
+use serde_json::{Value, json};
+use tokio_tungstenite::tungstenite::Message;
+
+#[tokio::test]
+async fn streams_gas_price_updates() {
+    let transport = MockTransport::default();
+    transport.add_response(json!("0x4a817c800")); // eth_gasPrice: 20 gwei
+    transport.add_response(json!("0x3b9aca00")); // eth_maxPriorityFeePerGas: 1 gwei
+    let app = build_router(AppState::new(GasEstimator::new(transport)));
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    let (mut socket, _) = tokio_tungstenite::connect_async(format!("ws://{address}/ws/gas-price"))
+        .await
+        .unwrap();
+
+    // The first update is sent immediately after the upgrade
+    let Some(Ok(Message::Text(text))) = socket.next().await else {
+        panic!("expected a text message");
+    };
+    let update: Value = serde_json::from_str(&text).unwrap();
+    assert_eq!(update["gas_price"], "0x4a817c800");
+    assert_eq!(update["max_priority_fee"], "0x3b9aca00");
+    assert!(update["timestamp_ms"].as_u64().unwrap() > 0);
+
+    socket.send(Message::Close(None)).await.unwrap();
+    // The server answers the close handshake and ends the stream
+    while let Some(Ok(message)) = socket.next().await {
+        if message.is_close() {
+            break;
+        }
+    }
+}